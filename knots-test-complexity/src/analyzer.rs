@@ -1,9 +1,10 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 use tree_sitter::{Node, Parser};
 use crate::boundary::{BoundaryAnalysis, BoundaryDetector};
-use knots::{calculate_mccabe_complexity, calculate_cognitive_complexity};
+use knots::{calculate_mccabe_complexity, calculate_cognitive_complexity, get_function_name};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionMetrics {
     pub function_name: String,
     pub cyclomatic_complexity: u32,
@@ -12,11 +13,21 @@ pub struct FunctionMetrics {
     pub line_end: usize,
 }
 
+/// An assertion whose arguments are all constant literals, so it can never fail
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrivialAssertion {
+    pub line: usize,
+    pub text: String,
+}
+
 pub struct FileAnalysis {
     pub file_path: String,
     pub functions: Vec<FunctionMetrics>,
     pub total_cyclomatic_complexity: u32,
     pub total_cognitive_complexity: u32,
+    pub trivial_assertions: Vec<TrivialAssertion>,
+    /// Loaded from a knots JSON report rather than parsed from C source
+    pub precomputed: bool,
 }
 
 impl FileAnalysis {
@@ -26,6 +37,8 @@ impl FileAnalysis {
             functions: Vec::new(),
             total_cyclomatic_complexity: 0,
             total_cognitive_complexity: 0,
+            trivial_assertions: Vec::new(),
+            precomputed: false,
         }
     }
 
@@ -41,8 +54,15 @@ pub struct TestQualityAnalyzer {
     pub source_analysis: FileAnalysis,
     pub threshold: f64,
     pub boundary_threshold: f64,
+    /// Headers scanned alongside the source file for boundary detection
+    pub source_headers: Vec<String>,
+    /// The test file does not exist yet (`--allow-missing`)
+    pub test_file_missing: bool,
+    /// Both sides were parsed from C files; false when either came from a knots JSON report
+    pub boundaries_available: bool,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct AnalysisResult {
     pub passed: bool,
     pub test_cyclomatic_complexity: u32,
@@ -55,10 +75,61 @@ pub struct AnalysisResult {
     pub boundary_threshold: f64,
     pub test_function_count: usize,
     pub source_function_count: usize,
+    /// Per-function complexity of the source file
+    pub source_functions: Vec<FunctionMetrics>,
     pub recommendations: Vec<String>,
     pub test_file: String,
     pub source_file: String,
     pub boundary_analysis: Option<BoundaryAnalysis>,
+    /// Tautological assertions found in the test file (test smell)
+    pub trivial_assertions: Vec<TrivialAssertion>,
+    /// The source has no test file yet; reported as 0% coverage
+    pub test_file_missing: bool,
+}
+
+/// Change in test quality between a previous run (`--compare`) and this one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityDelta {
+    /// Test/source cyclomatic ratio before and after
+    pub cyclomatic_ratio: (f64, f64),
+    /// Boundary coverage percent before and after, when both runs checked boundaries
+    pub coverage_percent: Option<(f64, f64)>,
+}
+
+impl QualityDelta {
+    pub fn between(previous: &AnalysisResult, current: &AnalysisResult) -> Self {
+        let coverage = |result: &AnalysisResult| result.boundary_analysis.as_ref().map(|boundary| boundary.coverage_percent);
+        Self {
+            cyclomatic_ratio: (previous.cyclomatic_ratio, current.cyclomatic_ratio),
+            coverage_percent: coverage(previous).zip(coverage(current)),
+        }
+    }
+
+    /// Measures that got worse since the previous run
+    pub fn regressions(&self) -> Vec<&'static str> {
+        let mut regressions = Vec::new();
+        if self.cyclomatic_ratio.1 < self.cyclomatic_ratio.0 {
+            regressions.push("cyclomatic ratio");
+        }
+        if let Some((before, after)) = self.coverage_percent {
+            if after < before {
+                regressions.push("boundary coverage");
+            }
+        }
+        regressions
+    }
+}
+
+/// Save a result as JSON for a later `--compare`
+pub fn save_result(result: &AnalysisResult, path: &str) -> Result<()> {
+    let json = serde_json::to_string_pretty(result).context("Failed to serialize analysis result")?;
+    std::fs::write(path, json).with_context(|| format!("Failed to write analysis result: {}", path))
+}
+
+/// Load a result saved with `--save-result`
+pub fn load_result(path: &str) -> Result<AnalysisResult> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read analysis result: {}", path))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse analysis result: {}", path))
 }
 
 impl TestQualityAnalyzer {
@@ -71,29 +142,73 @@ impl TestQualityAnalyzer {
         let test_analysis = analyze_file(test_file)?;
         let source_analysis = analyze_file(source_file)?;
 
-        Ok(Self {
+        Ok(Self::from_analyses(test_analysis, source_analysis, threshold, boundary_threshold))
+    }
+
+    /// Analyzer over already-computed file analyses, e.g. from `load_metrics_json`
+    /// Boundary analysis needs both C files, so it only runs when neither side came from JSON
+    pub fn from_analyses(
+        test_analysis: FileAnalysis,
+        source_analysis: FileAnalysis,
+        threshold: f64,
+        boundary_threshold: f64,
+    ) -> Self {
+        let boundaries_available = !test_analysis.precomputed && !source_analysis.precomputed;
+        Self {
             test_analysis,
             source_analysis,
             threshold,
             boundary_threshold,
+            source_headers: Vec::new(),
+            test_file_missing: false,
+            boundaries_available,
+        }
+    }
+
+    /// Analyzer for a source file whose test file does not exist yet
+    /// The result reports 0% coverage and fails with a "no test file" status
+    pub fn without_test(
+        test_file: &str,
+        source_file: &str,
+        threshold: f64,
+        boundary_threshold: f64,
+    ) -> Result<Self> {
+        Ok(Self {
+            test_analysis: FileAnalysis::new(test_file.to_string()),
+            source_analysis: analyze_file(source_file)?,
+            threshold,
+            boundary_threshold,
+            source_headers: Vec::new(),
+            test_file_missing: true,
+            boundaries_available: false,
         })
     }
 
+    /// Also scan these headers (types, MAX/MIN defines) during boundary detection
+    pub fn with_source_headers(mut self, source_headers: Vec<String>) -> Self {
+        self.source_headers = source_headers;
+        self
+    }
+
     pub fn analyze(&self, check_boundaries: bool) -> AnalysisResult {
         let test_cyclomatic = self.test_analysis.total_cyclomatic_complexity;
         let source_cyclomatic = self.source_analysis.total_cyclomatic_complexity;
         let test_cognitive = self.test_analysis.total_cognitive_complexity;
         let source_cognitive = self.source_analysis.total_cognitive_complexity;
 
-        // Calculate cyclomatic ratio
-        let cyclomatic_ratio = if source_cyclomatic > 0 {
+        // Calculate cyclomatic ratio; a missing test file covers nothing
+        let cyclomatic_ratio = if self.test_file_missing {
+            0.0
+        } else if source_cyclomatic > 0 {
             test_cyclomatic as f64 / source_cyclomatic as f64
         } else {
             1.0 // No source complexity = trivial, always pass
         };
 
         // Calculate cognitive ratio (for reporting and future ceiling checks)
-        let cognitive_ratio = if source_cognitive > 0 {
+        let cognitive_ratio = if self.test_file_missing {
+            0.0
+        } else if source_cognitive > 0 {
             test_cognitive as f64 / source_cognitive as f64
         } else {
             1.0
@@ -101,10 +216,10 @@ impl TestQualityAnalyzer {
 
         // Use cyclomatic ratio only for pass/fail determination
         // Cognitive complexity is tracked but not used in threshold calculation
-        let mut passed = cyclomatic_ratio >= self.threshold;
+        let mut passed = !self.test_file_missing && cyclomatic_ratio >= self.threshold;
 
-        // Perform boundary analysis if requested
-        let boundary_analysis = if check_boundaries {
+        // Perform boundary analysis if requested (there is nothing to check without tests)
+        let boundary_analysis = if check_boundaries && !self.test_file_missing && self.boundaries_available {
             match self.analyze_boundaries() {
                 Ok(analysis) => {
                     // Boundary coverage below threshold is a failure
@@ -123,7 +238,9 @@ impl TestQualityAnalyzer {
         };
 
         let mut recommendations = Vec::new();
-        if !passed {
+        if self.test_file_missing {
+            recommendations.push(format!("Create {} to start testing this module", self.test_analysis.file_path));
+        } else if !passed {
             self.generate_recommendations(&mut recommendations, cyclomatic_ratio, &boundary_analysis);
         }
 
@@ -139,16 +256,22 @@ impl TestQualityAnalyzer {
             boundary_threshold: self.boundary_threshold,
             test_function_count: self.test_analysis.functions.len(),
             source_function_count: self.source_analysis.functions.len(),
+            source_functions: self.source_analysis.functions.clone(),
             recommendations,
             test_file: self.test_analysis.file_path.clone(),
             source_file: self.source_analysis.file_path.clone(),
             boundary_analysis,
+            trivial_assertions: self.test_analysis.trivial_assertions.clone(),
+            test_file_missing: self.test_file_missing,
         }
     }
 
     fn analyze_boundaries(&self) -> Result<BoundaryAnalysis> {
         let mut detector = BoundaryDetector::new();
         detector.detect_boundaries(&self.source_analysis.file_path)?;
+        for header in &self.source_headers {
+            detector.detect_header_boundaries(header)?;
+        }
         detector.analyze_test_coverage(&self.test_analysis.file_path)
     }
 
@@ -215,7 +338,7 @@ pub fn analyze_file(file_path: &str) -> Result<FileAnalysis> {
     let source_code = std::fs::read(file_path)?;
 
     let mut parser = Parser::new();
-    let language = tree_sitter_c::language();
+    let language = tree_sitter::Language::from(tree_sitter_c::LANGUAGE);
     parser.set_language(&language)?;
 
     let tree = parser.parse(&source_code, None)
@@ -225,15 +348,179 @@ pub fn analyze_file(file_path: &str) -> Result<FileAnalysis> {
     let mut file_analysis = FileAnalysis::new(file_path.to_string());
 
     // Find all function definitions
-    visit_functions(&root_node, &source_code, &mut |node| {
+    visit_functions(&root_node, &mut |node| {
         let metrics = extract_function_metrics(&node, &source_code);
         file_analysis.add_function(metrics);
     });
 
+    find_trivial_assertions(&root_node, &source_code, &mut file_analysis.trivial_assertions);
+
     Ok(file_analysis)
 }
 
-fn visit_functions<F>(node: &Node, source_code: &[u8], callback: &mut F)
+/// The parts of a knots `--append` JSON report this tool needs
+#[derive(Deserialize)]
+struct KnotsReport {
+    functions: Vec<KnotsFunction>,
+}
+
+#[derive(Deserialize)]
+struct KnotsFunction {
+    name: String,
+    #[serde(default)]
+    file_path: String,
+    #[serde(default)]
+    line: usize,
+    mccabe: u32,
+    cognitive: u32,
+    #[serde(default)]
+    sloc: u32,
+}
+
+/// Whether two paths name the same file, allowing for `./` and for one being relative to
+/// a directory of the other (knots records paths as they were given on its command line)
+fn same_file(a: &str, b: &str) -> bool {
+    let components = |path: &str| -> std::path::PathBuf {
+        std::path::Path::new(path)
+            .components()
+            .filter(|component| *component != std::path::Component::CurDir)
+            .collect()
+    };
+    let (a, b) = (components(a), components(b));
+    !a.as_os_str().is_empty() && !b.as_os_str().is_empty() && (a.ends_with(&b) || b.ends_with(&a))
+}
+
+/// Load function metrics precomputed by `knots --append` instead of parsing C source
+///
+/// Only the functions of `file` are counted, so a project-wide report can be shared. Without
+/// `file` the report must cover a single file. Trivial assertions cannot be detected from
+/// metrics alone.
+pub fn load_metrics_json(report_path: &str, file: Option<&str>) -> Result<FileAnalysis> {
+    let content = std::fs::read_to_string(report_path)
+        .with_context(|| format!("Failed to read metrics report: {}", report_path))?;
+    let report: KnotsReport = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse knots JSON report: {}", report_path))?;
+
+    let functions: Vec<KnotsFunction> = match file {
+        Some(file) => {
+            let functions: Vec<_> =
+                report.functions.into_iter().filter(|func| same_file(&func.file_path, file)).collect();
+            if functions.is_empty() {
+                anyhow::bail!("No functions for {} in metrics report: {}", file, report_path);
+            }
+            functions
+        }
+        None => {
+            let mut files: Vec<&str> = report.functions.iter().map(|func| func.file_path.as_str()).collect();
+            files.sort_unstable();
+            files.dedup();
+            if files.len() > 1 {
+                anyhow::bail!(
+                    "Metrics report {} covers {} files ({}); name the file to use",
+                    report_path,
+                    files.len(),
+                    files.join(", ")
+                );
+            }
+            report.functions
+        }
+    };
+
+    let mut file_analysis = FileAnalysis::new(file.unwrap_or(report_path).to_string());
+    file_analysis.precomputed = true;
+    for func in functions {
+        file_analysis.add_function(FunctionMetrics {
+            function_name: func.name,
+            cyclomatic_complexity: func.mccabe,
+            cognitive_complexity: func.cognitive,
+            line_start: func.line,
+            // knots records SLOC rather than an end line; it is the closest span available
+            line_end: func.line + func.sloc.saturating_sub(1) as usize,
+        });
+    }
+    Ok(file_analysis)
+}
+
+/// Vendor HAL and SDK families, recognized by the start of an included header's path
+const HAL_PROFILES: [(&str, &str); 6] = [
+    ("stm32", "STM32 HAL"),
+    ("nrf", "Nordic nRF SDK"),
+    ("esp_", "ESP-IDF"),
+    ("fsl_", "NXP MCUXpresso SDK"),
+    ("driverlib/", "TI DriverLib"),
+    ("pico/", "Raspberry Pi Pico SDK"),
+];
+
+/// The vendor HAL a source file builds on, from its first matching `#include`,
+/// e.g. "STM32 HAL (stm32f4xx_hal.h)"
+pub fn detect_hal(source: &str) -> Option<String> {
+    source.lines().filter_map(|line| line.trim_start().strip_prefix("#include")).find_map(|rest| {
+        let rest = rest.trim_start();
+        let close = match rest.chars().next()? {
+            '<' => '>',
+            '"' => '"',
+            _ => return None,
+        };
+        let header = rest[1..].split(close).next()?;
+        let lowercase = header.to_lowercase();
+        HAL_PROFILES
+            .iter()
+            .find(|(prefix, _)| lowercase.starts_with(prefix))
+            .map(|(_, name)| format!("{} ({})", name, header))
+    })
+}
+
+/// Collect assertion calls (assert, TEST_ASSERT_*, EXPECT_*, ...) whose arguments are all constants
+fn find_trivial_assertions(node: &Node, source: &[u8], found: &mut Vec<TrivialAssertion>) {
+    if node.kind() == "call_expression" && is_assertion_call(node, source) {
+        if let Some(arguments) = node.child_by_field_name("arguments") {
+            let mut cursor = arguments.walk();
+            let args: Vec<Node> = arguments
+                .named_children(&mut cursor)
+                .filter(|arg| arg.kind() != "comment")
+                .collect();
+            if !args.is_empty() && args.iter().all(is_constant_expression) {
+                found.push(TrivialAssertion {
+                    line: node.start_position().row + 1,
+                    text: node.utf8_text(source).unwrap_or("").to_string(),
+                });
+            }
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        find_trivial_assertions(&child, source, found);
+    }
+}
+
+/// Name prefixes of assertion macros from common C test frameworks (assert/cmocka, Unity, CUnit, Check, gtest)
+const ASSERTION_PREFIXES: [&str; 7] =
+    ["assert", "TEST_ASSERT", "CU_ASSERT", "ck_assert", "ASSERT_", "EXPECT_", "configASSERT"];
+
+fn is_assertion_call(node: &Node, source: &[u8]) -> bool {
+    node.child_by_field_name("function")
+        .filter(|function| function.kind() == "identifier")
+        .and_then(|function| function.utf8_text(source).ok())
+        .map(|name| ASSERTION_PREFIXES.iter().any(|prefix| name.starts_with(prefix)))
+        .unwrap_or(false)
+}
+
+/// Literals, and operators applied only to literals (e.g. `-1`, `!0`, `1 == 1`)
+fn is_constant_expression(node: &Node) -> bool {
+    match node.kind() {
+        "number_literal" | "char_literal" | "string_literal" | "concatenated_string"
+        | "true" | "false" | "null" => true,
+        "parenthesized_expression" | "unary_expression" | "binary_expression" => {
+            let mut cursor = node.walk();
+            let mut operands = node.named_children(&mut cursor).peekable();
+            operands.peek().is_some() && operands.all(|operand| is_constant_expression(&operand))
+        }
+        _ => false,
+    }
+}
+
+fn visit_functions<F>(node: &Node, callback: &mut F)
 where
     F: FnMut(Node),
 {
@@ -243,12 +530,12 @@ where
 
     let mut cursor = node.walk();
     for child in node.children(&mut cursor) {
-        visit_functions(&child, source_code, callback);
+        visit_functions(&child, callback);
     }
 }
 
 fn extract_function_metrics(node: &Node, source: &[u8]) -> FunctionMetrics {
-    let function_name = extract_function_name(node, source);
+    let function_name = get_function_name(*node, source).unwrap_or_else(|| "unknown".to_string());
 
     // Use knots' complexity calculations directly
     let cyclomatic_complexity = calculate_mccabe_complexity(*node, source);
@@ -266,52 +553,167 @@ fn extract_function_metrics(node: &Node, source: &[u8]) -> FunctionMetrics {
     }
 }
 
-fn extract_function_name(node: &Node, source: &[u8]) -> String {
-    let mut cursor = node.walk();
-    for child in node.children(&mut cursor) {
-        if child.kind() == "function_declarator" {
-            return get_declarator_name(&child, source);
-        } else if child.kind() == "pointer_declarator" {
-            // For functions returning pointers
-            if let Some(name) = get_function_name_from_declarator(&child, source) {
-                return name;
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_detect_trivial_assertion() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/test_timer_tautology.c");
+        let analysis = analyze_file(path).unwrap();
+
+        // assert(1) is flagged; assert(get_timer_ms() == 0) is a real check
+        assert_eq!(analysis.trivial_assertions.len(), 1);
+        assert_eq!(analysis.trivial_assertions[0].line, 18);
+        assert_eq!(analysis.trivial_assertions[0].text, "assert(1)");
     }
-    "unknown".to_string()
-}
 
-fn get_function_name_from_declarator(node: &Node, source: &[u8]) -> Option<String> {
-    let mut cursor = node.walk();
+    #[test]
+    fn test_assertion_call_names() {
+        let code = b"void t(void) { assert(1); TEST_ASSERT_TRUE(1); \
+            static_assert(1, \"x\"); _Static_assert(1, \"x\"); reassert(1); }";
+        let mut parser = Parser::new();
+        parser.set_language(&tree_sitter_c::LANGUAGE.into()).unwrap();
+        let tree = parser.parse(code, None).unwrap();
+        let mut found = Vec::new();
+        find_trivial_assertions(&tree.root_node(), code, &mut found);
+
+        let texts: Vec<&str> = found.iter().map(|assertion| assertion.text.as_str()).collect();
+        assert_eq!(texts, vec!["assert(1)", "TEST_ASSERT_TRUE(1)"]);
+    }
 
-    for child in node.children(&mut cursor) {
-        if child.kind() == "function_declarator" {
-            return Some(get_declarator_name(&child, source));
-        } else if child.kind() == "pointer_declarator" {
-            if let Some(name) = get_function_name_from_declarator(&child, source) {
-                return Some(name);
-            }
-        }
+    #[test]
+    fn test_load_metrics_json() {
+        let dir = TempDir::new("metrics-json");
+        let path = dir.join("metrics.json");
+        std::fs::write(
+            &path,
+            r#"{"schema_version": 9, "functions": [
+                {"name": "timer_start", "file_path": "timer.c", "line": 10, "mccabe": 3, "cognitive": 2, "nesting": 1,
+                 "sloc": 8, "abc_magnitude": 2.0, "return_count": 1},
+                {"name": "timer_stop", "file_path": "timer.c", "line": 20, "mccabe": 2, "cognitive": 1, "nesting": 1,
+                 "sloc": 5, "abc_magnitude": 1.0, "return_count": 1}
+            ]}"#,
+        )
+        .unwrap();
+
+        let analysis = load_metrics_json(path.to_str().unwrap(), None).unwrap();
+
+        assert!(analysis.precomputed);
+        assert_eq!(analysis.functions.len(), 2);
+        assert_eq!(analysis.total_cyclomatic_complexity, 5);
+        assert_eq!(analysis.total_cognitive_complexity, 3);
+        assert_eq!((analysis.functions[0].line_start, analysis.functions[0].line_end), (10, 17));
+
+        let source = analyze_file(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/timer.c")).unwrap();
+        let analyzer = TestQualityAnalyzer::from_analyses(analysis, source, 0.70, 0.80);
+        assert!(!analyzer.boundaries_available);
+        assert!(analyzer.analyze(true).boundary_analysis.is_none());
     }
 
-    None
-}
+    #[test]
+    fn test_load_metrics_json_filters_by_file() {
+        let dir = TempDir::new("metrics-json-project");
+        let path = dir.join("metrics.json");
+        std::fs::write(
+            &path,
+            r#"{"schema_version": 32, "functions": [
+                {"name": "timer_start", "file_path": "Core/Src/timer.c", "line": 10, "mccabe": 3, "cognitive": 2},
+                {"name": "test_timer_start", "file_path": "Test/test_timer.c", "line": 5, "mccabe": 1, "cognitive": 0},
+                {"name": "uart_send", "file_path": "Core/Src/uart.c", "line": 30, "mccabe": 9, "cognitive": 12}
+            ]}"#,
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+
+        let source = load_metrics_json(path, Some("./Core/Src/timer.c")).unwrap();
+        let names: Vec<&str> = source.functions.iter().map(|func| func.function_name.as_str()).collect();
+        assert_eq!(names, vec!["timer_start"]);
+        assert_eq!(source.total_cyclomatic_complexity, 3);
+
+        let test = load_metrics_json(path, Some("/work/project/Test/test_timer.c")).unwrap();
+        assert_eq!(test.functions.len(), 1);
+        assert_eq!(test.functions[0].function_name, "test_timer_start");
+
+        // A whole-project report needs a file name, and the file must be in it
+        let error = load_metrics_json(path, None).err().unwrap().to_string();
+        assert!(error.contains("covers 3 files"), "{}", error);
+        assert!(load_metrics_json(path, Some("Core/Src/timer_extra.c")).is_err());
+        assert!(!same_file("Src/timer.c", "timer.c.bak") && !same_file("mer.c", "Core/Src/timer.c"));
+    }
 
-fn get_declarator_name(node: &Node, source: &[u8]) -> String {
-    let mut cursor = node.walk();
+    #[test]
+    fn test_missing_test_file_reports_zero_coverage() {
+        let source = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/timer.c");
+        let analyzer = TestQualityAnalyzer::without_test("Test/test_timer.c", source, 0.70, 0.80).unwrap();
+        let result = analyzer.analyze(true);
+
+        assert!(result.test_file_missing);
+        assert!(!result.passed);
+        assert_eq!(result.cyclomatic_ratio, 0.0);
+        assert_eq!(result.test_function_count, 0);
+        assert!(result.boundary_analysis.is_none());
+        assert_eq!(result.recommendations, vec!["Create Test/test_timer.c to start testing this module"]);
+    }
 
-    for child in node.children(&mut cursor) {
-        if child.kind() == "identifier" {
-            if let Ok(text) = child.utf8_text(source) {
-                return text.to_string();
-            }
-        } else if child.kind() == "pointer_declarator" || child.kind() == "function_declarator" {
-            let name = get_declarator_name(&child, source);
-            if name != "unknown" {
-                return name;
-            }
-        }
+    #[test]
+    fn test_quality_delta() {
+        let result = |test_cyclomatic: u32, coverage_percent: f64| {
+            let mut test = FileAnalysis::new("test_timer.c".to_string());
+            test.add_function(FunctionMetrics {
+                function_name: "test_timer".to_string(),
+                cyclomatic_complexity: test_cyclomatic,
+                cognitive_complexity: 0,
+                line_start: 1,
+                line_end: 5,
+            });
+            let mut source = FileAnalysis::new("timer.c".to_string());
+            source.add_function(FunctionMetrics {
+                function_name: "timer_start".to_string(),
+                cyclomatic_complexity: 10,
+                cognitive_complexity: 8,
+                line_start: 1,
+                line_end: 20,
+            });
+            let mut result = TestQualityAnalyzer::from_analyses(test, source, 0.70, 0.80).analyze(false);
+            result.boundary_analysis = Some(BoundaryAnalysis {
+                required_boundaries: Vec::new(),
+                found_test_values: Default::default(),
+                coverage_percent,
+                missing_boundaries: Vec::new(),
+            });
+            result
+        };
+
+        // Round-trips through --save-result
+        let dir = TempDir::new("result");
+        let path = dir.join("result.json");
+        let path = path.to_str().unwrap();
+        save_result(&result(6, 50.0), path).unwrap();
+        let previous = load_result(path).unwrap();
+        assert_eq!(previous.cyclomatic_ratio, 0.6);
+
+        let improving = QualityDelta::between(&previous, &result(8, 75.0));
+        assert_eq!(improving.cyclomatic_ratio, (0.6, 0.8));
+        assert_eq!(improving.coverage_percent, Some((50.0, 75.0)));
+        assert!(improving.regressions().is_empty());
+
+        let regressing = QualityDelta::between(&previous, &result(5, 25.0));
+        assert_eq!(regressing.regressions(), vec!["cyclomatic ratio", "boundary coverage"]);
+
+        let mut unchecked = result(9, 0.0);
+        unchecked.boundary_analysis = None;
+        let delta = QualityDelta::between(&previous, &unchecked);
+        assert_eq!(delta.coverage_percent, None);
+        assert!(delta.regressions().is_empty());
     }
 
-    "unknown".to_string()
+    #[test]
+    fn test_detect_hal() {
+        let source = "#include <stdint.h>\n#include \"motor.h\"\n  #include \"stm32f4xx_hal.h\" // clocks\n";
+        assert_eq!(detect_hal(source), Some("STM32 HAL (stm32f4xx_hal.h)".to_string()));
+        assert_eq!(detect_hal("#include <driverlib/gpio.h>\n"), Some("TI DriverLib (driverlib/gpio.h)".to_string()));
+        assert_eq!(detect_hal("#include <stdio.h>\n// #include \"nrf_gpio.h\"\n"), None);
+    }
 }
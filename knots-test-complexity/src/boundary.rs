@@ -1,16 +1,32 @@
 use anyhow::Result;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BoundaryValue {
     pub variable_name: String,
     pub type_name: String,
     pub min_value: i64,
     pub max_value: i64,
+    /// Comparisons referencing this boundary; each one weighs on coverage
+    #[serde(default = "single_occurrence")]
+    pub occurrences: u32,
+}
+
+fn single_occurrence() -> u32 {
+    1
 }
 
 impl BoundaryValue {
+    /// Same variable, kind, and range, however often it occurs
+    fn same_boundary(&self, other: &BoundaryValue) -> bool {
+        self.variable_name == other.variable_name
+            && self.type_name == other.type_name
+            && self.min_value == other.min_value
+            && self.max_value == other.max_value
+    }
+
     pub fn boundary_values(&self) -> Vec<i64> {
         vec![
             self.min_value,
@@ -25,6 +41,7 @@ pub struct BoundaryDetector {
     boundaries: Vec<BoundaryValue>,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct BoundaryAnalysis {
     pub required_boundaries: Vec<BoundaryValue>,
     pub found_test_values: HashSet<i64>,
@@ -52,6 +69,21 @@ impl BoundaryDetector {
         Ok(self.boundaries.clone())
     }
 
+    /// Detect boundary values in a header associated with the source file
+    /// Boundaries already found (e.g. a variable declared in both files) are not added twice
+    pub fn detect_header_boundaries(&mut self, header_path: &str) -> Result<()> {
+        let mut header = BoundaryDetector::new();
+        header.detect_boundaries(header_path)?;
+
+        for boundary in header.boundaries {
+            if !self.boundaries.iter().any(|existing| existing.same_boundary(&boundary)) {
+                self.boundaries.push(boundary);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Detect integer type declarations (uint8_t, uint16_t, etc.)
     fn detect_integer_types(&mut self, source: &str) -> Result<()> {
         let type_patterns = vec![
@@ -83,6 +115,7 @@ impl BoundaryDetector {
                         type_name: type_name.to_string(),
                         min_value: min_val,
                         max_value: max_val,
+                        occurrences: 1,
                     });
                 }
             }
@@ -92,6 +125,7 @@ impl BoundaryDetector {
     }
 
     /// Detect range checks (if (x > MAX), if (x < MIN), etc.)
+    /// A constant compared against more than once is one boundary with several occurrences
     fn detect_range_checks(&mut self, source: &str) -> Result<()> {
         // Patterns to detect comparison with constants
         let patterns = vec![
@@ -123,12 +157,17 @@ impl BoundaryDetector {
                             (value, value.saturating_add(1))
                         };
 
-                        self.boundaries.push(BoundaryValue {
+                        let boundary = BoundaryValue {
                             variable_name: format!("constant_{}", value),
                             type_name: boundary_type.to_string(),
                             min_value: min_val,
                             max_value: max_val,
-                        });
+                            occurrences: 1,
+                        };
+                        match self.boundaries.iter_mut().find(|existing| existing.same_boundary(&boundary)) {
+                            Some(existing) => existing.occurrences += 1,
+                            None => self.boundaries.push(boundary),
+                        }
                     }
                 }
             }
@@ -164,7 +203,7 @@ impl BoundaryDetector {
             }
         }
 
-        // Calculate coverage
+        // Calculate coverage, weighting each boundary by how often it is compared against
         let mut total_required = 0;
         let mut total_found = 0;
         let mut missing = Vec::new();
@@ -175,9 +214,10 @@ impl BoundaryDetector {
             let found_count = boundary_vals.iter()
                 .filter(|v| found_values.contains(v))
                 .count();
+            let weight = boundary.occurrences.max(1) as usize;
 
-            total_required += required_count;
-            total_found += found_count;
+            total_required += required_count * weight;
+            total_found += found_count * weight;
 
             // Track missing boundaries
             if found_count < required_count {
@@ -213,6 +253,7 @@ impl BoundaryDetector {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::TempDir;
 
     #[test]
     fn test_detect_uint8_boundary() {
@@ -246,4 +287,54 @@ mod tests {
 
         assert!(detector.boundaries.len() >= 2);
     }
+
+    #[test]
+    fn test_detect_header_boundaries() {
+        let dir = TempDir::new("boundary");
+        let source = dir.join("motor.c");
+        let header = dir.join("motor.h");
+        std::fs::write(&source, "void motor_set(uint8_t speed) {\n    if (speed > MAX_SPEED) { speed = MAX_SPEED; }\n}\n").unwrap();
+        std::fs::write(&header, "#define MAX_SPEED 100\nextern uint8_t duty;\n").unwrap();
+
+        let mut detector = BoundaryDetector::new();
+        let from_source = detector.detect_boundaries(source.to_str().unwrap()).unwrap();
+        detector.detect_header_boundaries(header.to_str().unwrap()).unwrap();
+        // Scanning the header twice must not duplicate its boundaries
+        detector.detect_header_boundaries(header.to_str().unwrap()).unwrap();
+
+        assert!(from_source.is_empty());
+        assert_eq!(detector.boundaries.len(), 2);
+        assert_eq!(detector.boundaries[0].variable_name, "duty");
+        assert_eq!(detector.boundaries[1].type_name, "constant_max");
+        assert_eq!(detector.boundaries[1].max_value, 100);
+    }
+
+    #[test]
+    fn test_boundary_occurrence_weighting() {
+        let code = r#"
+        if (speed > 100) { speed = 100; }
+        if (rpm > 100) { rpm = 100; }
+        if (load > 100) { load = 100; }
+        if (temp < 5) { temp = 5; }
+        "#;
+
+        let mut detector = BoundaryDetector::new();
+        detector.detect_range_checks(code).unwrap();
+        assert_eq!(detector.boundaries.len(), 2);
+        assert_eq!((detector.boundaries[0].variable_name.as_str(), detector.boundaries[0].occurrences), ("constant_100", 3));
+        assert_eq!((detector.boundaries[1].variable_name.as_str(), detector.boundaries[1].occurrences), ("constant_5", 1));
+
+        let dir = TempDir::new("boundary-weight");
+        let coverage = |tests: &str| {
+            let path = dir.join("test.c");
+            std::fs::write(&path, tests).unwrap();
+            detector.analyze_test_coverage(path.to_str().unwrap()).unwrap().coverage_percent
+        };
+
+        // 16 weighted values: 4 per occurrence of 100, 4 for the single 5
+        let one_off = coverage("check(4); check(5); check(6);");
+        let shared = coverage("check(98); check(99); check(100); check(101);");
+        assert!((one_off - 18.75).abs() < 1e-9);
+        assert!((shared - 75.0).abs() < 1e-9);
+    }
 }
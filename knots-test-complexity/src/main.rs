@@ -1,11 +1,15 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
 
 mod analyzer;
 mod boundary;
 mod reporter;
+#[cfg(test)]
+mod test_support;
 
-use analyzer::TestQualityAnalyzer;
+use analyzer::{
+    analyze_file, detect_hal, load_metrics_json, load_result, save_result, QualityDelta, TestQualityAnalyzer,
+};
 use reporter::Reporter;
 
 #[derive(Parser)]
@@ -14,18 +18,32 @@ use reporter::Reporter;
 #[command(about = "Test quality analyzer for C unit tests - validates test complexity against source complexity", long_about = None)]
 struct Args {
     /// Test file path (e.g., Test/test_battery_service.c)
-    test_file: String,
+    test_file: Option<String>,
 
     /// Source file path (e.g., Core/Src/modules/battery_service/battery_service.c)
-    source_file: String,
+    source_file: Option<String>,
 
-    /// Minimum test-to-source complexity ratio (default: 0.70 = 70%)
-    #[arg(short, long, default_value = "0.70")]
-    threshold: f64,
+    /// Use test function metrics from a knots --append JSON report instead of parsing the test file.
+    /// A single path is then the source file
+    #[arg(long, value_name = "JSON")]
+    test_metrics: Option<String>,
 
-    /// Minimum boundary test coverage ratio (default: 0.80 = 80%)
-    #[arg(short = 'b', long, default_value = "0.80")]
-    boundary_threshold: f64,
+    /// Use source function metrics from a knots --append JSON report instead of parsing the source file.
+    /// A single path is then the test file
+    #[arg(long, value_name = "JSON")]
+    source_metrics: Option<String>,
+
+    /// Header to scan for boundary types and MAX/MIN defines along with the source (repeatable)
+    #[arg(long = "source-headers", value_name = "FILE")]
+    source_headers: Vec<String>,
+
+    /// Minimum test-to-source complexity ratio (default: KNOTS_THRESHOLD, else 0.70 = 70%)
+    #[arg(short, long)]
+    threshold: Option<f64>,
+
+    /// Minimum boundary test coverage ratio (default: KNOTS_BOUNDARY_THRESHOLD, else 0.80 = 80%)
+    #[arg(short = 'b', long)]
+    boundary_threshold: Option<f64>,
 
     /// Enforcement level: warn or error
     #[arg(short, long, default_value = "warn")]
@@ -38,18 +56,92 @@ struct Args {
     /// Verbose output (shows detailed per-function analysis)
     #[arg(short, long)]
     verbose: bool,
+
+    /// Report a missing test file as 0% coverage ("no test file") instead of exiting with an error
+    #[arg(long)]
+    allow_missing: bool,
+
+    /// Print suggested starter flags, for the command line and the pre-commit hook, based on the
+    /// current test/source ratio and exit
+    #[arg(long)]
+    init: bool,
+
+    /// Disable colored output (NO_COLOR is also respected)
+    #[arg(long)]
+    no_color: bool,
+
+    /// Compare against a result saved with --save-result and flag a drop in the
+    /// test/source ratio or boundary coverage (a failure with --level error)
+    #[arg(long, value_name = "JSON")]
+    compare: Option<String>,
+
+    /// Save this run's result as JSON for a later --compare
+    #[arg(long, value_name = "JSON")]
+    save_result: Option<String>,
+
+    /// Decimal places for percentages (default: whole percentages)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=10))]
+    precision: Option<u8>,
+}
+
+/// The test and source file paths, in that order, from the positional arguments
+///
+/// Two paths name both files; a side with a --*-metrics report reads that file's functions from
+/// the report. A single path goes to the side without a report, and with both reports the paths
+/// can be left out when each report covers one file.
+fn assign_paths(args: &Args) -> Result<(Option<&str>, Option<&str>), String> {
+    let paths: Vec<&str> = args.test_file.iter().chain(&args.source_file).map(String::as_str).collect();
+    match (paths.as_slice(), args.test_metrics.is_some(), args.source_metrics.is_some()) {
+        ([test, source], _, _) => Ok((Some(test), Some(source))),
+        ([source], true, false) => Ok((None, Some(source))),
+        ([test], false, true) => Ok((Some(test), None)),
+        ([], true, true) => Ok((None, None)),
+        ([_], true, true) => {
+            Err("with --test-metrics and --source-metrics, give both file paths or neither".to_string())
+        }
+        ([_], false, false) => Err("the source file path is required".to_string()),
+        _ => Err("the test and source file paths are required".to_string()),
+    }
+}
+
+/// A threshold from its flag, else from the environment variable `variable` (read through `env`),
+/// else the built-in default
+fn resolve_threshold(
+    flag: Option<f64>,
+    variable: &str,
+    default: f64,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<f64> {
+    if let Some(value) = flag {
+        return Ok(value);
+    }
+    match env(variable) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid value for {}: '{}' is not a number", variable, value)),
+        None => Ok(default),
+    }
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
+    if args.no_color {
+        colored::control::set_override(false);
+    }
+
+    let env = |variable: &str| std::env::var(variable).ok();
+    let threshold = resolve_threshold(args.threshold, "KNOTS_THRESHOLD", 0.70, &env)?;
+    let boundary_threshold = resolve_threshold(args.boundary_threshold, "KNOTS_BOUNDARY_THRESHOLD", 0.80, &env)?;
+
     // Validate inputs
-    if args.threshold < 0.0 || args.threshold > 2.0 {
+    if !(0.0..=2.0).contains(&threshold) {
         eprintln!("Error: threshold must be between 0.0 and 2.0");
         std::process::exit(1);
     }
 
-    if args.boundary_threshold < 0.0 || args.boundary_threshold > 1.0 {
+    if !(0.0..=1.0).contains(&boundary_threshold) {
         eprintln!("Error: boundary-threshold must be between 0.0 and 1.0");
         std::process::exit(1);
     }
@@ -59,35 +151,148 @@ fn main() -> Result<()> {
         std::process::exit(1);
     }
 
+    let (test_file, source_file) = match assign_paths(&args) {
+        Ok(paths) => paths,
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            std::process::exit(1);
+        }
+    };
+    // The file to parse or, with a --*-metrics flag, the report to read; one of the two is always set
+    let test_path = args.test_metrics.as_deref().or(test_file).unwrap_or_default();
+    let source_path = args.source_metrics.as_deref().or(source_file).unwrap_or_default();
+
     // Check if files exist
-    if !std::path::Path::new(&args.test_file).exists() {
-        eprintln!("Error: Test file not found: {}", args.test_file);
+    let test_file_missing = !std::path::Path::new(test_path).exists();
+    if test_file_missing && (!args.allow_missing || args.test_metrics.is_some()) {
+        eprintln!("Error: Test file not found: {}", test_path);
         std::process::exit(1);
     }
 
-    if !std::path::Path::new(&args.source_file).exists() {
-        eprintln!("Error: Source file not found: {}", args.source_file);
+    if !std::path::Path::new(source_path).exists() {
+        eprintln!("Error: Source file not found: {}", source_path);
         std::process::exit(1);
     }
 
+    for header in &args.source_headers {
+        if !std::path::Path::new(header).exists() {
+            eprintln!("Error: Source header not found: {}", header);
+            std::process::exit(1);
+        }
+    }
+
+    let precomputed = args.test_metrics.is_some() || args.source_metrics.is_some();
+    if precomputed && !args.no_check_boundaries {
+        eprintln!("Note: Boundary analysis is unavailable with --test-metrics/--source-metrics");
+    }
+
+    // Read the previous result up front so a bad path fails before the analysis
+    let previous = args.compare.as_deref().map(load_result).transpose()?;
+
     // Create analyzer and run analysis
-    let analyzer = TestQualityAnalyzer::new(
-        &args.test_file,
-        &args.source_file,
-        args.threshold,
-        args.boundary_threshold,
-    )?;
+    let analyzer = if test_file_missing {
+        TestQualityAnalyzer::without_test(test_path, source_path, threshold, boundary_threshold)?
+    } else if precomputed {
+        let load = |path: &str, file: Option<&str>, is_json: bool| {
+            if is_json { load_metrics_json(path, file) } else { analyze_file(path) }
+        };
+        TestQualityAnalyzer::from_analyses(
+            load(test_path, test_file, args.test_metrics.is_some())?,
+            load(source_path, source_file, args.source_metrics.is_some())?,
+            threshold,
+            boundary_threshold,
+        )
+    } else {
+        TestQualityAnalyzer::new(test_path, source_path, threshold, boundary_threshold)?
+    }
+    .with_source_headers(args.source_headers.clone());
 
     let result = analyzer.analyze(!args.no_check_boundaries);
 
+    if args.init {
+        // The HAL comes from the source's #include lines, which a metrics report does not have
+        let hal = source_file
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|code| detect_hal(&code));
+        print!("{}", reporter::suggested_config(&result, hal.as_deref()));
+        return Ok(());
+    }
+
     // Generate report
-    let reporter = Reporter::new(args.verbose);
+    let reporter = Reporter::new(args.verbose, args.precision.map(usize::from));
     reporter.print_report(&result);
 
+    let regressed = match &previous {
+        Some(previous) => {
+            let delta = QualityDelta::between(previous, &result);
+            reporter.print_comparison(&delta);
+            !delta.regressions().is_empty()
+        }
+        None => false,
+    };
+
+    if let Some(path) = &args.save_result {
+        save_result(&result, path)?;
+    }
+
     // Exit based on enforcement level and result
-    if !result.passed && args.level == "error" {
+    if (!result.passed || regressed) && args.level == "error" {
         std::process::exit(1);
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold_precedence() {
+        // Flag over environment over the built-in default, with the environment passed in as a lookup
+        let threshold = |env: &[(&str, &str)], extra: &[&str]| {
+            let argv = ["knots-test-complexity", "test.c", "source.c"].iter().chain(extra);
+            let args = Args::try_parse_from(argv).unwrap();
+            let lookup = |variable: &str| {
+                env.iter().find(|(name, _)| *name == variable).map(|(_, value)| value.to_string())
+            };
+            resolve_threshold(args.threshold, "KNOTS_THRESHOLD", 0.70, &lookup).unwrap()
+        };
+        let env = [("KNOTS_THRESHOLD", "0.85"), ("KNOTS_BOUNDARY_THRESHOLD", "0.9")];
+
+        assert_eq!(threshold(&[], &[]), 0.70);
+        assert_eq!(threshold(&env, &[]), 0.85);
+        assert_eq!(threshold(&env, &["--threshold", "0.5"]), 0.5);
+        assert_eq!(threshold(&[], &["-t", "0.6"]), 0.6);
+
+        let invalid = |_: &str| Some("high".to_string());
+        assert!(resolve_threshold(None, "KNOTS_THRESHOLD", 0.70, &invalid).is_err());
+        assert_eq!(resolve_threshold(Some(0.5), "KNOTS_THRESHOLD", 0.70, &invalid).unwrap(), 0.5);
+    }
+
+    #[test]
+    fn test_assign_paths() {
+        let assign = |argv: &[&str]| {
+            let args = Args::try_parse_from(["knots-test-complexity"].iter().chain(argv)).unwrap();
+            assign_paths(&args).map(|(test, source)| (test.map(str::to_string), source.map(str::to_string)))
+        };
+        let some = |path: &str| Some(path.to_string());
+
+        assert_eq!(assign(&["test.c", "src.c"]), Ok((some("test.c"), some("src.c"))));
+        // A single path goes to the side without a report, wherever the flag is
+        assert_eq!(assign(&["--test-metrics", "r.json", "src.c"]), Ok((None, some("src.c"))));
+        assert_eq!(assign(&["src.c", "--test-metrics", "r.json"]), Ok((None, some("src.c"))));
+        assert_eq!(assign(&["--source-metrics", "r.json", "test.c"]), Ok((some("test.c"), None)));
+        // Two paths name the files to take from the reports
+        assert_eq!(
+            assign(&["--test-metrics", "r.json", "--source-metrics", "r.json", "test.c", "src.c"]),
+            Ok((some("test.c"), some("src.c")))
+        );
+        assert_eq!(assign(&["--test-metrics", "r.json", "--source-metrics", "r.json"]), Ok((None, None)));
+
+        assert!(assign(&["test.c"]).is_err());
+        assert!(assign(&[]).is_err());
+        assert!(assign(&["--test-metrics", "r.json"]).is_err());
+        assert!(assign(&["--test-metrics", "r.json", "--source-metrics", "r.json", "src.c"]).is_err());
+    }
+}
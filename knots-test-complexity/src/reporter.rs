@@ -1,14 +1,61 @@
 use colored::*;
-use crate::analyzer::AnalysisResult;
+use crate::analyzer::{AnalysisResult, FunctionMetrics, QualityDelta};
 use std::path::Path;
 
 pub struct Reporter {
     verbose: bool,
+    /// Decimal places for percentages; `None` keeps whole percentages
+    precision: Option<usize>,
 }
 
 impl Reporter {
-    pub fn new(verbose: bool) -> Self {
-        Self { verbose }
+    pub fn new(verbose: bool, precision: Option<usize>) -> Self {
+        Self { verbose, precision }
+    }
+
+    /// Format a ratio (1.0 = 100%) as a percentage without the trailing `%`
+    fn percent(&self, ratio: f64) -> String {
+        match self.precision {
+            Some(precision) => format!("{:.*}", precision, ratio * 100.0),
+            None => ((ratio * 100.0) as i32).to_string(),
+        }
+    }
+
+    /// Show how the ratio and boundary coverage moved since the previous run (--compare)
+    pub fn print_comparison(&self, delta: &QualityDelta) {
+        println!("\n{}", "Since Previous Run:".bold());
+        let (before, after) = delta.cyclomatic_ratio;
+        println!(
+            "  Test/Source Ratio: {}% -> {}% ({})",
+            self.percent(before),
+            self.percent(after),
+            self.colorize_change(after - before)
+        );
+        if let Some((before, after)) = delta.coverage_percent {
+            println!(
+                "  Boundary Test Coverage: {}% -> {}% ({})",
+                self.percent(before / 100.0),
+                self.percent(after / 100.0),
+                self.colorize_change((after - before) / 100.0)
+            );
+        }
+        let regressions = delta.regressions();
+        if !regressions.is_empty() {
+            println!("  {}", format!("Regression: {} dropped", regressions.join(" and ")).red().bold());
+        }
+    }
+
+    /// Signed percentage-point change: green when tests got stronger, red when weaker
+    fn colorize_change(&self, ratio_change: f64) -> ColoredString {
+        let sign = if ratio_change >= 0.0 { "+" } else { "-" };
+        let text = format!("{}{}%", sign, self.percent(ratio_change.abs()));
+        if ratio_change > 0.0 {
+            text.green()
+        } else if ratio_change < 0.0 {
+            text.red()
+        } else {
+            text.normal()
+        }
     }
 
     pub fn print_report(&self, result: &AnalysisResult) {
@@ -33,17 +80,37 @@ impl Reporter {
         println!("  Total Cyclomatic Complexity: {}", result.source_cyclomatic_complexity);
         println!("  Total Cognitive Complexity: {}", result.source_cognitive_complexity);
 
+        // Most complex first, so hotspots lead the list
+        let mut source_functions: Vec<&FunctionMetrics> = result.source_functions.iter().collect();
+        source_functions.sort_by_key(|func| std::cmp::Reverse(func.cyclomatic_complexity.max(func.cognitive_complexity)));
+        let shown = if self.verbose { source_functions.len() } else { 1 };
+        if !source_functions.is_empty() {
+            println!("  {}", if self.verbose { "Function Complexity:" } else { "Most Complex Function:" });
+        }
+        for func in source_functions.iter().take(shown) {
+            println!(
+                "    {} (line {}): Cyclomatic {}, Cognitive {}",
+                func.function_name,
+                func.line_start,
+                colorize_complexity(func.cyclomatic_complexity),
+                colorize_complexity(func.cognitive_complexity)
+            );
+        }
+
         // Test metrics
         println!("\n{}", "Test File:".bold());
         println!("  File: {}", test_name);
+        if result.test_file_missing {
+            println!("  Status: {}", "no test file".red().bold());
+        }
         println!("  Functions: {}", result.test_function_count);
         println!("  Total Cyclomatic Complexity: {}", result.test_cyclomatic_complexity);
         println!("  Total Cognitive Complexity: {}", result.test_cognitive_complexity);
 
         // Ratio analysis
         println!("\n{}", "Complexity Analysis:".bold());
-        let cyclomatic_percent = (result.cyclomatic_ratio * 100.0) as i32;
-        let threshold_percent = (result.threshold * 100.0) as i32;
+        let cyclomatic_percent = self.percent(result.cyclomatic_ratio);
+        let threshold_percent = self.percent(result.threshold);
 
         let status = if result.passed {
             format!("{}% ✓", cyclomatic_percent).green()
@@ -59,7 +126,7 @@ impl Reporter {
             println!("\n  Cognitive Complexity (informational):");
             println!("    Test: {}", result.test_cognitive_complexity);
             println!("    Source: {}", result.source_cognitive_complexity);
-            println!("    Ratio: {:.0}%", result.cognitive_ratio * 100.0);
+            println!("    Ratio: {}%", self.percent(result.cognitive_ratio));
         }
 
         // Boundary analysis
@@ -70,11 +137,11 @@ impl Reporter {
             if boundary_count > 0 {
                 println!("  Boundary Values Detected: {}", boundary_count);
 
-                let boundary_threshold_percent = (result.boundary_threshold * 100.0) as i32;
+                let boundary_threshold_percent = self.percent(result.boundary_threshold);
                 let coverage_status = if boundary.coverage_percent >= (result.boundary_threshold * 100.0) {
-                    format!("{:.0}% ✓", boundary.coverage_percent).green()
+                    format!("{:.*}% ✓", self.precision.unwrap_or(0), boundary.coverage_percent).green()
                 } else {
-                    format!("{:.0}% ✗", boundary.coverage_percent).red()
+                    format!("{:.*}% ✗", self.precision.unwrap_or(0), boundary.coverage_percent).red()
                 };
 
                 println!("  Boundary Test Coverage: {} (threshold: {}%)", coverage_status, boundary_threshold_percent);
@@ -84,12 +151,13 @@ impl Reporter {
                 if self.verbose && !boundary.required_boundaries.is_empty() {
                     println!("\n  Detected Boundaries:");
                     for (i, bv) in boundary.required_boundaries.iter().take(5).enumerate() {
-                        println!("    {}. {} ({}) - range: {} to {}",
+                        println!("    {}. {} ({}) - range: {} to {}, {} occurrence(s)",
                             i + 1,
                             bv.variable_name,
                             bv.type_name,
                             bv.min_value,
-                            bv.max_value
+                            bv.max_value,
+                            bv.occurrences
                         );
                     }
                     if boundary.required_boundaries.len() > 5 {
@@ -101,6 +169,15 @@ impl Reporter {
             }
         }
 
+        // Test smells
+        if !result.trivial_assertions.is_empty() {
+            println!("\n{}", "Test Smells:".bold().yellow());
+            println!("  Trivial assertions (constant arguments, can never fail): {}", result.trivial_assertions.len());
+            for assertion in &result.trivial_assertions {
+                println!("    line {}: {}", assertion.line, assertion.text);
+            }
+        }
+
         // Recommendations
         if !result.recommendations.is_empty() {
             println!("\n{}", "Recommendations:".bold().yellow());
@@ -113,9 +190,139 @@ impl Reporter {
         println!("\n{}", "━".repeat(70).bright_black());
         if result.passed {
             println!("{}", "Result: ✓ PASS".green().bold());
+        } else if result.test_file_missing {
+            println!("{}", "Result: ✗ FAIL (no test file)".red().bold());
         } else {
             println!("{}", "Result: ✗ FAIL".red().bold());
         }
         println!("{}\n", "━".repeat(70).bright_black());
     }
 }
+
+/// Color for a per-function complexity value, matching the knots emoji bands
+/// (1-10 😊, 11-20 😐, 21-49 😠, 50+ 😢)
+fn complexity_color(complexity: u32) -> Color {
+    match complexity {
+        0..=10 => Color::Green,
+        11..=20 => Color::Yellow,
+        21..=49 => Color::Red,
+        _ => Color::BrightRed,
+    }
+}
+
+fn colorize_complexity(complexity: u32) -> ColoredString {
+    let text = complexity.to_string().color(complexity_color(complexity));
+    if complexity >= 50 {
+        text.bold()
+    } else {
+        text
+    }
+}
+
+/// Round a ratio down to the nearest 0.05 so the suggestion passes on today's code
+fn ratchet(ratio: f64) -> f64 {
+    (ratio * 20.0).floor() / 20.0
+}
+
+/// Suggested starter settings, derived from the current analysis, as a command line
+/// and as `args:` for the test-complexity pre-commit hook
+///
+/// The complexity threshold is the current test/source ratio rounded down (capped at
+/// the 0.70 default), so the project passes now and can be raised as tests improve.
+/// A ratio below 0.05 suggests 0.00 rather than a threshold the code would fail.
+/// The boundary threshold follows the same rule using the measured coverage.
+pub fn suggested_config(result: &AnalysisResult, hal: Option<&str>) -> String {
+    let threshold = ratchet(result.cyclomatic_ratio).min(0.70);
+    let (check_boundaries, boundary_threshold) = match &result.boundary_analysis {
+        Some(boundary) if !boundary.required_boundaries.is_empty() => {
+            (true, ratchet(boundary.coverage_percent / 100.0).min(0.80))
+        }
+        _ => (false, 0.80),
+    };
+
+    let mut flags = vec![
+        format!("--threshold={:.2}", threshold),
+        format!("--boundary-threshold={:.2}", boundary_threshold),
+        "--level=warn".to_string(),
+    ];
+    if !check_boundaries {
+        flags.push("--no-check-boundaries".to_string());
+    }
+
+    let mut out = String::new();
+    out.push_str(&format!(
+        "# knots-test-complexity starter settings, generated from {} and {}\n",
+        result.test_file, result.source_file
+    ));
+    out.push_str(&format!(
+        "# Current test/source ratio: {:.2}; raise the thresholds as tests improve\n",
+        result.cyclomatic_ratio
+    ));
+    if let Some(hal) = hal {
+        out.push_str(&format!("# Detected HAL: {}\n", hal));
+    }
+    out.push_str("\nCommand line:\n");
+    out.push_str(&format!(
+        "  knots-test-complexity {} {} {}\n",
+        flags.join(" "),
+        result.test_file,
+        result.source_file
+    ));
+    out.push_str("\npre-commit (.pre-commit-config.yaml, test-complexity hook):\n");
+    out.push_str(&format!("  args: [{}]\n", flags.join(", ")));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::{FileAnalysis, TestQualityAnalyzer};
+
+    #[test]
+    fn test_complexity_color_bands() {
+        assert_eq!(complexity_color(1), Color::Green);
+        assert_eq!(complexity_color(10), Color::Green);
+        assert_eq!(complexity_color(11), Color::Yellow);
+        assert_eq!(complexity_color(21), Color::Red);
+        assert_eq!(complexity_color(50), Color::BrightRed);
+    }
+
+    #[test]
+    fn test_ratchet_rounds_down_to_five_percent() {
+        assert_eq!(ratchet(0.437), 0.40);
+        assert_eq!(ratchet(0.70), 0.70);
+        assert_eq!(ratchet(0.049), 0.0);
+    }
+
+    #[test]
+    fn test_suggested_config_never_exceeds_current_ratio() {
+        let suggest = |test_cyclomatic: u32, source_cyclomatic: u32| {
+            let metrics = |name: &str, cyclomatic_complexity: u32| FunctionMetrics {
+                function_name: name.to_string(),
+                cyclomatic_complexity,
+                cognitive_complexity: 0,
+                line_start: 1,
+                line_end: 5,
+            };
+            let mut test = FileAnalysis::new("test_timer.c".to_string());
+            test.add_function(metrics("test_timer", test_cyclomatic));
+            let mut source = FileAnalysis::new("timer.c".to_string());
+            source.add_function(metrics("timer_start", source_cyclomatic));
+            let result = TestQualityAnalyzer::from_analyses(test, source, 0.70, 0.80).analyze(false);
+            suggested_config(&result, Some("STM32 HAL (stm32f4xx_hal.h)"))
+        };
+
+        assert!(suggest(9, 20).contains(" --threshold=0.45 "));
+        assert!(suggest(19, 20).contains(" --threshold=0.70 "));
+        // 0.04 rounds down to 0.00 rather than up to a threshold the code fails
+        let config = suggest(1, 25);
+        assert!(config.contains("# Detected HAL: STM32 HAL (stm32f4xx_hal.h)\n"));
+        assert!(config.contains(
+            "  knots-test-complexity --threshold=0.00 --boundary-threshold=0.80 --level=warn --no-check-boundaries \
+             test_timer.c timer.c\n"
+        ));
+        assert!(config.ends_with(
+            "  args: [--threshold=0.00, --boundary-threshold=0.80, --level=warn, --no-check-boundaries]\n"
+        ));
+    }
+}
@@ -0,0 +1,24 @@
+use std::path::{Path, PathBuf};
+
+/// An empty directory under the system temp dir, removed when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// `knots-<name>-test-<pid>`, so tests running in parallel need distinct names
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("knots-{}-test-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
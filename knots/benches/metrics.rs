@@ -0,0 +1,86 @@
+//! Times a default run against `--metrics mccabe` over a generated file
+//!
+//! Run with `cargo bench -p knots --bench metrics`; the generated file has
+//! FUNCTIONS functions mixing loops, switches, error checks, and struct access.
+
+use std::fmt::Write as _;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const FUNCTIONS: usize = 3000;
+/// Each configuration reports its fastest run
+const RUNS: usize = 3;
+
+fn generated_source() -> String {
+    let mut source = String::from("#include <stdlib.h>\n\ntypedef struct { int len; int data[16]; } Buffer;\n\n");
+    for index in 0..FUNCTIONS {
+        let _ = write!(
+            source,
+            r#"/* Step {index} of the pipeline */
+static int step_{index}(Buffer *buf, int mode, int limit) {{
+    int total = 0;
+    char *scratch = malloc(limit * 4);
+    if (buf == NULL || scratch == NULL) {{
+        return -1;
+    }}
+    for (int i = 0; i < buf->len && i < limit; i++) {{
+        switch (mode) {{
+        case 1:
+            total += buf->data[i] * 3;
+            break;
+        case 2:
+            if (buf->data[i] > 42 && total < 1000) {{
+                total -= clamp(scale(buf->data[i], 7), 0, 255);
+            }}
+            break;
+        default:
+            while (total > limit) {{
+                total /= 2;
+            }}
+        }}
+    }}
+    free(scratch);
+    return total;
+}}
+
+"#
+        );
+    }
+    source
+}
+
+/// Fastest of RUNS runs of knots on `path` with `extra` options
+fn time_runs(path: &Path, extra: &[&str]) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let status = Command::new(env!("CARGO_BIN_EXE_knots"))
+                .arg(path)
+                .args(extra)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .expect("failed to run knots");
+            assert!(status.success(), "knots {:?} failed", extra);
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let dir = std::env::temp_dir().join(format!("knots-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("failed to create the bench directory");
+    let path = dir.join("generated.c");
+    std::fs::write(&path, generated_source()).expect("failed to write the generated file");
+
+    let all = time_runs(&path, &[]);
+    let mccabe = time_runs(&path, &["--metrics", "mccabe"]);
+    println!("{} functions, fastest of {} runs", FUNCTIONS, RUNS);
+    println!("  all metrics:       {:>8.1?}", all);
+    println!("  --metrics mccabe:  {:>8.1?}", mccabe);
+    println!("  speedup:           {:>8.2}x", all.as_secs_f64() / mccabe.as_secs_f64());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
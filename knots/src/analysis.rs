@@ -0,0 +1,168 @@
+//! Whole-file analysis for library users, with errors that can be matched on
+//!
+//! The `calculate_*` functions in `complexity` work on a single parsed function and
+//! cannot fail. The functions here read and parse a whole source file first, and report
+//! what went wrong as an `AnalysisError` rather than a string.
+
+use crate::complexity::{
+    calculate_all_metrics_with_options, find_aggregate_typedefs, get_function_name, AllMetrics, MetricOptions,
+};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tree_sitter::{LanguageError, Node, Parser};
+
+/// Why a source file could not be analyzed
+#[derive(Debug, Error)]
+pub enum AnalysisError {
+    /// The file could not be read
+    #[error("failed to read {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// tree-sitter gave up, or syntax errors left no function definitions to measure
+    #[error("failed to parse {}{}", path.display(), line.map(|line| format!(": syntax error on line {}", line)).unwrap_or_default())]
+    ParseFailed {
+        path: PathBuf,
+        /// First line with a syntax error; None when tree-sitter produced no tree at all
+        line: Option<usize>,
+    },
+    /// The C grammar is incompatible with the linked tree-sitter
+    #[error("failed to load the C grammar: {0}")]
+    LanguageInit(#[from] LanguageError),
+    /// The source is not valid UTF-8
+    #[error("{}:{line}: not valid UTF-8", path.display())]
+    Encoding {
+        path: PathBuf,
+        /// Line holding the first invalid byte
+        line: usize,
+    },
+}
+
+/// One function definition and its core metrics
+#[derive(Debug, Clone)]
+pub struct FunctionAnalysis {
+    pub name: String,
+    /// First line of the definition, 1-based
+    pub line: usize,
+    pub metrics: AllMetrics,
+}
+
+/// Read `path` and measure every function it defines
+pub fn analyze_file(path: &Path) -> Result<Vec<FunctionAnalysis>, AnalysisError> {
+    let bytes = std::fs::read(path).map_err(|source| AnalysisError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    analyze_bytes(&bytes, path)
+}
+
+/// Measure every function defined in raw source bytes; `path` only labels errors
+pub fn analyze_bytes(bytes: &[u8], path: &Path) -> Result<Vec<FunctionAnalysis>, AnalysisError> {
+    let source = std::str::from_utf8(bytes).map_err(|e| AnalysisError::Encoding {
+        path: path.to_path_buf(),
+        line: bytes[..e.valid_up_to()].iter().filter(|&&byte| byte == b'\n').count() + 1,
+    })?;
+    analyze_source(source, path)
+}
+
+/// Measure every function defined in `source`; `path` only labels errors
+///
+/// Syntax errors are tolerated as long as some function definitions survive them, as in
+/// the knots binary; a file without any only fails when it has syntax errors.
+pub fn analyze_source(source: &str, path: &Path) -> Result<Vec<FunctionAnalysis>, AnalysisError> {
+    let mut parser = Parser::new();
+    parser.set_language(&tree_sitter_c::LANGUAGE.into())?;
+    let tree = parser.parse(source, None).ok_or_else(|| AnalysisError::ParseFailed {
+        path: path.to_path_buf(),
+        line: None,
+    })?;
+
+    let root = tree.root_node();
+    let mut functions = Vec::new();
+    let aggregates = find_aggregate_typedefs(root, source.as_bytes());
+    collect_functions(root, source.as_bytes(), &aggregates, &mut functions);
+    if functions.is_empty() && root.has_error() {
+        return Err(AnalysisError::ParseFailed {
+            path: path.to_path_buf(),
+            line: first_error(root).map(|node| node.start_position().row + 1),
+        });
+    }
+    Ok(functions)
+}
+
+fn collect_functions(
+    node: Node,
+    source_code: &[u8],
+    aggregates: &HashSet<String>,
+    functions: &mut Vec<FunctionAnalysis>,
+) {
+    if node.kind() == "function_definition" {
+        if let Some(name) = get_function_name(node, source_code) {
+            functions.push(FunctionAnalysis {
+                name,
+                line: node.start_position().row + 1,
+                metrics: calculate_all_metrics_with_options(node, source_code, &MetricOptions::default(), aggregates),
+            });
+        }
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_functions(child, source_code, aggregates, functions);
+    }
+}
+
+/// The first ERROR or MISSING node in document order
+fn first_error(node: Node) -> Option<Node> {
+    if node.is_error() || node.is_missing() {
+        return Some(node);
+    }
+    if !node.has_error() {
+        return None;
+    }
+    let mut cursor = node.walk();
+    let found = node.children(&mut cursor).find_map(first_error);
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analysis_errors() {
+        let missing = Path::new("/nonexistent/knots/missing.c");
+        match analyze_file(missing) {
+            Err(AnalysisError::Io { path, source }) => {
+                assert_eq!(path, missing);
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+            }
+            other => panic!("expected Io, got {:?}", other),
+        }
+
+        let garbage = "int x = ;\n)))) {{ while\n";
+        match analyze_source(garbage, Path::new("garbage.c")) {
+            Err(AnalysisError::ParseFailed { path, line }) => {
+                assert_eq!(path, Path::new("garbage.c"));
+                assert_eq!(line, Some(1));
+            }
+            other => panic!("expected ParseFailed, got {:?}", other),
+        }
+
+        let invalid = b"int ok(void) { return 0; }\nchar *s = \"\xff\";\n";
+        match analyze_bytes(invalid, Path::new("latin1.c")) {
+            Err(AnalysisError::Encoding { line, .. }) => assert_eq!(line, 2),
+            other => panic!("expected Encoding, got {:?}", other),
+        }
+
+        // A clean file, and one without functions, are fine
+        let functions = analyze_source("int one(void) { return 1; }\n", Path::new("one.c")).unwrap();
+        assert_eq!(functions[0].name, "one");
+        assert_eq!(functions[0].metrics.mccabe, 1);
+        assert!(analyze_source("int counter;\n", Path::new("data.c")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,112 @@
+use crate::recent::{blame_file, BlameHunk};
+use crate::FunctionMetrics;
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// Author given to uncommitted lines, as `git blame` does
+const UNCOMMITTED: &str = "Not Committed Yet";
+
+/// Complexity attributed to one author by --by-author
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthorSummary {
+    pub author: String,
+    pub functions: usize,
+    /// Sum of max(McCabe, Cognitive) over the author's functions
+    pub total_complexity: u32,
+}
+
+impl AuthorSummary {
+    pub fn average_complexity(&self) -> f64 {
+        self.total_complexity as f64 / self.functions as f64
+    }
+}
+
+/// Attribute each function to the author of most of its lines and total them per author,
+/// highest total complexity first
+///
+/// Files are blamed once each, in parallel. Files not tracked by git are skipped with a warning.
+pub fn complexity_by_author(all_metrics: &[FunctionMetrics]) -> Vec<AuthorSummary> {
+    let mut by_file: BTreeMap<&str, Vec<&FunctionMetrics>> = BTreeMap::new();
+    for func in all_metrics {
+        by_file.entry(func.file_path.as_str()).or_default().push(func);
+    }
+    let files: Vec<&str> = by_file.keys().copied().collect();
+    let blames = blame_files(&files);
+
+    let mut totals: HashMap<String, AuthorSummary> = HashMap::new();
+    for (file, blame) in files.iter().zip(blames) {
+        let hunks = match blame {
+            Ok(hunks) => hunks,
+            Err(e) => {
+                eprintln!("Warning: Skipping {} for --by-author: {:#}", file, e);
+                continue;
+            }
+        };
+        for func in &by_file[file] {
+            if let Some(author) = majority_author(&hunks, func.line, func.end_line.max(func.line)) {
+                let summary = totals.entry(author.clone()).or_insert(AuthorSummary {
+                    author,
+                    functions: 0,
+                    total_complexity: 0,
+                });
+                summary.functions += 1;
+                summary.total_complexity += func.max_complexity();
+            }
+        }
+    }
+
+    let mut summaries: Vec<AuthorSummary> = totals.into_values().collect();
+    summaries.sort_by(|a, b| b.total_complexity.cmp(&a.total_complexity).then_with(|| a.author.cmp(&b.author)));
+    summaries
+}
+
+/// Blame every file, spreading the files over the available cores; results are in `files` order
+fn blame_files(files: &[&str]) -> Vec<Result<Vec<BlameHunk>>> {
+    let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let chunk_size = files.len().div_ceil(threads).max(1);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = files
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(|file| blame_file(Path::new(file))).collect::<Vec<_>>()))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("blame thread panicked"))
+            .collect()
+    })
+}
+
+/// Author of the most lines in `start..=end`; ties go to the alphabetically first name
+fn majority_author(hunks: &[BlameHunk], start: usize, end: usize) -> Option<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for hunk in hunks {
+        let overlap_start = hunk.start.max(start);
+        let overlap_end = (hunk.start + hunk.lines).min(end + 1);
+        if overlap_start < overlap_end {
+            let author = hunk.author.as_ref().map(|(name, _)| name.as_str()).unwrap_or(UNCOMMITTED);
+            *counts.entry(author).or_default() += overlap_end - overlap_start;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+        .map(|(author, _)| author.to_string())
+}
+
+/// The --by-author table
+pub fn format_author_table(summaries: &[AuthorSummary], precision: usize) -> String {
+    let width = summaries.iter().map(|summary| summary.author.len()).max().unwrap_or(0).max("AUTHOR".len());
+    let mut out = format!("{:<width$}  FUNCTIONS  TOTAL  AVERAGE\n", "AUTHOR");
+    for summary in summaries {
+        out.push_str(&format!(
+            "{:<width$}  {:>9}  {:>5}  {:>7.*}\n",
+            summary.author,
+            summary.functions,
+            summary.total_complexity,
+            precision,
+            summary.average_complexity()
+        ));
+    }
+    out
+}
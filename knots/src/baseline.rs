@@ -0,0 +1,90 @@
+use anyhow::{Context, Result};
+use git2::build::CheckoutBuilder;
+use git2::Repository;
+use std::path::{Path, PathBuf};
+
+/// A git ref checked out into a temporary directory for `--baseline-branch`
+///
+/// Only the files are written out; the repository's HEAD, index, and working tree are
+/// left alone. The directory is removed when the checkout is dropped.
+pub struct BaselineCheckout {
+    /// Temporary directory holding the ref's files
+    root: PathBuf,
+    /// The repository's working directory, which paths are mapped from
+    workdir: PathBuf,
+}
+
+impl BaselineCheckout {
+    /// Check out `reference` (a branch, tag, or commit) of the repository containing `path`
+    pub fn new(reference: &str, path: &Path) -> Result<Self> {
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve {}", path.display()))?;
+        let repo = Repository::discover(&path)
+            .with_context(|| format!("--baseline-branch needs a git repository, but {} is not in one", path.display()))?;
+        let workdir = repo
+            .workdir()
+            .context("git repository has no working directory")?
+            .canonicalize()?;
+        let tree = repo
+            .revparse_single(reference)
+            .and_then(|object| object.peel_to_tree())
+            .with_context(|| format!("Unknown git ref '{}'", reference))?;
+
+        let root = std::env::temp_dir().join(format!("knots-baseline-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).with_context(|| format!("Failed to create {}", root.display()))?;
+        let checkout = BaselineCheckout { root, workdir };
+
+        let mut builder = CheckoutBuilder::new();
+        builder.target_dir(&checkout.root).force().recreate_missing(true).update_index(false);
+        repo.checkout_tree(tree.as_object(), Some(&mut builder))
+            .with_context(|| format!("Failed to check out '{}'", reference))?;
+        Ok(checkout)
+    }
+
+    /// Where `file` lives in the checkout, or None if it does not exist at the ref
+    pub fn path_of(&self, file: &Path) -> Option<PathBuf> {
+        let relative = file.canonicalize().ok()?.strip_prefix(&self.workdir).ok()?.to_path_buf();
+        Some(self.root.join(relative)).filter(|path| path.is_file())
+    }
+}
+
+impl Drop for BaselineCheckout {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{commit_file, TempDir};
+    use git2::Signature;
+
+    #[test]
+    fn test_baseline_checkout() {
+        let dir = TempDir::new("baseline");
+        let repo = Repository::init(dir.path()).unwrap();
+        let author = Signature::now("Dev", "dev@example.com").unwrap();
+        commit_file(&repo, "src/a.c", "int a(void) { return 1; }\n", &author);
+        let file = dir.join("src/a.c");
+
+        // Edit the file and add an untracked one after the commit
+        std::fs::write(&file, "int a(int x) { return x ? 1 : 2; }\n").unwrap();
+        let untracked = dir.join("src/b.c");
+        std::fs::write(&untracked, "int b(void) { return 0; }\n").unwrap();
+
+        let checkout = BaselineCheckout::new("HEAD", dir.path()).unwrap();
+        let baseline = checkout.path_of(&file).unwrap();
+        assert_eq!(std::fs::read_to_string(&baseline).unwrap(), "int a(void) { return 1; }\n");
+        assert!(checkout.path_of(&untracked).is_none());
+        assert!(BaselineCheckout::new("no-such-branch", dir.path()).is_err());
+
+        // The working tree is untouched, and the checkout is removed on drop
+        assert!(std::fs::read_to_string(&file).unwrap().contains("int x"));
+        assert_eq!(repo.statuses(None).unwrap().len(), 2);
+        drop(checkout);
+        assert!(!baseline.exists());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{
+    find_crowded_files, find_file_violations, find_violations, FunctionMetrics, ReportDiff, Thresholds,
+    JSON_SCHEMA_VERSION,
+};
+use serde::Serialize;
+
+/// Outcome of one policy gate in `knots check`
+#[derive(Debug, Serialize)]
+pub struct Gate {
+    /// What is checked, e.g. "mccabe" or "baseline"
+    pub name: String,
+    /// The configured limit, or the baseline ref
+    pub limit: String,
+    pub passed: bool,
+    pub offenders: Vec<Offender>,
+}
+
+/// A function that fails a gate
+#[derive(Debug, Serialize)]
+pub struct Offender {
+    pub function: String,
+    pub file: String,
+    pub line: usize,
+    /// Why it fails, e.g. "mccabe 14 (max 10)"
+    pub detail: String,
+}
+
+impl Offender {
+    fn new(func: &FunctionMetrics, detail: String) -> Self {
+        Offender {
+            function: func.name.clone(),
+            file: func.file_path.clone(),
+            line: func.line,
+            detail,
+        }
+    }
+
+    /// A whole file failing a gate; `function` is empty and `line` is 0
+    fn file(file: &str, detail: String) -> Self {
+        Offender {
+            function: String::new(),
+            file: file.to_string(),
+            line: 0,
+            detail,
+        }
+    }
+}
+
+impl Gate {
+    fn new(name: &str, limit: String, offenders: Vec<Offender>) -> Self {
+        Gate {
+            name: name.to_string(),
+            limit,
+            passed: offenders.is_empty(),
+            offenders,
+        }
+    }
+}
+
+/// Every gate result, as printed by `knots check --json`
+#[derive(Debug, Serialize)]
+pub struct CheckReport {
+    /// Layout version shared with the JSON report
+    pub schema_version: u32,
+    pub passed: bool,
+    pub gates: Vec<Gate>,
+}
+
+impl CheckReport {
+    pub fn new(gates: Vec<Gate>) -> Self {
+        CheckReport {
+            schema_version: JSON_SCHEMA_VERSION,
+            passed: gates.iter().all(|gate| gate.passed),
+            gates,
+        }
+    }
+
+    pub fn failed_count(&self) -> usize {
+        self.gates.iter().filter(|gate| !gate.passed).count()
+    }
+}
+
+/// One gate per --max-* threshold that is set
+pub fn threshold_gates(all_metrics: &[FunctionMetrics], thresholds: &Thresholds) -> Vec<Gate> {
+    let violations = find_violations(all_metrics, thresholds);
+    thresholds
+        .limits()
+        .iter()
+        .filter_map(|&(metric, limit, _)| {
+            let limit = limit?;
+            let offenders = violations
+                .iter()
+                .filter(|violation| violation.metric == metric)
+                .map(|violation| Offender::new(violation.func, format!("{} {} (max {})", metric, violation.value, limit)))
+                .collect();
+            Some(Gate::new(metric, limit.to_string(), offenders))
+        })
+        .collect()
+}
+
+/// --strict: non-void functions must return a value
+pub fn missing_return_gate(all_metrics: &[FunctionMetrics]) -> Gate {
+    let offenders = all_metrics
+        .iter()
+        .filter(|func| func.missing_return)
+        .map(|func| Offender::new(func, "non-void function has no return statement".to_string()))
+        .collect();
+    Gate::new("missing return", "none".to_string(), offenders)
+}
+
+/// --require-default: every switch needs a `default:` label
+pub fn missing_default_gate(all_metrics: &[FunctionMetrics]) -> Gate {
+    let offenders = all_metrics
+        .iter()
+        .flat_map(|func| {
+            func.missing_default_lines
+                .iter()
+                .map(move |line| Offender::new(func, format!("switch on line {} has no default label", line)))
+        })
+        .collect();
+    Gate::new("missing default", "none".to_string(), offenders)
+}
+
+/// --max-file-complexity: no file's functions may sum to more McCabe complexity than the budget
+pub fn file_complexity_gate(all_metrics: &[FunctionMetrics], limit: u32) -> Gate {
+    let offenders = find_file_violations(all_metrics, limit)
+        .iter()
+        .map(|totals| {
+            let detail = format!(
+                "total McCabe {} over {} functions (max {})",
+                totals.total_mccabe, totals.functions, limit
+            );
+            Offender::file(totals.file_path, detail)
+        })
+        .collect();
+    Gate::new("file complexity", limit.to_string(), offenders)
+}
+
+/// --max-functions-per-file: no file may define more functions than the limit
+pub fn functions_per_file_gate(all_metrics: &[FunctionMetrics], limit: usize) -> Gate {
+    let offenders = find_crowded_files(all_metrics, limit)
+        .iter()
+        .map(|totals| Offender::file(totals.file_path, format!("{} functions (max {})", totals.functions, limit)))
+        .collect();
+    Gate::new("functions per file", limit.to_string(), offenders)
+}
+
+/// --baseline-branch: no function may get more complex than on the ref
+pub fn baseline_gate(reference: &str, diff: &ReportDiff) -> Gate {
+    let offenders = diff
+        .changed
+        .iter()
+        .filter(|change| change.regression() > 0)
+        .map(|change| {
+            let detail = format!(
+                "max complexity {} -> {}",
+                change.old.max_complexity(),
+                change.new.max_complexity()
+            );
+            Offender::new(change.new, detail)
+        })
+        .collect();
+    Gate::new("baseline", reference.to_string(), offenders)
+}
+
+/// Pass/fail table with each failing gate's offenders listed underneath
+pub fn format_table(report: &CheckReport) -> String {
+    let width = report.gates.iter().map(|gate| gate.name.len()).max().unwrap_or(0).max("GATE".len());
+    let limit_width = report.gates.iter().map(|gate| gate.limit.len()).max().unwrap_or(0).max("LIMIT".len());
+
+    let mut out = format!("{:<width$}  {:<limit_width$}  RESULT  OFFENDERS\n", "GATE", "LIMIT");
+    for gate in &report.gates {
+        out.push_str(&format!(
+            "{:<width$}  {:<limit_width$}  {:<6}  {}\n",
+            gate.name,
+            gate.limit,
+            if gate.passed { "PASS" } else { "FAIL" },
+            gate.offenders.len()
+        ));
+        for offender in &gate.offenders {
+            if offender.function.is_empty() {
+                out.push_str(&format!("    {}: {}\n", offender.file, offender.detail));
+            } else {
+                out.push_str(&format!("    {}:{}: {}() {}\n", offender.file, offender.line, offender.function, offender.detail));
+            }
+        }
+    }
+    out.push_str(&format!(
+        "\nResult: {} ({} of {} gates passed)\n",
+        if report.passed { "PASS" } else { "FAIL" },
+        report.gates.len() - report.failed_count(),
+        report.gates.len()
+    ));
+    out
+}
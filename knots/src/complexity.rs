@@ -1,77 +1,504 @@
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use tree_sitter::Node;
 
 /// Calculates McCabe cyclomatic complexity for a function
 /// Formula: M = E - N + 2P where E = edges, N = nodes, P = connected components
 /// Simplified: Count decision points + 1
 pub fn calculate_mccabe_complexity(node: Node, source_code: &[u8]) -> u32 {
+    calculate_mccabe_complexity_with_options(node, source_code, &McCabeOptions::default())
+}
+
+/// Optional variations of the McCabe calculation
+/// All options default to off, which matches pmccabe
+#[derive(Debug, Clone, Default)]
+pub struct McCabeOptions {
+    /// Count only control-flow statements, not `&&`/`||` (classic decision-statement McCabe)
+    pub exclude_logical_ops: bool,
+    /// Calls to these assertion macros count as a decision each (empty: assertions are plain calls)
+    pub assert_macros: Vec<String>,
+    /// Count each `case` label instead of each `switch`, like pmccabe's traditional figure
+    pub count_cases: bool,
+}
+
+/// Calculates McCabe cyclomatic complexity with the given options
+pub fn calculate_mccabe_complexity_with_options(node: Node, source_code: &[u8], options: &McCabeOptions) -> u32 {
     let mut complexity = 1; // Base complexity
 
-    visit_node_mccabe(node, source_code, &mut complexity);
+    visit_node_mccabe(node, source_code, &mut complexity, options);
 
     complexity
 }
 
-fn visit_node_mccabe(node: Node, source_code: &[u8], complexity: &mut u32) {
-    // Decision points that increase cyclomatic complexity
+fn visit_node_mccabe(node: Node, source_code: &[u8], complexity: &mut u32, options: &McCabeOptions) {
+    *complexity += mccabe_decisions(node, source_code, options);
+
+    // Recursively visit children
+    // GNU statement expressions `({ ... })` parse as a compound_statement inside a
+    // parenthesized_expression, so decisions inside them are counted here as well
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_mccabe(child, source_code, complexity, options);
+    }
+}
+
+/// Kinds of decision point, in the order `tally_mccabe_decisions` lists them
+const DECISION_KINDS: [&str; 13] = [
+    "if", "for", "while", "do", "switch", "case", "&&", "||", "?:", "goto", "computed goto", "setjmp/longjmp", "assert",
+];
+
+/// McCabe's decision points bucketed by kind, e.g. `[("if", 6), ("for", 2), ("&&", 3)]`
+///
+/// Kinds are listed in a fixed order and only when present; the counts add up to the
+/// McCabe complexity less one, a computed goto counting for its full weight.
+pub fn tally_mccabe_decisions(node: Node, source_code: &[u8], options: &McCabeOptions) -> Vec<(&'static str, u32)> {
+    let mut counts = [0; DECISION_KINDS.len()];
+    visit_node_decision_kinds(node, source_code, options, &mut counts);
+    DECISION_KINDS
+        .iter()
+        .zip(counts)
+        .filter(|&(_, count)| count > 0)
+        .map(|(&kind, count)| (kind, count))
+        .collect()
+}
+
+fn visit_node_decision_kinds(node: Node, source_code: &[u8], options: &McCabeOptions, counts: &mut [u32]) {
+    let decisions = mccabe_decisions(node, source_code, options);
+    if decisions > 0 {
+        let kind = decision_kind(node, source_code);
+        if let Some(index) = DECISION_KINDS.iter().position(|&known| known == kind) {
+            counts[index] += decisions;
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_decision_kinds(child, source_code, options, counts);
+    }
+}
+
+/// The `DECISION_KINDS` entry for a node `mccabe_decisions` counts
+fn decision_kind(node: Node, source_code: &[u8]) -> &'static str {
+    match node.kind() {
+        "if_statement" => "if",
+        "for_statement" => "for",
+        "while_statement" => "while",
+        "do_statement" => "do",
+        "switch_statement" => "switch",
+        "case_statement" => "case",
+        "binary_expression" if logical_operator(node, source_code) == Some("||") => "||",
+        "binary_expression" => "&&",
+        "conditional_expression" => "?:",
+        _ if is_computed_goto(node, source_code) => "computed goto",
+        "goto_statement" => "goto",
+        "call_expression" if is_setjmp_or_longjmp_call(node, source_code) => "setjmp/longjmp",
+        _ => "assert",
+    }
+}
+
+/// Decision points a single node adds to cyclomatic complexity
+fn mccabe_decisions(node: Node, source_code: &[u8], options: &McCabeOptions) -> u32 {
     match node.kind() {
         // Conditional statements
-        "if_statement" => *complexity += 1,
-        "while_statement" => *complexity += 1,
-        "do_statement" => *complexity += 1,
-        "for_statement" => *complexity += 1,
+        "if_statement" => 1,
+        "while_statement" => 1,
+        "do_statement" => 1,
+        "for_statement" => 1,
 
         // Switch statement: pmccabe compatibility - count as +1 regardless of cases
         // This matches pmccabe's simpler approach 
-        "switch_statement" => {
-            *complexity += 1;
-        }
+        "switch_statement" if !options.count_cases => 1,
 
-        // Don't count individual case statements - handled by switch above
-        // "case_statement" => *complexity += 1,
+        // With count_cases, each case label instead (`default:` is not a case); decisions
+        // inside the cases are counted either way as the walk descends into them
+        "case_statement" if options.count_cases && node.child_by_field_name("value").is_some() => 1,
 
         // Logical operators (each adds a path)
-        "binary_expression" => {
-            if let Some(op) = node.child_by_field_name("operator") {
-                if let Ok(op_text) = op.utf8_text(source_code) {
-                    if op_text == "&&" || op_text == "||" {
-                        *complexity += 1;
-                    }
-                }
-            }
-        }
+        "binary_expression" if !options.exclude_logical_ops && logical_operator(node, source_code).is_some() => 1,
 
         // Ternary operator
-        "conditional_expression" => *complexity += 1,
+        "conditional_expression" => 1,
+
+        // goto/continue/break can create additional paths; a computed goto can reach any
+        // label whose address was taken
+        _ if is_computed_goto(node, source_code) => COMPUTED_GOTO_WEIGHT,
+        "goto_statement" => 1,
+
+        // setjmp returns twice (a hidden branch); longjmp is a non-local goto
+        "call_expression" if is_setjmp_or_longjmp_call(node, source_code) => 1,
+
+        // A runtime assertion branches to an abort when its condition fails
+        "call_expression" if is_assert_call(node, source_code, &options.assert_macros) => 1,
+
+        _ => 0,
+    }
+}
+
+/// Assertion macros counted as decisions when asserts are counted and no list is given
+pub const DEFAULT_ASSERT_MACROS: [&str; 3] = ["assert", "BUG_ON", "configASSERT"];
+
+/// Compile-time assertions, which never branch at run time and so are never counted
+const STATIC_ASSERTS: [&str; 2] = ["static_assert", "_Static_assert"];
+
+/// Checks whether a call invokes one of `macros`
+fn is_assert_call(node: Node, source_code: &[u8], macros: &[String]) -> bool {
+    if macros.is_empty() {
+        return false;
+    }
+    node.child_by_field_name("function")
+        .filter(|function| function.kind() == "identifier")
+        .and_then(|function| function.utf8_text(source_code).ok())
+        .map(|name| !STATIC_ASSERTS.contains(&name) && macros.iter().any(|assert| assert == name))
+        .unwrap_or(false)
+}
 
-        // goto/continue/break can create additional paths
-        "goto_statement" => *complexity += 1,
+/// A function's figures as pmccabe reports them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PmccabeCounts {
+    /// Decisions + 1, counting each `switch` once
+    pub modified: u32,
+    /// Decisions + 1, counting each `case` label instead of the `switch`
+    pub traditional: u32,
+    /// Declarations and statements, not counting blocks and labels
+    pub statements: u32,
+}
+
+/// Calculates McCabe complexity by pmccabe's rules: `if`, loops, `&&`, `||`, and `?:`
+/// add one each, while `goto`, setjmp/longjmp, and assertions add nothing
+pub fn calculate_pmccabe(node: Node, source_code: &[u8]) -> PmccabeCounts {
+    let mut counts = PmccabeCounts {
+        modified: 1,
+        traditional: 1,
+        statements: 0,
+    };
+    visit_node_pmccabe(node, source_code, &mut counts);
+    counts
+}
 
+fn visit_node_pmccabe(node: Node, source_code: &[u8], counts: &mut PmccabeCounts) {
+    match node.kind() {
+        "if_statement" | "while_statement" | "do_statement" | "for_statement" | "conditional_expression" => {
+            counts.modified += 1;
+            counts.traditional += 1;
+        }
+        "binary_expression" if logical_operator(node, source_code).is_some() => {
+            counts.modified += 1;
+            counts.traditional += 1;
+        }
+        "switch_statement" => counts.modified += 1,
+        // `default:` is not a case
+        "case_statement" if node.child_by_field_name("value").is_some() => counts.traditional += 1,
+        _ => {}
+    }
+    match node.kind() {
+        "compound_statement" | "labeled_statement" | "case_statement" => {}
+        "declaration" => counts.statements += 1,
+        kind if kind.ends_with("_statement") => counts.statements += 1,
         _ => {}
     }
 
-    // Recursively visit children
     let mut cursor = node.walk();
     for child in node.children(&mut cursor) {
-        visit_node_mccabe(child, source_code, complexity);
+        visit_node_pmccabe(child, source_code, counts);
+    }
+}
+
+/// The operator of a `&&` or `||` binary expression
+fn logical_operator<'a>(node: Node, source_code: &'a [u8]) -> Option<&'a str> {
+    node.child_by_field_name("operator")
+        .and_then(|op| op.utf8_text(source_code).ok())
+        .filter(|op| *op == "&&" || *op == "||")
+}
+
+/// The loop and branch decision points behind a McCabe score
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ControlFlowShape {
+    /// `for`, `while`, and `do` loops
+    pub loops: u32,
+    /// `if` statements, `switch` statements, and ternaries
+    pub branches: u32,
+}
+
+/// Splits a function's control structures into loops and branches
+pub fn calculate_control_flow_shape(node: Node) -> ControlFlowShape {
+    let mut shape = ControlFlowShape::default();
+    visit_node_shape(node, &mut shape);
+    shape
+}
+
+fn visit_node_shape(node: Node, shape: &mut ControlFlowShape) {
+    shape.note(node);
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_shape(child, shape);
+    }
+}
+
+impl ControlFlowShape {
+    fn note(&mut self, node: Node) {
+        match node.kind() {
+            "for_statement" | "while_statement" | "do_statement" => self.loops += 1,
+            "if_statement" | "switch_statement" | "conditional_expression" => self.branches += 1,
+            _ => {}
+        }
+    }
+}
+
+/// Counts the `case` labels of every switch in a function (`default` is not counted)
+///
+/// Informational only: cognitive complexity scores a switch once however many cases
+/// it has, and this total explains a low score for dispatch-heavy code.
+pub fn calculate_switch_case_total(node: Node) -> u32 {
+    let own = u32::from(node.kind() == "case_statement" && node.child_by_field_name("value").is_some());
+    let mut cursor = node.walk();
+    let nested: u32 = node.children(&mut cursor).map(calculate_switch_case_total).sum();
+    own + nested
+}
+
+/// Longest chain of member accesses in one expression, counting the base and each
+/// member: `a->b->c` is 3, `cfg.dev[i]->ops` is 3, and a function without member
+/// accesses is 0
+pub fn calculate_max_dereference_chain(node: Node) -> u32 {
+    let own = if node.kind() == "field_expression" { dereference_chain_length(node) } else { 0 };
+    let mut cursor = node.walk();
+    let nested = node.children(&mut cursor).map(calculate_max_dereference_chain).max().unwrap_or(0);
+    own.max(nested)
+}
+
+/// Names in the chain ending at `node`, looking through subscripts, calls, and parentheses
+fn dereference_chain_length(node: Node) -> u32 {
+    let inner = match node.kind() {
+        "field_expression" => {
+            return 1 + node.child_by_field_name("argument").map(dereference_chain_length).unwrap_or(1);
+        }
+        "subscript_expression" | "pointer_expression" => node.child_by_field_name("argument"),
+        "call_expression" => node.child_by_field_name("function"),
+        "parenthesized_expression" => node.named_child(0),
+        _ => None,
+    };
+    inner.map(dereference_chain_length).unwrap_or(1)
+}
+
+/// Deepest nesting of calls passed as arguments to other calls in one expression:
+/// `a(b(c(d())))` is 4, `a(x + b(y))` is 2, and ten sequential calls are 1 each
+pub fn calculate_max_call_nesting(node: Node) -> u32 {
+    let own = if node.kind() == "call_expression" { call_nesting_depth(node) } else { 0 };
+    let mut cursor = node.walk();
+    let nested = node.children(&mut cursor).map(calculate_max_call_nesting).max().unwrap_or(0);
+    own.max(nested)
+}
+
+/// The call itself plus the deepest call anywhere in its arguments
+fn call_nesting_depth(call: Node) -> u32 {
+    1 + call.child_by_field_name("arguments").map(deepest_argument_call).unwrap_or(0)
+}
+
+fn deepest_argument_call(node: Node) -> u32 {
+    if node.kind() == "call_expression" {
+        return call_nesting_depth(node);
+    }
+    let mut cursor = node.walk();
+    let deepest = node.children(&mut cursor).map(deepest_argument_call).max().unwrap_or(0);
+    deepest
+}
+
+/// Names of the functions called directly by name, each listed once in order of first call
+pub fn find_called_functions(node: Node, source_code: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    visit_node_calls(node, source_code, &mut names);
+    names
+}
+
+fn visit_node_calls(node: Node, source_code: &[u8], names: &mut Vec<String>) {
+    note_called_function(node, source_code, names);
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_calls(child, source_code, names);
+    }
+}
+
+/// Adds the callee of a call by name to `names` unless already listed
+fn note_called_function(node: Node, source_code: &[u8], names: &mut Vec<String>) {
+    if node.kind() != "call_expression" {
+        return;
+    }
+    let callee = node
+        .child_by_field_name("function")
+        .filter(|function| function.kind() == "identifier")
+        .and_then(|function| function.utf8_text(source_code).ok());
+    if let Some(name) = callee {
+        if !names.iter().any(|known| known == name) {
+            names.push(name.to_string());
+        }
+    }
+}
+
+/// Counts the type associations (including `default`) of C11 `_Generic` selections
+///
+/// The type is picked at compile time, so these are not runtime branches and stay out
+/// of McCabe; the count is informational, for readers facing long association lists.
+pub fn calculate_generic_association_count(node: Node) -> u32 {
+    let own = generic_associations(node);
+    let mut cursor = node.walk();
+    let nested: u32 = node.children(&mut cursor).map(calculate_generic_association_count).sum();
+    own + nested
+}
+
+/// Associations of a `_Generic` selection, 0 for any other node
+fn generic_associations(node: Node) -> u32 {
+    if node.kind() != "generic_expression" {
+        return 0;
+    }
+    let mut cursor = node.walk();
+    let count = node.children(&mut cursor).filter(|child| child.kind() == ":").count() as u32;
+    count
+}
+
+/// Counts the conditional-compilation branches inside a function: each `#if`, `#ifdef`,
+/// `#ifndef`, and `#elif` (`#else` adds none, like `else`)
+///
+/// These pick code at build time rather than at run time, so they stay out of McCabe and
+/// cognitive complexity; the count says how much a function depends on configuration.
+pub fn calculate_config_branch_count(node: Node) -> u32 {
+    let own = u32::from(matches!(node.kind(), "preproc_if" | "preproc_ifdef" | "preproc_elif" | "preproc_elifdef"));
+    let mut cursor = node.walk();
+    let nested: u32 = node.children(&mut cursor).map(calculate_config_branch_count).sum();
+    own + nested
+}
+
+/// Counts `static` local variable declarations, which keep state between calls and make
+/// a function non-reentrant
+pub fn calculate_static_local_count(node: Node, source_code: &[u8]) -> u32 {
+    let own = u32::from(is_static_local(node, source_code));
+    let mut cursor = node.walk();
+    let nested: u32 = node
+        .children(&mut cursor)
+        .map(|child| calculate_static_local_count(child, source_code))
+        .sum();
+    own + nested
+}
+
+/// Checks whether a node is a declaration with `static` storage (a static local inside a function)
+fn is_static_local(node: Node, source_code: &[u8]) -> bool {
+    node.kind() == "declaration" && is_static_function(node, source_code)
+}
+
+/// McCabe and cognitive increment for a computed goto (an ordinary goto adds 1)
+const COMPUTED_GOTO_WEIGHT: u32 = 2;
+
+/// Counts GCC computed gotos (`goto *ptr;`), the labels-as-values dispatch used by interpreters
+pub fn calculate_computed_goto_count(node: Node, source_code: &[u8]) -> u32 {
+    let own = u32::from(is_computed_goto(node, source_code));
+    let mut cursor = node.walk();
+    let nested: u32 = node
+        .children(&mut cursor)
+        .map(|child| calculate_computed_goto_count(child, source_code))
+        .sum();
+    own + nested
+}
+
+/// Checks whether a node is the `goto` of a computed goto
+///
+/// tree-sitter-c only accepts a label name after `goto`, so `goto *table[op];` parses
+/// as an ERROR holding the `goto` keyword followed by the `*table[op];` expression
+/// statement. A `goto_statement` whose target is not a plain label is also accepted.
+fn is_computed_goto(node: Node, source_code: &[u8]) -> bool {
+    match node.kind() {
+        "goto_statement" => node
+            .child_by_field_name("label")
+            .is_some_and(|label| label.kind() != "statement_identifier"),
+        "ERROR" => {
+            let ends_with_goto = node
+                .child(node.child_count().saturating_sub(1))
+                .is_some_and(|last| last.kind() == "goto");
+            let target_is_deref = node
+                .next_named_sibling()
+                .filter(|next| next.kind() == "expression_statement")
+                .and_then(|next| next.named_child(0))
+                .is_some_and(|expression| {
+                    expression.kind() == "pointer_expression"
+                        && expression.child_by_field_name("operator").and_then(|op| op.utf8_text(source_code).ok()) == Some("*")
+                });
+            ends_with_goto && target_is_deref
+        }
+        _ => false,
     }
 }
 
+/// Checks whether a call is to one of the setjmp/longjmp family
+fn is_setjmp_or_longjmp_call(node: Node, source_code: &[u8]) -> bool {
+    node.child_by_field_name("function")
+        .filter(|function| function.kind() == "identifier")
+        .and_then(|function| function.utf8_text(source_code).ok())
+        .map(|name| {
+            matches!(
+                name,
+                "setjmp" | "_setjmp" | "sigsetjmp" | "__builtin_setjmp"
+                    | "longjmp" | "_longjmp" | "siglongjmp" | "__builtin_longjmp"
+            )
+        })
+        .unwrap_or(false)
+}
+
+/// Optional extensions to the cognitive complexity calculation
+/// All options default to off, which matches the SonarSource specification
+#[derive(Debug, Clone, Default)]
+pub struct CognitiveOptions {
+    /// Add +1 for each non-empty `case` that falls through into the next label
+    pub count_fallthrough: bool,
+    /// Add +1 for each C11 `_Generic` selection (no nesting increment: it is an expression)
+    pub count_generic: bool,
+    /// Add +1 for each call to these assertion macros (no nesting increment: it has no body)
+    pub assert_macros: Vec<String>,
+    /// Cap the nesting increment of each structure at this depth, so state machines nested
+    /// deeper than N add +1+N each; diverges from the SonarSource specification
+    pub max_nesting_penalty: Option<u32>,
+}
 
+impl CognitiveOptions {
+    /// The nesting increment for a structure at `nesting_level`
+    fn nesting_penalty(&self, nesting_level: u32) -> u32 {
+        self.max_nesting_penalty.map_or(nesting_level, |cap| nesting_level.min(cap))
+    }
+}
 
 /// Calculates cognitive complexity for a function
 /// Based on the Cognitive Complexity specification by SonarSource
 pub fn calculate_cognitive_complexity(node: Node, source_code: &[u8]) -> u32 {
+    calculate_cognitive_complexity_with_options(node, source_code, &CognitiveOptions::default())
+}
+
+/// Calculates cognitive complexity with optional extensions enabled
+pub fn calculate_cognitive_complexity_with_options(node: Node, source_code: &[u8], options: &CognitiveOptions) -> u32 {
     let mut complexity = 0;
-    visit_node_cognitive(node, source_code, 0, &mut complexity, None);
+    // A nested function measured on its own is not nested in itself
+    if is_nested_function(node) {
+        visit_children_cognitive(node, source_code, 0, &mut complexity, None, options);
+    } else {
+        visit_node_cognitive(node, source_code, 0, &mut complexity, None, options);
+    }
     complexity
 }
 
-fn visit_node_cognitive(node: Node, source_code: &[u8], nesting_level: u32, complexity: &mut u32, parent_binary_op: Option<&str>) {
+/// A GCC nested function (a definition inside a block) or a C++ lambda
+fn is_nested_function(node: Node) -> bool {
+    match node.kind() {
+        "function_definition" => node.parent().map(|parent| parent.kind() == "compound_statement").unwrap_or(false),
+        "lambda_expression" => true,
+        _ => false,
+    }
+}
+
+fn visit_node_cognitive(node: Node, source_code: &[u8], nesting_level: u32, complexity: &mut u32, parent_binary_op: Option<&str>, options: &CognitiveOptions) {
     match node.kind() {
         // Control flow structures that increase complexity
         "if_statement" => {
-            *complexity += 1 + nesting_level;
-            visit_children_cognitive(node, source_code, nesting_level + 1, complexity, None);
+            *complexity += 1 + options.nesting_penalty(nesting_level);
+            visit_children_cognitive(node, source_code, nesting_level + 1, complexity, None, options);
             return;
         }
 
@@ -85,26 +512,29 @@ fn visit_node_cognitive(node: Node, source_code: &[u8], nesting_level: u32, comp
                     // For else-if, only add +1 total (not +1 for else and +1+nesting for if)
                     // Process the if with current nesting level, not increased
                     *complexity += 1;
-                    visit_children_cognitive(child, source_code, nesting_level, complexity, None);
+                    visit_children_cognitive(child, source_code, nesting_level, complexity, None, options);
                     return;
                 }
             }
 
             // Regular else clause adds +1 without nesting increment
             *complexity += 1;
-            visit_children_cognitive(node, source_code, nesting_level, complexity, None);
+            visit_children_cognitive(node, source_code, nesting_level, complexity, None, options);
             return;
         }
 
         "while_statement" | "do_statement" | "for_statement" => {
-            *complexity += 1 + nesting_level;
-            visit_children_cognitive(node, source_code, nesting_level + 1, complexity, None);
+            *complexity += 1 + options.nesting_penalty(nesting_level);
+            visit_children_cognitive(node, source_code, nesting_level + 1, complexity, None, options);
             return;
         }
 
         "switch_statement" => {
-            *complexity += 1 + nesting_level;
-            visit_children_cognitive(node, source_code, nesting_level + 1, complexity, None);
+            *complexity += 1 + options.nesting_penalty(nesting_level);
+            if options.count_fallthrough {
+                *complexity += count_case_fallthroughs(node);
+            }
+            visit_children_cognitive(node, source_code, nesting_level + 1, complexity, None, options);
             return;
         }
 
@@ -113,16 +543,38 @@ fn visit_node_cognitive(node: Node, source_code: &[u8], nesting_level: u32, comp
 
         // Catch blocks
         "catch_clause" => {
-            *complexity += 1 + nesting_level;
-            visit_children_cognitive(node, source_code, nesting_level + 1, complexity, None);
+            *complexity += 1 + options.nesting_penalty(nesting_level);
+            visit_children_cognitive(node, source_code, nesting_level + 1, complexity, None, options);
+            return;
+        }
+
+        // Nested functions and lambdas are structures of their own, and nest their bodies
+        _ if is_nested_function(node) => {
+            *complexity += 1 + options.nesting_penalty(nesting_level);
+            visit_children_cognitive(node, source_code, nesting_level + 1, complexity, None, options);
             return;
         }
 
+        "generic_expression" if options.count_generic => {
+            *complexity += 1;
+        }
+
         // Jump statements: only goto (not break/continue in switches)
+        _ if is_computed_goto(node, source_code) => {
+            *complexity += COMPUTED_GOTO_WEIGHT;
+        }
         "goto_statement" => {
             *complexity += 1;
         }
 
+        // Non-local control flow: setjmp acts like a hidden if, longjmp like a goto
+        "call_expression" if is_setjmp_or_longjmp_call(node, source_code) => {
+            *complexity += 1;
+        }
+        "call_expression" if is_assert_call(node, source_code, &options.assert_macros) => {
+            *complexity += 1;
+        }
+
         // Binary logical operators - only count if not same as parent operator
         "binary_expression" => {
             if let Some(op) = node.child_by_field_name("operator") {
@@ -134,7 +586,7 @@ fn visit_node_cognitive(node: Node, source_code: &[u8], nesting_level: u32, comp
                             *complexity += 1;
                         }
                         // Pass this operator as parent to children
-                        visit_children_cognitive_with_op(node, source_code, nesting_level, complexity, Some(op_text));
+                        visit_children_cognitive_with_op(node, source_code, nesting_level, complexity, Some(op_text), options);
                         return;
                     }
                 }
@@ -148,20 +600,71 @@ fn visit_node_cognitive(node: Node, source_code: &[u8], nesting_level: u32, comp
     }
 
     // Visit children with current nesting level for non-control-flow nodes
-    visit_children_cognitive(node, source_code, nesting_level, complexity, parent_binary_op);
+    // (including GNU statement expressions, which do not add nesting themselves)
+    visit_children_cognitive(node, source_code, nesting_level, complexity, parent_binary_op, options);
 }
 
-fn visit_children_cognitive(node: Node, source_code: &[u8], nesting_level: u32, complexity: &mut u32, parent_binary_op: Option<&str>) {
+fn visit_children_cognitive(node: Node, source_code: &[u8], nesting_level: u32, complexity: &mut u32, parent_binary_op: Option<&str>, options: &CognitiveOptions) {
     let mut cursor = node.walk();
     for child in node.children(&mut cursor) {
-        visit_node_cognitive(child, source_code, nesting_level, complexity, parent_binary_op);
+        visit_node_cognitive(child, source_code, nesting_level, complexity, parent_binary_op, options);
     }
 }
 
-fn visit_children_cognitive_with_op(node: Node, source_code: &[u8], nesting_level: u32, complexity: &mut u32, parent_binary_op: Option<&str>) {
+fn visit_children_cognitive_with_op(node: Node, source_code: &[u8], nesting_level: u32, complexity: &mut u32, parent_binary_op: Option<&str>, options: &CognitiveOptions) {
     let mut cursor = node.walk();
     for child in node.children(&mut cursor) {
-        visit_node_cognitive(child, source_code, nesting_level, complexity, parent_binary_op);
+        visit_node_cognitive(child, source_code, nesting_level, complexity, parent_binary_op, options);
+    }
+}
+
+/// Counts `case` labels in a switch whose statements fall through into the next label
+/// Empty cases (stacked labels like `case 1: case 2:`) are grouping, not fallthrough
+fn count_case_fallthroughs(switch_node: Node) -> u32 {
+    let body = match switch_node.child_by_field_name("body") {
+        Some(body) => body,
+        None => return 0,
+    };
+
+    let mut cursor = body.walk();
+    let cases: Vec<Node> = body
+        .children(&mut cursor)
+        .filter(|child| child.kind() == "case_statement")
+        .collect();
+
+    let mut count = 0;
+    // The last case has no following label to fall into
+    for case in cases.iter().take(cases.len().saturating_sub(1)) {
+        let value = case.child_by_field_name("value");
+        let mut case_cursor = case.walk();
+        let last_statement = case
+            .named_children(&mut case_cursor)
+            .filter(|child| child.kind() != "comment" && Some(*child) != value)
+            .last();
+
+        if let Some(statement) = last_statement {
+            if !is_terminating_statement(statement) {
+                count += 1;
+            }
+        }
+    }
+
+    count
+}
+
+/// Checks whether a statement unconditionally leaves the current case
+fn is_terminating_statement(node: Node) -> bool {
+    match node.kind() {
+        "break_statement" | "return_statement" | "goto_statement" | "continue_statement" => true,
+        "compound_statement" => {
+            let mut cursor = node.walk();
+            let last = node
+                .named_children(&mut cursor)
+                .filter(|child| child.kind() != "comment")
+                .last();
+            last.map(is_terminating_statement).unwrap_or(false)
+        }
+        _ => false,
     }
 }
 
@@ -174,8 +677,7 @@ pub fn calculate_nesting_depth(node: Node) -> u32 {
 
 fn visit_node_nesting(node: Node, current_depth: u32, max_depth: &mut u32) {
     let new_depth = match node.kind() {
-        "if_statement" | "while_statement" | "do_statement" | "for_statement"
-        | "switch_statement" | "compound_statement" => {
+        kind if is_nesting_node(kind) => {
             let depth = current_depth + 1;
             if depth > *max_depth {
                 *max_depth = depth;
@@ -191,6 +693,78 @@ fn visit_node_nesting(node: Node, current_depth: u32, max_depth: &mut u32) {
     }
 }
 
+/// A control statement on the way to a function's deepest nesting point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NestingStep {
+    /// `if`, `else if`, `else`, `for`, `while`, `do`, `switch`, or `block` for a bare `{ }`
+    pub label: &'static str,
+    /// Line of the statement (1-based)
+    pub line: usize,
+}
+
+/// The control statements enclosing the first of a function's most deeply nested points,
+/// outermost first
+///
+/// The path follows the same nodes as `calculate_nesting_depth`; the braces that form the
+/// body of a control statement or of the function are part of the depth but not listed.
+pub fn find_deepest_nesting_path(node: Node) -> Vec<NestingStep> {
+    let mut path = Vec::new();
+    let mut deepest = Vec::new();
+    visit_node_nesting_path(node, &mut path, &mut deepest);
+    deepest.into_iter().filter_map(nesting_step).collect()
+}
+
+fn visit_node_nesting_path<'tree>(node: Node<'tree>, path: &mut Vec<Node<'tree>>, deepest: &mut Vec<Node<'tree>>) {
+    let nests = is_nesting_node(node.kind());
+    if nests {
+        path.push(node);
+        if path.len() > deepest.len() {
+            deepest.clone_from(path);
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_nesting_path(child, path, deepest);
+    }
+
+    if nests {
+        path.pop();
+    }
+}
+
+fn nesting_step(node: Node) -> Option<NestingStep> {
+    let in_else = node.parent().is_some_and(|parent| parent.kind() == "else_clause");
+    let label = match node.kind() {
+        "if_statement" if in_else => "else if",
+        "if_statement" => "if",
+        "for_statement" => "for",
+        "while_statement" => "while",
+        "do_statement" => "do",
+        "switch_statement" => "switch",
+        "compound_statement" if in_else => "else",
+        "compound_statement" => match node.parent().map(|parent| parent.kind()) {
+            Some(kind) if is_nesting_node(kind) && kind != "compound_statement" => return None,
+            Some("function_definition") => return None,
+            _ => "block",
+        },
+        _ => return None,
+    };
+    let start = if label == "else" { node.parent()? } else { node };
+    Some(NestingStep {
+        label,
+        line: start.start_position().row + 1,
+    })
+}
+
+/// Control structures and blocks that add a nesting level
+fn is_nesting_node(kind: &str) -> bool {
+    matches!(
+        kind,
+        "if_statement" | "while_statement" | "do_statement" | "for_statement" | "switch_statement" | "compound_statement"
+    )
+}
+
 /// Calculates Source Lines of Code (SLOC) - non-comment, non-blank lines
 pub fn calculate_sloc(node: Node, source_code: &[u8]) -> u32 {
     let start_byte = node.start_byte();
@@ -307,7 +881,25 @@ pub fn calculate_abc_complexity(node: Node, source_code: &[u8]) -> AbcComplexity
     let mut branches = 0;
     let mut conditions = 0;
 
-    visit_node_abc(node, source_code, &mut assignments, &mut branches, &mut conditions);
+    visit_node_abc(node, source_code, &mut assignments, &mut branches, &mut conditions, None);
+
+    AbcComplexity {
+        assignments,
+        branches,
+        conditions,
+    }
+}
+
+/// Calculates a nesting-aware variant of the ABC metric
+/// Assignments and branches are counted as in `calculate_abc_complexity`; each
+/// condition contributes 1 + its nesting level, as increments do in cognitive
+/// complexity. `else if` chains stay at the level of the first `if`.
+pub fn calculate_abc_weighted(node: Node, source_code: &[u8]) -> AbcComplexity {
+    let mut assignments = 0;
+    let mut branches = 0;
+    let mut conditions = 0;
+
+    visit_node_abc(node, source_code, &mut assignments, &mut branches, &mut conditions, Some(0));
 
     AbcComplexity {
         assignments,
@@ -316,45 +908,108 @@ pub fn calculate_abc_complexity(node: Node, source_code: &[u8]) -> AbcComplexity
     }
 }
 
-fn visit_node_abc(node: Node, source_code: &[u8], assignments: &mut u32, branches: &mut u32, conditions: &mut u32) {
+/// Visits the ABC components; `nesting` is `None` for the canonical (unweighted) count
+fn visit_node_abc(node: Node, source_code: &[u8], assignments: &mut u32, branches: &mut u32, conditions: &mut u32, nesting: Option<u32>) {
+    let condition_weight = nesting.map(|level| level + 1).unwrap_or(1);
+
+    let counts = abc_counts(node, source_code);
+    *assignments += counts.assignments;
+    *branches += counts.branches;
+    *conditions += counts.conditions * condition_weight;
+
+    let mut cursor = node.walk();
+    for (index, child) in node.children(&mut cursor).enumerate() {
+        let child_nesting = nesting.map(|level| {
+            if abc_child_is_nested(node, child, index) {
+                level + 1
+            } else {
+                level
+            }
+        });
+        visit_node_abc(child, source_code, assignments, branches, conditions, child_nesting);
+    }
+}
+
+/// Unweighted ABC components contributed by a single node
+fn abc_counts(node: Node, source_code: &[u8]) -> AbcComplexity {
+    let mut counts = AbcComplexity {
+        assignments: 0,
+        branches: 0,
+        conditions: 0,
+    };
+
     match node.kind() {
-        // Assignments
-        "assignment_expression" => {
-            *assignments += 1;
-        }
-        "update_expression" => {
-            // ++ and -- operators
-            *assignments += 1;
-        }
+        // Assignments, including ++ and -- operators
+        "assignment_expression" | "update_expression" => counts.assignments = 1,
 
         // Branches (function calls)
-        "call_expression" => {
-            *branches += 1;
-        }
+        "call_expression" => counts.branches = 1,
 
         // Conditions
         "if_statement" | "while_statement" | "do_statement" | "for_statement"
-        | "switch_statement" | "conditional_expression" => {
-            *conditions += 1;
-        }
+        | "switch_statement" | "conditional_expression" => counts.conditions = 1,
 
         // Logical operators
-        "binary_expression" => {
-            if let Some(op) = node.child_by_field_name("operator") {
-                if let Ok(op_text) = op.utf8_text(source_code) {
-                    if op_text == "&&" || op_text == "||" {
-                        *conditions += 1;
-                    }
-                }
-            }
-        }
+        "binary_expression" if logical_operator(node, source_code).is_some() => counts.conditions = 1,
 
         _ => {}
     }
 
+    counts
+}
+
+/// ABC assignments and branches, and source lines, that sit inside brace initializers
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct InitializerData {
+    pub assignments: u32,
+    pub branches: u32,
+    /// Lines of initializer data below the line each initializer opens on
+    pub sloc: u32,
+}
+
+/// Measures the table data in a function: everything inside `{ ... }` initializers,
+/// designated ones (`.field = value`) included
+///
+/// Lookup tables inflate ABC and SLOC without adding control complexity; `--data-aware`
+/// subtracts these counts. Conditions are left alone, since a `?:` in a table is still logic.
+pub fn calculate_initializer_data(node: Node, source_code: &[u8]) -> InitializerData {
+    let mut data = InitializerData::default();
+    visit_node_initializers(node, source_code, &mut data);
+    data
+}
+
+fn visit_node_initializers(node: Node, source_code: &[u8], data: &mut InitializerData) {
+    if node.kind() == "initializer_list" {
+        let abc = calculate_abc_complexity(node, source_code);
+        data.assignments += abc.assignments;
+        data.branches += abc.branches;
+        // The opening line also holds the declaration, which stays counted
+        data.sloc += calculate_sloc(node, source_code).saturating_sub(1);
+        return;
+    }
+
     let mut cursor = node.walk();
     for child in node.children(&mut cursor) {
-        visit_node_abc(child, source_code, assignments, branches, conditions);
+        visit_node_initializers(child, source_code, data);
+    }
+}
+
+/// Whether a child sits one level deeper than its parent for weighted ABC
+/// Bodies and branches of control structures are nested; their conditions are not.
+fn abc_child_is_nested(parent: Node, child: Node, index: usize) -> bool {
+    match parent.kind() {
+        "if_statement" | "while_statement" | "do_statement" | "for_statement"
+        | "switch_statement" | "conditional_expression" => {
+            match parent.field_name_for_child(index as u32) {
+                Some("body") | Some("consequence") => true,
+                // An else clause is at the if's level; its contents decide below
+                Some("alternative") => child.kind() != "else_clause",
+                _ => false,
+            }
+        }
+        // `else if` continues the chain; a plain else body is nested
+        "else_clause" => child.is_named() && child.kind() != "if_statement",
+        _ => false,
     }
 }
 
@@ -376,10 +1031,507 @@ fn visit_node_returns(node: Node, count: &mut u32) {
     }
 }
 
-/// Represents test scoring metric components
-/// Based on automated test generation difficulty assessment
-#[derive(Debug, Clone, Copy)]
-pub struct TestScoringMetric {
+/// Counts the ways a function can exit, for single-exit (MISRA C 15.5) checks
+///
+/// Sums `return` statements, forward `goto`s to a label at the top level of the
+/// function body (the usual `cleanup:` tail), and calls that never return to the
+/// caller (`exit`, `abort`, `longjmp` and friends).
+pub fn calculate_exit_point_count(node: Node, source_code: &[u8]) -> u32 {
+    let cleanup_labels = find_cleanup_labels(node, source_code);
+    let mut count = 0;
+    visit_node_exit_points(node, source_code, &cleanup_labels, &mut count);
+    count
+}
+
+/// Labels at the top level of the function body with their start byte: candidates
+/// for a `cleanup:` tail that error paths jump forward to
+fn find_cleanup_labels<'a>(node: Node, source_code: &'a [u8]) -> Vec<(&'a str, usize)> {
+    let mut cleanup_labels = Vec::new();
+    if let Some(body) = node.child_by_field_name("body") {
+        let mut cursor = body.walk();
+        for statement in body.named_children(&mut cursor) {
+            if let Some(label) = statement.child_by_field_name("label") {
+                if statement.kind() == "labeled_statement" {
+                    if let Ok(name) = label.utf8_text(source_code) {
+                        cleanup_labels.push((name, statement.start_byte()));
+                    }
+                }
+            }
+        }
+    }
+    cleanup_labels
+}
+
+/// Heuristic for a possible leak on an error path: the function allocates memory and
+/// has more than one `return`, but no top-level cleanup label to funnel them through
+pub fn is_leak_risk(node: Node, source_code: &[u8]) -> bool {
+    calculate_return_count(node) > 1
+        && find_cleanup_labels(node, source_code).is_empty()
+        && calls_allocator(node, source_code)
+}
+
+fn calls_allocator(node: Node, source_code: &[u8]) -> bool {
+    let mut cursor = node.walk();
+    let nested = node.children(&mut cursor).any(|child| calls_allocator(child, source_code));
+    is_allocation_call(node, source_code) || nested
+}
+
+fn is_allocation_call(node: Node, source_code: &[u8]) -> bool {
+    node.kind() == "call_expression"
+        && node
+            .child_by_field_name("function")
+            .and_then(|function| function.utf8_text(source_code).ok())
+            .map(is_allocator)
+            .unwrap_or(false)
+}
+
+/// Standard functions returning memory the caller must free
+fn is_allocator(name: &str) -> bool {
+    matches!(name, "malloc" | "calloc" | "realloc" | "aligned_alloc" | "strdup" | "strndup")
+}
+
+fn visit_node_exit_points(node: Node, source_code: &[u8], cleanup_labels: &[(&str, usize)], count: &mut u32) {
+    if is_exit_point(node, source_code, cleanup_labels) {
+        *count += 1;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_exit_points(child, source_code, cleanup_labels, count);
+    }
+}
+
+/// Checks whether a node is a `return`, a forward `goto` to a cleanup label, or a call that never returns
+fn is_exit_point(node: Node, source_code: &[u8], cleanup_labels: &[(&str, usize)]) -> bool {
+    match node.kind() {
+        "return_statement" => true,
+        "goto_statement" => {
+            let target = node
+                .child_by_field_name("label")
+                .and_then(|label| label.utf8_text(source_code).ok());
+            cleanup_labels
+                .iter()
+                .any(|&(name, start)| Some(name) == target && start > node.start_byte())
+        }
+        "call_expression" => is_exit_call(node, source_code),
+        _ => false,
+    }
+}
+
+/// Calculates McCabe's essential complexity: what is left of the cyclomatic complexity
+/// once every structured construct (`if`, loops, `switch` with `break`) is reduced away
+///
+/// Counted as 1 plus each unstructured jump: `goto`, a `return` other than the final
+/// statement of the body, `break` out of a loop (a `break` ending a `case` is structured),
+/// `continue`, and calls that never return (`exit`, `abort`, `longjmp`). A fully
+/// structured function scores 1.
+pub fn calculate_essential_complexity(node: Node, source_code: &[u8]) -> u32 {
+    let final_return = node.child_by_field_name("body").and_then(final_return_statement).map(|statement| statement.id());
+    let mut complexity = 1;
+    visit_node_essential(node, source_code, final_return, &mut complexity);
+    complexity
+}
+
+/// The `return` ending a block, looking through a trailing label (`out: return rc;`)
+fn final_return_statement(body: Node) -> Option<Node> {
+    let mut cursor = body.walk();
+    let mut last = body
+        .named_children(&mut cursor)
+        .filter(|statement| statement.kind() != "comment")
+        .last()?;
+    while last.kind() == "labeled_statement" {
+        last = last.named_child(last.named_child_count().checked_sub(1)?)?;
+    }
+    (last.kind() == "return_statement").then_some(last)
+}
+
+fn visit_node_essential(node: Node, source_code: &[u8], final_return: Option<usize>, complexity: &mut u32) {
+    let unstructured = match node.kind() {
+        "goto_statement" | "continue_statement" => true,
+        "return_statement" => Some(node.id()) != final_return,
+        "break_statement" => breaks_out_of_loop(node),
+        "call_expression" => is_exit_call(node, source_code),
+        _ => false,
+    };
+    if unstructured {
+        *complexity += 1;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_essential(child, source_code, final_return, complexity);
+    }
+}
+
+/// Whether a `break` leaves a loop rather than a `switch`
+fn breaks_out_of_loop(node: Node) -> bool {
+    let mut current = node.parent();
+    while let Some(parent) = current {
+        match parent.kind() {
+            "for_statement" | "while_statement" | "do_statement" => return true,
+            "switch_statement" | "function_definition" => return false,
+            _ => current = parent.parent(),
+        }
+    }
+    false
+}
+
+/// Checks whether a call terminates the function without returning to the caller
+fn is_exit_call(node: Node, source_code: &[u8]) -> bool {
+    node.child_by_field_name("function")
+        .filter(|function| function.kind() == "identifier")
+        .and_then(|function| function.utf8_text(source_code).ok())
+        .map(|name| {
+            matches!(
+                name,
+                "exit" | "_exit" | "_Exit" | "quick_exit" | "abort"
+                    | "longjmp" | "_longjmp" | "siglongjmp" | "__builtin_longjmp"
+            )
+        })
+        .unwrap_or(false)
+}
+
+/// Default patterns marking an `if` condition as an error check: comparisons against
+/// 0 or NULL, and names like `err`, `status`, `ret`, or `rc`
+pub const DEFAULT_ERROR_PATTERNS: [&str; 4] = [
+    r"!=\s*0\b",
+    r"<\s*0\b",
+    r"==\s*NULL\b",
+    r"(?i)err|status|\bret(val|code)?\b|\brc\b",
+];
+
+/// Comment markers for known technical debt
+pub const DEFAULT_DEBT_MARKERS: [&str; 4] = ["TODO", "FIXME", "HACK", "XXX"];
+
+/// Counts debt markers (`TODO`, `FIXME`, ...) in the comments inside a function
+///
+/// Markers are matched case-sensitively as whole words, so `TODOS` or `todo` do not
+/// count; a comment holding two markers counts 2.
+pub fn calculate_debt_marker_count(node: Node, source_code: &[u8], markers: &[String]) -> u32 {
+    if node.kind() == "comment" {
+        return comment_debt_markers(node, source_code, markers);
+    }
+
+    let mut cursor = node.walk();
+    node.children(&mut cursor)
+        .map(|child| calculate_debt_marker_count(child, source_code, markers))
+        .sum()
+}
+
+/// Debt markers in the text of one comment
+fn comment_debt_markers(comment: Node, source_code: &[u8], markers: &[String]) -> u32 {
+    let text = comment.utf8_text(source_code).unwrap_or("");
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| markers.iter().any(|marker| marker == word))
+        .count() as u32
+}
+
+/// Estimates the SLOC a function spends handling errors
+///
+/// Counts the statements in the body of every `if` whose condition matches one of
+/// `patterns`, e.g. `if (ret != OK) { log_error(ret); return ret; }` counts 2. Ifs
+/// nested inside an error block are part of that block and not counted again.
+pub fn calculate_error_handling_sloc(node: Node, source_code: &[u8], patterns: &[Regex]) -> u32 {
+    let mut sloc = 0;
+    visit_node_error_handling(node, source_code, patterns, &mut sloc);
+    sloc
+}
+
+fn visit_node_error_handling(node: Node, source_code: &[u8], patterns: &[Regex], sloc: &mut u32) {
+    if let Some(block_sloc) = error_block_sloc(node, source_code, patterns) {
+        *sloc += block_sloc;
+        // The else branch is normal code again
+        if let Some(alternative) = node.child_by_field_name("alternative") {
+            visit_node_error_handling(alternative, source_code, patterns, sloc);
+        }
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_error_handling(child, source_code, patterns, sloc);
+    }
+}
+
+/// SLOC of the body of an `if` whose condition matches one of `patterns`, or `None`
+/// when the node is not such an error check
+fn error_block_sloc(node: Node, source_code: &[u8], patterns: &[Regex]) -> Option<u32> {
+    if node.kind() != "if_statement" {
+        return None;
+    }
+    let is_error_check = node
+        .child_by_field_name("condition")
+        .and_then(|condition| condition.utf8_text(source_code).ok())
+        .is_some_and(|condition| patterns.iter().any(|pattern| pattern.is_match(condition)));
+    let consequence = node.child_by_field_name("consequence").filter(|_| is_error_check)?;
+
+    if consequence.kind() == "compound_statement" {
+        let mut cursor = consequence.walk();
+        let sloc = consequence
+            .named_children(&mut cursor)
+            .filter(|statement| statement.kind() != "comment")
+            .map(|statement| calculate_sloc(statement, source_code))
+            .sum::<u32>();
+        Some(sloc)
+    } else {
+        Some(calculate_sloc(consequence, source_code))
+    }
+}
+
+/// Formatting consistency within one function, from its source text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct StyleConsistency {
+    /// Body lines starting with indentation
+    pub indented_lines: u32,
+    /// Indented lines in the function's minority style (tabs vs spaces), plus lines
+    /// whose indentation has a space before a tab
+    pub off_style_indent_lines: u32,
+    /// Braces opening an if/else/for/while/do/switch body
+    pub control_braces: u32,
+    /// Control braces in the minority placement (same line vs next line)
+    pub off_style_braces: u32,
+}
+
+impl StyleConsistency {
+    /// Percentage of indented lines and control braces in the function's majority style
+    /// (100 when there is nothing to compare)
+    pub fn score(&self) -> f64 {
+        let total = self.indented_lines + self.control_braces;
+        if total == 0 {
+            return 100.0;
+        }
+        100.0 * (total - self.off_style_indent_lines - self.off_style_braces) as f64 / total as f64
+    }
+}
+
+/// Detects mixed tab/space indentation and inconsistent brace placement in a function
+///
+/// Indentation is compared line by line within the function's text, skipping the
+/// signature line; a brace is "same line" when it follows its condition (or `else`/`do`)
+/// on that line. The function's own opening brace is not compared, since many styles
+/// place it differently from control statements on purpose.
+pub fn calculate_style_consistency(node: Node, source_code: &[u8]) -> StyleConsistency {
+    let text = String::from_utf8_lossy(&source_code[node.start_byte()..node.end_byte()]);
+    let (mut tab_lines, mut space_lines, mut mixed_lines) = (0u32, 0u32, 0u32);
+    for line in text.lines().skip(1).filter(|line| !line.trim().is_empty()) {
+        let indent: String = line.chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+        if indent.contains(" \t") {
+            mixed_lines += 1;
+        } else if indent.starts_with('\t') {
+            tab_lines += 1;
+        } else if indent.starts_with(' ') {
+            space_lines += 1;
+        }
+    }
+
+    let (mut same_line, mut next_line) = (0u32, 0u32);
+    visit_node_braces(node, &mut same_line, &mut next_line);
+
+    StyleConsistency {
+        indented_lines: tab_lines + space_lines + mixed_lines,
+        off_style_indent_lines: tab_lines.min(space_lines) + mixed_lines,
+        control_braces: same_line + next_line,
+        off_style_braces: same_line.min(next_line),
+    }
+}
+
+fn visit_node_braces(node: Node, same_line: &mut u32, next_line: &mut u32) {
+    let is_control_body = node.kind() == "compound_statement"
+        && node.parent().is_some_and(|parent| {
+            matches!(
+                parent.kind(),
+                "if_statement" | "else_clause" | "for_statement" | "while_statement" | "do_statement" | "switch_statement"
+            )
+        });
+    if let (true, Some(previous)) = (is_control_body, node.prev_sibling()) {
+        if previous.end_position().row == node.start_position().row {
+            *same_line += 1;
+        } else {
+            *next_line += 1;
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_braces(child, same_line, next_line);
+    }
+}
+
+/// Counts unnamed numeric literals ("magic numbers") in a function
+///
+/// The values 0 and 1 (including `-1`, `1U`, `0.0`, `0x0`) are not counted, and
+/// neither are literals in `#define`s, enumerators, array sizes, or `case` labels,
+/// where a number is either naming itself or idiomatic.
+pub fn calculate_magic_number_count(node: Node, source_code: &[u8]) -> u32 {
+    let mut count = 0;
+    visit_node_magic_numbers(node, source_code, &mut count);
+    count
+}
+
+fn visit_node_magic_numbers(node: Node, source_code: &[u8], count: &mut u32) {
+    if is_magic_number(node, source_code) {
+        *count += 1;
+    }
+    if node.kind() == "number_literal" || is_magic_number_scope(node) {
+        return;
+    }
+
+    let exempt = magic_number_exempt_child(node);
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if Some(child) != exempt {
+            visit_node_magic_numbers(child, source_code, count);
+        }
+    }
+}
+
+fn is_magic_number(node: Node, source_code: &[u8]) -> bool {
+    node.kind() == "number_literal" && !is_zero_or_one(node.utf8_text(source_code).unwrap_or(""))
+}
+
+/// Definitions whose numbers name themselves, so nothing inside them is a magic number
+fn is_magic_number_scope(node: Node) -> bool {
+    matches!(node.kind(), "preproc_def" | "preproc_function_def" | "enumerator")
+}
+
+/// The child of a `case` or array declarator whose numbers are idiomatic
+fn magic_number_exempt_child(node: Node) -> Option<Node> {
+    match node.kind() {
+        "case_statement" => node.child_by_field_name("value"),
+        "array_declarator" => node.child_by_field_name("size"),
+        _ => None,
+    }
+}
+
+/// Checks whether a numeric literal is 0 or 1, ignoring base and type suffixes
+fn is_zero_or_one(literal: &str) -> bool {
+    let lower = literal.to_ascii_lowercase();
+    let value = if let Some(hex) = lower.strip_prefix("0x") {
+        u64::from_str_radix(hex.trim_end_matches(['u', 'l']), 16).ok().map(|value| value as f64)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u64::from_str_radix(binary.trim_end_matches(['u', 'l']), 2).ok().map(|value| value as f64)
+    } else {
+        lower.trim_end_matches(['u', 'l', 'f']).parse::<f64>().ok()
+    };
+    matches!(value, Some(value) if value == 0.0 || value == 1.0)
+}
+
+/// Finds statements that follow an unconditional `return`/`goto`/`break`/`continue`
+/// in the same block, returning their 1-based line numbers
+///
+/// A label (or `case`) after the terminating statement makes the code reachable
+/// again, so scanning of that block resumes from there.
+pub fn find_unreachable_lines(node: Node) -> Vec<usize> {
+    let mut lines = Vec::new();
+    visit_node_unreachable(node, &mut lines);
+    lines.sort_unstable();
+    lines
+}
+
+/// Finds `switch` statements with no `default:` label, returning their 1-based line numbers
+pub fn find_switches_without_default(node: Node) -> Vec<usize> {
+    let mut lines = Vec::new();
+    visit_node_switch_default(node, &mut lines);
+    lines
+}
+
+fn visit_node_switch_default(node: Node, lines: &mut Vec<usize>) {
+    if lacks_default(node) {
+        lines.push(node.start_position().row + 1);
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_switch_default(child, lines);
+    }
+}
+
+/// Checks whether a node is a `switch` whose body has no `default:` label
+fn lacks_default(node: Node) -> bool {
+    if node.kind() != "switch_statement" {
+        return false;
+    }
+    node.child_by_field_name("body").is_some_and(|body| {
+        let mut cursor = body.walk();
+        let has_default = body
+            .named_children(&mut cursor)
+            .any(|child| child.kind() == "case_statement" && child.child_by_field_name("value").is_none());
+        !has_default
+    })
+}
+
+/// Deepest nesting of `switch` statements inside one another's cases: 0 without a
+/// switch, 1 for a plain switch, 2 for a switch in a case of another
+pub fn calculate_max_switch_nesting(node: Node) -> u32 {
+    let own = u32::from(node.kind() == "switch_statement");
+    let mut cursor = node.walk();
+    let nested = node.children(&mut cursor).map(calculate_max_switch_nesting).max().unwrap_or(0);
+    own + nested
+}
+
+/// Finds `switch` statements nested inside another switch, returning their 1-based line numbers
+pub fn find_nested_switches(node: Node) -> Vec<usize> {
+    let mut lines = Vec::new();
+    visit_node_nested_switches(node, 0, &mut lines);
+    lines
+}
+
+fn visit_node_nested_switches(node: Node, depth: u32, lines: &mut Vec<usize>) {
+    let depth = if node.kind() == "switch_statement" {
+        if depth > 0 {
+            lines.push(node.start_position().row + 1);
+        }
+        depth + 1
+    } else {
+        depth
+    };
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_nested_switches(child, depth, lines);
+    }
+}
+
+fn visit_node_unreachable(node: Node, lines: &mut Vec<usize>) {
+    note_unreachable_statements(node, lines);
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_unreachable(child, lines);
+    }
+}
+
+/// Adds the lines of the unreachable statements directly inside a block or `case` to `lines`
+fn note_unreachable_statements(node: Node, lines: &mut Vec<usize>) {
+    if node.kind() != "compound_statement" && node.kind() != "case_statement" {
+        return;
+    }
+    let value = node.child_by_field_name("value");
+    let mut terminated = false;
+    let mut cursor = node.walk();
+    for statement in node.named_children(&mut cursor) {
+        if statement.kind() == "comment" || Some(statement) == value {
+            continue;
+        }
+        if statement.kind() == "labeled_statement"
+            || statement.kind() == "case_statement"
+            || statement.kind().starts_with("preproc")
+        {
+            terminated = false;
+        } else if terminated {
+            lines.push(statement.start_position().row + 1);
+        }
+        if is_terminating_statement(statement) {
+            terminated = true;
+        }
+    }
+}
+
+/// Represents test scoring metric components
+/// Based on automated test generation difficulty assessment
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema)]
+pub struct TestScoringMetric {
     pub signature_score: u32,
     pub dependency_score: u32,
     pub observable_score: u32,
@@ -413,397 +1565,3643 @@ impl TestScoringMetric {
     }
 }
 
+/// Optional extensions to the test scoring metric
+/// All options default to off
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TestScoringOptions {
+    /// Credit Doxygen-style comments inside the body toward the documentation score
+    /// (+1 each, up to +3); the comment before the function stays the main signal
+    pub credit_inline_docs: bool,
+}
+
+/// Most documentation credit inline doc comments can add
+const MAX_INLINE_DOC_CREDIT: u32 = 3;
+
 /// Calculates test scoring metric for assessing test generation difficulty
 /// Score components: signature, dependency, observable behavior, implementation, documentation
 pub fn calculate_test_scoring(node: Node, source_code: &[u8]) -> TestScoringMetric {
-    let signature = calculate_signature_complexity(node, source_code);
-    let dependency = calculate_dependency_score(node, source_code);
-    let observable = calculate_observable_behavior_score(node, source_code);
+    let aggregates = aggregate_typedefs_around(node, source_code);
+    calculate_test_scoring_with_options(node, source_code, &TestScoringOptions::default(), &aggregates)
+}
+
+/// Calculates the test scoring metric with optional extensions enabled
+///
+/// `aggregates` is the file's `find_aggregate_typedefs`, built once for all its functions.
+pub fn calculate_test_scoring_with_options(
+    node: Node,
+    source_code: &[u8],
+    options: &TestScoringOptions,
+    aggregates: &HashSet<String>,
+) -> TestScoringMetric {
+    let mut dependencies = DependencyFlags::default();
+    visit_node_dependencies(node, source_code, &mut dependencies);
+    let mut observability = ObservabilityFlags::default();
+    visit_node_observability(node, source_code, &mut observability);
 
     // Use existing cyclomatic complexity for implementation score
     let mccabe = calculate_mccabe_complexity(node, source_code);
+
+    let inline_docs = if options.credit_inline_docs {
+        count_inline_doc_comments(node, source_code)
+    } else {
+        0
+    };
+
+    test_scoring_from_parts(node, source_code, &dependencies, &observability, mccabe, inline_docs, aggregates)
+}
+
+/// Counts Doxygen-style comments (`/**`, `/*!`, `///`, `//!`) inside a function
+fn count_inline_doc_comments(node: Node, source_code: &[u8]) -> u32 {
+    let mut count = u32::from(is_inline_doc_comment(node, source_code));
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        count += count_inline_doc_comments(child, source_code);
+    }
+    count
+}
+
+fn is_inline_doc_comment(node: Node, source_code: &[u8]) -> bool {
+    node.kind() == "comment"
+        && node
+            .utf8_text(source_code)
+            .map(|text| ["/**", "/*!", "///", "//!"].iter().any(|marker| text.starts_with(marker)))
+            .unwrap_or(false)
+}
+
+/// Combines the walked components of the test scoring metric with the signature and
+/// documentation, which only look at the function's declaration and leading comment
+fn test_scoring_from_parts(
+    node: Node,
+    source_code: &[u8],
+    dependencies: &DependencyFlags,
+    observability: &ObservabilityFlags,
+    mccabe: u32,
+    inline_docs: u32,
+    aggregates: &HashSet<String>,
+) -> TestScoringMetric {
+    let signature = calculate_signature_complexity(node, source_code, aggregates);
+    let dependency = dependencies.score();
+    let observable = calculate_observable_behavior_score(node, source_code, observability);
     let implementation = map_cyclomatic_to_implementation_score(mccabe);
 
-    let documentation = calculate_documentation_score(node, source_code);
+    let inline_credit = inline_docs.min(MAX_INLINE_DOC_CREDIT) as i32;
+    let documentation = (calculate_documentation_score(node, source_code) + inline_credit).min(10);
+
+    let total = signature as i32 + dependency as i32 + observable as i32 + implementation as i32 - documentation;
+
+    TestScoringMetric {
+        signature_score: signature,
+        dependency_score: dependency,
+        observable_score: observable,
+        implementation_score: implementation,
+        documentation_score: documentation,
+        total_score: total,
+    }
+}
+
+/// Maps cyclomatic complexity to implementation score (0-10 scale)
+fn map_cyclomatic_to_implementation_score(cyclomatic: u32) -> u32 {
+    match cyclomatic {
+        1..=5 => (cyclomatic - 1) / 2,      // 1-5 -> 0-2
+        6..=10 => 3 + (cyclomatic - 6) / 2, // 6-10 -> 3-5
+        11..=20 => 6 + (cyclomatic - 11) / 5, // 11-20 -> 6-8
+        _ => 9, // 20+ -> 9
+    }
+}
+
+/// Calculates signature complexity based on function parameters and return type
+fn calculate_signature_complexity(node: Node, source_code: &[u8], aggregates: &HashSet<String>) -> u32 {
+    let mut input_score = 0;
+    let mut output_score = 0;
+
+    if let Some(function) = find_signature_node(node) {
+        // Analyze parameters of the innermost function declarator
+        if let Some(declarator) = find_function_declarator(function) {
+            input_score = analyze_parameters(declarator, source_code, aggregates);
+        }
+
+        // Analyze return type
+        if let Some(type_node) = function.child_by_field_name("type") {
+            output_score = analyze_return_type(function, type_node, source_code);
+        }
+
+        // Callbacks, and functions returning or taking them, get harder to set up per level
+        input_score += FUNCTION_POINTER_WEIGHT * calculate_function_pointer_depth(function);
+    }
+
+    // Combined score capped at 10, less the credit for explicit preconditions
+    let guard_credit = count_leading_guards(node, source_code).min(MAX_GUARD_CREDIT);
+    (input_score + output_score).min(10).saturating_sub(guard_credit)
+}
+
+/// Signature score added per level of function-pointer indirection
+const FUNCTION_POINTER_WEIGHT: u32 = 2;
+
+/// Deepest nesting of function pointers in a signature
+///
+/// Each function-pointer declarator on the way down counts one level, whether it is
+/// a parameter, the return type, or nested inside either: `void f(void (*cb)(int))`
+/// is 1, while `void (*signal(int, void (*)(int)))(int)`, which returns a function
+/// pointer and takes one, is 2. Function pointers hidden behind a typedef are not seen.
+pub fn calculate_function_pointer_depth(node: Node) -> u32 {
+    let own = u32::from(is_function_pointer_declarator(node));
+    let mut cursor = node.walk();
+    let deepest = node
+        .named_children(&mut cursor)
+        .map(calculate_function_pointer_depth)
+        .max()
+        .unwrap_or(0);
+    own + deepest
+}
+
+/// `(*name)(...)` or the abstract `(*)(...)`: a function declarator around a parenthesized pointer
+fn is_function_pointer_declarator(node: Node) -> bool {
+    if !matches!(node.kind(), "function_declarator" | "abstract_function_declarator") {
+        return false;
+    }
+    let Some(inner) = node.child_by_field_name("declarator") else {
+        return false;
+    };
+    let mut cursor = inner.walk();
+    let is_pointer = inner.kind().ends_with("parenthesized_declarator")
+        && inner
+            .named_children(&mut cursor)
+            .any(|child| child.kind().ends_with("pointer_declarator"));
+    is_pointer
+}
+
+/// Most the signature score is reduced for leading precondition guards
+const MAX_GUARD_CREDIT: u32 = 2;
+
+/// Counts the precondition guards that open a function body
+///
+/// A guard is an `assert`-style call (`assert`, `ASSERT`, `configASSERT`, ...) or an
+/// `if` without `else` whose body only returns, such as `if (buf == NULL) return -1;`.
+/// Declarations may sit between guards; the run ends at the first other statement.
+fn count_leading_guards(node: Node, source_code: &[u8]) -> u32 {
+    let Some(body) = node.child_by_field_name("body") else {
+        return 0;
+    };
+
+    let mut guards = 0;
+    let mut cursor = body.walk();
+    for statement in body.named_children(&mut cursor) {
+        match statement.kind() {
+            "comment" | "declaration" => continue,
+            _ if is_assert_statement(statement, source_code) || is_return_guard(statement) => guards += 1,
+            _ => break,
+        }
+    }
+    guards
+}
+
+fn is_assert_statement(statement: Node, source_code: &[u8]) -> bool {
+    statement.kind() == "expression_statement"
+        && statement
+            .named_child(0)
+            .filter(|expression| expression.kind() == "call_expression")
+            .and_then(|call| call.child_by_field_name("function"))
+            .and_then(|function| function.utf8_text(source_code).ok())
+            .map(|name| name.to_lowercase().contains("assert"))
+            .unwrap_or(false)
+}
+
+fn is_return_guard(statement: Node) -> bool {
+    if statement.kind() != "if_statement" || statement.child_by_field_name("alternative").is_some() {
+        return false;
+    }
+
+    match statement.child_by_field_name("consequence") {
+        Some(consequence) if consequence.kind() == "return_statement" => true,
+        Some(consequence) if consequence.kind() == "compound_statement" => {
+            let mut cursor = consequence.walk();
+            let statements: Vec<Node> = consequence
+                .named_children(&mut cursor)
+                .filter(|child| child.kind() != "comment")
+                .collect();
+            matches!(statements.as_slice(), [only] if only.kind() == "return_statement")
+        }
+        _ => false,
+    }
+}
+
+/// Returns the node carrying a function signature: the node itself when it is a
+/// definition or prototype, otherwise the last such direct child
+fn find_signature_node(node: Node) -> Option<Node> {
+    if matches!(node.kind(), "function_definition" | "declaration") {
+        return Some(node);
+    }
+
+    let mut cursor = node.walk();
+    let result = node
+        .children(&mut cursor)
+        .filter(|child| matches!(child.kind(), "function_definition" | "declaration"))
+        .last();
+    result
+}
+
+/// Counts assignments and `++`/`--` whose target is one of the function's own parameters
+///
+/// Writes through a parameter (`*p = 0`, `p->len = 0`) change what it points to, not the
+/// parameter, and are not counted; neither are locals that shadow a parameter's name.
+pub fn calculate_parameter_modification_count(node: Node, source_code: &[u8]) -> u32 {
+    let parameters = parameter_names(node, source_code);
+    if parameters.is_empty() {
+        return 0;
+    }
+    let body = node.child_by_field_name("body").unwrap_or(node);
+    count_parameter_writes(body, source_code, &parameters)
+}
+
+/// Names of a function definition's parameters, in order
+fn parameter_names<'a>(node: Node, source_code: &'a [u8]) -> Vec<&'a str> {
+    let Some(parameters) = find_function_declarator(node).and_then(|declarator| declarator.child_by_field_name("parameters"))
+    else {
+        return Vec::new();
+    };
+    let mut cursor = parameters.walk();
+    let names = parameters
+        .children(&mut cursor)
+        .filter(|param| param.kind() == "parameter_declaration")
+        .filter_map(|param| param.child_by_field_name("declarator"))
+        .filter_map(declared_identifier)
+        .filter_map(|identifier| identifier.utf8_text(source_code).ok())
+        .collect();
+    names
+}
+
+/// The identifier a declarator declares, through pointers, arrays, and parentheses
+pub fn declared_identifier(declarator: Node) -> Option<Node> {
+    let mut current = declarator;
+    loop {
+        match current.kind() {
+            "identifier" => return Some(current),
+            "pointer_declarator" | "array_declarator" | "function_declarator" => {
+                current = current.child_by_field_name("declarator")?;
+            }
+            "attributed_declarator" => current = attributed_target(current)?,
+            "parenthesized_declarator" => current = current.named_child(0)?,
+            _ => return None,
+        }
+    }
+}
+
+fn count_parameter_writes(node: Node, source_code: &[u8], parameters: &[&str]) -> u32 {
+    let own = u32::from(writes_parameter(node, source_code, parameters));
+
+    let mut cursor = node.walk();
+    let nested: u32 = node
+        .children(&mut cursor)
+        .map(|child| count_parameter_writes(child, source_code, parameters))
+        .sum();
+    own + nested
+}
+
+/// Checks whether a node is an assignment or `++`/`--` whose target is one of `parameters`
+fn writes_parameter(node: Node, source_code: &[u8], parameters: &[&str]) -> bool {
+    let target = match node.kind() {
+        "assignment_expression" => node.child_by_field_name("left"),
+        "update_expression" => node.child_by_field_name("argument"),
+        _ => None,
+    };
+    target
+        .map(strip_parentheses)
+        .filter(|target| target.kind() == "identifier")
+        .and_then(|target| target.utf8_text(source_code).ok())
+        .is_some_and(|name| parameters.contains(&name))
+}
+
+/// Names of non-const pointer parameters the body never writes through, which could
+/// take `const T *` instead
+///
+/// A parameter is written through by an assignment or `++`/`--` whose target reaches it
+/// (`*p = 0`, `p->len++`, `p[i] = 0`). Passing it to a call, assigning or returning it, or
+/// taking the address of its pointee (`&p->field`) also rules it out, since the write may
+/// happen elsewhere. Pointers to pointers and function pointers are never reported.
+pub fn find_const_candidates(node: Node, source_code: &[u8]) -> Vec<String> {
+    let Some(body) = node.child_by_field_name("body") else {
+        return Vec::new();
+    };
+    non_const_pointer_parameters(node, source_code)
+        .into_iter()
+        .filter(|name| !is_written_through(body, source_code, name))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Names of the pointer parameters not declared `const`, excluding pointers to pointers
+fn non_const_pointer_parameters<'a>(node: Node, source_code: &'a [u8]) -> Vec<&'a str> {
+    let Some(parameters) = find_function_declarator(node).and_then(|declarator| declarator.child_by_field_name("parameters"))
+    else {
+        return Vec::new();
+    };
+    let mut cursor = parameters.walk();
+    let candidates = parameters
+        .children(&mut cursor)
+        .filter(|param| param.kind() == "parameter_declaration")
+        .filter(|param| !has_type_qualifier(*param, source_code, "const"))
+        .filter_map(|param| param.child_by_field_name("declarator"))
+        .filter(|declarator| declarator.kind() == "pointer_declarator")
+        .filter_map(|declarator| declarator.child_by_field_name("declarator"))
+        .filter(|declarator| declarator.kind() == "identifier")
+        .filter_map(|identifier| identifier.utf8_text(source_code).ok())
+        .collect();
+    candidates
+}
+
+fn is_written_through(node: Node, source_code: &[u8], name: &str) -> bool {
+    if writes_through_here(node, source_code, name) {
+        return true;
+    }
+
+    let mut cursor = node.walk();
+    let nested = node.children(&mut cursor).any(|child| is_written_through(child, source_code, name));
+    nested
+}
+
+/// Whether this node itself (not its children) writes through `name` or lets it escape
+fn writes_through_here(node: Node, source_code: &[u8], name: &str) -> bool {
+    let field = |field: &str| node.child_by_field_name(field);
+    match node.kind() {
+        "assignment_expression" => {
+            field("left").is_some_and(|target| writes_through(target, source_code, name))
+                || field("right").is_some_and(|value| escapes(value, source_code, name))
+        }
+        "update_expression" => field("argument").is_some_and(|target| writes_through(target, source_code, name)),
+        "init_declarator" => field("value").is_some_and(|value| escapes(value, source_code, name)),
+        "return_statement" => node.named_child(0).is_some_and(|value| escapes(value, source_code, name)),
+        "argument_list" => {
+            let mut cursor = node.walk();
+            let passed = node.named_children(&mut cursor).any(|argument| escapes(argument, source_code, name));
+            passed
+        }
+        _ => false,
+    }
+}
+
+/// Whether assigning to `target` writes to memory `name` points at
+fn writes_through(target: Node, source_code: &[u8], name: &str) -> bool {
+    let target = strip_parentheses(target);
+    let Some(argument) = target.child_by_field_name("argument") else {
+        return false;
+    };
+    let operator = target.child_by_field_name("operator").and_then(|op| op.utf8_text(source_code).ok());
+    let dereferences = match target.kind() {
+        "pointer_expression" => operator == Some("*"),
+        "field_expression" => operator == Some("->"),
+        "subscript_expression" => true,
+        _ => return false,
+    };
+    (dereferences && points_to(argument, source_code, name)) || writes_through(argument, source_code, name)
+}
+
+/// Whether a value handed elsewhere lets the callee or another variable write through `name`
+fn escapes(value: Node, source_code: &[u8], name: &str) -> bool {
+    let value = strip_casts(value);
+    match value.kind() {
+        "pointer_expression" => {
+            let address_of = value.child_by_field_name("operator").and_then(|op| op.utf8_text(source_code).ok()) == Some("&");
+            address_of
+                && value.child_by_field_name("argument").is_some_and(|argument| {
+                    points_to(argument, source_code, name) || writes_through(argument, source_code, name)
+                })
+        }
+        "conditional_expression" => ["consequence", "alternative"].iter().any(|field| {
+            value
+                .child_by_field_name(field)
+                .is_some_and(|branch| escapes(branch, source_code, name))
+        }),
+        _ => points_to(value, source_code, name),
+    }
+}
+
+/// Whether an expression evaluates to `name`, maybe offset or cast (`p`, `(char *)p + 1`)
+fn points_to(expression: Node, source_code: &[u8], name: &str) -> bool {
+    let expression = strip_casts(expression);
+    match expression.kind() {
+        "identifier" => expression.utf8_text(source_code) == Ok(name),
+        "binary_expression" => {
+            let operator = expression.child_by_field_name("operator").and_then(|op| op.utf8_text(source_code).ok());
+            matches!(operator, Some("+" | "-"))
+                && ["left", "right"].iter().any(|field| {
+                    expression
+                        .child_by_field_name(field)
+                        .is_some_and(|operand| points_to(operand, source_code, name))
+                })
+        }
+        _ => false,
+    }
+}
+
+/// The expression inside any number of parentheses and casts: `(char *)(p)` is `p`
+fn strip_casts(node: Node) -> Node {
+    let mut current = strip_parentheses(node);
+    while current.kind() == "cast_expression" {
+        match current.child_by_field_name("value") {
+            Some(value) => current = strip_parentheses(value),
+            None => break,
+        }
+    }
+    current
+}
+
+/// The expression inside any number of parentheses: `((x))` is `x`
+fn strip_parentheses(node: Node) -> Node {
+    let mut current = node;
+    while current.kind() == "parenthesized_expression" {
+        match current.named_child(0) {
+            Some(inner) => current = inner,
+            None => break,
+        }
+    }
+    current
+}
+
+/// Counts parameters that pass a struct or union by value instead of through a pointer
+///
+/// Typedef'd aggregates are recognized from `typedef struct`/`typedef union` definitions
+/// in the same file, e.g. `void f(struct Config cfg)` counts 1 while
+/// `void f(const struct Config *cfg)` counts 0. `aggregates` is the file's
+/// `find_aggregate_typedefs`, built once for all its functions.
+pub fn calculate_large_value_param_count(node: Node, source_code: &[u8], aggregates: &HashSet<String>) -> u32 {
+    find_signature_node(node)
+        .and_then(find_function_declarator)
+        .map(|declarator| count_large_value_params(declarator, source_code, aggregates))
+        .unwrap_or(0)
+}
+
+fn count_large_value_params(declarator: Node, source_code: &[u8], aggregates: &HashSet<String>) -> u32 {
+    let Some(parameters) = declarator.child_by_field_name("parameters") else {
+        return 0;
+    };
+
+    let mut cursor = parameters.walk();
+    let count = parameters
+        .children(&mut cursor)
+        .filter(|param| param.kind() == "parameter_declaration")
+        .filter(|param| is_large_value_param(*param, source_code, aggregates))
+        .count();
+    count as u32
+}
+
+fn is_large_value_param(param: Node, source_code: &[u8], aggregates: &HashSet<String>) -> bool {
+    // Pointers and arrays (which decay to pointers) are passed by reference
+    if let Some(declarator) = param.child_by_field_name("declarator") {
+        if matches!(
+            declarator.kind(),
+            "pointer_declarator" | "abstract_pointer_declarator" | "array_declarator" | "abstract_array_declarator"
+                | "function_declarator" | "abstract_function_declarator"
+        ) {
+            return false;
+        }
+    }
+
+    match param.child_by_field_name("type") {
+        Some(type_node) if matches!(type_node.kind(), "struct_specifier" | "union_specifier") => true,
+        Some(type_node) if type_node.kind() == "type_identifier" => type_node
+            .utf8_text(source_code)
+            .map(|name| aggregates.contains(name))
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Names introduced by typedefs of structs and unions (or of other such typedefs) at the
+/// top level of a file; build it once per file and pass it to the per-function metrics
+pub fn find_aggregate_typedefs(root: Node, source_code: &[u8]) -> HashSet<String> {
+    let mut aggregates = HashSet::new();
+    let mut cursor = root.walk();
+    for definition in root.children(&mut cursor) {
+        if definition.kind() != "type_definition" {
+            continue;
+        }
+        let is_aggregate = match definition.child_by_field_name("type") {
+            Some(type_node) if matches!(type_node.kind(), "struct_specifier" | "union_specifier") => true,
+            Some(type_node) if type_node.kind() == "type_identifier" => type_node
+                .utf8_text(source_code)
+                .map(|name| aggregates.contains(name))
+                .unwrap_or(false),
+            _ => false,
+        };
+        let name = definition
+            .child_by_field_name("declarator")
+            .filter(|declarator| declarator.kind() == "type_identifier")
+            .and_then(|declarator| declarator.utf8_text(source_code).ok());
+        if let (true, Some(name)) = (is_aggregate, name) {
+            aggregates.insert(name.to_string());
+        }
+    }
+    aggregates
+}
+
+/// `find_aggregate_typedefs` for the file containing `node`, for callers measuring a
+/// single function
+fn aggregate_typedefs_around(node: Node, source_code: &[u8]) -> HashSet<String> {
+    let mut root = node;
+    while let Some(parent) = root.parent() {
+        root = parent;
+    }
+    find_aggregate_typedefs(root, source_code)
+}
+
+fn analyze_parameters(declarator: Node, source_code: &[u8], aggregates: &HashSet<String>) -> u32 {
+    let mut param_count = 0;
+    let mut has_pointer = false;
+    let mut has_const_pointer = false;
+    let mut has_void_ptr = false;
+    let mut has_variadic = false;
+
+    // Find parameter list
+    let mut cursor = declarator.walk();
+    for child in declarator.children(&mut cursor) {
+        if child.kind() == "parameter_list" {
+            let mut param_cursor = child.walk();
+            for param in child.children(&mut param_cursor) {
+                if param.kind() == "parameter_declaration" {
+                    param_count += 1;
+
+                    // Function pointers score as pointers here and add their indirection
+                    // depth in calculate_signature_complexity
+                    let indirection = param
+                        .child_by_field_name("declarator")
+                        .map(parameter_indirection)
+                        .unwrap_or_default();
+                    let is_void = param
+                        .child_by_field_name("type")
+                        .map(|type_node| type_node.kind() == "primitive_type" && type_node.utf8_text(source_code) == Ok("void"))
+                        .unwrap_or(false);
+                    if indirection.function_pointer {
+                        has_pointer = true;
+                    } else if indirection.pointer_levels > 0 && is_void {
+                        has_void_ptr = true;
+                    } else if indirection.pointer_levels == 1 && is_read_only(param, source_code) {
+                        // The function can only read through it, so callers need not check it afterwards
+                        has_const_pointer = true;
+                    } else if indirection.pointer_levels > 0 {
+                        has_pointer = true;
+                    }
+                } else if param.kind() == "variadic_parameter" {
+                    has_variadic = true;
+                }
+            }
+        }
+    }
+
+    // Each struct or union copied by value adds to the setup burden
+    let large_value_params = count_large_value_params(declarator, source_code, aggregates);
+
+    // Score based on complexity
+    let score = if has_void_ptr || has_variadic {
+        10
+    } else if has_pointer && param_count > 1 {
+        8
+    } else if has_pointer {
+        6
+    } else if has_const_pointer && param_count > 1 {
+        5
+    } else if has_const_pointer {
+        3
+    } else if param_count > 1 {
+        4
+    } else if param_count == 1 {
+        2
+    } else {
+        0
+    };
+    score + 2 * large_value_params
+}
+
+/// Signature score added for a `volatile` return, typically a hardware register read
+const VOLATILE_RETURN_WEIGHT: u32 = 4;
+
+fn analyze_return_type(function: Node, type_node: Node, source_code: &[u8]) -> u32 {
+    // In `int *f(void)` the `*` belongs to the declarator, not the type
+    let returns_pointer = function
+        .child_by_field_name("declarator")
+        .map(|declarator| declarator.kind() == "pointer_declarator")
+        .unwrap_or(false);
+
+    let score = match type_node.kind() {
+        "primitive_type" if !returns_pointer && type_node.utf8_text(source_code) == Ok("void") => 0,
+        "struct_specifier" | "union_specifier" => 10,
+        _ if returns_pointer => 6,
+        "enum_specifier" => 4,
+        _ => 2,
+    };
+    if has_type_qualifier(function, source_code, "volatile") {
+        score + VOLATILE_RETURN_WEIGHT
+    } else {
+        score
+    }
+}
+
+/// How a parameter's declarator reaches its name
+#[derive(Debug, Clone, Copy, Default)]
+struct ParameterIndirection {
+    /// `*` levels before the name or the function declarator
+    pointer_levels: u32,
+    /// The parameter is a function pointer, like `void (*cb)(int)`
+    function_pointer: bool,
+}
+
+fn parameter_indirection(declarator: Node) -> ParameterIndirection {
+    let mut indirection = ParameterIndirection::default();
+    let mut current = Some(declarator);
+    while let Some(node) = current {
+        current = match node.kind() {
+            "pointer_declarator" | "abstract_pointer_declarator" => {
+                indirection.pointer_levels += 1;
+                node.child_by_field_name("declarator")
+            }
+            "function_declarator" | "abstract_function_declarator" => {
+                indirection.function_pointer = true;
+                None
+            }
+            "parenthesized_declarator" | "abstract_parenthesized_declarator" => node.named_child(0),
+            "attributed_declarator" => attributed_target(node),
+            _ => None,
+        };
+    }
+    indirection
+}
+
+/// Whether a declaration's own `type_qualifier` nodes include `keyword`
+///
+/// Only qualifiers on the declaration itself are seen: in `const char *p` the `const`
+/// applies to the pointee, while in `char *const p` it sits on the pointer declarator.
+fn has_type_qualifier(declaration: Node, source_code: &[u8], keyword: &str) -> bool {
+    let mut cursor = declaration.walk();
+    let found = declaration
+        .children(&mut cursor)
+        .filter(|child| child.kind() == "type_qualifier")
+        .any(|qualifier| qualifier.utf8_text(source_code) == Ok(keyword));
+    found
+}
+
+/// A pointer parameter whose pointee is `const` and not `volatile`
+fn is_read_only(param: Node, source_code: &[u8]) -> bool {
+    has_type_qualifier(param, source_code, "const") && !has_type_qualifier(param, source_code, "volatile")
+}
+
+/// A value to test a parameter with, at the edge of a comparison against a constant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoundaryValue {
+    pub parameter: String,
+    /// Argument to pass, e.g. "15", "MAX_LEN + 1", or "NULL"
+    pub value: String,
+    /// The comparison it probes, as written, e.g. "len < 16"
+    pub condition: String,
+}
+
+/// Values on either side of each comparison between a parameter and a constant, in
+/// order of first appearance and without repeats
+///
+/// `n < 16` and `n >= 16` give 15 and 16, `n <= 16` and `n > 16` give 16 and 17, and
+/// `==`/`!=` give the constant itself. Constants are number, character, and boolean
+/// literals, `NULL`, and all-caps macro names, for which `MAX - 1` is spelled out.
+pub fn find_boundary_values(node: Node, source_code: &[u8]) -> Vec<BoundaryValue> {
+    let parameters = parameter_names(node, source_code);
+    let mut values = Vec::new();
+    if let Some(body) = node.child_by_field_name("body") {
+        visit_node_boundaries(body, source_code, &parameters, &mut values);
+    }
+    values
+}
+
+fn visit_node_boundaries(node: Node, source_code: &[u8], parameters: &[&str], values: &mut Vec<BoundaryValue>) {
+    if let Some((parameter, operator, constant)) = parameter_comparison(node, source_code, parameters) {
+        let offsets: &[i64] = match operator {
+            "<" | ">=" => &[-1, 0],
+            "<=" | ">" => &[0, 1],
+            _ => &[0],
+        };
+        for &offset in offsets {
+            let value = offset_constant(constant, offset);
+            if !values.iter().any(|known| known.parameter == parameter && known.value == value) {
+                values.push(BoundaryValue {
+                    parameter: parameter.to_string(),
+                    value,
+                    condition: node.utf8_text(source_code).unwrap_or_default().to_string(),
+                });
+            }
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_boundaries(child, source_code, parameters, values);
+    }
+}
+
+/// (parameter, operator, constant) for a comparison like `n < 16`; `16 > n` is turned
+/// around to read `n < 16`
+fn parameter_comparison<'a>(node: Node, source_code: &'a [u8], parameters: &[&str]) -> Option<(&'a str, &'static str, &'a str)> {
+    if node.kind() != "binary_expression" {
+        return None;
+    }
+    let operator = node.child_by_field_name("operator")?.utf8_text(source_code).ok()?;
+    let (operator, flipped) = match operator {
+        "<" => ("<", ">"),
+        "<=" => ("<=", ">="),
+        ">" => (">", "<"),
+        ">=" => (">=", "<="),
+        "==" => ("==", "=="),
+        "!=" => ("!=", "!="),
+        _ => return None,
+    };
+    let left = strip_parentheses(node.child_by_field_name("left")?);
+    let right = strip_parentheses(node.child_by_field_name("right")?);
+    let parameter = |side: Node| {
+        let name = side.utf8_text(source_code).ok()?;
+        (side.kind() == "identifier" && parameters.contains(&name)).then_some(name)
+    };
+    let constant = |side: Node| is_constant(side, source_code).then(|| side.utf8_text(source_code).ok()).flatten();
+
+    match (parameter(left), constant(right)) {
+        (Some(name), Some(value)) => Some((name, operator, value)),
+        _ => Some((parameter(right)?, flipped, constant(left)?)),
+    }
+}
+
+fn is_constant(node: Node, source_code: &[u8]) -> bool {
+    match node.kind() {
+        "number_literal" | "char_literal" | "null" | "true" | "false" => true,
+        "identifier" => node.utf8_text(source_code).is_ok_and(|name| {
+            name.chars().any(|c| c.is_ascii_uppercase())
+                && name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+        }),
+        _ => false,
+    }
+}
+
+/// `constant + offset`, computed for integer literals and spelled out for anything else
+fn offset_constant(constant: &str, offset: i64) -> String {
+    if offset == 0 {
+        return constant.to_string();
+    }
+    let digits = constant.trim_end_matches(['u', 'U', 'l', 'L']);
+    let parsed = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => digits.parse::<i64>().ok(),
+    };
+    match parsed {
+        Some(value) => (value + offset).to_string(),
+        None if offset < 0 => format!("{} - {}", constant, -offset),
+        None => format!("{} + {}", constant, offset),
+    }
+}
+
+/// External dependencies and side effects found in a function body
+#[derive(Debug, Clone, Copy, Default)]
+struct DependencyFlags {
+    has_io: bool,
+    has_allocation: bool,
+    has_system_calls: bool,
+    modifies_globals: bool,
+    has_static_locals: bool,
+}
+
+impl DependencyFlags {
+    /// Records the dependencies a single node introduces
+    fn note(&mut self, node: Node, source_code: &[u8]) {
+        if node.kind() == "call_expression" {
+            if let Some(function) = node.child_by_field_name("function") {
+                if let Ok(func_name) = function.utf8_text(source_code) {
+                    // File I/O functions
+                    if matches!(func_name, "fopen" | "fclose" | "fread" | "fwrite" | "fprintf" |
+                               "fscanf" | "fgets" | "fputs" | "fseek" | "ftell" | "rewind" |
+                               "printf" | "scanf" | "puts" | "getc" | "putc") {
+                        self.has_io = true;
+                    }
+
+                    // Memory allocation
+                    if is_allocator(func_name) || func_name == "free" {
+                        self.has_allocation = true;
+                    }
+
+                    // System calls
+                    if matches!(func_name, "time" | "clock" | "rand" | "srand" | "getpid" |
+                               "fork" | "exec" | "system" | "signal" | "kill" | "wait" | "pipe") {
+                        self.has_system_calls = true;
+                    }
+                }
+            }
+        }
+
+        // Static locals carry hidden state from one call to the next
+        if is_static_local(node, source_code) {
+            self.has_static_locals = true;
+        }
+
+        // Check for global variable modifications (simplified - looks for assignments to identifiers)
+        if node.kind() == "assignment_expression" {
+            if let Some(left) = node.child_by_field_name("left") {
+                if left.kind() == "identifier" {
+                    // Heuristic: if identifier doesn't start with lowercase, might be global
+                    if let Ok(name) = left.utf8_text(source_code) {
+                        if !name.is_empty() && name.chars().next().unwrap().is_uppercase() {
+                            self.modifies_globals = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Dependency and side effect score (0-10)
+    fn score(&self) -> u32 {
+        let mut score = 0;
+
+        // Check for global state access (simplified heuristic)
+        if self.modifies_globals {
+            score += 6;
+        }
+
+        // Hidden state between calls: tests depend on call order
+        if self.has_static_locals {
+            score += 3;
+        }
+
+        // I/O operations
+        if self.has_io {
+            score += 2;
+        }
+
+        // Memory allocation
+        if self.has_allocation {
+            score += 3;
+        }
+
+        // System calls
+        if self.has_system_calls {
+            score += 2;
+        }
+
+        score.min(10)
+    }
+}
+
+fn visit_node_dependencies(node: Node, source_code: &[u8], flags: &mut DependencyFlags) {
+    flags.note(node, source_code);
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_dependencies(child, source_code, flags);
+    }
+}
+
+/// Calculates observable behavior score (how easy to verify correctness)
+fn calculate_observable_behavior_score(node: Node, source_code: &[u8], flags: &ObservabilityFlags) -> u32 {
+    let mut score = 0;
+
+    // A noreturn function's only outcome is ending the process or jumping away, which a
+    // test can only catch with a death test or a longjmp-ing stub
+    if is_noreturn_function(node, source_code) {
+        score += 6;
+    } else if let Some(type_node) = find_signature_node(node).and_then(|function| function.child_by_field_name("type")) {
+        let type_text = type_node.utf8_text(source_code).unwrap_or("");
+        if type_text.contains("void") && !type_text.contains('*') {
+            score += 4;
+        }
+    }
+
+    // Check for I/O, randomness, time dependencies
+    if flags.has_io {
+        score += 2;
+    }
+    if flags.has_random {
+        score += 3;
+    }
+    if flags.has_time {
+        score += 2;
+    }
+
+    score.min(10)
+}
+
+/// Calls that make a function's behavior hard to observe in a test
+#[derive(Debug, Clone, Copy, Default)]
+struct ObservabilityFlags {
+    has_io: bool,
+    has_random: bool,
+    has_time: bool,
+}
+
+impl ObservabilityFlags {
+    /// Records whether a single node is an I/O, random, or time call
+    fn note(&mut self, node: Node, source_code: &[u8]) {
+        if node.kind() == "call_expression" {
+            if let Some(function) = node.child_by_field_name("function") {
+                if let Ok(func_name) = function.utf8_text(source_code) {
+                    if matches!(func_name, "fopen" | "fclose" | "fread" | "fwrite" | "fprintf" |
+                               "printf" | "scanf" | "puts") {
+                        self.has_io = true;
+                    }
+                    if matches!(func_name, "rand" | "srand" | "random") {
+                        self.has_random = true;
+                    }
+                    if matches!(func_name, "time" | "clock" | "gettimeofday") {
+                        self.has_time = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn visit_node_observability(node: Node, source_code: &[u8], flags: &mut ObservabilityFlags) {
+    flags.note(node, source_code);
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_observability(child, source_code, flags);
+    }
+}
+
+/// The comment right before a function definition, if any: the block the documentation
+/// score rates and suppression markers are read from
+pub fn preceding_comment<'a>(node: Node, source_code: &'a [u8]) -> Option<&'a str> {
+    node.prev_sibling()
+        .filter(|sibling| sibling.kind() == "comment")
+        .and_then(|comment| comment.utf8_text(source_code).ok())
+}
+
+/// Marker in the comment before a function that silences some or all of its --max-* gates
+const ALLOW_MARKER: &str = "knots:allow";
+/// Marker in the comment before a function that silences all of its --max-* gates
+const DISABLE_NEXT_LINE_MARKER: &str = "knots-disable-next-line";
+/// Suppression covering every gate
+pub const SUPPRESS_ALL: &str = "all";
+
+/// Gates silenced by the comment right before a function, found with the same lookup as
+/// the documentation score
+///
+/// `// knots-disable-next-line`, or `knots:allow` alone, gives `["all"]`, while
+/// `/* knots:allow mccabe, sloc */` gives the listed names, lowercased.
+pub fn find_suppressions(node: Node, source_code: &[u8]) -> Vec<String> {
+    let Some(comment) = preceding_comment(node, source_code) else {
+        return Vec::new();
+    };
+    if comment.contains(DISABLE_NEXT_LINE_MARKER) {
+        return vec![SUPPRESS_ALL.to_string()];
+    }
+    let Some(start) = comment.find(ALLOW_MARKER) else {
+        return Vec::new();
+    };
+    let names: Vec<String> = comment[start + ALLOW_MARKER.len()..]
+        .trim_end_matches("*/")
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|name| !name.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    if names.is_empty() {
+        vec![SUPPRESS_ALL.to_string()]
+    } else {
+        names
+    }
+}
+
+/// Calculates documentation quality score (higher is better, reduces total difficulty)
+fn calculate_documentation_score(node: Node, source_code: &[u8]) -> i32 {
+    let mut score = 0;
+
+    // Look for comment before the function
+    if let Some(comment_text) = preceding_comment(node, source_code) {
+        // Check for Doxygen-style documentation
+        if comment_text.contains("/**") || comment_text.contains("///") {
+            score += 4; // Base documentation
+
+            // Check for specific Doxygen tags
+            if comment_text.contains("@intent") {
+                score += 5;
+            }
+            if comment_text.contains("@param") {
+                score += 2;
+            }
+            if comment_text.contains("@return") {
+                score += 2;
+            }
+            if comment_text.contains("@requires") {
+                score += 2;
+            }
+            if comment_text.contains("@ensures") {
+                score += 2;
+            }
+            if comment_text.contains("@side_effects") {
+                score += 2;
+            }
+            if comment_text.contains("@example") {
+                score += 3;
+            }
+            if comment_text.contains("@edge_cases") {
+                score += 2;
+            }
+            if comment_text.contains("@complexity") {
+                score += 2;
+            }
+        } else if comment_text.starts_with("//") || comment_text.starts_with("/*") {
+            score += 2; // Basic comment
+        }
+    }
+
+    score.min(10)
+}
+
+/// Finds the function declarator holding a function's own parameter list
+///
+/// Follows the `declarator` fields through pointer, parenthesized, and attributed
+/// declarators, so attributes, asm labels, and section macros placed before or after
+/// the parameter list are skipped. For declarations like
+/// `void (*signal(int, void (*)(int)))(int)` the innermost declarator is returned.
+pub fn find_function_declarator(node: Node) -> Option<Node> {
+    let mut current = node.child_by_field_name("declarator")?;
+    let mut function_declarator = None;
+
+    loop {
+        match current.kind() {
+            "function_declarator" => {
+                function_declarator = Some(current);
+                current = current.child_by_field_name("declarator")?;
+            }
+            "pointer_declarator" => {
+                current = current.child_by_field_name("declarator")?;
+            }
+            "attributed_declarator" => current = attributed_target(current)?,
+            "parenthesized_declarator" => {
+                let mut cursor = current.walk();
+                let inner = current
+                    .named_children(&mut cursor)
+                    .find(|child| !matches!(child.kind(), "attribute_specifier" | "ms_call_modifier"));
+                current = inner?;
+            }
+            "identifier" | "field_identifier" => return function_declarator,
+            _ => return None,
+        }
+    }
+}
+
+/// The declarator a C23 `[[attribute]]` is attached to, as in `int f [[maybe_unused]] (void)`
+///
+/// Unlike the other declarator kinds, `attributed_declarator` has no `declarator` field;
+/// the wrapped declarator is its one named child that is not an `attribute_declaration`.
+fn attributed_target(node: Node) -> Option<Node> {
+    let mut cursor = node.walk();
+    let target = node
+        .named_children(&mut cursor)
+        .find(|child| child.kind() != "attribute_declaration");
+    target
+}
+
+/// Extracts the name of a function definition or prototype
+pub fn get_function_name(node: Node, source_code: &[u8]) -> Option<String> {
+    let declarator = find_function_declarator(node)?;
+    let mut name = declarator.child_by_field_name("declarator")?;
+    if name.kind() == "attributed_declarator" {
+        name = attributed_target(name)?;
+    }
+    Some(name.utf8_text(source_code).ok()?.to_string())
+}
+
+/// A function-like macro whose replacement text has been wrapped in a function for analysis
+#[derive(Debug, Clone, PartialEq)]
+pub struct MacroFunction {
+    pub name: String,
+    /// Line of the `#define` (1-based)
+    pub line: usize,
+    /// `void NAME(void) { <replacement text> ;}`, keeping the replacement's line breaks
+    pub code: String,
+}
+
+/// Collects function-like macros (`#define NAME(args) ...`) with a non-empty replacement
+///
+/// tree-sitter parses a macro body as an opaque token sequence, so each body is wrapped
+/// in a function that can be re-parsed and measured like any other. The macro's
+/// parameters become undeclared identifiers, which parse the same as variables.
+pub fn find_function_like_macros(root: Node, source_code: &[u8]) -> Vec<MacroFunction> {
+    let mut macros = Vec::new();
+    visit_node_macros(root, source_code, &mut macros);
+    macros
+}
+
+fn visit_node_macros(node: Node, source_code: &[u8], macros: &mut Vec<MacroFunction>) {
+    if node.kind() == "preproc_function_def" {
+        let name = node.child_by_field_name("name").and_then(|name| name.utf8_text(source_code).ok());
+        let value = node.child_by_field_name("value").and_then(|value| value.utf8_text(source_code).ok());
+        if let (Some(name), Some(value)) = (name, value) {
+            if !value.trim().is_empty() {
+                macros.push(MacroFunction {
+                    name: name.to_string(),
+                    line: node.start_position().row + 1,
+                    code: format!("void {}(void) {{ {} ;}}\n", name, value.replace("\\\n", "\n")),
+                });
+            }
+        }
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_macros(child, source_code, macros);
+    }
+}
+
+/// Checks whether a function definition returns a value (anything but plain `void`)
+/// A pointer declarator around the function (`void *f(void)`) makes it non-void.
+/// Definitions without a type (K&R implicit int) are treated as not returning a value.
+pub fn returns_value(node: Node, source_code: &[u8]) -> bool {
+    let Some(type_node) = node.child_by_field_name("type") else {
+        return false;
+    };
+    if type_node.utf8_text(source_code) != Ok("void") {
+        return true;
+    }
+
+    let mut current = node.child_by_field_name("declarator");
+    while let Some(declarator) = current {
+        match declarator.kind() {
+            "pointer_declarator" => return true,
+            "function_declarator" => return false,
+            _ => current = declarator.child_by_field_name("declarator").or_else(|| declarator.named_child(0)),
+        }
+    }
+    false
+}
+
+/// Lines of `return` statements that disagree with the declared return type:
+/// a bare `return;` in a non-void function, or `return value;` in a void one.
+/// The return type of a noreturn function means nothing, so none are reported there.
+pub fn find_inconsistent_returns(node: Node, source_code: &[u8]) -> Vec<usize> {
+    if is_noreturn_function(node, source_code) {
+        return Vec::new();
+    }
+    let expects_value = returns_value(node, source_code);
+    let mut lines = Vec::new();
+    visit_node_inconsistent_returns(node, expects_value, &mut lines);
+    lines
+}
+
+fn visit_node_inconsistent_returns(node: Node, expects_value: bool, lines: &mut Vec<usize>) {
+    if node.kind() == "return_statement" {
+        if returns_with_value(node) != expects_value {
+            lines.push(node.start_position().row + 1);
+        }
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_inconsistent_returns(child, expects_value, lines);
+    }
+}
+
+fn returns_with_value(return_statement: Node) -> bool {
+    let mut cursor = return_statement.walk();
+    let has_value = return_statement.named_children(&mut cursor).any(|child| child.kind() != "comment");
+    has_value
+}
+
+/// Checks whether a function is declared as never returning
+/// (`_Noreturn`, `noreturn`, `__attribute__((noreturn))`, `[[noreturn]]`, `__declspec(noreturn)`)
+/// Only the specifiers and attributes of the definition count, not names that merely contain "noreturn"
+pub fn is_noreturn_function(node: Node, source_code: &[u8]) -> bool {
+    let is_noreturn = |name: Node| matches!(name.utf8_text(source_code), Ok("_Noreturn" | "noreturn" | "__noreturn__"));
+    let mut cursor = node.walk();
+    let is_noreturn_fn = node.children(&mut cursor).any(|child| {
+        let mut cursor = child.walk();
+        match child.kind() {
+            "storage_class_specifier" | "type_qualifier" => is_noreturn(child),
+            // __attribute__((cold, noreturn))
+            "attribute_specifier" => child
+                .children(&mut cursor)
+                .filter(|arguments| arguments.kind() == "argument_list")
+                .any(|arguments| {
+                    let mut cursor = arguments.walk();
+                    let found = arguments.named_children(&mut cursor).any(is_noreturn);
+                    found
+                }),
+            // [[noreturn]], [[gnu::noreturn]]
+            "attribute_declaration" => child
+                .named_children(&mut cursor)
+                .filter_map(|attribute| attribute.child_by_field_name("name"))
+                .any(is_noreturn),
+            // __declspec(noreturn)
+            "ms_declspec_modifier" => child.named_children(&mut cursor).any(is_noreturn),
+            _ => false,
+        }
+    });
+    is_noreturn_fn
+}
+
+/// Flags non-void functions without any `return` statement, which fall off the end
+/// `main` (implicitly returns 0) and functions declared noreturn are exempt.
+pub fn is_missing_return(node: Node, source_code: &[u8]) -> bool {
+    returns_value(node, source_code)
+        && calculate_return_count(node) == 0
+        && !is_noreturn_function(node, source_code)
+        && get_function_name(node, source_code).as_deref() != Some("main")
+}
+
+/// Syntax errors tree-sitter recovered from while parsing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseErrors {
+    /// Number of `ERROR` and `MISSING` nodes
+    pub error_nodes: usize,
+    /// Bytes of source covered by `ERROR` nodes
+    pub error_bytes: usize,
+}
+
+/// Counts the `ERROR`/`MISSING` nodes in a tree and how much source they cover
+pub fn count_parse_errors(root: Node) -> ParseErrors {
+    let mut errors = ParseErrors::default();
+    if root.has_error() {
+        visit_node_parse_errors(root, &mut errors, false);
+    }
+    errors
+}
+
+fn visit_node_parse_errors(node: Node, errors: &mut ParseErrors, inside_error: bool) {
+    let is_error = node.is_error();
+    if is_error || node.is_missing() {
+        errors.error_nodes += 1;
+        if is_error && !inside_error {
+            errors.error_bytes += node.end_byte() - node.start_byte();
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if child.has_error() || child.is_missing() {
+            visit_node_parse_errors(child, errors, inside_error || is_error);
+        }
+    }
+}
+
+/// Checks whether a function definition has `static` storage
+pub fn is_static_function(node: Node, source_code: &[u8]) -> bool {
+    let mut cursor = node.walk();
+    let is_static = node
+        .children(&mut cursor)
+        .any(|child| child.kind() == "storage_class_specifier" && child.utf8_text(source_code) == Ok("static"));
+    is_static
+}
+
+/// Collects the names of functions declared with a prototype, e.g. the public API in a header
+/// Function definitions and typedefs are not included
+pub fn find_declared_functions(root: Node, source_code: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    visit_node_declarations(root, source_code, &mut names);
+    names
+}
+
+fn visit_node_declarations(node: Node, source_code: &[u8], names: &mut Vec<String>) {
+    match node.kind() {
+        "declaration" => {
+            names.extend(get_function_name(node, source_code));
+            return;
+        }
+        "function_definition" | "type_definition" => return,
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_declarations(child, source_code, names);
+    }
+}
+
+/// A function prototype's test-difficulty scores, from its declaration alone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrototypeScore {
+    pub name: String,
+    /// Line of the declaration (1-based)
+    pub line: usize,
+    /// Parameters and return type (0-10), as in the test score of a definition
+    pub signature_score: u32,
+    /// Credit for the comment before the declaration (0-10)
+    pub documentation_score: i32,
+}
+
+impl PrototypeScore {
+    /// Signature score less documentation credit: the part of the test score a header shows
+    pub fn difficulty(&self) -> i32 {
+        self.signature_score as i32 - self.documentation_score
+    }
+}
+
+/// Scores every function prototype (a declaration without a body), e.g. a header's API
+///
+/// Declarations inside `#if` blocks and `extern "C"` wrappers are included; function
+/// definitions, typedefs, and declarations inside function bodies are not.
+pub fn find_prototypes(root: Node, source_code: &[u8]) -> Vec<PrototypeScore> {
+    let mut prototypes = Vec::new();
+    let aggregates = find_aggregate_typedefs(root, source_code);
+    visit_node_prototypes(root, source_code, &aggregates, &mut prototypes);
+    prototypes
+}
+
+fn visit_node_prototypes(
+    node: Node,
+    source_code: &[u8],
+    aggregates: &HashSet<String>,
+    prototypes: &mut Vec<PrototypeScore>,
+) {
+    match node.kind() {
+        "declaration" => {
+            if let Some(name) = get_function_name(node, source_code) {
+                prototypes.push(PrototypeScore {
+                    name,
+                    line: node.start_position().row + 1,
+                    signature_score: calculate_signature_complexity(node, source_code, aggregates),
+                    documentation_score: calculate_documentation_score(node, source_code),
+                });
+            }
+            return;
+        }
+        "function_definition" | "type_definition" => return,
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_node_prototypes(child, source_code, aggregates, prototypes);
+    }
+}
+
+/// Finds `static` functions that are never referenced anywhere else in the file
+///
+/// Runs in two passes: the first collects every static function definition, the
+/// second gathers every referenced identifier in a single walk, skipping a
+/// function's own name inside its body. The names in prototypes and definitions
+/// are not references; calls and uses such as taking the function's address are.
+pub fn find_unused_static_functions(root: Node, source_code: &[u8]) -> Vec<String> {
+    let mut statics = Vec::new();
+    collect_static_functions(root, source_code, &mut statics);
+
+    let mut references = HashSet::new();
+    collect_references(root, source_code, None, &mut references);
+
+    statics.into_iter().filter(|name| !references.contains(name)).collect()
+}
+
+fn collect_static_functions(node: Node, source_code: &[u8], statics: &mut Vec<String>) {
+    if node.kind() == "function_definition" && is_static_function(node, source_code) {
+        statics.extend(get_function_name(node, source_code));
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_static_functions(child, source_code, statics);
+    }
+}
+
+/// Adds every identifier used as a reference; `enclosing` is the function whose body is being walked,
+/// so recursion does not count as a use
+fn collect_references(node: Node, source_code: &[u8], enclosing: Option<&str>, references: &mut HashSet<String>) {
+    let name = if node.kind() == "function_definition" { get_function_name(node, source_code) } else { None };
+    let enclosing = name.as_deref().or(enclosing);
+
+    if node.kind() == "identifier" {
+        let is_declarator_name = node
+            .parent()
+            .map(|parent| parent.kind() == "function_declarator")
+            .unwrap_or(false);
+        if let Ok(text) = node.utf8_text(source_code) {
+            if !is_declarator_name && enclosing != Some(text) {
+                references.insert(text.to_string());
+            }
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_references(child, source_code, enclosing, references);
+    }
+}
+
+/// Options for every metric computed by `calculate_all_metrics_with_options`
+#[derive(Debug, Clone, Default)]
+pub struct MetricOptions {
+    pub mccabe: McCabeOptions,
+    pub cognitive: CognitiveOptions,
+    pub test_scoring: TestScoringOptions,
+    /// Use `calculate_abc_weighted` instead of the canonical ABC count
+    pub abc_weighted: bool,
+}
+
+/// The core metrics of one function, as computed by the individual `calculate_*` functions
+#[derive(Debug, Clone, Copy)]
+pub struct AllMetrics {
+    pub mccabe: u32,
+    pub cognitive: u32,
+    pub nesting: u32,
+    pub sloc: u32,
+    pub abc: AbcComplexity,
+    pub return_count: u32,
+    pub shape: ControlFlowShape,
+    pub test_scoring: TestScoringMetric,
+}
+
+/// Calculates McCabe, cognitive, nesting, SLOC, ABC, return count, loop/branch shape,
+/// and test scoring in a single traversal of the function
+pub fn calculate_all_metrics(node: Node, source_code: &[u8]) -> AllMetrics {
+    let aggregates = aggregate_typedefs_around(node, source_code);
+    calculate_all_metrics_with_options(node, source_code, &MetricOptions::default(), &aggregates)
+}
+
+/// Single-pass equivalent of calling each metric function with the given options
+///
+/// `aggregates` is the file's `find_aggregate_typedefs`, built once for all its functions.
+pub fn calculate_all_metrics_with_options(
+    node: Node,
+    source_code: &[u8],
+    options: &MetricOptions,
+    aggregates: &HashSet<String>,
+) -> AllMetrics {
+    let mut walker = MetricsWalker {
+        source_code,
+        options,
+        root_id: node.id(),
+        mccabe: 1,
+        test_mccabe: 1,
+        cognitive: 0,
+        nesting: 0,
+        abc: AbcComplexity {
+            assignments: 0,
+            branches: 0,
+            conditions: 0,
+        },
+        return_count: 0,
+        shape: ControlFlowShape::default(),
+        inline_docs: 0,
+        dependencies: DependencyFlags::default(),
+        observability: ObservabilityFlags::default(),
+    };
+    walker.visit(node, WalkState::default());
+
+    AllMetrics {
+        mccabe: walker.mccabe,
+        cognitive: walker.cognitive,
+        nesting: walker.nesting,
+        sloc: calculate_sloc(node, source_code),
+        abc: walker.abc,
+        return_count: walker.return_count,
+        shape: walker.shape,
+        test_scoring: test_scoring_from_parts(
+            node,
+            source_code,
+            &walker.dependencies,
+            &walker.observability,
+            walker.test_mccabe,
+            walker.inline_docs,
+            aggregates,
+        ),
+    }
+}
+
+/// Running totals for `calculate_all_metrics`
+struct MetricsWalker<'a> {
+    source_code: &'a [u8],
+    options: &'a MetricOptions,
+    /// The measured node, which is never nested in itself
+    root_id: usize,
+    mccabe: u32,
+    /// McCabe with default options, which test scoring always uses
+    test_mccabe: u32,
+    cognitive: u32,
+    nesting: u32,
+    abc: AbcComplexity,
+    return_count: u32,
+    shape: ControlFlowShape,
+    /// Inline doc comments, counted only when crediting them
+    inline_docs: u32,
+    dependencies: DependencyFlags,
+    observability: ObservabilityFlags,
+}
+
+/// Per-node context passed down the single-pass walk
+#[derive(Debug, Clone, Copy, Default)]
+struct WalkState<'a> {
+    /// Nesting level for cognitive increments
+    cognitive_nesting: u32,
+    /// Enclosing `&&`/`||` operator, so a sequence of the same operator counts once
+    binary_op: Option<&'a str>,
+    /// An `if` directly inside an `else`, already counted by the else clause
+    else_if: bool,
+    /// Depth for the maximum nesting metric
+    depth: u32,
+    /// Nesting level for weighted ABC conditions
+    abc_nesting: u32,
+}
+
+impl<'a> MetricsWalker<'a> {
+    fn visit(&mut self, node: Node, state: WalkState<'a>) {
+        let source_code = self.source_code;
+
+        self.mccabe += mccabe_decisions(node, source_code, &self.options.mccabe);
+        self.test_mccabe += mccabe_decisions(node, source_code, &McCabeOptions::default());
+
+        let depth = if is_nesting_node(node.kind()) { state.depth + 1 } else { state.depth };
+        self.nesting = self.nesting.max(depth);
+
+        let counts = abc_counts(node, source_code);
+        let condition_weight = if self.options.abc_weighted { state.abc_nesting + 1 } else { 1 };
+        self.abc.assignments += counts.assignments;
+        self.abc.branches += counts.branches;
+        self.abc.conditions += counts.conditions * condition_weight;
+
+        if node.kind() == "return_statement" {
+            self.return_count += 1;
+        }
+        self.shape.note(node);
+        if self.options.test_scoring.credit_inline_docs && is_inline_doc_comment(node, source_code) {
+            self.inline_docs += 1;
+        }
+        self.dependencies.note(node, source_code);
+        self.observability.note(node, source_code);
+
+        // Mirrors visit_node_cognitive: the increment for this node, then the
+        // nesting level and enclosing logical operator for its children
+        let level = state.cognitive_nesting;
+        let penalty = self.options.cognitive.nesting_penalty(level);
+        let (increment, child_level, child_op) = match node.kind() {
+            "if_statement" if state.else_if => (0, level, None),
+            "if_statement" | "while_statement" | "do_statement" | "for_statement" | "catch_clause" => {
+                (1 + penalty, level + 1, None)
+            }
+            "switch_statement" => {
+                let fallthroughs = if self.options.cognitive.count_fallthrough {
+                    count_case_fallthroughs(node)
+                } else {
+                    0
+                };
+                (1 + penalty + fallthroughs, level + 1, None)
+            }
+            "else_clause" => (1, level, None),
+            _ if node.id() != self.root_id && is_nested_function(node) => (1 + penalty, level + 1, None),
+            "generic_expression" if self.options.cognitive.count_generic => (1, level, state.binary_op),
+            _ if is_computed_goto(node, source_code) => (COMPUTED_GOTO_WEIGHT, level, state.binary_op),
+            "goto_statement" => (1, level, state.binary_op),
+            "call_expression" if is_setjmp_or_longjmp_call(node, source_code) => (1, level, state.binary_op),
+            "call_expression" if is_assert_call(node, source_code, &self.options.cognitive.assert_macros) => {
+                (1, level, state.binary_op)
+            }
+            "binary_expression" => match logical_operator(node, source_code) {
+                Some(op) => (u32::from(state.binary_op != Some(op)), level, Some(op)),
+                None => (0, level, state.binary_op),
+            },
+            _ => (0, level, state.binary_op),
+        };
+        self.cognitive += increment;
+
+        let mut cursor = node.walk();
+        for (index, child) in node.children(&mut cursor).enumerate() {
+            let abc_nesting = if abc_child_is_nested(node, child, index) {
+                state.abc_nesting + 1
+            } else {
+                state.abc_nesting
+            };
+            let child_state = WalkState {
+                cognitive_nesting: child_level,
+                binary_op: child_op,
+                else_if: node.kind() == "else_clause" && child.kind() == "if_statement",
+                depth,
+                abc_nesting,
+            };
+            self.visit(child, child_state);
+        }
+    }
+}
+
+/// The secondary metrics of one function, as computed by the individual functions
+/// named in each field's doc
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SecondaryMetrics {
+    /// `calculate_exit_point_count`
+    pub exit_points: u32,
+    /// `calculate_switch_case_total`
+    pub switch_case_total: u32,
+    /// `calculate_computed_goto_count`
+    pub computed_goto_count: u32,
+    /// `calculate_essential_complexity`
+    pub essential_complexity: u32,
+    /// `calculate_magic_number_count`
+    pub magic_numbers: u32,
+    /// `calculate_max_dereference_chain`
+    pub max_dereference_chain: u32,
+    /// `calculate_max_call_nesting`
+    pub max_call_nesting: u32,
+    /// `calculate_generic_association_count`
+    pub generic_association_count: u32,
+    /// `calculate_config_branch_count`
+    pub config_branch_count: u32,
+    /// `calculate_static_local_count`
+    pub static_local_count: u32,
+    /// `calculate_debt_marker_count`
+    pub debt_markers: u32,
+    /// `calculate_error_handling_sloc`
+    pub error_handling_sloc: u32,
+    /// `calculate_parameter_modification_count`
+    pub parameter_modifications: u32,
+    /// `calculate_max_switch_nesting`
+    pub max_switch_nesting: u32,
+    /// `find_unreachable_lines`
+    pub unreachable_lines: Vec<usize>,
+    /// `find_switches_without_default`
+    pub missing_default_lines: Vec<usize>,
+    /// `find_nested_switches`
+    pub nested_switch_lines: Vec<usize>,
+    /// `find_inconsistent_returns`
+    pub inconsistent_return_lines: Vec<usize>,
+    /// `find_const_candidates`
+    pub const_candidates: Vec<String>,
+    /// `find_called_functions`
+    pub calls: Vec<String>,
+    /// `is_missing_return`
+    pub missing_return: bool,
+    /// `is_leak_risk`
+    pub leak_risk: bool,
+}
+
+/// Single-pass equivalent of calling each secondary metric function, which would
+/// otherwise walk the function once per metric
+pub fn calculate_secondary_metrics(
+    node: Node,
+    source_code: &[u8],
+    debt_markers: &[String],
+    error_patterns: &[Regex],
+) -> SecondaryMetrics {
+    let body = node.child_by_field_name("body");
+    let noreturn = is_noreturn_function(node, source_code);
+    let expects_value = returns_value(node, source_code);
+    let mut walker = SecondaryWalker {
+        source_code,
+        debt_markers,
+        error_patterns,
+        cleanup_labels: find_cleanup_labels(node, source_code),
+        final_return: body.and_then(final_return_statement).map(|statement| statement.id()),
+        body_id: body.map(|body| body.id()),
+        expects_value: (!noreturn).then_some(expects_value),
+        parameters: parameter_names(node, source_code),
+        const_candidates: non_const_pointer_parameters(node, source_code)
+            .into_iter()
+            .map(|name| (name, false))
+            .collect(),
+        return_count: 0,
+        allocates: false,
+        metrics: SecondaryMetrics {
+            essential_complexity: 1,
+            ..SecondaryMetrics::default()
+        },
+    };
+    // Without a body the whole node stands in for it, as in calculate_parameter_modification_count
+    let state = SecondaryState {
+        in_body: body.is_none(),
+        ..SecondaryState::default()
+    };
+    walker.visit(node, state);
+
+    let mut metrics = walker.metrics;
+    metrics.unreachable_lines.sort_unstable();
+    if body.is_some() {
+        metrics.const_candidates = walker
+            .const_candidates
+            .iter()
+            .filter(|(_, written)| !written)
+            .map(|(name, _)| name.to_string())
+            .collect();
+    }
+    metrics.missing_return = expects_value
+        && walker.return_count == 0
+        && !noreturn
+        && get_function_name(node, source_code).as_deref() != Some("main");
+    metrics.leak_risk = walker.return_count > 1 && walker.cleanup_labels.is_empty() && walker.allocates;
+    metrics
+}
+
+/// Running totals for `calculate_secondary_metrics`
+struct SecondaryWalker<'a> {
+    source_code: &'a [u8],
+    debt_markers: &'a [String],
+    error_patterns: &'a [Regex],
+    cleanup_labels: Vec<(&'a str, usize)>,
+    /// The body's final `return`, which is structured
+    final_return: Option<usize>,
+    body_id: Option<usize>,
+    /// Whether returns should carry a value; `None` for noreturn functions
+    expects_value: Option<bool>,
+    parameters: Vec<&'a str>,
+    /// Non-const pointer parameters and whether the body writes through them
+    const_candidates: Vec<(&'a str, bool)>,
+    return_count: u32,
+    allocates: bool,
+    metrics: SecondaryMetrics,
+}
+
+/// Per-node context passed down the secondary walk
+#[derive(Debug, Clone, Copy, Default)]
+struct SecondaryState {
+    /// Enclosing `switch` statements
+    switch_depth: u32,
+    /// Inside the condition or body of an error check, already counted as a whole
+    error_block: bool,
+    /// Where numbers are never magic: definitions, `case` labels, array sizes
+    magic_exempt: bool,
+    /// Inside a `return`, whose nested returns are not checked again
+    in_return: bool,
+    /// Inside the function body
+    in_body: bool,
+}
+
+impl SecondaryWalker<'_> {
+    fn visit(&mut self, node: Node, state: SecondaryState) {
+        let source_code = self.source_code;
+        let metrics = &mut self.metrics;
+        // Node::kind is not free, so each node is dispatched on its kind once
+        let kind = node.kind();
+
+        let magic_exempt = state.magic_exempt || is_magic_number_scope(node);
+        let mut error_block = None;
+        let mut switch_depth = state.switch_depth;
+        match kind {
+            "return_statement" => {
+                self.return_count += 1;
+                metrics.exit_points += 1;
+                metrics.essential_complexity += u32::from(Some(node.id()) != self.final_return);
+                if let (false, Some(expects_value)) = (state.in_return, self.expects_value) {
+                    if returns_with_value(node) != expects_value {
+                        metrics.inconsistent_return_lines.push(node.start_position().row + 1);
+                    }
+                }
+            }
+            "goto_statement" => {
+                metrics.exit_points += u32::from(is_exit_point(node, source_code, &self.cleanup_labels));
+                metrics.computed_goto_count += u32::from(is_computed_goto(node, source_code));
+                metrics.essential_complexity += 1;
+            }
+            "continue_statement" => metrics.essential_complexity += 1,
+            "break_statement" => metrics.essential_complexity += u32::from(breaks_out_of_loop(node)),
+            "call_expression" => {
+                self.allocates |= is_allocation_call(node, source_code);
+                let exits = u32::from(is_exit_call(node, source_code));
+                metrics.exit_points += exits;
+                metrics.essential_complexity += exits;
+                metrics.max_call_nesting = metrics.max_call_nesting.max(call_nesting_depth(node));
+                note_called_function(node, source_code, &mut metrics.calls);
+            }
+            "case_statement" => {
+                metrics.switch_case_total += u32::from(node.child_by_field_name("value").is_some());
+                note_unreachable_statements(node, &mut metrics.unreachable_lines);
+            }
+            "compound_statement" => note_unreachable_statements(node, &mut metrics.unreachable_lines),
+            "field_expression" => {
+                metrics.max_dereference_chain = metrics.max_dereference_chain.max(dereference_chain_length(node));
+            }
+            "generic_expression" => metrics.generic_association_count += generic_associations(node),
+            "preproc_if" | "preproc_ifdef" | "preproc_elif" | "preproc_elifdef" => metrics.config_branch_count += 1,
+            "declaration" => metrics.static_local_count += u32::from(is_static_local(node, source_code)),
+            "comment" => metrics.debt_markers += comment_debt_markers(node, source_code, self.debt_markers),
+            "number_literal" if !magic_exempt => metrics.magic_numbers += u32::from(is_magic_number(node, source_code)),
+            "if_statement" if !state.error_block => {
+                error_block = error_block_sloc(node, source_code, self.error_patterns);
+                metrics.error_handling_sloc += error_block.unwrap_or(0);
+            }
+            "switch_statement" => {
+                if state.switch_depth > 0 {
+                    metrics.nested_switch_lines.push(node.start_position().row + 1);
+                }
+                if lacks_default(node) {
+                    metrics.missing_default_lines.push(node.start_position().row + 1);
+                }
+                switch_depth += 1;
+                metrics.max_switch_nesting = metrics.max_switch_nesting.max(switch_depth);
+            }
+            "ERROR" => metrics.computed_goto_count += u32::from(is_computed_goto(node, source_code)),
+            _ => {}
+        }
+
+        let writes = matches!(
+            kind,
+            "assignment_expression" | "update_expression" | "init_declarator" | "return_statement" | "argument_list"
+        );
+        if state.in_body && writes {
+            metrics.parameter_modifications += u32::from(writes_parameter(node, source_code, &self.parameters));
+            for (name, written) in self.const_candidates.iter_mut().filter(|(_, written)| !written) {
+                *written = writes_through_here(node, source_code, name);
+            }
+        }
+
+        let magic_exempt_child = magic_number_exempt_child(node);
+        let alternative = error_block.and_then(|_| node.child_by_field_name("alternative"));
+        let is_return = kind == "return_statement";
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            let child_state = SecondaryState {
+                switch_depth,
+                error_block: state.error_block || (error_block.is_some() && Some(child) != alternative),
+                magic_exempt: magic_exempt || Some(child) == magic_exempt_child,
+                in_return: state.in_return || is_return,
+                in_body: state.in_body || Some(child.id()) == self.body_id,
+            };
+            self.visit(child, child_state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tree_sitter::Tree;
+
+    fn parse_c_function(code: &str) -> Tree {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_c::LANGUAGE.into()).unwrap();
+        parser.parse(code, None).unwrap()
+    }
+
+    fn signature_complexity(node: Node, source_code: &[u8]) -> u32 {
+        calculate_signature_complexity(node, source_code, &aggregate_typedefs_around(node, source_code))
+    }
+
+    #[test]
+    fn test_scoring_from_function_definition() {
+        let code = r#"
+        void store(int *slot, int value) {
+            *slot = value;
+        }
+        "#;
+        let tree = parse_c_function(code);
+        let function = tree.root_node().named_child(0).unwrap();
+        assert_eq!(function.kind(), "function_definition");
+
+        // The definition node itself carries the signature, as the CLI passes it; both
+        // scores used to be 0 here because only the node's children were searched
+        let scoring = calculate_test_scoring(function, code.as_bytes());
+        assert_eq!(scoring.signature_score, 8);
+        assert_eq!(scoring.observable_score, 4);
+    }
+
+    #[test]
+    fn test_simple_function_mccabe() {
+        let code = r#"
+        void simple() {
+            int x = 1;
+        }
+        "#;
+        let tree = parse_c_function(code);
+        let node = tree.root_node();
+        // Simple function with no branches should have complexity 1
+        assert_eq!(calculate_mccabe_complexity(node, code.as_bytes()), 1);
+    }
+
+    #[test]
+    fn test_if_statement_mccabe() {
+        let code = r#"
+        void with_if() {
+            if (1) {
+                int x = 1;
+            }
+        }
+        "#;
+        let tree = parse_c_function(code);
+        let node = tree.root_node();
+        // One if statement increases complexity to 2
+        assert_eq!(calculate_mccabe_complexity(node, code.as_bytes()), 2);
+    }
+
+    #[test]
+    fn test_simple_function_cognitive() {
+        let code = r#"
+        void simple() {
+            int x = 1;
+        }
+        "#;
+        let tree = parse_c_function(code);
+        let node = tree.root_node();
+        // Simple function with no branches should have complexity 0
+        assert_eq!(calculate_cognitive_complexity(node, code.as_bytes()), 0);
+    }
+
+    #[test]
+    fn test_nested_if_cognitive() {
+        let code = r#"
+        void nested() {
+            if (1) {
+                if (2) {
+                    int x = 1;
+                }
+            }
+        }
+        "#;
+        let tree = parse_c_function(code);
+        let node = tree.root_node();
+        // Outer if: +1, inner if: +1 (base) +1 (nesting) = 3
+        assert_eq!(calculate_cognitive_complexity(node, code.as_bytes()), 3);
+    }
+
+    #[test]
+    fn test_statement_expression_mccabe() {
+        // Expansion of `#define MAX(a, b) ({ typeof(a) _a = (a); typeof(b) _b = (b); _a > _b ? _a : _b; })`
+        let code = r#"
+        int max_reading(int a, int b) {
+            return ({ int _a = (a); int _b = (b); _a > _b ? _a : _b; });
+        }
+        "#;
+        let tree = parse_c_function(code);
+        let node = tree.root_node();
+        assert!(!node.has_error());
+        // Base 1 + ternary inside the statement expression
+        assert_eq!(calculate_mccabe_complexity(node, code.as_bytes()), 2);
+        let func = first_function(&tree);
+        assert_eq!(calculate_return_count(func), 1);
+        assert!(!is_missing_return(func, code.as_bytes()));
+    }
+
+    #[test]
+    fn test_statement_expression_cognitive() {
+        let code = r#"
+        int sum_positive(int *values, int n) {
+            int total = 0;
+            for (int i = 0; i < n; i++) {
+                total += ({ int v = values[i]; if (v < 0) { v = 0; } v; });
+            }
+            return total;
+        }
+        "#;
+        let tree = parse_c_function(code);
+        let node = tree.root_node();
+        assert!(!node.has_error());
+        // for: +1, if inside the statement expression: +1 (base) +1 (nested in the for) = 3
+        assert_eq!(calculate_cognitive_complexity(node, code.as_bytes()), 3);
+        assert_eq!(calculate_mccabe_complexity(node, code.as_bytes()), 3);
+    }
+
+    #[test]
+    fn test_function_name_with_attributes() {
+        let code = r#"
+        int read_sensor(void) __attribute__((section(".ram"))) { return 1; }
+        __attribute__((weak)) void SysTick_Handler(void) { }
+        void DMA_IRQHandler(void) RAM_FUNC { }
+        void flash_write(int addr) __asm__("flash_write_impl") { }
+        static char *get_buffer(int idx) __attribute__((used)) { return 0; }
+        "#;
+        let tree = parse_c_function(code);
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let names: Vec<String> = root
+            .children(&mut cursor)
+            .filter_map(|node| get_function_name(node, code.as_bytes()))
+            .collect();
+        assert_eq!(names, vec!["read_sensor", "SysTick_Handler", "DMA_IRQHandler", "flash_write", "get_buffer"]);
+    }
+
+    #[test]
+    fn test_function_name_with_c23_attributes() {
+        let code = r#"
+        [[nodiscard]] int compute(void) { return 1; }
+        int [[maybe_unused]] spare(void) { return 2; }
+        int reserved [[maybe_unused]] (void) { return 3; }
+        static char *scratch [[gnu::hot]] (int *buf [[maybe_unused]]) { return 0; }
+        "#;
+        let tree = parse_c_function(code);
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let functions: Vec<Node> = root.children(&mut cursor).collect();
+        let names: Vec<String> = functions
+            .iter()
+            .filter_map(|&node| get_function_name(node, code.as_bytes()))
+            .collect();
+        assert_eq!(names, vec!["compute", "spare", "reserved", "scratch"]);
+
+        let parameters = find_function_declarator(functions[3]).unwrap().child_by_field_name("parameters").unwrap();
+        let buf = parameters.named_child(0).unwrap().child_by_field_name("declarator").unwrap();
+        assert_eq!(declared_identifier(buf).unwrap().utf8_text(code.as_bytes()), Ok("buf"));
+    }
+
+    #[test]
+    fn test_signature_complexity_with_attributes() {
+        let code = r#"
+        int scale(int value, int factor) __attribute__((section(".ram"))) {
+            return value * factor;
+        }
+        "#;
+        let tree = parse_c_function(code);
+        let function = tree.root_node().named_child(0).unwrap();
+        // Two plain parameters: 4, int return: 2
+        assert_eq!(signature_complexity(function, code.as_bytes()), 6);
+    }
+
+    #[test]
+    fn test_case_fallthrough_cognitive() {
+        let code = r#"
+        void dispatch(int cmd) {
+            switch (cmd) {
+                case 1:
+                case 2:
+                    start();
+                case 3:
+                    run();
+                    break;
+                default:
+                    stop();
+            }
+        }
+        "#;
+        let tree = parse_c_function(code);
+        let node = tree.root_node();
+        let options = CognitiveOptions {
+            count_fallthrough: true,
+            ..CognitiveOptions::default()
+        };
+        // Switch: +1, case 2 falls into case 3: +1 (stacked case 1 is not a fallthrough)
+        assert_eq!(calculate_cognitive_complexity(node, code.as_bytes()), 1);
+        assert_eq!(calculate_cognitive_complexity_with_options(node, code.as_bytes(), &options), 2);
+    }
+
+    #[test]
+    fn test_case_without_fallthrough_cognitive() {
+        let code = r#"
+        int lookup(int cmd) {
+            switch (cmd) {
+                case 1:
+                    start();
+                    break;
+                case 2: {
+                    run();
+                    return 2;
+                }
+                default:
+                    stop();
+            }
+            return 0;
+        }
+        "#;
+        let tree = parse_c_function(code);
+        let node = tree.root_node();
+        let options = CognitiveOptions {
+            count_fallthrough: true,
+            ..CognitiveOptions::default()
+        };
+        // Every case terminates, so only the switch itself counts
+        assert_eq!(calculate_cognitive_complexity_with_options(node, code.as_bytes(), &options), 1);
+    }
+
+    #[test]
+    fn test_unreachable_after_return() {
+        let code = r#"
+int compute(int x) {
+    return x * 2;
+    x++;
+    log_value(x);
+}
+"#;
+        let tree = parse_c_function(code);
+        assert_eq!(find_unreachable_lines(tree.root_node()), vec![4, 5]);
+    }
+
+    #[test]
+    fn test_conditional_return_is_reachable() {
+        let code = r#"
+int compute(int x) {
+    if (x < 0)
+        return 0;
+    if (x > 10) {
+        return 10;
+    }
+    goto done;
+done:
+    return x;
+}
+"#;
+        let tree = parse_c_function(code);
+        assert!(find_unreachable_lines(tree.root_node()).is_empty());
+    }
+
+    #[test]
+    fn test_unused_static_functions() {
+        let code = r#"
+static int helper(int x);
+static int unused(void) { return 0; }
+static int recursive(int n) { return n ? recursive(n - 1) : 0; }
+static int helper(int x) { return x + 1; }
+static void callback(void) {}
+void (*handler)(void) = callback;
+int api(int x) { return helper(x); }
+"#;
+        let tree = parse_c_function(code);
+        let unused = find_unused_static_functions(tree.root_node(), code.as_bytes());
+        // Self-recursion and the prototype don't count as references
+        assert_eq!(unused, vec!["unused", "recursive"]);
+    }
+
+    #[test]
+    fn test_abc_weighted_flat_conditions() {
+        let code = r#"
+int check(int a, int b) {
+    if (a) return 1;
+    if (b) return 2;
+    return 0;
+}
+"#;
+        let tree = parse_c_function(code);
+        let node = tree.root_node();
+        // Top-level conditions weigh 1, the same as canonical ABC
+        assert_eq!(calculate_abc_complexity(node, code.as_bytes()).conditions, 2);
+        assert_eq!(calculate_abc_weighted(node, code.as_bytes()).conditions, 2);
+    }
+
+    #[test]
+    fn test_abc_weighted_nested_conditions() {
+        let code = r#"
+int check(int a, int b) {
+    if (a) {
+        while (b) {
+            if (a && b) return 1;
+        }
+    } else if (b) {
+        b--;
+    }
+    return 0;
+}
+"#;
+        let tree = parse_c_function(code);
+        let node = tree.root_node();
+        assert_eq!(calculate_abc_complexity(node, code.as_bytes()).conditions, 5);
+        // if: 1, while: 2, inner if: 3, &&: 3, else if: 1
+        assert_eq!(calculate_abc_weighted(node, code.as_bytes()).conditions, 10);
+    }
+
+    #[test]
+    fn test_setjmp_longjmp_complexity() {
+        let code = r#"
+static jmp_buf recovery;
+
+void fail(void) {
+    longjmp(recovery, 1);
+}
+
+int run(void) {
+    if (setjmp(recovery) != 0) {
+        return -1;
+    }
+    fail();
+    return 0;
+}
+"#;
+        let tree = parse_c_function(code);
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let functions: Vec<Node> = root
+            .children(&mut cursor)
+            .filter(|node| node.kind() == "function_definition")
+            .collect();
+
+        // longjmp: +1 like a goto
+        assert_eq!(calculate_mccabe_complexity(functions[0], code.as_bytes()), 2);
+        assert_eq!(calculate_cognitive_complexity(functions[0], code.as_bytes()), 1);
+        // if: +1, setjmp: +1 as a hidden branch
+        assert_eq!(calculate_mccabe_complexity(functions[1], code.as_bytes()), 3);
+        assert_eq!(calculate_cognitive_complexity(functions[1], code.as_bytes()), 2);
+    }
+
+    fn first_function(tree: &Tree) -> Node<'_> {
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let function = root
+            .children(&mut cursor)
+            .find(|node| node.kind() == "function_definition")
+            .unwrap();
+        function
+    }
+
+    #[test]
+    fn test_void_function_needs_no_return() {
+        let code = "void log_event(int id) { record(id); }";
+        let tree = parse_c_function(code);
+        assert!(!is_missing_return(first_function(&tree), code.as_bytes()));
+    }
+
+    #[test]
+    fn test_int_function_missing_return() {
+        let code = "int parse(int x) { if (x) { x++; } }";
+        let tree = parse_c_function(code);
+        assert!(is_missing_return(first_function(&tree), code.as_bytes()));
+
+        let code = "void *lookup(int key) { cache_miss(key); }";
+        let tree = parse_c_function(code);
+        assert!(is_missing_return(first_function(&tree), code.as_bytes()));
+    }
+
+    #[test]
+    fn test_noreturn_function_is_exempt() {
+        let code = "_Noreturn int fatal(const char *msg) { puts(msg); abort(); }";
+        let tree = parse_c_function(code);
+        assert!(!is_missing_return(first_function(&tree), code.as_bytes()));
+
+        // An abort() wrapper: no returns, nothing inconsistent, and only observable by dying
+        let code = "__attribute__((noreturn)) void die(int code) { if (code) { return; } abort(); }";
+        let tree = parse_c_function(code);
+        let func = first_function(&tree);
+        assert!(is_noreturn_function(func, code.as_bytes()));
+        assert!(find_inconsistent_returns(func, code.as_bytes()).is_empty());
+        let flags = ObservabilityFlags::default();
+        assert_eq!(calculate_observable_behavior_score(func, code.as_bytes(), &flags), 6);
+        let code = "void quit(int code) { abort(); }";
+        let tree = parse_c_function(code);
+        assert!(!is_noreturn_function(first_function(&tree), code.as_bytes()));
+        assert_eq!(calculate_observable_behavior_score(first_function(&tree), code.as_bytes(), &flags), 4);
+
+        let code = "__attribute__((noreturn)) int panic_now(void) { for (;;) {} }";
+        let tree = parse_c_function(code);
+        assert!(!is_missing_return(first_function(&tree), code.as_bytes()));
+
+        for code in [
+            "noreturn void fatal(void) { abort(); }",
+            "static _Noreturn void fatal(void) { abort(); }",
+            "void __attribute__((cold, noreturn)) die(void) { abort(); }",
+            "[[noreturn]] void die(void) { abort(); }",
+            "[[gnu::noreturn]] void die(void) { abort(); }",
+            "__declspec(noreturn) void die(void) { abort(); }",
+        ] {
+            let tree = parse_c_function(code);
+            assert!(is_noreturn_function(first_function(&tree), code.as_bytes()), "{}", code);
+        }
+
+        // Names and parameters that only mention noreturn declare nothing
+        for code in [
+            "int parse_noreturn_opts(void) { int x = 0; }",
+            "int check(int noreturn) { if (noreturn) { x(); } }",
+            "__attribute__((unused)) int noreturn_count(void) { int n = 0; }",
+        ] {
+            let tree = parse_c_function(code);
+            let func = first_function(&tree);
+            assert!(!is_noreturn_function(func, code.as_bytes()), "{}", code);
+            assert!(is_missing_return(func, code.as_bytes()), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_count_parse_errors() {
+        let clean = "int add(int a, int b) { return a + b; }";
+        let tree = parse_c_function(clean);
+        assert_eq!(count_parse_errors(tree.root_node()), ParseErrors::default());
+
+        let broken = "int add(int a, int b) { return a + ; } int ok(void) { return 1; }";
+        let tree = parse_c_function(broken);
+        let errors = count_parse_errors(tree.root_node());
+        assert!(errors.error_nodes > 0);
+        assert!(errors.error_bytes < broken.len());
+    }
+
+    #[test]
+    fn test_exit_points_multiple_returns() {
+        let code = r#"
+int classify(int x) {
+    if (x < 0) {
+        return -1;
+    }
+    if (x == 0) {
+        exit(1);
+    }
+    return 1;
+}
+"#;
+        let tree = parse_c_function(code);
+        let func = first_function(&tree);
+        assert_eq!(calculate_return_count(func), 2);
+        assert_eq!(calculate_exit_point_count(func, code.as_bytes()), 3);
+    }
+
+    #[test]
+    fn test_exit_points_goto_cleanup() {
+        let code = r#"
+int load(const char *path) {
+    int rc = -1;
+retry:
+    if (busy()) {
+        goto retry;
+    }
+    char *buf = malloc(64);
+    if (!buf) {
+        goto cleanup;
+    }
+    if (read_into(path, buf) < 0) {
+        goto cleanup;
+    }
+    rc = 0;
+cleanup:
+    free(buf);
+    return rc;
+}
+"#;
+        let tree = parse_c_function(code);
+        let func = first_function(&tree);
+        // One return plus two forward gotos to the cleanup tail; the backward retry is a loop
+        assert_eq!(calculate_return_count(func), 1);
+        assert_eq!(calculate_exit_point_count(func, code.as_bytes()), 3);
+    }
+
+    #[test]
+    fn test_find_declared_functions() {
+        let code = r#"
+#ifndef UART_H
+#define UART_H
+typedef void (*uart_cb)(int);
+extern int uart_baud;
+int uart_init(int baud);
+void uart_write(const char *buf, int len);
+static inline int uart_ready(void) { return 1; }
+#endif
+"#;
+        let tree = parse_c_function(code);
+        let names = find_declared_functions(tree.root_node(), code.as_bytes());
+        assert_eq!(names, vec!["uart_init", "uart_write"]);
+    }
+
+    #[test]
+    fn test_magic_numbers() {
+        let code = r#"
+int scale(int *values, int n, int x) {
+    int buf[16];
+    for (int i = 0; i < n; i++) {
+        values[i] = values[i] * 1U - 1;
+    }
+    switch (x) {
+        case 5:
+            return x + 2;
+        default:
+            return x * 0x0 + 1024;
+    }
+}
+"#;
+        let tree = parse_c_function(code);
+        let func = first_function(&tree);
+        // `x + 2` and `1024` are magic; the loop bounds, `1U`, `-1`, `0x0`, `case 5:`, and `buf[16]` are not
+        assert_eq!(calculate_magic_number_count(func, code.as_bytes()), 2);
+    }
+
+    #[test]
+    fn test_mccabe_without_logical_ops() {
+        let code = r#"
+int in_range(int x, int lo, int hi, int strict) {
+    if (x >= lo && x <= hi || !strict) {
+        return 1;
+    }
+    return 0;
+}
+"#;
+        let tree = parse_c_function(code);
+        let func = first_function(&tree);
+        let options = McCabeOptions {
+            exclude_logical_ops: true,
+            ..McCabeOptions::default()
+        };
+        assert_eq!(calculate_mccabe_complexity(func, code.as_bytes()), 4);
+        assert_eq!(calculate_mccabe_complexity_with_options(func, code.as_bytes(), &options), 2);
+    }
+
+    #[test]
+    fn test_error_handling_sloc() {
+        let code = r#"
+int init(void) {
+    int ret = hw_init();
+    if (ret != OK) {
+        log_error(ret);
+        return ret;
+    }
+    if (count > limit) {
+        count = limit;
+    }
+    configure();
+    return OK;
+}
+"#;
+        let tree = parse_c_function(code);
+        let func = first_function(&tree);
+        let patterns: Vec<Regex> = DEFAULT_ERROR_PATTERNS.iter().map(|p| Regex::new(p).unwrap()).collect();
+        assert_eq!(calculate_sloc(func, code.as_bytes()), 12);
+        assert_eq!(calculate_error_handling_sloc(func, code.as_bytes(), &patterns), 2);
+
+        // A custom pattern set replaces the defaults
+        let custom = vec![Regex::new(r"count").unwrap()];
+        assert_eq!(calculate_error_handling_sloc(func, code.as_bytes(), &custom), 1);
+    }
+
+    #[test]
+    fn test_large_value_params() {
+        let by_value = "struct Config { int rate; int mode; };\nvoid f(struct Config cfg) { apply(cfg.rate); }\n";
+        let tree = parse_c_function(by_value);
+        let function = tree.root_node().named_child(1).unwrap();
+        let aggregates = find_aggregate_typedefs(tree.root_node(), by_value.as_bytes());
+        assert_eq!(calculate_large_value_param_count(function, by_value.as_bytes(), &aggregates), 1);
+        // One plain parameter: 2, copied struct: +2, void return: 0
+        assert_eq!(signature_complexity(function, by_value.as_bytes()), 4);
+
+        let by_pointer = "struct Config { int rate; int mode; };\nvoid f(const struct Config *cfg) { apply(cfg->rate); }\n";
+        let tree = parse_c_function(by_pointer);
+        let function = tree.root_node().named_child(1).unwrap();
+        assert_eq!(calculate_large_value_param_count(function, by_pointer.as_bytes(), &aggregates), 0);
+
+        let typedefs = "typedef struct { int rate; } Config;\ntypedef Config Settings;\nvoid f(Settings s, Config c[], int n) { }\n";
+        let tree = parse_c_function(typedefs);
+        let function = tree.root_node().named_child(2).unwrap();
+        let aggregates = find_aggregate_typedefs(tree.root_node(), typedefs.as_bytes());
+        assert_eq!(aggregates, HashSet::from(["Config".to_string(), "Settings".to_string()]));
+        assert_eq!(calculate_large_value_param_count(function, typedefs.as_bytes(), &aggregates), 1);
+    }
+
+    #[test]
+    fn test_all_metrics_match_individual_functions() {
+        let code = r#"
+/** Process a batch @param items input @return status */
+int process(struct Item *items, int count, int mode) {
+    int total = 0;
+    if (count <= 0 || items == NULL) {
+        return -1;
+    } else if (mode == 2 && count > 4 && Flags) {
+        Total = count;
+    } else {
+        for (int i = 0; i < count; i++) {
+            while (items[i].busy || items[i].locked) {
+                wait_for(&items[i]);
+            }
+            switch (items[i].kind) {
+            case 1:
+                total += items[i].value;
+            case 2:
+                total++;
+                break;
+            default:
+                goto fail;
+            }
+            total = total > 100 ? 100 : total;
+        }
+    }
+    do { total--; } while (total > 50 && rand() % 2);
+    assert(total >= 0);
+    if (setjmp(env)) {
+        printf("%d", time(NULL));
+    }
+    return total;
+fail:
+    free(items);
+    return 0;
+}
+"#;
+        let tree = parse_c_function(code);
+        let func = tree.root_node().named_child(1).unwrap();
+        let src = code.as_bytes();
+        let aggregates = find_aggregate_typedefs(tree.root_node(), src);
+
+        for options in [
+            MetricOptions::default(),
+            MetricOptions {
+                mccabe: McCabeOptions {
+                    exclude_logical_ops: true,
+                    assert_macros: vec!["assert".to_string()],
+                    count_cases: true,
+                },
+                cognitive: CognitiveOptions {
+                    count_fallthrough: true,
+                    count_generic: true,
+                    assert_macros: vec!["assert".to_string()],
+                    max_nesting_penalty: Some(1),
+                },
+                test_scoring: TestScoringOptions { credit_inline_docs: true },
+                abc_weighted: true,
+            },
+        ] {
+            let all = calculate_all_metrics_with_options(func, src, &options, &aggregates);
+            let abc = if options.abc_weighted {
+                calculate_abc_weighted(func, src)
+            } else {
+                calculate_abc_complexity(func, src)
+            };
+            let test_scoring = calculate_test_scoring_with_options(func, src, &options.test_scoring, &aggregates);
+
+            assert_eq!(all.mccabe, calculate_mccabe_complexity_with_options(func, src, &options.mccabe));
+            assert_eq!(all.cognitive, calculate_cognitive_complexity_with_options(func, src, &options.cognitive));
+            assert_eq!(all.nesting, calculate_nesting_depth(func));
+            assert_eq!(all.sloc, calculate_sloc(func, src));
+            assert_eq!(
+                (all.abc.assignments, all.abc.branches, all.abc.conditions),
+                (abc.assignments, abc.branches, abc.conditions)
+            );
+            assert_eq!(all.return_count, calculate_return_count(func));
+            assert_eq!(all.shape, calculate_control_flow_shape(func));
+            assert_eq!(all.test_scoring.total_score, test_scoring.total_score);
+            assert_eq!(all.test_scoring.dependency_score, test_scoring.dependency_score);
+            assert_eq!(all.test_scoring.observable_score, test_scoring.observable_score);
+            assert_eq!(all.test_scoring.documentation_score, test_scoring.documentation_score);
+        }
+    }
+
+    #[test]
+    fn test_secondary_metrics_match_individual_functions() {
+        let code = r#"
+int load(struct Dev *dev, char *buf, const char *name, int len) {
+    static int calls;
+    char local[64];
+    char *copy = malloc(len * 4);
+    // TODO check len, FIXME overflow
+    if (dev == NULL) {
+        if (len > 7) { log_error(3); }
+        return -1;
+    } else if (status(name) != 0) {
+        return 2;
+    }
+#ifdef TRACE
+    trace(_Generic(len, int: 1, long: 2, default: 3));
+#endif
+    switch (dev->ops->kind) {
+    case 5:
+        switch (len) { case 9: len = 10; }
+        break;
+    }
+    for (int i = 0; i < len; i++) {
+        if (buf[i] == 42) break;
+        len++;
+        continue;
+        buf[i] = 0;
+    }
+    emit(fmt(pad(dev->cfg.ops->name)));
+    goto *table[len];
+    return;
+}
+
+void worker(int *out, int *seen) {
+    *seen = 1;
+    if (!out) exit(1);
+    return 5;
+}
+
+int fallthrough(int *p) {
+    if (*p) goto done;
+    cleanup(p);
+done:
+    return 0;
+}
+
+int no_return(int x) {
+    x = 2;
+}
+"#;
+        let tree = parse_c_function(code);
+        let src = code.as_bytes();
+        let markers: Vec<String> = DEFAULT_DEBT_MARKERS.iter().map(|marker| marker.to_string()).collect();
+        let patterns: Vec<Regex> = DEFAULT_ERROR_PATTERNS.iter().map(|pattern| Regex::new(pattern).unwrap()).collect();
+
+        let mut cursor = tree.root_node().walk();
+        let functions: Vec<Node> = tree
+            .root_node()
+            .named_children(&mut cursor)
+            .filter(|node| node.kind() == "function_definition")
+            .collect();
+        assert_eq!(functions.len(), 4);
+        for func in functions {
+            let expected = SecondaryMetrics {
+                exit_points: calculate_exit_point_count(func, src),
+                switch_case_total: calculate_switch_case_total(func),
+                computed_goto_count: calculate_computed_goto_count(func, src),
+                essential_complexity: calculate_essential_complexity(func, src),
+                magic_numbers: calculate_magic_number_count(func, src),
+                max_dereference_chain: calculate_max_dereference_chain(func),
+                max_call_nesting: calculate_max_call_nesting(func),
+                generic_association_count: calculate_generic_association_count(func),
+                config_branch_count: calculate_config_branch_count(func),
+                static_local_count: calculate_static_local_count(func, src),
+                debt_markers: calculate_debt_marker_count(func, src, &markers),
+                error_handling_sloc: calculate_error_handling_sloc(func, src, &patterns),
+                parameter_modifications: calculate_parameter_modification_count(func, src),
+                max_switch_nesting: calculate_max_switch_nesting(func),
+                unreachable_lines: find_unreachable_lines(func),
+                missing_default_lines: find_switches_without_default(func),
+                nested_switch_lines: find_nested_switches(func),
+                inconsistent_return_lines: find_inconsistent_returns(func, src),
+                const_candidates: find_const_candidates(func, src),
+                calls: find_called_functions(func, src),
+                missing_return: is_missing_return(func, src),
+                leak_risk: is_leak_risk(func, src),
+            };
+            assert_eq!(calculate_secondary_metrics(func, src, &markers, &patterns), expected);
+        }
+    }
+
+    #[test]
+    fn test_inline_doc_comments_credit_documentation() {
+        let code = r#"
+// Apply a calibration record
+int calibrate(int raw, int offset) {
+    /** Readings below the floor are sensor faults: clamp them to zero */
+    if (raw < offset) {
+        return 0;
+    }
+    /// Normal path
+    return raw - offset;
+}
+"#;
+        let tree = parse_c_function(code);
+        let func = tree.root_node().named_child(1).unwrap();
+        let plain = calculate_test_scoring(func, code.as_bytes());
+        let options = TestScoringOptions { credit_inline_docs: true };
+        let credited = calculate_test_scoring_with_options(func, code.as_bytes(), &options, &HashSet::new());
+
+        // The leading plain comment is worth 2; each inline doc comment adds 1
+        assert_eq!(plain.documentation_score, 2);
+        assert_eq!(credited.documentation_score, 4);
+        assert_eq!(credited.total_score, plain.total_score - 2);
+    }
+
+    #[test]
+    fn test_control_flow_shape() {
+        let loop_heavy = r#"
+void fill(int grid[8][8]) {
+    for (int y = 0; y < 8; y++) {
+        for (int x = 0; x < 8; x++) {
+            grid[y][x] = 0;
+        }
+    }
+    int n = 8;
+    while (n--) {
+        do { tick(); } while (busy());
+    }
+}
+"#;
+        let tree = parse_c_function(loop_heavy);
+        let shape = calculate_control_flow_shape(first_function(&tree));
+        assert_eq!(shape, ControlFlowShape { loops: 4, branches: 0 });
+
+        let branch_heavy = r#"
+int classify(int v) {
+    if (v < 0) {
+        return -1;
+    } else if (v == 0) {
+        return 0;
+    }
+    switch (v) {
+    case 1: return 1;
+    default: break;
+    }
+    return v > 100 ? 100 : v;
+}
+"#;
+        let tree = parse_c_function(branch_heavy);
+        let shape = calculate_control_flow_shape(first_function(&tree));
+        assert_eq!(shape, ControlFlowShape { loops: 0, branches: 4 });
+    }
+
+    #[test]
+    fn test_precondition_guards_reduce_signature_score() {
+        let guarded = r#"
+int sum(const int *values, int count) {
+    int total = 0;
+    assert(values != NULL);
+    if (count <= 0) {
+        return 0;
+    }
+    for (int i = 0; i < count; i++) {
+        total += values[i];
+    }
+    return total;
+}
+"#;
+        let unguarded = r#"
+int sum(const int *values, int count) {
+    int total = 0;
+    for (int i = 0; i < count; i++) {
+        total += values[i];
+    }
+    return total;
+}
+"#;
+        let tree = parse_c_function(guarded);
+        let function = first_function(&tree);
+        assert_eq!(count_leading_guards(function, guarded.as_bytes()), 2);
+        // Const pointer with several parameters: 5, int return: 2, two guards: -2
+        assert_eq!(signature_complexity(function, guarded.as_bytes()), 5);
+
+        let tree = parse_c_function(unguarded);
+        let function = first_function(&tree);
+        assert_eq!(count_leading_guards(function, unguarded.as_bytes()), 0);
+        assert_eq!(signature_complexity(function, unguarded.as_bytes()), 7);
+    }
+
+    #[test]
+    fn test_function_like_macros() {
+        let code = "#define LOOP(n) for(int i=0;i<n;i++)\n#define ZERO 0\n#define EMPTY(x)\n#define SWAP(a, b) do { \\\n    int t = a; a = b; b = t; \\\n} while (0)\n";
+        let tree = parse_c_function(code);
+        let macros = find_function_like_macros(tree.root_node(), code.as_bytes());
+
+        let names: Vec<&str> = macros.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["LOOP", "SWAP"]);
+        assert_eq!(macros[0].line, 1);
+        assert_eq!(macros[0].code, "void LOOP(void) { for(int i=0;i<n;i++) ;}\n");
+        assert_eq!(macros[1].line, 4);
+        assert_eq!(macros[1].code.lines().count(), 3);
+
+        let wrapped = parse_c_function(&macros[0].code);
+        let function = wrapped.root_node().child(0).unwrap();
+        assert_eq!(function.kind(), "function_definition");
+        assert_eq!(calculate_mccabe_complexity(function, macros[0].code.as_bytes()), 2);
+        assert_eq!(calculate_cognitive_complexity(function, macros[0].code.as_bytes()), 1);
+    }
+
+    #[test]
+    fn test_style_consistency() {
+        let code = "void f(int x)\n{\n    if (x) {\n        g();\n    } else {\n        h();\n    }\n    while (x)\n    {\n        x--;\n    }\n}\n";
+        let tree = parse_c_function(code);
+        let style = calculate_style_consistency(tree.root_node().child(0).unwrap(), code.as_bytes());
+        // if and else on the same line, while on the next; all indentation is spaces
+        assert_eq!(style, StyleConsistency { indented_lines: 9, off_style_indent_lines: 0, control_braces: 3, off_style_braces: 1 });
+        assert_eq!(style.score(), 100.0 * 11.0 / 12.0);
+
+        let code = "void f(int x) {\n\tif (x) {\n\t\tg();\n  \t\th();\n\t}\n    x++;\n}\n";
+        let tree = parse_c_function(code);
+        let style = calculate_style_consistency(tree.root_node().child(0).unwrap(), code.as_bytes());
+        // One space-indented line among tab-indented ones, and one space-before-tab line
+        assert_eq!((style.indented_lines, style.off_style_indent_lines), (5, 2));
+        assert_eq!((style.control_braces, style.off_style_braces), (1, 0));
+
+        let code = "int f(void) { return 0; }\n";
+        let tree = parse_c_function(code);
+        assert_eq!(calculate_style_consistency(tree.root_node().child(0).unwrap(), code.as_bytes()).score(), 100.0);
+    }
+
+    #[test]
+    fn test_function_pointer_depth() {
+        let depth_and_signature = |code: &str| {
+            let tree = parse_c_function(code);
+            let function = tree.root_node().child(0).unwrap();
+            (calculate_function_pointer_depth(function), signature_complexity(function, code.as_bytes()))
+        };
+
+        // One pointer parameter (6) plus one level of indirection (2)
+        assert_eq!(depth_and_signature("void f(void (*cb)(int)) { cb(1); }"), (1, 8));
+        // Returns a function pointer and takes one: two levels, capped at 10
+        assert_eq!(depth_and_signature("void (*signal(int sig, void (*)(int)))(int) { return 0; }"), (2, 10));
+        // Plain pointers are not indirection levels
+        assert_eq!(depth_and_signature("int g(int *p) { return *p; }"), (0, 8));
+        assert_eq!(depth_and_signature("void h(int (*table[4])(void)) { }"), (1, 8));
+    }
+
+    #[test]
+    fn test_switch_case_total() {
+        let code = r#"
+int dispatch(int op, int mode) {
+    switch (op) {
+        case 1: return 10;
+        case 2: return 20;
+        case 3:
+        case 4: return 30;
+        default: break;
+    }
+    switch (mode) {
+        case 0: return 0;
+        case 1:
+            if (op) return 1;
+            break;
+        case 2: return 2;
+    }
+    return -1;
+}
+"#;
+        let tree = parse_c_function(code);
+        let function = tree.root_node().child(0).unwrap();
+        assert_eq!(calculate_switch_case_total(function), 7);
+        // Each switch is still one structure for cognitive complexity: 1 + 1 + if nested in the second (2)
+        assert_eq!(calculate_cognitive_complexity(function, code.as_bytes()), 4);
+    }
+
+    #[test]
+    fn test_computed_goto() {
+        let code = r#"
+int run(const int *code) {
+    static void *dispatch[] = { &&op_add, &&op_halt };
+    int acc = 0;
+    goto *dispatch[*code];
+op_add:
+    acc++;
+    goto *dispatch[*++code];
+op_halt:
+    goto done;
+done:
+    return acc;
+}
+"#;
+        let tree = parse_c_function(code);
+        let function = tree.root_node().child(0).unwrap();
+        assert_eq!(calculate_computed_goto_count(function, code.as_bytes()), 2);
+        // 1 + two computed gotos (2 each) + one ordinary goto
+        assert_eq!(calculate_mccabe_complexity(function, code.as_bytes()), 6);
+        assert_eq!(calculate_cognitive_complexity(function, code.as_bytes()), 5);
+
+        let all = calculate_all_metrics(function, code.as_bytes());
+        assert_eq!((all.mccabe, all.cognitive), (6, 5));
+    }
+
+    #[test]
+    fn test_switch_without_default() {
+        let code = r#"
+int classify(enum color c, int mode) {
+    switch (c) {
+        case RED: return 1;
+        case GREEN: return 2;
+    }
+    switch (mode) {
+        case 0:
+            switch (c) {
+                case BLUE: return 3;
+                default: return 4;
+            }
+        default:
+            return 0;
+    }
+}
+"#;
+        let tree = parse_c_function(code);
+        assert_eq!(find_switches_without_default(tree.root_node()), vec![3]);
+
+        let covered = parse_c_function("int f(int x) { switch (x) { case 1: return 1; default: return 0; } }");
+        assert!(find_switches_without_default(covered.root_node()).is_empty());
+    }
+
+    #[test]
+    fn test_static_local_count() {
+        let code = r#"
+int next_id(void) {
+    static int counter = 0;
+    static const char *prefix = "id", *suffix;
+    int local = 1;
+    return counter += local;
+}
+"#;
+        let tree = parse_c_function(code);
+        let node = tree.root_node();
+        assert_eq!(calculate_static_local_count(node, code.as_bytes()), 2);
+        assert_eq!(calculate_test_scoring(node, code.as_bytes()).dependency_score, 3);
+
+        let stateless = "int add(int a, int b) { int sum = a + b; return sum; }";
+        let tree = parse_c_function(stateless);
+        assert_eq!(calculate_static_local_count(tree.root_node(), stateless.as_bytes()), 0);
+        assert_eq!(calculate_test_scoring(tree.root_node(), stateless.as_bytes()).dependency_score, 0);
+    }
+
+    #[test]
+    fn test_max_dereference_chain() {
+        let code = r#"
+int speed(struct car *car, struct garage g) {
+    int top = car->engine->limits;
+    if (g.bays[0]->car->engine->limits > top) {
+        return (*car).wheels;
+    }
+    return 0;
+}
+"#;
+        let tree = parse_c_function(code);
+        assert_eq!(calculate_max_dereference_chain(tree.root_node()), 5);
+
+        let short = parse_c_function("int f(struct p *p) { return p->a->b; }");
+        assert_eq!(calculate_max_dereference_chain(short.root_node()), 3);
+        let none = parse_c_function("int f(int a) { return a + 1; }");
+        assert_eq!(calculate_max_dereference_chain(none.root_node()), 0);
+    }
+
+    #[test]
+    fn test_find_called_functions() {
+        let code = r#"
+int load(const char *path) {
+    FILE *f = fopen(path, "r");
+    if (!f) return log_error(path);
+    parse(f, handlers[0](f));
+    return parse(f, 0) + ops->read(f);
+}
+"#;
+        let tree = parse_c_function(code);
+        assert_eq!(find_called_functions(tree.root_node(), code.as_bytes()), vec!["fopen", "log_error", "parse"]);
+    }
+
+    #[test]
+    fn test_generic_selection() {
+        let code = r#"
+#define cbrt_any(x) 0
+double cube_root(double x) {
+    return _Generic((x), float: cbrtf, double: cbrt, long double: cbrtl, default: cbrt)(x);
+}
+"#;
+        let tree = parse_c_function(code);
+        let node = tree.root_node();
+        assert_eq!(calculate_generic_association_count(node), 4);
+        // Not a runtime branch
+        assert_eq!(calculate_mccabe_complexity(node, code.as_bytes()), 1);
+        assert_eq!(calculate_cognitive_complexity(node, code.as_bytes()), 0);
+
+        let options = CognitiveOptions {
+            count_generic: true,
+            ..CognitiveOptions::default()
+        };
+        assert_eq!(calculate_cognitive_complexity_with_options(node, code.as_bytes(), &options), 1);
+    }
+
+    #[test]
+    fn test_leak_risk() {
+        let leaky = r#"
+int load(const char *path) {
+    char *buf = malloc(4096);
+    if (!buf) return -1;
+    FILE *f = fopen(path, "r");
+    if (!f) return -2;
+    fclose(f);
+    free(buf);
+    return 0;
+}
+"#;
+        let tree = parse_c_function(leaky);
+        assert!(is_leak_risk(first_function(&tree), leaky.as_bytes()));
+
+        let cleanup = r#"
+int load(const char *path) {
+    int ret = -1;
+    char *buf = malloc(4096);
+    if (!buf) return -1;
+    FILE *f = fopen(path, "r");
+    if (!f) goto cleanup;
+    fclose(f);
+    ret = 0;
+cleanup:
+    free(buf);
+    return ret;
+}
+"#;
+        let tree = parse_c_function(cleanup);
+        assert!(!is_leak_risk(first_function(&tree), cleanup.as_bytes()));
 
-    let total = signature as i32 + dependency as i32 + observable as i32 + implementation as i32 - documentation;
+        // Multiple returns without allocation are not a leak risk
+        let plain = "int sign(int x) { if (x < 0) return -1; return x > 0; }";
+        let tree = parse_c_function(plain);
+        assert!(!is_leak_risk(first_function(&tree), plain.as_bytes()));
+    }
 
-    TestScoringMetric {
-        signature_score: signature,
-        dependency_score: dependency,
-        observable_score: observable,
-        implementation_score: implementation,
-        documentation_score: documentation,
-        total_score: total,
+    #[test]
+    fn test_initializer_data() {
+        let code = r#"
+void init_ops(struct op *ops) {
+    static const struct op table[] = {
+        { .name = "add", .fn = make_handler(ADD) },
+        { .name = "sub", .fn = make_handler(SUB) },
+        { .name = "mul", .fn = make_handler(MUL) },
+    };
+    for (int i = 0; i < 3; i++) {
+        ops[i] = table[i];
     }
 }
+"#;
+        let tree = parse_c_function(code);
+        let func = first_function(&tree);
+        let data = calculate_initializer_data(func, code.as_bytes());
+        assert_eq!(data, InitializerData { assignments: 0, branches: 3, sloc: 4 });
 
-/// Maps cyclomatic complexity to implementation score (0-10 scale)
-fn map_cyclomatic_to_implementation_score(cyclomatic: u32) -> u32 {
-    match cyclomatic {
-        1..=5 => (cyclomatic - 1) / 2,      // 1-5 -> 0-2
-        6..=10 => 3 + (cyclomatic - 6) / 2, // 6-10 -> 3-5
-        11..=20 => 6 + (cyclomatic - 11) / 5, // 11-20 -> 6-8
-        _ => 9.min(10), // 20+ -> 9-10
+        // The loop's i++ and assignment stay; only the table is excluded
+        let abc = calculate_abc_complexity(func, code.as_bytes());
+        assert_eq!((abc.assignments, abc.branches), (2, 3));
+        assert_eq!(calculate_sloc(func, code.as_bytes()), 10);
     }
+
+    #[test]
+    fn test_inconsistent_returns() {
+        let code = r#"
+int open_device(const char *path) {
+    if (!path)
+        return;
+    if (probe(path) < 0)
+        return -1;
+    return 0;
 }
 
-/// Calculates signature complexity based on function parameters and return type
-fn calculate_signature_complexity(node: Node, source_code: &[u8]) -> u32 {
-    let mut input_score = 0;
-    let mut output_score = 0;
+void reset(struct dev *d) {
+    if (!d)
+        return -1;
+    d->state = 0;
+    return;
+}
 
-    // Find function declarator
-    let mut cursor = node.walk();
-    for child in node.children(&mut cursor) {
-        if child.kind() == "function_definition" {
-            if let Some(declarator) = child.child_by_field_name("declarator") {
-                // Analyze parameters
-                input_score = analyze_parameters(declarator, source_code);
-
-                // Analyze return type
-                if let Some(type_node) = child.child_by_field_name("type") {
-                    output_score = analyze_return_type(type_node, source_code);
-                }
-            }
-        } else if child.kind() == "declaration" {
-            // For function declarations
-            if let Some(declarator) = child.child_by_field_name("declarator") {
-                input_score = analyze_parameters(declarator, source_code);
-            }
-            if let Some(type_node) = child.child_by_field_name("type") {
-                output_score = analyze_return_type(type_node, source_code);
-            }
-        }
+int consistent(int x) {
+    if (x < 0)
+        return -1;
+    return x;
+}
+"#;
+        let tree = parse_c_function(code);
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let functions: Vec<Node> = root.children(&mut cursor).filter(|node| node.kind() == "function_definition").collect();
+
+        assert_eq!(find_inconsistent_returns(functions[0], code.as_bytes()), vec![4]);
+        assert_eq!(find_inconsistent_returns(functions[1], code.as_bytes()), vec![12]);
+        assert!(find_inconsistent_returns(functions[2], code.as_bytes()).is_empty());
     }
 
-    // Combined score capped at 10
-    (input_score + output_score).min(10)
+    #[test]
+    fn test_nested_function_cognitive() {
+        // No C++ grammar is bundled, so lambdas are covered through GCC nested functions
+        let code = r#"
+int outer(int n) {
+    if (n < 0)
+        return 0;
+    int inner(int x) {
+        if (x > 0)
+            return x;
+        return 0;
+    }
+    return inner(n);
 }
+"#;
+        let tree = parse_c_function(code);
+        let outer = first_function(&tree);
+        // if (+1), nested function (+1), and its if one level deeper (+2)
+        assert_eq!(calculate_cognitive_complexity(outer, code.as_bytes()), 4);
+        assert_eq!(calculate_all_metrics(outer, code.as_bytes()).cognitive, 4);
 
-fn analyze_parameters(declarator: Node, source_code: &[u8]) -> u32 {
-    let mut param_count = 0;
-    let mut has_pointer = false;
-    let mut has_function_pointer = false;
-    let mut has_void_ptr = false;
-    let mut has_variadic = false;
-
-    // Find parameter list
-    let mut cursor = declarator.walk();
-    for child in declarator.children(&mut cursor) {
-        if child.kind() == "parameter_list" {
-            let mut param_cursor = child.walk();
-            for param in child.children(&mut param_cursor) {
-                if param.kind() == "parameter_declaration" {
-                    param_count += 1;
+        // Measured on its own, the nested function only pays for its if
+        let body = outer.child_by_field_name("body").unwrap();
+        let mut cursor = body.walk();
+        let inner = body.named_children(&mut cursor).find(|node| node.kind() == "function_definition").unwrap();
+        assert_eq!(calculate_cognitive_complexity(inner, code.as_bytes()), 1);
+        assert_eq!(calculate_all_metrics(inner, code.as_bytes()).cognitive, 1);
+    }
 
-                    // Check for pointers, function pointers, void*
-                    let param_text = param.utf8_text(source_code).unwrap_or("");
-                    if param_text.contains("void*") || param_text.contains("void *") {
-                        has_void_ptr = true;
-                    } else if param_text.contains("(*") || param_text.contains("* )") {
-                        has_function_pointer = true;
-                    } else if param_text.contains('*') {
-                        has_pointer = true;
-                    }
-                } else if param.kind() == "variadic_parameter" {
-                    has_variadic = true;
-                }
-            }
-        }
+    #[test]
+    fn test_debt_marker_count() {
+        let code = r#"
+int parse(const char *s) {
+    // FIXME: handles only ASCII
+    if (!s) return -1;
+    /* FIXME: leaks on error; TODOS and todo are not markers */
+    return decode(s);
+}
+"#;
+        let tree = parse_c_function(code);
+        let func = first_function(&tree);
+        let markers: Vec<String> = DEFAULT_DEBT_MARKERS.iter().map(|marker| marker.to_string()).collect();
+        assert_eq!(calculate_debt_marker_count(func, code.as_bytes(), &markers), 2);
+        assert_eq!(calculate_debt_marker_count(func, code.as_bytes(), &["NOTE".to_string()]), 0);
     }
 
-    // Score based on complexity
-    if has_function_pointer || has_void_ptr || has_variadic {
-        10
-    } else if has_pointer && param_count > 1 {
-        8
-    } else if has_pointer {
-        6
-    } else if param_count > 1 {
-        4
-    } else if param_count == 1 {
-        2
-    } else {
-        0
+    #[test]
+    fn test_assert_macros_as_decisions() {
+        let code = r#"
+void update(struct dev *dev, int len) {
+    assert(dev != NULL);
+    static_assert(sizeof(int) == 4, "int");
+    _Static_assert(sizeof(long) >= 4, "long");
+    BUG_ON(len < 0);
+    if (len > 0) {
+        configASSERT(dev->ready);
     }
 }
+"#;
+        let tree = parse_c_function(code);
+        let func = first_function(&tree);
+        let src = code.as_bytes();
+        assert_eq!(calculate_mccabe_complexity(func, src), 2);
+        assert_eq!(calculate_cognitive_complexity(func, src), 1);
 
-fn analyze_return_type(type_node: Node, source_code: &[u8]) -> u32 {
-    let type_text = type_node.utf8_text(source_code).unwrap_or("");
+        let asserts: Vec<String> = DEFAULT_ASSERT_MACROS.iter().map(|name| name.to_string()).collect();
+        let mccabe = McCabeOptions {
+            assert_macros: asserts.clone(),
+            ..McCabeOptions::default()
+        };
+        let cognitive = CognitiveOptions {
+            assert_macros: asserts,
+            ..CognitiveOptions::default()
+        };
+        // Three runtime assertions; the static ones never branch, even when listed
+        assert_eq!(calculate_mccabe_complexity_with_options(func, src, &mccabe), 5);
+        assert_eq!(calculate_cognitive_complexity_with_options(func, src, &cognitive), 4);
 
-    if type_text.contains("void") && !type_text.contains('*') {
-        0
-    } else if type_text.contains("struct") {
-        10
-    } else if type_text.contains('*') {
-        6
-    } else if type_text.contains("enum") {
-        4
-    } else {
-        2
+        let listed = McCabeOptions {
+            assert_macros: vec!["static_assert".to_string(), "BUG_ON".to_string()],
+            ..McCabeOptions::default()
+        };
+        assert_eq!(calculate_mccabe_complexity_with_options(func, src, &listed), 3);
+    }
+
+    #[test]
+    fn test_pmccabe_counts() {
+        // Expected values follow pmccabe's counting rules
+        let code = r#"
+int classify(int c) {
+    switch (c) {
+    case 'a': case 'e':
+        return 1;
+    case ' ':
+        return 2;
+    default:
+        return 0;
     }
 }
 
-/// Calculates dependency and side effect score
-fn calculate_dependency_score(node: Node, source_code: &[u8]) -> u32 {
-    let mut score = 0;
-    let mut has_io = false;
-    let mut has_allocation = false;
-    let mut has_system_calls = false;
-    let mut modifies_globals = false;
+int clamp(int a, int b) {
+    int r = 0;
+    if (a && b || !a)
+        r = a > b ? a : b;
+    while (r > 10)
+        r--;
+    if (r < 0)
+        goto out;
+    return r;
+out:
+    return 0;
+}
+"#;
+        let tree = parse_c_function(code);
+        let src = code.as_bytes();
+        let classify = tree.root_node().named_child(0).unwrap();
+        let clamp = tree.root_node().named_child(1).unwrap();
 
-    visit_node_dependencies(node, source_code, &mut has_io, &mut has_allocation,
-                           &mut has_system_calls, &mut modifies_globals);
+        let expected = PmccabeCounts {
+            modified: 2,
+            traditional: 4,
+            statements: 4,
+        };
+        assert_eq!(calculate_pmccabe(classify, src), expected);
+        let expected = PmccabeCounts {
+            modified: 7,
+            traditional: 7,
+            statements: 9,
+        };
+        assert_eq!(calculate_pmccabe(clamp, src), expected);
+        // knots also counts the goto
+        assert_eq!(calculate_mccabe_complexity(clamp, src), 8);
+    }
 
-    // Check for global state access (simplified heuristic)
-    if modifies_globals {
-        score += 6;
+    #[test]
+    fn test_parameter_modification_count() {
+        let code = r#"
+int checksum(const char *buf, int len, int (*mix)(int)) {
+    int sum = 0;
+    while (len-- > 0) {
+        sum = mix(sum + *buf);
+        buf++;
     }
+    (len) = 0;
+    return sum;
+}
+"#;
+        let tree = parse_c_function(code);
+        let func = first_function(&tree);
+        assert_eq!(calculate_parameter_modification_count(func, code.as_bytes()), 3);
 
-    // I/O operations
-    if has_io {
-        score += 2;
+        // Writing through a pointer parameter leaves the parameter itself alone
+        let code = "void reset(struct ctx *ctx, int *out) { ctx->count = 0; *out = 0; out[1] = 0; }";
+        let tree = parse_c_function(code);
+        assert_eq!(calculate_parameter_modification_count(first_function(&tree), code.as_bytes()), 0);
     }
 
-    // Memory allocation
-    if has_allocation {
-        score += 3;
+    #[test]
+    fn test_qualified_pointer_parameters() {
+        let signature = |code: &str| {
+            let tree = parse_c_function(code);
+            signature_complexity(first_function(&tree), code.as_bytes())
+        };
+        // Read-only input: 3, mutable: 6, int return: 2
+        assert_eq!(signature("int len(const char *s) { return 0; }"), 5);
+        assert_eq!(signature("int len(char *s) { return 0; }"), 8);
+        // `const` on the pointer itself, or a volatile pointee, is still a mutable input
+        assert_eq!(signature("int len(char *const s) { return 0; }"), 8);
+        assert_eq!(signature("int poll(const volatile uint8_t *reg) { return 0; }"), 8);
+        // restrict and _Atomic do not hide the pointer, and `volatile` is not `void`
+        assert_eq!(signature("int copy(int *restrict dst, _Atomic int *src) { return 0; }"), 10);
+        assert_eq!(signature("int peek(volatile uint8_t *reg) { return 0; }"), 8);
+        assert_eq!(signature("int hash(const void *key) { return 0; }"), 10);
+        // A function pointer whose callback returns void is not a void pointer
+        assert_eq!(signature("void each(void (*visit)(int)) { }"), 6 + FUNCTION_POINTER_WEIGHT);
+
+        // Pointer returns live in the declarator; volatile returns are harder to test
+        assert_eq!(signature("void reset(int mode) { }"), 2);
+        assert_eq!(signature("int *find(int key) { return 0; }"), 2 + 6);
+        assert_eq!(signature("volatile int status(int port) { return 0; }"), 2 + 2 + VOLATILE_RETURN_WEIGHT);
     }
 
-    // System calls
-    if has_system_calls {
-        score += 2;
+    #[test]
+    fn test_max_call_nesting() {
+        let nesting = |code: &str| {
+            let tree = parse_c_function(code);
+            calculate_max_call_nesting(first_function(&tree))
+        };
+        assert_eq!(nesting("void f(void) { a(b(c(d()))); }"), 4);
+        assert_eq!(nesting("void f(void) { a(); b(); c(); d(); }"), 1);
+        assert_eq!(nesting("int f(int x) { if (check(x + len(buf))) return 0; return log(2, fmt(x)); }"), 2);
+        assert_eq!(nesting("int f(int x) { return x + 1; }"), 0);
     }
 
-    score.min(10)
-}
+    #[test]
+    fn test_find_suppressions() {
+        let code = r#"
+/* knots:allow mccabe, SLOC */
+int table(int x) { return x; }
 
-fn visit_node_dependencies(node: Node, source_code: &[u8], has_io: &mut bool,
-                          has_allocation: &mut bool, has_system_calls: &mut bool,
-                          modifies_globals: &mut bool) {
-    if node.kind() == "call_expression" {
-        if let Some(function) = node.child_by_field_name("function") {
-            if let Ok(func_name) = function.utf8_text(source_code) {
-                // File I/O functions
-                if matches!(func_name, "fopen" | "fclose" | "fread" | "fwrite" | "fprintf" |
-                           "fscanf" | "fgets" | "fputs" | "fseek" | "ftell" | "rewind" |
-                           "printf" | "scanf" | "puts" | "getc" | "putc") {
-                    *has_io = true;
-                }
+// knots-disable-next-line
+int legacy(int x) { return x; }
 
-                // Memory allocation
-                if matches!(func_name, "malloc" | "calloc" | "realloc" | "free" | "aligned_alloc") {
-                    *has_allocation = true;
-                }
+// knots:allow
+int parser(int x) { return x; }
 
-                // System calls
-                if matches!(func_name, "time" | "clock" | "rand" | "srand" | "getpid" |
-                           "fork" | "exec" | "system" | "signal" | "kill" | "wait" | "pipe") {
-                    *has_system_calls = true;
-                }
-            }
-        }
+/** Plain documentation */
+int documented(int x) { return x; }
+"#;
+        let tree = parse_c_function(code);
+        let functions: Vec<Node> = {
+            let mut cursor = tree.root_node().walk();
+            let found = tree.root_node().children(&mut cursor).filter(|node| node.kind() == "function_definition").collect();
+            found
+        };
+        let suppressions: Vec<Vec<String>> = functions.iter().map(|func| find_suppressions(*func, code.as_bytes())).collect();
+        assert_eq!(suppressions[0], vec!["mccabe", "sloc"]);
+        assert_eq!(suppressions[1], vec![SUPPRESS_ALL]);
+        assert_eq!(suppressions[2], vec![SUPPRESS_ALL]);
+        assert!(suppressions[3].is_empty());
     }
 
-    // Check for global variable modifications (simplified - looks for assignments to identifiers)
-    if node.kind() == "assignment_expression" {
-        if let Some(left) = node.child_by_field_name("left") {
-            if left.kind() == "identifier" {
-                // Heuristic: if identifier doesn't start with lowercase, might be global
-                if let Ok(name) = left.utf8_text(source_code) {
-                    if !name.is_empty() && name.chars().next().unwrap().is_uppercase() {
-                        *modifies_globals = true;
-                    }
-                }
-            }
+    #[test]
+    fn test_essential_complexity() {
+        let structured = r#"
+int count_vowels(const char *s, int n) {
+    int count = 0;
+    for (int i = 0; i < n; i++) {
+        switch (s[i]) {
+        case 'a': case 'e': case 'i': case 'o': case 'u':
+            count++;
+            break;
+        default:
+            break;
         }
     }
+    if (count > n / 2) {
+        count = n / 2;
+    } else {
+        count += 0;
+    }
+    return count;
+}
+"#;
+        let tree = parse_c_function(structured);
+        let func = first_function(&tree);
+        assert_eq!(calculate_essential_complexity(func, structured.as_bytes()), 1);
+        assert_eq!(calculate_mccabe_complexity(func, structured.as_bytes()), 4);
 
-    let mut cursor = node.walk();
-    for child in node.children(&mut cursor) {
-        visit_node_dependencies(child, source_code, has_io, has_allocation,
-                               has_system_calls, modifies_globals);
+        let tangled = r#"
+int load(struct dev *dev, int n) {
+    int rc = -1;
+    if (!dev)
+        return -1;
+    for (int i = 0; i < n; i++) {
+        if (skip(i))
+            continue;
+        if (fatal(i))
+            abort();
+        if (done(i))
+            break;
+        if (read_block(dev, i) < 0)
+            goto fail;
     }
+    if (verify(dev) < 0)
+        goto fail;
+    rc = 0;
+fail:
+    return rc;
 }
+"#;
+        let tree = parse_c_function(tangled);
+        // Early return, continue, abort, break, and two gotos; the final return is structured
+        assert_eq!(calculate_essential_complexity(first_function(&tree), tangled.as_bytes()), 7);
+    }
 
-/// Calculates observable behavior score (how easy to verify correctness)
-fn calculate_observable_behavior_score(node: Node, source_code: &[u8]) -> u32 {
-    let mut score = 0;
-    let mut has_io = false;
-    let mut has_random = false;
-    let mut has_time = false;
+    #[test]
+    fn test_const_candidates() {
+        let candidates = |code: &str| {
+            let tree = parse_c_function(code);
+            find_const_candidates(first_function(&tree), code.as_bytes())
+        };
+        // Only read: could be const
+        let code = "int sum(int *values, int n) { int total = 0; while (n--) total += values[n]; return total; }";
+        assert_eq!(candidates(code), vec!["values"]);
+        assert_eq!(candidates("int len(struct buf *b) { return b->len + (*b).cap; }"), vec!["b"]);
 
-    // Check return type
-    let mut cursor = node.walk();
-    for child in node.children(&mut cursor) {
-        if child.kind() == "function_definition" {
-            if let Some(type_node) = child.child_by_field_name("type") {
-                let type_text = type_node.utf8_text(source_code).unwrap_or("");
-                if type_text.contains("void") && !type_text.contains('*') {
-                    score += 4;
-                }
-            }
-        }
-    }
+        // Written through, in each form
+        assert!(candidates("void clear(int *p) { *p = 0; }").is_empty());
+        assert!(candidates("void reset(struct ctx *ctx) { ctx->stats.count = 0; }").is_empty());
+        assert!(candidates("void fill(char *buf, int n) { for (int i = 0; i < n; i++) buf[i] = 0; }").is_empty());
+        assert!(candidates("void bump(int *p) { (*p)++; }").is_empty());
 
-    // Check for I/O, randomness, time dependencies
-    visit_node_observability(node, source_code, &mut has_io, &mut has_random, &mut has_time);
+        // Escapes where a write can't be seen
+        assert!(candidates("void zero(char *buf) { memset(buf, 0, 16); }").is_empty());
+        assert!(candidates("void init(struct ctx *ctx) { lock(&ctx->mutex); }").is_empty());
+        assert!(candidates("char *skip(char *s) { return s + 1; }").is_empty());
 
-    if has_io {
-        score += 2;
+        // Already const, pointers to pointers, and function pointers are left alone
+        assert!(candidates("int first(const int *p, char **argv, void (*cb)(void)) { return *p + **argv; }").is_empty());
+        // Advancing the pointer itself is not a write through it
+        assert_eq!(candidates("int count(char *s) { int n = 0; while (*s++) n++; return n; }"), vec!["s"]);
     }
-    if has_random {
-        score += 3;
-    }
-    if has_time {
-        score += 2;
+
+    #[test]
+    fn test_boundary_values() {
+        let code = r#"
+int clamp(const char *buf, int len, int mode) {
+    if (buf == NULL || len < 0) return -1;
+    if (16 >= len) return len;
+    if (mode != MODE_RAW && len <= MAX_LEN) return 0;
+    if (len < 0x10 && len > count) return 1;
+    return 2;
+}
+"#;
+        let tree = parse_c_function(code);
+        let values: Vec<(String, String)> = find_boundary_values(first_function(&tree), code.as_bytes())
+            .into_iter()
+            .map(|boundary| (boundary.parameter, boundary.value))
+            .collect();
+        let expected = [
+            ("buf", "NULL"),
+            ("len", "-1"),
+            ("len", "0"),
+            ("len", "16"),
+            ("len", "17"),
+            ("mode", "MODE_RAW"),
+            ("len", "MAX_LEN"),
+            ("len", "MAX_LEN + 1"),
+            ("len", "15"),
+            ("len", "0x10"),
+        ];
+        let expected: Vec<(String, String)> =
+            expected.iter().map(|(parameter, value)| (parameter.to_string(), value.to_string())).collect();
+        assert_eq!(values, expected);
     }
 
-    score.min(10)
+    #[test]
+    fn test_config_branch_count() {
+        let code = r#"
+int send(int x) {
+#ifdef DEBUG
+    log_value(x);
+#elif defined(TRACE)
+    trace(x);
+#else
+    x++;
+#endif
+#if LEVEL > 2
+    if (x > 0) x--;
+#endif
+    return x;
 }
+"#;
+        let tree = parse_c_function(code);
+        let func = first_function(&tree);
+        assert_eq!(calculate_config_branch_count(func), 3);
+        // Only the runtime `if` is a decision
+        assert_eq!(calculate_mccabe_complexity(func, code.as_bytes()), 2);
 
-fn visit_node_observability(node: Node, source_code: &[u8], has_io: &mut bool,
-                            has_random: &mut bool, has_time: &mut bool) {
-    if node.kind() == "call_expression" {
-        if let Some(function) = node.child_by_field_name("function") {
-            if let Ok(func_name) = function.utf8_text(source_code) {
-                if matches!(func_name, "fopen" | "fclose" | "fread" | "fwrite" | "fprintf" |
-                           "printf" | "scanf" | "puts") {
-                    *has_io = true;
-                }
-                if matches!(func_name, "rand" | "srand" | "random") {
-                    *has_random = true;
-                }
-                if matches!(func_name, "time" | "clock" | "gettimeofday") {
-                    *has_time = true;
-                }
-            }
-        }
+        let code = "int plain(int x) { return x; }";
+        let tree = parse_c_function(code);
+        assert_eq!(calculate_config_branch_count(first_function(&tree)), 0);
     }
 
-    let mut cursor = node.walk();
-    for child in node.children(&mut cursor) {
-        visit_node_observability(child, source_code, has_io, has_random, has_time);
+    #[test]
+    fn test_deepest_nesting_path() {
+        let code = r#"
+int scan(int *rows, int n) {
+    if (rows) {
+        for (int i = 0; i < n; i++) {
+            if (rows[i] < 0) {
+                return i;
+            }
+        }
+    } else {
+        while (n--) {
+            {
+                if (n == 2)
+                    return n;
+            }
+        }
     }
+    return -1;
 }
+"#;
+        let tree = parse_c_function(code);
+        let func = first_function(&tree);
+        let steps = find_deepest_nesting_path(func);
+        let labels: Vec<(&str, usize)> = steps.iter().map(|step| (step.label, step.line)).collect();
+        // The else branch is just as deep; the first path found is reported
+        assert_eq!(labels, vec![("if", 3), ("for", 4), ("if", 5)]);
+        assert_eq!(calculate_nesting_depth(func), 7);
 
-/// Calculates documentation quality score (higher is better, reduces total difficulty)
-fn calculate_documentation_score(node: Node, source_code: &[u8]) -> i32 {
-    let mut score = 0;
+        let code = "void f(int a) {\n    if (a) {\n    } else {\n        while (a) {\n            {\n                a--;\n            }\n        }\n    }\n}\n";
+        let tree = parse_c_function(code);
+        let labels: Vec<(&str, usize)> =
+            find_deepest_nesting_path(first_function(&tree)).iter().map(|step| (step.label, step.line)).collect();
+        assert_eq!(labels, vec![("if", 2), ("else", 3), ("while", 4), ("block", 5)]);
 
-    // Look for comment before the function
-    if let Some(prev_sibling) = node.prev_sibling() {
-        if prev_sibling.kind() == "comment" {
-            if let Ok(comment_text) = prev_sibling.utf8_text(source_code) {
-                // Check for Doxygen-style documentation
-                if comment_text.contains("/**") || comment_text.contains("///") {
-                    score += 4; // Base documentation
-
-                    // Check for specific Doxygen tags
-                    if comment_text.contains("@intent") {
-                        score += 5;
-                    }
-                    if comment_text.contains("@param") {
-                        score += 2;
-                    }
-                    if comment_text.contains("@return") {
-                        score += 2;
-                    }
-                    if comment_text.contains("@requires") {
-                        score += 2;
-                    }
-                    if comment_text.contains("@ensures") {
-                        score += 2;
-                    }
-                    if comment_text.contains("@side_effects") {
-                        score += 2;
-                    }
-                    if comment_text.contains("@example") {
-                        score += 3;
-                    }
-                    if comment_text.contains("@edge_cases") {
-                        score += 2;
-                    }
-                    if comment_text.contains("@complexity") {
-                        score += 2;
-                    }
-                } else if comment_text.starts_with("//") || comment_text.starts_with("/*") {
-                    score += 2; // Basic comment
-                }
-            }
-        }
+        let tree = parse_c_function("int flat(void) { return 0; }");
+        assert!(find_deepest_nesting_path(first_function(&tree)).is_empty());
     }
 
-    score.min(10)
+    #[test]
+    fn test_switch_with_conditions_in_cases() {
+        let code = r#"
+int dispatch(int cmd, int arg) {
+    switch (cmd) {
+    case 1:
+        if (arg > 0)
+            return arg;
+        break;
+    case 2:
+    case 3:
+        if (arg && cmd == 3)
+            return 0;
+        return arg ? 1 : 2;
+    default:
+        while (arg--)
+            poll();
+    }
+    return -1;
 }
+"#;
+        let tree = parse_c_function(code);
+        let func = first_function(&tree);
+        let src = code.as_bytes();
+        let count_cases = McCabeOptions {
+            count_cases: true,
+            ..McCabeOptions::default()
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tree_sitter::Tree;
+        // Inside the cases: 2 ifs, &&, ?:, while = 5 decisions, counted in both modes
+        // switch once: 1 + 1 + 5
+        assert_eq!(calculate_mccabe_complexity(func, src), 7);
+        // each of the 3 case labels, not default: 1 + 3 + 5
+        assert_eq!(calculate_mccabe_complexity_with_options(func, src, &count_cases), 9);
 
-    fn parse_c_function(code: &str) -> Tree {
-        let mut parser = tree_sitter::Parser::new();
-        parser.set_language(&tree_sitter_c::language()).unwrap();
-        parser.parse(code, None).unwrap()
+        let pmccabe = calculate_pmccabe(func, src);
+        assert_eq!((pmccabe.modified, pmccabe.traditional), (7, 9));
     }
 
     #[test]
-    fn test_simple_function_mccabe() {
+    fn test_max_nesting_penalty() {
         let code = r#"
-        void simple() {
-            int x = 1;
+void step(int a, int b, int c, int d) {
+    if (a) {
+        while (b) {
+            for (;;) {
+                if (c) {
+                    if (d)
+                        d--;
+                }
+            }
         }
-        "#;
+    }
+}
+"#;
         let tree = parse_c_function(code);
-        let node = tree.root_node();
-        // Simple function with no branches should have complexity 1
-        assert_eq!(calculate_mccabe_complexity(node, code.as_bytes()), 1);
+        let func = first_function(&tree);
+        let src = code.as_bytes();
+        let capped = |cap| CognitiveOptions {
+            max_nesting_penalty: Some(cap),
+            ..CognitiveOptions::default()
+        };
+
+        // Depths 0-4: (1+0) + (1+1) + (1+2) + (1+3) + (1+4)
+        assert_eq!(calculate_cognitive_complexity(func, src), 15);
+        // Capped at 2: (1+0) + (1+1) + (1+2) + (1+2) + (1+2)
+        assert_eq!(calculate_cognitive_complexity_with_options(func, src, &capped(2)), 12);
+        assert_eq!(calculate_cognitive_complexity_with_options(func, src, &capped(0)), 5);
+        // A cap at or beyond the deepest level changes nothing
+        assert_eq!(calculate_cognitive_complexity_with_options(func, src, &capped(4)), 15);
     }
 
     #[test]
-    fn test_if_statement_mccabe() {
+    fn test_prototypes() {
         let code = r#"
-        void with_if() {
-            if (1) {
-                int x = 1;
-            }
-        }
-        "#;
+#ifndef API_H
+#define API_H
+
+typedef int (*handler_t)(int);
+extern int error_count;
+
+/** Reset the device. */
+void device_reset(void);
+
+int read_block(int fd, char *buf, unsigned long len, int flags);
+void register_handler(void (*cb)(int, void *), void *ctx);
+static inline int twice(int x) { return 2 * x; }
+
+#endif
+"#;
         let tree = parse_c_function(code);
-        let node = tree.root_node();
-        // One if statement increases complexity to 2
-        assert_eq!(calculate_mccabe_complexity(node, code.as_bytes()), 2);
+        let prototypes = find_prototypes(tree.root_node(), code.as_bytes());
+        let names: Vec<&str> = prototypes.iter().map(|prototype| prototype.name.as_str()).collect();
+        assert_eq!(names, vec!["device_reset", "read_block", "register_handler"]);
+
+        assert_eq!(prototypes[0].line, 9);
+        assert_eq!(prototypes[0].documentation_score, 4);
+        assert_eq!(prototypes[0].difficulty(), prototypes[0].signature_score as i32 - 4);
+        assert_eq!(prototypes[1].documentation_score, 0);
+        // The scores match those of a definition with the same signature
+        let definition = "int read_block(int fd, char *buf, unsigned long len, int flags) { return 0; }";
+        let tree = parse_c_function(definition);
+        let expected = signature_complexity(first_function(&tree), definition.as_bytes());
+        assert_eq!(prototypes[1].signature_score, expected);
+        assert!(prototypes[2].signature_score > prototypes[0].signature_score);
     }
 
     #[test]
-    fn test_simple_function_cognitive() {
+    fn test_tally_mccabe_decisions() {
         let code = r#"
-        void simple() {
-            int x = 1;
-        }
-        "#;
+int mixed(int *a, int n, int mode) {
+    int total = 0;
+    for (int i = 0; i < n; i++) {
+        if (a[i] > 0 && a[i] < 100)
+            total += a[i];
+        else if (a[i] == -1 || a[i] == -2 || !a)
+            goto out;
+    }
+    while (n-- > 0)
+        total = total > 0 ? total - 1 : 0;
+    switch (mode) {
+    case 1:
+        if (total)
+            total++;
+        break;
+    case 2:
+        break;
+    default:
+        break;
+    }
+out:
+    assert(total >= 0);
+    return total;
+}
+"#;
         let tree = parse_c_function(code);
-        let node = tree.root_node();
-        // Simple function with no branches should have complexity 0
-        assert_eq!(calculate_cognitive_complexity(node, code.as_bytes()), 0);
+        let function = first_function(&tree);
+        let options = McCabeOptions::default();
+        let tally = tally_mccabe_decisions(function, code.as_bytes(), &options);
+        assert_eq!(
+            tally,
+            vec![("if", 3), ("for", 1), ("while", 1), ("switch", 1), ("&&", 1), ("||", 2), ("?:", 1), ("goto", 1)]
+        );
+        let decisions: u32 = tally.iter().map(|(_, count)| count).sum();
+        assert_eq!(decisions + 1, calculate_mccabe_complexity_with_options(function, code.as_bytes(), &options));
+
+        // The tally follows the McCabe options: cases instead of the switch, asserts counted
+        let options = McCabeOptions {
+            exclude_logical_ops: true,
+            assert_macros: vec!["assert".to_string()],
+            count_cases: true,
+        };
+        let tally = tally_mccabe_decisions(function, code.as_bytes(), &options);
+        assert_eq!(
+            tally,
+            vec![("if", 3), ("for", 1), ("while", 1), ("case", 2), ("?:", 1), ("goto", 1), ("assert", 1)]
+        );
     }
 
     #[test]
-    fn test_nested_if_cognitive() {
+    fn test_nested_switches() {
         let code = r#"
-        void nested() {
-            if (1) {
-                if (2) {
-                    int x = 1;
-                }
+int dispatch(int state, int event) {
+    switch (state) {
+    case 0:
+        switch (event) {
+        case 1:
+            return 1;
+        default:
+            break;
+        }
+        break;
+    case 1:
+        if (event) {
+            switch (event) {
+            case 2:
+                return 2;
             }
         }
-        "#;
+        break;
+    }
+    switch (event) {
+    case 3:
+        return 3;
+    }
+    return 0;
+}
+"#;
         let tree = parse_c_function(code);
-        let node = tree.root_node();
-        // Outer if: +1, inner if: +1 (base) +1 (nesting) = 3
-        assert_eq!(calculate_cognitive_complexity(node, code.as_bytes()), 3);
+        let function = first_function(&tree);
+        assert_eq!(calculate_max_switch_nesting(function), 2);
+        assert_eq!(find_nested_switches(function), vec![5, 14]);
+
+        let flat = "int f(int x) { switch (x) { case 1: return 1; } switch (x) { default: return 0; } }";
+        let tree = parse_c_function(flat);
+        assert_eq!(calculate_max_switch_nesting(first_function(&tree)), 1);
+        assert!(find_nested_switches(first_function(&tree)).is_empty());
+        let plain = "int g(int x) { return x; }";
+        let tree = parse_c_function(plain);
+        assert_eq!(calculate_max_switch_nesting(first_function(&tree)), 0);
     }
 }
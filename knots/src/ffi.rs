@@ -0,0 +1,129 @@
+//! C-compatible entry points for embedding knots in other languages (the `ffi` feature)
+//!
+//! Build a shared library with `cargo rustc -p knots --lib --features ffi --crate-type cdylib`
+//! and call `knots_analyze` from Python (ctypes), Node (N-API), or C. Every buffer it hands
+//! out must be released with `knots_free`.
+
+use crate::analysis::{analyze_bytes, AnalysisError};
+use serde::Serialize;
+use std::path::Path;
+
+/// Analysis succeeded and `*out_json` holds the report
+pub const KNOTS_OK: i32 = 0;
+/// A pointer argument was null
+pub const KNOTS_ERR_NULL: i32 = 1;
+/// The source was not valid UTF-8
+pub const KNOTS_ERR_UTF8: i32 = 2;
+/// tree-sitter could not parse the source, or syntax errors left no functions
+pub const KNOTS_ERR_PARSE: i32 = 3;
+
+/// One function in the JSON array returned by `knots_analyze`
+#[derive(Debug, Serialize)]
+struct FunctionReport {
+    name: String,
+    line: usize,
+    mccabe: u32,
+    cognitive: u32,
+    nesting: u32,
+    sloc: u32,
+    abc_magnitude: f64,
+    return_count: u32,
+}
+
+/// Metrics for every function defined in `source`, as a JSON array, or a `KNOTS_ERR_*` code
+fn analyze_to_json(source: &[u8]) -> Result<String, i32> {
+    let functions = analyze_bytes(source, Path::new("<source>")).map_err(|e| match e {
+        AnalysisError::Encoding { .. } => KNOTS_ERR_UTF8,
+        _ => KNOTS_ERR_PARSE,
+    })?;
+    let reports: Vec<FunctionReport> = functions
+        .into_iter()
+        .map(|function| FunctionReport {
+            name: function.name,
+            line: function.line,
+            mccabe: function.metrics.mccabe,
+            cognitive: function.metrics.cognitive,
+            nesting: function.metrics.nesting,
+            sloc: function.metrics.sloc,
+            abc_magnitude: function.metrics.abc.magnitude(),
+            return_count: function.metrics.return_count,
+        })
+        .collect();
+    serde_json::to_string(&reports).map_err(|_| KNOTS_ERR_PARSE)
+}
+
+/// Analyze `len` bytes of C source at `source` and store a newly allocated JSON array
+/// (not NUL-terminated) in `*out_json` and its length in `*out_len`
+///
+/// Returns `KNOTS_OK` or one of the `KNOTS_ERR_*` codes; on error the outputs are untouched.
+///
+/// # Safety
+///
+/// `source` must point to `len` readable bytes, and `out_json` and `out_len` must be valid
+/// for writes. The returned buffer must be freed with `knots_free`, passing the same length.
+#[no_mangle]
+pub unsafe extern "C" fn knots_analyze(
+    source: *const u8,
+    len: usize,
+    out_json: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    if source.is_null() || out_json.is_null() || out_len.is_null() {
+        return KNOTS_ERR_NULL;
+    }
+    let bytes = std::slice::from_raw_parts(source, len);
+    let json = match analyze_to_json(bytes) {
+        Ok(json) => json,
+        Err(status) => return status,
+    };
+
+    let buffer = json.into_bytes().into_boxed_slice();
+    *out_len = buffer.len();
+    *out_json = Box::into_raw(buffer) as *mut u8;
+    KNOTS_OK
+}
+
+/// Release a buffer returned by `knots_analyze`; null is ignored
+///
+/// # Safety
+///
+/// `json` must be null or a pointer from `knots_analyze` that has not been freed yet, and
+/// `len` must be the length it reported.
+#[no_mangle]
+pub unsafe extern "C" fn knots_free(json: *mut u8, len: usize) {
+    if !json.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(json, len)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffi_round_trip() {
+        let source = "int clamp(int x) {\n    if (x < 0) return 0;\n    return x > 9 ? 9 : x;\n}\n";
+        let mut json: *mut u8 = std::ptr::null_mut();
+        let mut len = 0;
+
+        let status = unsafe { knots_analyze(source.as_ptr(), source.len(), &mut json, &mut len) };
+        assert_eq!(status, KNOTS_OK);
+        let text = unsafe { std::str::from_utf8(std::slice::from_raw_parts(json, len)).unwrap().to_string() };
+        unsafe { knots_free(json, len) };
+
+        let functions: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(functions[0]["name"], "clamp");
+        assert_eq!(functions[0]["line"], 1);
+        assert_eq!(functions[0]["mccabe"], 3);
+        assert_eq!(functions[0]["return_count"], 2);
+
+        let invalid = [0xff, 0xfe];
+        let status = unsafe { knots_analyze(invalid.as_ptr(), invalid.len(), &mut json, &mut len) };
+        assert_eq!(status, KNOTS_ERR_UTF8);
+        let garbage = "int x = ;\n))))\n";
+        let status = unsafe { knots_analyze(garbage.as_ptr(), garbage.len(), &mut json, &mut len) };
+        assert_eq!(status, KNOTS_ERR_PARSE);
+        let status = unsafe { knots_analyze(std::ptr::null(), 0, &mut json, &mut len) };
+        assert_eq!(status, KNOTS_ERR_NULL);
+    }
+}
@@ -1,9 +1,14 @@
 // knots library - shared complexity calculation functions
 
+pub mod analysis;
 pub mod complexity;
 
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
 // Re-export complexity functions for use by workspace members
-pub use complexity::{calculate_mccabe_complexity, calculate_cognitive_complexity};
+pub use complexity::{calculate_mccabe_complexity, calculate_cognitive_complexity, get_function_name};
+pub use analysis::{analyze_file, analyze_source, AnalysisError, FunctionAnalysis};
 
 // Re-export tree-sitter for convenience
 pub use tree_sitter;
@@ -1,18 +1,54 @@
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
+use colored::Colorize;
 use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::Write;
+use std::io::{BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
 use tree_sitter::{Node, Tree, TreeCursor};
 use walkdir::WalkDir;
 
-mod complexity;
-use complexity::{
-    calculate_abc_complexity, calculate_cognitive_complexity, calculate_mccabe_complexity,
-    calculate_nesting_depth, calculate_return_count, calculate_sloc, calculate_test_scoring,
-    TestScoringMetric,
+#[cfg(feature = "git")]
+mod author;
+#[cfg(feature = "git")]
+mod baseline;
+mod check;
+mod objc;
+mod preprocess;
+mod rank;
+mod recent;
+mod scaffold;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod template;
+#[cfg(test)]
+mod test_support;
+#[cfg(feature = "sqlite")]
+mod trend;
+#[cfg(feature = "tui")]
+mod tui;
+
+#[cfg(feature = "git")]
+use author::{complexity_by_author, format_author_table};
+#[cfg(feature = "git")]
+use baseline::BaselineCheckout;
+use check::{baseline_gate, file_complexity_gate, format_table, functions_per_file_gate, missing_default_gate, missing_return_gate, threshold_gates, CheckReport};
+use objc::is_objc_file;
+use preprocess::{parse_define, preprocessor_flags, Defines, Preprocessor, SourceText};
+use rank::RankExpr;
+use recent::parse_duration;
+#[cfg(feature = "git")]
+use recent::recent_lines;
+use scaffold::{scaffold, Framework};
+use template::MessageTemplate;
+
+use knots::complexity::{
+    calculate_abc_complexity, calculate_abc_weighted, calculate_all_metrics_with_options, calculate_cognitive_complexity_with_options, calculate_computed_goto_count, calculate_control_flow_shape, calculate_exit_point_count, find_aggregate_typedefs, calculate_initializer_data, calculate_large_value_param_count, calculate_mccabe_complexity_with_options,
+    calculate_nesting_depth, calculate_return_count, calculate_sloc, calculate_style_consistency, calculate_switch_case_total, calculate_test_scoring_with_options,
+    calculate_pmccabe, calculate_secondary_metrics, count_parse_errors, find_deepest_nesting_path, preceding_comment, find_declared_functions, find_suppressions, find_unused_static_functions, find_function_like_macros, find_prototypes, tally_mccabe_decisions, get_function_name, is_missing_return, is_noreturn_function, is_static_function, AbcComplexity, AllMetrics, CognitiveOptions, ControlFlowShape, McCabeOptions, MetricOptions, PmccabeCounts, PrototypeScore, SecondaryMetrics, DEFAULT_ASSERT_MACROS, DEFAULT_DEBT_MARKERS, DEFAULT_ERROR_PATTERNS, SUPPRESS_ALL, ParseErrors, StyleConsistency, TestScoringMetric, TestScoringOptions,
 };
 
 fn get_complexity_emoji(complexity: u32) -> &'static str {
@@ -24,17 +60,343 @@ fn get_complexity_emoji(complexity: u32) -> &'static str {
     }
 }
 
+/// Metric columns that can be selected with --fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Field {
+    Mccabe,
+    Cognitive,
+    Nesting,
+    Sloc,
+    Abc,
+    Returns,
+    #[value(name = "test_score")]
+    TestScore,
+    Loops,
+    Branches,
+}
+
+impl Field {
+    const ALL: [Field; 9] = [
+        Field::Mccabe,
+        Field::Cognitive,
+        Field::Nesting,
+        Field::Sloc,
+        Field::Abc,
+        Field::Returns,
+        Field::TestScore,
+        Field::Loops,
+        Field::Branches,
+    ];
+
+    /// Columns printed when --fields is not given
+    const DEFAULT: [Field; 7] = [
+        Field::Mccabe,
+        Field::Cognitive,
+        Field::Nesting,
+        Field::Sloc,
+        Field::Abc,
+        Field::Returns,
+        Field::TestScore,
+    ];
+
+    /// Snake-case metric name used in machine-readable output
+    fn key(&self) -> &'static str {
+        match self {
+            Field::Mccabe => "mccabe",
+            Field::Cognitive => "cognitive",
+            Field::Nesting => "nesting",
+            Field::Sloc => "sloc",
+            Field::Abc => "abc",
+            Field::Returns => "returns",
+            Field::TestScore => "test_score",
+            Field::Loops => "loops",
+            Field::Branches => "branches",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Field::Mccabe => "McCabe",
+            Field::Cognitive => "Cognitive",
+            Field::Nesting => "Nesting",
+            Field::Sloc => "SLOC",
+            Field::Abc => "ABC",
+            Field::Returns => "Returns",
+            Field::TestScore => "TestScore",
+            Field::Loops => "Loops",
+            Field::Branches => "Branches",
+        }
+    }
+
+    fn value(&self, func: &FunctionMetrics) -> f64 {
+        match self {
+            Field::Mccabe => func.mccabe as f64,
+            Field::Cognitive => func.cognitive as f64,
+            Field::Nesting => func.nesting as f64,
+            Field::Sloc => func.sloc as f64,
+            Field::Abc => func.abc_magnitude,
+            Field::Returns => func.return_count as f64,
+            Field::TestScore => func.test_scoring.total_score as f64,
+            Field::Loops => func.loop_count as f64,
+            Field::Branches => func.branch_count as f64,
+        }
+    }
+
+    /// Whether the metric is fractional (only ABC magnitude); the rest are exact counts
+    fn is_float(&self) -> bool {
+        matches!(self, Field::Abc)
+    }
+
+    fn format_value(&self, func: &FunctionMetrics, precision: usize) -> String {
+        match self {
+            Field::Mccabe => func.mccabe.to_string(),
+            Field::Cognitive => func.cognitive.to_string(),
+            Field::Nesting => func.nesting.to_string(),
+            Field::Sloc => func.sloc.to_string(),
+            Field::Abc => format!("{:.*}", precision, func.abc_magnitude),
+            Field::Returns => func.return_count.to_string(),
+            Field::TestScore => func.test_scoring.total_score.to_string(),
+            Field::Loops => func.loop_count.to_string(),
+            Field::Branches => func.branch_count.to_string(),
+        }
+    }
+
+    /// JSON keys of the `FunctionMetrics` fields computed for this metric
+    fn json_keys(&self) -> &'static [&'static str] {
+        match self {
+            Field::Mccabe => &["mccabe", "computed_goto_count"],
+            Field::Cognitive => &["cognitive", "switch_case_total"],
+            Field::Nesting => &["nesting"],
+            Field::Sloc => &["sloc"],
+            Field::Abc => &["abc_magnitude"],
+            Field::Returns => &["return_count", "exit_points"],
+            Field::TestScore => &["test_scoring"],
+            Field::Loops => &["loop_count"],
+            Field::Branches => &["branch_count"],
+        }
+    }
+}
+
+/// JSON keys of the metrics outside `Field`, which are only computed without --metrics
+const SECONDARY_METRICS: [&str; 19] = [
+    "essential_complexity",
+    "magic_numbers",
+    "static_local_count",
+    "debt_markers",
+    "max_dereference_chain",
+    "max_call_nesting",
+    "calls",
+    "generic_association_count",
+    "config_branch_count",
+    "error_handling_sloc",
+    "large_value_param_count",
+    "parameter_modifications",
+    "const_candidates",
+    "unreachable_lines",
+    "missing_default_lines",
+    "max_switch_nesting",
+    "nested_switch_lines",
+    "inconsistent_return_lines",
+    "leak_risk",
+];
+
+/// Format the selected metric columns as "McCabe: 3, Cognitive: 2, ..."
+/// followed by the style score when --style-check measured it
+fn format_fields(func: &FunctionMetrics, output: &OutputOptions) -> String {
+    output
+        .fields
+        .iter()
+        .map(|field| format!("{}: {}", field.label(), field.format_value(func, output.precision)))
+        .chain(func.style.map(|style| format!("Style: {:.*}%", output.precision, style.score())))
+        .chain((!func.suppressed.is_empty()).then(|| format!("Suppressed: {}", func.suppressed.join(" "))))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Suffix for the verbose cognitive line, e.g. " (7 switch cases)", empty without switches
+fn switch_case_note(func: &FunctionMetrics) -> String {
+    match func.switch_case_total {
+        0 => String::new(),
+        1 => " (1 switch case)".to_string(),
+        cases => format!(" ({} switch cases)", cases),
+    }
+}
+
+/// Verbose "Style Consistency" line, e.g. "92% (1 of 9 indented lines, 0 of 3 braces off-style)"
+fn format_style(style: &StyleConsistency, precision: usize) -> String {
+    format!(
+        "{:.*}% ({} of {} indented lines, {} of {} braces off-style)",
+        precision,
+        style.score(),
+        style.off_style_indent_lines,
+        style.indented_lines,
+        style.off_style_braces,
+        style.control_braces
+    )
+}
+
+/// Mean style score over the functions --style-check measured
+fn average_style_score(metrics: &[FunctionMetrics]) -> Option<f64> {
+    let scores: Vec<f64> = metrics.iter().filter_map(|func| func.style.map(|style| style.score())).collect();
+    (!scores.is_empty()).then(|| scores.iter().sum::<f64>() / scores.len() as f64)
+}
+
+/// Escape a Prometheus label value (backslash, double quote, and newline)
+fn escape_prometheus_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render metrics in the Prometheus text exposition format
+/// Emits one gauge family per selected field plus totals and the function count
+fn format_prometheus(all_metrics: &[FunctionMetrics], fields: &[Field], precision: usize) -> String {
+    let mut out = String::new();
+
+    for field in fields {
+        let name = format!("knots_function_{}", field.key());
+        out.push_str(&format!("# HELP {} {} per function\n", name, field.label()));
+        out.push_str(&format!("# TYPE {} gauge\n", name));
+        for func in all_metrics {
+            out.push_str(&format!(
+                "{}{{file=\"{}\",func=\"{}\",line=\"{}\"}} {}\n",
+                name,
+                escape_prometheus_label(&func.file_path),
+                escape_prometheus_label(&func.name),
+                func.line,
+                field.format_value(func, precision)
+            ));
+        }
+    }
+
+    for field in fields {
+        let name = format!("knots_total_{}", field.key());
+        let total: f64 = all_metrics.iter().map(|func| field.value(func)).sum();
+        out.push_str(&format!("# HELP {} Total {} across all functions\n", name, field.label()));
+        out.push_str(&format!("# TYPE {} gauge\n", name));
+        out.push_str(&format!("{} {}\n", name, total));
+    }
+
+    out.push_str("# HELP knots_function_count Number of functions analyzed\n");
+    out.push_str("# TYPE knots_function_count gauge\n");
+    out.push_str(&format!("knots_function_count {}\n", all_metrics.len()));
+
+    out
+}
+
+/// Fill color for a function's complexity band, matching the emoji bands
+fn dot_fill_color(complexity: u32) -> &'static str {
+    match complexity {
+        0..=10 => "palegreen",
+        11..=20 => "khaki",
+        21..=49 => "orange",
+        _ => "tomato",
+    }
+}
+
+/// shields.io color for an average complexity, by the same bands as the emoji
+fn badge_color(average: f64) -> &'static str {
+    match average.round() as u32 {
+        0..=10 => "green",
+        11..=20 => "yellow",
+        21..=49 => "orange",
+        _ => "red",
+    }
+}
+
+/// A shields.io endpoint badge (https://shields.io/badges/endpoint-badge)
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Badge {
+    schema_version: u32,
+    label: &'static str,
+    message: String,
+    color: &'static str,
+}
+
+/// Badge JSON for the average --primary-metric complexity over all functions
+fn format_badge(all_metrics: &[FunctionMetrics], primary: PrimaryMetric, precision: usize) -> Result<String> {
+    let total: u32 = all_metrics.iter().map(|func| primary.complexity(func)).sum();
+    let average = total as f64 / all_metrics.len() as f64;
+    let badge = Badge {
+        schema_version: 1,
+        label: "complexity",
+        message: format!("avg {:.*}", precision, average),
+        color: badge_color(average),
+    };
+    serde_json::to_string(&badge).context("Failed to serialize badge")
+}
+
+/// --format editor: `name\tmccabe\tcognitive\tline_start\tline_end` per function, in
+/// file order, with no header or summary; the columns are stable for plugins to parse
+fn format_editor(all_metrics: &[FunctionMetrics]) -> String {
+    all_metrics
+        .iter()
+        .map(|func| format!("{}\t{}\t{}\t{}\t{}\n", func.name, func.mccabe, func.cognitive, func.line, func.end_line))
+        .collect()
+}
+
+/// Quote a Graphviz ID, escaping double quotes and backslashes
+fn dot_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Render a Graphviz DOT call graph with one cluster per file
+///
+/// Nodes are functions labeled with max(McCabe, Cognitive) and filled by band; edges
+/// are calls to functions defined in the same file.
+fn format_dot(all_metrics: &[FunctionMetrics]) -> String {
+    let mut out = String::from("digraph knots {\n    rankdir=LR;\n    node [shape=box, style=filled];\n");
+    for (index, (file_path, functions)) in group_by_file(all_metrics).into_iter().enumerate() {
+        let id = |name: &str| dot_quote(&format!("{}:{}", file_path, name));
+        out.push_str(&format!("    subgraph cluster_{} {{\n        label={};\n", index, dot_quote(file_path)));
+        for func in &functions {
+            out.push_str(&format!(
+                "        {} [label={}, fillcolor={}];\n",
+                id(&func.name),
+                dot_quote(&format!("{} ({})", func.name, func.max_complexity())),
+                dot_fill_color(func.max_complexity())
+            ));
+        }
+        for func in &functions {
+            for callee in func.calls.iter().filter(|callee| functions.iter().any(|other| other.name == **callee)) {
+                out.push_str(&format!("        {} -> {};\n", id(&func.name), id(callee)));
+            }
+        }
+        out.push_str("    }\n");
+    }
+    out.push_str("}\n");
+    out
+}
+
 /// Compilation database entry from compile_commands.json
 #[derive(Debug, Clone, Deserialize)]
 struct CompileCommand {
     #[serde(default)]
     directory: String,
     #[serde(default)]
-    _command: String,
+    command: String,
     file: String,
     #[serde(default)]
-    #[serde(rename = "arguments")]
-    _arguments: Vec<String>,
+    arguments: Vec<String>,
+}
+
+impl CompileCommand {
+    /// Compiler arguments, from `arguments` or split from the `command` string
+    fn argument_list(&self) -> Vec<String> {
+        if self.arguments.is_empty() {
+            self.command.split_whitespace().map(str::to_string).collect()
+        } else {
+            self.arguments.clone()
+        }
+    }
+}
+
+/// Translation units listed in compile_commands.json, with each file's preprocessor flags
+struct CompilationDatabase {
+    files: Vec<PathBuf>,
+    file_flags: HashMap<PathBuf, Vec<String>>,
 }
 
 /// Filter rules for including/excluding files and functions
@@ -153,9 +515,17 @@ fn glob_match(pattern: &str, path: &str) -> bool {
 #[command(name = "knots")]
 #[command(version = env!("CARGO_PKG_VERSION"))]
 #[command(about = "Analyzes C code complexity with visual indicators: 😊 (1-10), 😐 (11-20), 😠 (21-49), 😢 (50+)", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+#[command(group(ArgGroup::new("mode").args([
+    "metric", "pmccabe", "table", "tui", "matrix", "format",
+    "scaffold", "prototypes", "explain_nesting", "explain_summary",
+])))]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to the C file or directory to analyze
-    #[arg(value_name = "FILE", required_unless_present = "compile_commands")]
+    #[arg(value_name = "FILE", required_unless_present_any = ["compile_commands", "print_schema"])]
     file: Option<PathBuf>,
 
     /// Recursively process all C files in directories
@@ -174,6 +544,10 @@ struct Args {
     #[arg(short, long)]
     matrix: bool,
 
+    /// With --matrix, print a separate matrix for each file instead of one across all files
+    #[arg(long, requires = "matrix")]
+    matrix_per_file: bool,
+
     /// Include filter rules from JSON file (whitelist files/functions)
     #[arg(long, value_name = "FILE")]
     include: Option<PathBuf>,
@@ -181,734 +555,6162 @@ struct Args {
     /// Exclude filter rules from JSON file (blacklist files/functions)
     #[arg(long, value_name = "FILE")]
     exclude: Option<PathBuf>,
-}
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+    /// Add +1 cognitive complexity for each case that falls through without break/return/goto
+    #[arg(long)]
+    count_fallthrough: bool,
 
-    // Load filter rules
-    let include_rules = if let Some(path) = &args.include {
-        Some(FilterRules::from_file(path)?)
-    } else {
-        None
-    };
+    /// Cap the cognitive nesting increment at N, so structures nested deeper than N add
+    /// +1+N each instead of +1+depth (not part of the SonarSource specification)
+    #[arg(long, value_name = "N")]
+    max_nesting_penalty: Option<u32>,
 
-    let exclude_rules = if let Some(path) = &args.exclude {
-        Some(FilterRules::from_file(path)?)
-    } else {
-        None
-    };
+    /// Add +1 cognitive complexity for each C11 _Generic selection
+    #[arg(long)]
+    count_generic: bool,
 
-    // Collect files to process
-    let files = if let Some(compile_commands_path) = &args.compile_commands {
-        // Load files from compile_commands.json
-        load_compile_commands(compile_commands_path, &include_rules, &exclude_rules)?
-    } else if let Some(file_path) = &args.file {
-        // Use regular file/directory path
-        collect_files(file_path, args.recursive, &include_rules, &exclude_rules)?
-    } else {
-        anyhow::bail!("Either FILE or --compile-commands must be specified");
-    };
+    /// Count runtime assertion macro calls as decisions in McCabe and Cognitive
+    #[arg(long)]
+    count_asserts: bool,
 
-    // For matrix mode
-    if args.matrix {
-        let mut all_metrics = Vec::new();
-        let mut skipped_files = 0;
+    /// Assertion macro counted by --count-asserts (repeatable; replaces assert, BUG_ON, configASSERT)
+    #[arg(long = "assert-macro", value_name = "NAME", requires = "count_asserts")]
+    assert_macros: Vec<String>,
 
-        for file in &files {
-            let source_code = match fs::read_to_string(file) {
-                Ok(code) => code,
-                Err(e) => {
-                    eprintln!("Warning: Skipping {}: {}", file.display(), e);
-                    skipped_files += 1;
-                    continue;
-                }
-            };
+    /// Credit Doxygen-style comments inside function bodies toward the test documentation score
+    #[arg(long)]
+    credit_inline_docs: bool,
 
-            let mut parser = tree_sitter::Parser::new();
-            parser
-                .set_language(&tree_sitter_c::language())
-                .context("Failed to set C language")?;
-
-            let tree = match parser.parse(&source_code, None) {
-                Some(t) => t,
-                None => {
-                    eprintln!("Warning: Failed to parse {}", file.display());
-                    skipped_files += 1;
-                    continue;
-                }
-            };
+    /// Also report function-like macros whose body contains control flow, as pseudo-functions
+    /// named after the macro
+    #[arg(long)]
+    macros: bool,
 
-            let metrics = collect_function_metrics(&tree, &source_code, file.to_str().unwrap_or(""), &include_rules, &exclude_rules);
-            all_metrics.extend(metrics);
-        }
+    /// Score each function's indentation (tabs vs spaces) and brace placement consistency
+    #[arg(long)]
+    style_check: bool,
 
-        if all_metrics.is_empty() {
-            anyhow::bail!("No functions found in any files (skipped {} files)", skipped_files);
-        }
+    /// Count only control-flow statements in McCabe complexity, not && and || operators
+    #[arg(long)]
+    no_logical_ops: bool,
 
-        display_testability_matrix(&all_metrics, files.len(), skipped_files);
-        return Ok(());
-    }
+    /// Count each `case` label as a McCabe decision instead of each `switch` (traditional
+    /// McCabe, pmccabe's second column)
+    #[arg(long)]
+    switch_cases: bool,
 
-    // For single file mode, use traditional output
-    if files.len() == 1 {
-        let file = &files[0];
-        let source_code = fs::read_to_string(file)
-            .with_context(|| format!("Failed to read file: {}", file.display()))?;
+    /// Regex for an `if` condition that checks for an error (repeatable; replaces the defaults)
+    #[arg(long = "error-pattern", value_name = "REGEX")]
+    error_patterns: Vec<String>,
 
-        let mut parser = tree_sitter::Parser::new();
-        parser
-            .set_language(&tree_sitter_c::language())
-            .context("Failed to set C language")?;
+    /// Comment word counted as a technical debt marker (repeatable; replaces TODO, FIXME, HACK, XXX)
+    #[arg(long = "debt-marker", value_name = "WORD")]
+    debt_markers: Vec<String>,
 
-        let tree = parser
-            .parse(&source_code, None)
-            .with_context(|| format!("Failed to parse C code in {}", file.display()))?;
+    /// Use the nesting-weighted ABC variant (conditions count 1 + nesting level)
+    #[arg(long)]
+    abc_weighted: bool,
 
-        analyze_code(&tree, &source_code, args.verbose, &include_rules, &exclude_rules)?;
-        return Ok(());
-    }
+    /// Leave `{ ... }` initializer data (lookup tables) out of ABC assignments/branches and SLOC
+    #[arg(long)]
+    data_aware: bool,
 
-    // For recursive mode with multiple files: collect all metrics, write report, show summary
-    let mut all_metrics = Vec::new();
-    let mut skipped_files = 0;
+    /// Run each file through this compiler's preprocessor (`<CC> -E`) before analysis
+    #[arg(long, value_name = "CC")]
+    preprocess: Option<String>,
 
-    for file in &files {
-        let source_code = match fs::read_to_string(file) {
-            Ok(code) => code,
-            Err(e) => {
-                eprintln!("Warning: Skipping {}: {}", file.display(), e);
-                skipped_files += 1;
-                continue;
-            }
-        };
+    /// Extra flags for the preprocessor, e.g. "-Iinclude -DBOARD_REV=2"
+    #[arg(long, value_name = "FLAGS", requires = "preprocess", allow_hyphen_values = true)]
+    cflags: Option<String>,
 
-        let mut parser = tree_sitter::Parser::new();
-        parser
-            .set_language(&tree_sitter_c::language())
-            .context("Failed to set C language")?;
+    /// Define a macro for choosing among #ifdef/#if branches without a preprocessor; functions
+    /// in unselected branches are skipped (repeatable, NAME alone means NAME=1)
+    #[arg(long, value_name = "NAME[=VAL]", value_parser = parse_define, conflicts_with = "preprocess")]
+    define: Vec<(String, String)>,
 
-        let tree = match parser.parse(&source_code, None) {
-            Some(t) => t,
-            None => {
-                eprintln!("Warning: Failed to parse {}", file.display());
-                skipped_files += 1;
-                continue;
-            }
-        };
+    /// Only report functions with a line changed within this window according to git blame (e.g. 7d, 12h, 2w)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    since: Option<std::time::Duration>,
 
-        let metrics = collect_function_metrics(&tree, &source_code, file.to_str().unwrap_or(""), &include_rules, &exclude_rules);
-        all_metrics.extend(metrics);
-    }
+    /// Only report functions whose lines overlap START:END (1-based, inclusive) in a single file
+    #[arg(long, value_name = "START:END", value_parser = parse_line_range, conflicts_with = "recursive")]
+    lines: Option<(usize, usize)>,
 
-    if all_metrics.is_empty() {
-        anyhow::bail!("No functions found in any files (skipped {} files)", skipped_files);
-    }
+    /// Skip files whose header comment marks them as generated (protobuf, bison, flex, "DO NOT EDIT")
+    #[arg(long)]
+    exclude_generated: bool,
 
-    // Write detailed report to file
-    write_detailed_report(&all_metrics, args.verbose)?;
+    /// Regex matched against the first lines of each file by --exclude-generated
+    #[arg(long, value_name = "REGEX", requires = "exclude_generated")]
+    generated_marker: Option<String>,
 
-    // Display summary with top 5 worst functions and totals/averages
-    display_recursive_summary(&all_metrics, files.len(), skipped_files);
+    /// Headers declaring the public API; with it, only non-static functions declared there count as exported
+    #[arg(long, value_name = "GLOB")]
+    public_headers: Option<String>,
 
-    Ok(())
-}
+    /// Comma-separated metrics to compute, skipping the rest for speed (default: all);
+    /// also the default for --fields
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "METRICS")]
+    metrics: Vec<Field>,
 
-/// Load file paths from compile_commands.json
-fn load_compile_commands(
-    compile_commands_path: &Path,
-    include_rules: &Option<FilterRules>,
-    exclude_rules: &Option<FilterRules>,
-) -> Result<Vec<PathBuf>> {
-    let content = fs::read_to_string(compile_commands_path)
-        .with_context(|| format!("Failed to read compile_commands.json: {}", compile_commands_path.display()))?;
+    /// Comma-separated metric columns to print, in order (default: all)
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "FIELDS")]
+    fields: Vec<Field>,
 
-    let commands: Vec<CompileCommand> = serde_json::from_str(&content)
-        .with_context(|| format!("Failed to parse compile_commands.json: {}", compile_commands_path.display()))?;
+    /// Print only the per-function lines, without totals, averages, or top-5 sections
+    #[arg(long)]
+    no_summary: bool,
 
-    let mut files = Vec::new();
+    /// Do not write report.txt in recursive mode; the summary is still printed
+    #[arg(long)]
+    no_report: bool,
 
-    for cmd in commands {
-        let file_path = PathBuf::from(&cmd.file);
+    /// Print at most N per-function lines, then "… and M more"; totals still cover every function
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
 
-        // Only process C files
-        if let Some(ext) = file_path.extension() {
-            if ext == "c" {
-                let file_str = file_path.to_string_lossy();
-                if should_process_file(&file_str, include_rules, exclude_rules) {
-                    // Use absolute path if available, otherwise relative
-                    if file_path.is_absolute() {
-                        files.push(file_path);
-                    } else {
-                        // Try to make it absolute using the directory from compile command
-                        let abs_path = if !cmd.directory.is_empty() {
-                            PathBuf::from(&cmd.directory).join(&file_path)
-                        } else {
-                            file_path.clone()
-                        };
+    /// Merge per-function results into a JSON report, replacing entries for re-analyzed files
+    #[arg(long, value_name = "REPORT")]
+    append: Option<PathBuf>,
 
-                        if abs_path.exists() {
-                            files.push(abs_path);
-                        } else if file_path.exists() {
-                            files.push(file_path);
-                        }
-                    }
-                }
-            }
-        }
-    }
+    /// Store the comment block before each function as "doc_comment" in the JSON report
+    #[arg(long)]
+    include_docs: bool,
 
-    if files.is_empty() {
-        anyhow::bail!("No .c files found in compile_commands.json");
-    }
+    /// With --include-docs, cut comments longer than CHARS characters short with an ellipsis
+    #[arg(long, value_name = "CHARS", default_value_t = 2000, requires = "include_docs")]
+    doc_limit: usize,
 
-    Ok(files)
-}
+    /// Insert this run's per-function metrics, with a timestamp and the git commit, into a
+    /// SQLite database (created if absent)
+    #[arg(long, value_name = "FILE")]
+    sqlite: Option<PathBuf>,
 
-/// Collect files to process based on the path and recursive flag
-fn collect_files(
-    path: &PathBuf,
-    recursive: bool,
-    include_rules: &Option<FilterRules>,
-    exclude_rules: &Option<FilterRules>,
-) -> Result<Vec<PathBuf>> {
-    let mut files = Vec::new();
+    /// Leave out static functions never referenced in their file and list them separately
+    #[arg(long)]
+    flag_unused_static: bool,
 
-    if path.is_file() {
-        // Single file mode
-        let file_str = path.to_string_lossy();
-        if should_process_file(&file_str, include_rules, exclude_rules) {
-            files.push(path.clone());
-        }
-    } else if path.is_dir() {
-        if !recursive {
-            anyhow::bail!(
-                "Path '{}' is a directory. Use -r/--recursive to process directories recursively.",
-                path.display()
-            );
-        }
+    /// Fail (exit 1) when a non-void function has no return statement
+    #[arg(long)]
+    strict: bool,
 
-        // Recursive directory mode - only scan .c files by default
-        // (headers often contain inline/vendor code)
-        for entry in WalkDir::new(path)
-            .follow_links(true)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            let file_path = entry.path();
-            if file_path.is_file() {
-                if let Some(ext) = file_path.extension() {
-                    if ext == "c" {
-                        let file_str = file_path.to_string_lossy();
-                        if should_process_file(&file_str, include_rules, exclude_rules) {
-                            files.push(file_path.to_path_buf());
-                        }
-                    }
-                }
-            }
-        }
+    /// Fail (exit 1) when a switch has no `default:` label
+    #[arg(long)]
+    require_default: bool,
 
-        if files.is_empty() {
-            anyhow::bail!("No .c files found in directory: {}", path.display());
-        }
-    } else {
-        anyhow::bail!("Path '{}' does not exist", path.display());
-    }
+    /// Stop at the first function over a --max-* threshold instead of reporting all violations
+    #[arg(long)]
+    fail_fast: bool,
 
-    Ok(files)
-}
+    /// Print each threshold violation in this format instead of the default
+    /// "Error: {file}:{line}: {func}() has {metric} {value} (max {threshold})"
+    #[arg(long, value_name = "TEMPLATE", value_parser = MessageTemplate::parse)]
+    message_template: Option<MessageTemplate>,
 
-/// Check if a file should be processed based on include/exclude rules
-fn should_process_file(
-    file_path: &str,
-    include_rules: &Option<FilterRules>,
-    exclude_rules: &Option<FilterRules>,
-) -> bool {
-    // Check include rules first (whitelist)
-    if let Some(rules) = include_rules {
-        if !rules.matches_file(file_path) {
-            return false;
-        }
-    }
+    /// Fail (exit 1) when a function's McCabe complexity exceeds N
+    #[arg(long, value_name = "N", env = "KNOTS_MAX_MCCABE")]
+    max_mccabe: Option<u32>,
 
-    // Check exclude rules (blacklist) - if it matches exclude, DON'T process
-    if let Some(rules) = exclude_rules {
-        if rules.matches_file(file_path) {
-            return false;
-        }
-    }
+    /// Fail (exit 1) when a function's cognitive complexity exceeds N
+    #[arg(long, value_name = "N", env = "KNOTS_MAX_COGNITIVE")]
+    max_cognitive: Option<u32>,
 
-    true
-}
+    /// Fail (exit 1) when a function's nesting depth exceeds N
+    #[arg(long, value_name = "N", env = "KNOTS_MAX_NESTING")]
+    max_nesting: Option<u32>,
 
-/// Collect function metrics from a file
-fn collect_function_metrics(
-    tree: &Tree,
-    source_code: &str,
-    file_path: &str,
-    include_rules: &Option<FilterRules>,
-    exclude_rules: &Option<FilterRules>,
-) -> Vec<FunctionMetrics> {
-    let root_node = tree.root_node();
-    let mut cursor = root_node.walk();
-    let mut metrics = Vec::new();
+    /// Fail (exit 1) when a function's SLOC exceeds N
+    #[arg(long, value_name = "N", env = "KNOTS_MAX_SLOC")]
+    max_sloc: Option<u32>,
 
-    visit_functions(&mut cursor, source_code, &mut |node, src| {
-        if let Some(name) = get_function_name(node, src) {
-            let mccabe = calculate_mccabe_complexity(node, src.as_bytes());
-            let cognitive = calculate_cognitive_complexity(node, src.as_bytes());
-            let nesting = calculate_nesting_depth(node);
-            let sloc = calculate_sloc(node, src.as_bytes());
-            let abc = calculate_abc_complexity(node, src.as_bytes());
-            let abc_magnitude = abc.magnitude();
-            let return_count = calculate_return_count(node);
-            let test_scoring = calculate_test_scoring(node, src.as_bytes());
-
-            let max_complexity = std::cmp::max(mccabe, cognitive);
+    /// Fail (exit 1) when a function has more than N magic numbers (literals other than 0 and 1)
+    #[arg(long, value_name = "N", env = "KNOTS_MAX_MAGIC_NUMBERS")]
+    max_magic_numbers: Option<u32>,
 
-            // Apply filter rules
-            if should_process_function(&name, max_complexity, include_rules, exclude_rules) {
-                metrics.push(FunctionMetrics {
-                    name,
-                    file_path: file_path.to_string(),
-                    mccabe,
-                    cognitive,
-                    nesting,
-                    sloc,
-                    abc_magnitude,
-                    return_count,
-                    test_scoring,
-                });
-            }
-        }
-    });
+    /// Fail (exit 1) when a function has more exit points (returns, cleanup gotos, exit/abort/longjmp)
+    #[arg(long, value_name = "N", env = "KNOTS_MAX_EXITS")]
+    max_exits: Option<u32>,
 
-    metrics
-}
+    /// Fail (exit 1) when an expression chains more than N member accesses (`a->b->c` is 3)
+    #[arg(long, value_name = "N", env = "KNOTS_MAX_DEREF_CHAIN")]
+    max_deref_chain: Option<u32>,
 
-/// Check if a function should be processed based on include/exclude rules
-fn should_process_function(
-    function_name: &str,
-    complexity: u32,
-    include_rules: &Option<FilterRules>,
-    exclude_rules: &Option<FilterRules>,
-) -> bool {
-    // Check include rules first (whitelist)
-    if let Some(rules) = include_rules {
-        if !rules.matches_function(function_name) {
-            return false;
-        }
-        if !rules.matches_complexity(complexity) {
-            return false;
-        }
-    }
+    /// Fail (exit 1) when a function's cognitive complexity exceeds its McCabe complexity
+    /// by more than N, a sign of nesting that guard clauses could flatten
+    #[arg(long, value_name = "N", env = "KNOTS_MAX_GAP")]
+    max_gap: Option<u32>,
 
-    // Check exclude rules (blacklist) - if it matches exclude, DON'T process
-    // Only apply function/complexity filters if they're actually specified
-    if let Some(rules) = exclude_rules {
-        let matches_func = !rules.function_patterns.is_empty() && rules.matches_function(function_name);
-        let matches_complexity = (rules.min_complexity.is_some() || rules.max_complexity.is_some()) && rules.matches_complexity(complexity);
+    /// Fail (exit 1) when a file's total McCabe complexity, summed over its functions, exceeds N
+    #[arg(long, value_name = "N", env = "KNOTS_MAX_FILE_COMPLEXITY")]
+    max_file_complexity: Option<u32>,
 
-        // If no function patterns specified, only check complexity
-        // If no complexity bounds specified, only check function patterns
-        // If both specified, require both to match
-        let should_exclude = if rules.function_patterns.is_empty() && rules.min_complexity.is_none() && rules.max_complexity.is_none() {
-            // No function-level filters, don't exclude based on function criteria
-            false
-        } else if rules.function_patterns.is_empty() {
-            // Only complexity filter
-            matches_complexity
-        } else if rules.min_complexity.is_none() && rules.max_complexity.is_none() {
-            // Only function pattern filter
-            matches_func
+    /// Fail (exit 1) when a file defines more than N functions
+    #[arg(long, value_name = "N", env = "KNOTS_MAX_FUNCTIONS_PER_FILE")]
+    max_functions_per_file: Option<usize>,
+
+    /// Apply a coding standard's recommended limits as --max-* thresholds and report
+    /// compliance; explicit --max-* options override the standard's values
+    #[arg(long, value_enum, value_name = "STANDARD")]
+    standard: Option<Standard>,
+
+    /// Warn about statements that follow an unconditional return/goto/break/continue
+    #[arg(long)]
+    warn_unreachable: bool,
+
+    /// Warn about pointer parameters a function never writes through, which could be `const`
+    #[arg(long)]
+    warn_const: bool,
+
+    /// Decimal places for floating-point values (ABC magnitude, averages, grade scores)
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(0..=10))]
+    precision: u8,
+
+    /// Analyze the same files as of this git ref, show what changed, and fail if any
+    /// function got more complex (text output only)
+    #[arg(long, value_name = "REF", conflicts_with = "since")]
+    baseline_branch: Option<String>,
+
+    /// Ignore baseline changes of at most EPS in float metrics (ABC magnitude);
+    /// integer metrics are always compared exactly
+    #[arg(long, value_name = "EPS", default_value_t = 0.0, requires = "baseline_branch")]
+    baseline_tolerance: f64,
+
+    /// Rank the recursive summary's top 5 by an expression over metric names, e.g. "mccabe*2 + test_score"
+    #[arg(long, value_name = "EXPR", value_parser = RankExpr::parse)]
+    rank_by: Option<RankExpr>,
+
+    /// Complexity figure behind the emoji indicators and the worst-function ranking
+    #[arg(long, value_enum, default_value = "max")]
+    primary_metric: PrimaryMetric,
+
+    /// Aggregate the summary by directory, DEPTH levels below the scanned path (default: 1)
+    #[arg(long, value_name = "DEPTH", num_args = 0..=1, default_missing_value = "1")]
+    group_by_dir: Option<usize>,
+
+    /// Add a summary section bucketing files into emoji bands by their worst function
+    #[arg(long)]
+    by_emoji: bool,
+
+    /// Add a summary section ranking functions by risk score: weighted max
+    /// complexity plus test score plus missing documentation
+    #[arg(long)]
+    by_risk: bool,
+
+    /// Add a summary section listing functions with a switch inside another
+    /// switch's case, with the nested switches' line numbers
+    #[arg(long)]
+    nested_switches: bool,
+
+    /// Add a summary section with McCabe, cognitive, ABC, returns and test score
+    /// per 100 SLOC for the whole run and each file, to compare code of different sizes
+    #[arg(long)]
+    normalized: bool,
+
+    /// Weights of max complexity, test score and missing documentation in the --by-risk score
+    #[arg(long, value_delimiter = ',', value_name = "C,T,D", requires = "by_risk")]
+    risk_weights: Vec<f64>,
+
+    /// Print only one metric's value as a bare number for scripting: the total over all
+    /// functions unless --max or --function is given
+    #[arg(long, value_enum, value_name = "NAME")]
+    metric: Option<Field>,
+
+    /// With --metric, print the sum over all functions (the default)
+    #[arg(long, requires = "metric", conflicts_with_all = ["max", "function"])]
+    total: bool,
+
+    /// With --metric, print the largest value of any function
+    #[arg(long, requires = "metric", conflicts_with = "function")]
+    max: bool,
+
+    /// With --metric, print the value for the function with this name
+    #[arg(long, value_name = "NAME", requires = "metric")]
+    function: Option<String>,
+
+    /// Print pmccabe-compatible columns (modified McCabe, traditional McCabe, statements,
+    /// first line, lines, file(line): name) for diffing against pmccabe reports
+    #[arg(long)]
+    pmccabe: bool,
+
+    /// Print one aligned row per function under a header row, with the --fields columns
+    /// in the order given; names longer than 40 characters are cut short with an ellipsis
+    #[arg(long)]
+    table: bool,
+
+    /// Sort the --table rows by this metric, largest first (default: file order)
+    #[arg(long, value_enum, value_name = "NAME", requires = "table")]
+    sort: Option<Field>,
+
+    /// Print a unit-test stub for the named function: a test per boundary value in its
+    /// comparisons, fakes for the functions it calls, and assertion placeholders
+    #[arg(long, value_name = "FUNCTION")]
+    scaffold: Option<String>,
+
+    /// Test framework the --scaffold stub is written for
+    #[arg(long, value_enum, default_value = "unity", requires = "scaffold")]
+    framework: Framework,
+
+    /// Print the chain of control statements leading to the deepest nesting point of the
+    /// named function, or of the most deeply nested function when no name is given
+    #[arg(long, value_name = "FUNCTION", num_args = 0..=1)]
+    explain_nesting: Option<Option<String>>,
+
+    /// Print each function's McCabe complexity with a tally of the decision kinds behind
+    /// it, e.g. "6 if, 2 for, 1 switch, 3 &&"
+    #[arg(long)]
+    explain_summary: bool,
+
+    /// Score the function prototypes (declarations without a body) instead of definitions:
+    /// signature and documentation only. Directory scans include headers
+    #[arg(long)]
+    prototypes: bool,
+
+    /// After the summary, print the source of the top 5 worst functions, or of the named
+    /// function, with line numbers
+    #[arg(long, value_name = "FUNCTION", num_args = 0..=1)]
+    show_source: Option<Option<String>>,
+
+    /// With --show-source, show at most N lines per function, eliding the middle of longer ones
+    #[arg(long, value_name = "N", default_value_t = 40, requires = "show_source")]
+    source_lines: usize,
+
+    /// List the files that would be analyzed, after filters, with each file's language,
+    /// then exit without parsing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Browse the analyzed functions in an interactive, sortable table (needs the `tui` feature)
+    #[arg(long)]
+    tui: bool,
+
+    /// Attribute each function to the author of most of its lines (git blame) and
+    /// print total and average complexity per author; files not in git are skipped
+    #[arg(long)]
+    by_author: bool,
+
+    /// SLOC above which a low-complexity function is reported as "long but simple"
+    #[arg(long, default_value_t = 100, value_name = "N")]
+    long_simple_sloc: u32,
+
+    /// Max(McCabe, Cognitive) below which a long function is reported as "long but simple"
+    #[arg(long, default_value_t = 5, value_name = "N")]
+    long_simple_complexity: u32,
+
+    /// Print mean/std-dev, Pearson correlations, and histograms for the selected metrics
+    #[arg(long)]
+    stats: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "text")]
+    format: OutputFormat,
+
+    /// Format of the summary (totals, averages, worst functions) independent of --format;
+    /// with json the summary is the only thing on stdout and the per-function lines go to stderr
+    #[arg(long, value_enum, default_value = "text", conflicts_with = "no_summary")]
+    summary_format: SummaryFormat,
+
+    /// With --format badge, write the badge JSON to FILE instead of stdout
+    #[arg(long, value_name = "FILE")]
+    badge_file: Option<PathBuf>,
+
+    /// Show a maintainability letter grade (A-F) next to each function and in the summary
+    #[arg(long)]
+    grade: bool,
+
+    /// Maintainability score cut points for grades A,B,C,D (anything above is F)
+    #[arg(long, value_delimiter = ',', value_name = "A,B,C,D", requires = "grade")]
+    grade_cut_points: Vec<f64>,
+
+    /// Print the JSON Schema of the JSON report and exit
+    #[arg(long, hide = true)]
+    print_schema: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compare two JSON reports (from --append) and show per-function metric changes
+    Diff {
+        /// Report before the change
+        old: PathBuf,
+        /// Report after the change
+        new: PathBuf,
+        /// Decimal places for ABC magnitude
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(0..=10))]
+        precision: u8,
+        /// Ignore changes of at most EPS in float metrics (ABC magnitude)
+        #[arg(long, value_name = "EPS", default_value_t = 0.0)]
+        tolerance: f64,
+        /// Disable colored output
+        #[arg(long)]
+        no_color: bool,
+    },
+    /// Run every configured gate (--max-*, --strict, --baseline-branch) and print a
+    /// pass/fail table; exits 1 if any gate fails
+    ///
+    /// Example: knots check --json src -r --max-mccabe 15 --baseline-branch main
+    Check {
+        /// Print the table as JSON
+        #[arg(long)]
+        json: bool,
+        /// FILE and analysis options, as for the default command
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, value_name = "ARGS")]
+        args: Vec<String>,
+    },
+    /// Chart the per-run average of a metric recorded with --sqlite, oldest run first
+    ///
+    /// Example: knots trend --sqlite metrics.db --metric cognitive --svg > trend.svg
+    Trend {
+        /// Database written by --sqlite
+        #[arg(long, value_name = "FILE")]
+        sqlite: PathBuf,
+        /// Metric to average over each run's functions
+        #[arg(long, value_enum, default_value = "mccabe")]
+        metric: Field,
+        /// Print an SVG sparkline, with each run's date and commit on hover, instead of text
+        #[arg(long)]
+        svg: bool,
+        /// Decimal places for the averages
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(0..=10))]
+        precision: u8,
+    },
+}
+
+/// Output formats selectable with --format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human-readable text with emoji indicators
+    Text,
+    /// Prometheus text exposition format (gauges per function plus totals)
+    Prometheus,
+    /// GitHub Actions workflow commands (::warning/::error annotations)
+    Github,
+    /// Graphviz call graph: functions colored by complexity band, edges for calls within a file
+    Dot,
+    /// shields.io endpoint JSON for a badge showing the average complexity
+    Badge,
+    /// One tab-separated row per function for editor plugins:
+    /// name, McCabe, cognitive, first line, last line
+    Editor,
+}
+
+/// Summary formats selectable with --summary-format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SummaryFormat {
+    /// The text sections after the per-function output
+    Text,
+    /// One JSON object on stdout: totals, averages, worst functions, and file counts
+    Json,
+}
+
+/// Weight of McCabe in `--primary-metric blend`
+const BLEND_MCCABE_WEIGHT: f64 = 0.4;
+/// Weight of cognitive complexity in `--primary-metric blend`
+const BLEND_COGNITIVE_WEIGHT: f64 = 0.6;
+
+/// How McCabe and cognitive complexity combine into one figure (--primary-metric)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum PrimaryMetric {
+    /// McCabe cyclomatic complexity alone
+    Mccabe,
+    /// Cognitive complexity alone
+    Cognitive,
+    /// The larger of the two
+    #[default]
+    Max,
+    /// 0.4 x McCabe + 0.6 x Cognitive, rounded
+    Blend,
+}
+
+impl PrimaryMetric {
+    fn complexity(&self, func: &FunctionMetrics) -> u32 {
+        match self {
+            PrimaryMetric::Mccabe => func.mccabe,
+            PrimaryMetric::Cognitive => func.cognitive,
+            PrimaryMetric::Max => func.max_complexity(),
+            PrimaryMetric::Blend => {
+                (BLEND_MCCABE_WEIGHT * func.mccabe as f64 + BLEND_COGNITIVE_WEIGHT * func.cognitive as f64).round() as u32
+            }
+        }
+    }
+
+    /// Metrics the figure is computed from, so --metrics keeps them
+    fn fields(&self) -> Vec<Field> {
+        match self {
+            PrimaryMetric::Mccabe => vec![Field::Mccabe],
+            PrimaryMetric::Cognitive => vec![Field::Cognitive],
+            PrimaryMetric::Max | PrimaryMetric::Blend => vec![Field::Mccabe, Field::Cognitive],
+        }
+    }
+}
+
+/// Coding standard whose recommended limits --standard applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Standard {
+    /// MISRA C with the HIS metrics usually audited alongside it
+    Misra,
+    /// NASA/JPL Power of Ten
+    Nasa,
+    /// SEI CERT C
+    Cert,
+}
+
+/// MISRA C: single exit (Rule 15.5); HIS limits v(G) <= 10 and nesting <= 4
+const MISRA_THRESHOLDS: Thresholds = Thresholds {
+    mccabe: Some(10),
+    cognitive: None,
+    nesting: Some(4),
+    sloc: None,
+    exits: Some(1),
+    magic_numbers: None,
+    dereference_chain: None,
+    cognitive_gap: None,
+};
+
+/// Power of Ten rule 4: no function longer than 60 lines
+const NASA_THRESHOLDS: Thresholds = Thresholds {
+    mccabe: None,
+    cognitive: None,
+    nesting: None,
+    sloc: Some(60),
+    exits: None,
+    magic_numbers: None,
+    dereference_chain: None,
+    cognitive_gap: None,
+};
+
+/// CERT DCL06-C: use symbolic constants instead of literal values
+const CERT_THRESHOLDS: Thresholds = Thresholds {
+    mccabe: None,
+    cognitive: None,
+    nesting: None,
+    sloc: None,
+    exits: None,
+    magic_numbers: Some(0),
+    dereference_chain: None,
+    cognitive_gap: None,
+};
+
+impl Standard {
+    fn name(&self) -> &'static str {
+        match self {
+            Standard::Misra => "MISRA C",
+            Standard::Nasa => "NASA Power of Ten",
+            Standard::Cert => "CERT C",
+        }
+    }
+
+    fn thresholds(&self) -> Thresholds {
+        match self {
+            Standard::Misra => MISRA_THRESHOLDS,
+            Standard::Nasa => NASA_THRESHOLDS,
+            Standard::Cert => CERT_THRESHOLDS,
+        }
+    }
+}
+
+/// Per-function limits set with the --max-* options; unset limits are not checked
+#[derive(Debug, Clone, Copy, Default)]
+struct Thresholds {
+    mccabe: Option<u32>,
+    cognitive: Option<u32>,
+    nesting: Option<u32>,
+    sloc: Option<u32>,
+    exits: Option<u32>,
+    magic_numbers: Option<u32>,
+    dereference_chain: Option<u32>,
+    cognitive_gap: Option<u32>,
+}
+
+/// Reads one integer metric from a function
+type MetricAccessor = fn(&FunctionMetrics) -> u32;
+
+impl Args {
+    /// The --max-* limits: each flag, else its KNOTS_MAX_* environment variable (both
+    /// resolved by clap), else the --standard's value
+    fn thresholds(&self) -> Thresholds {
+        Thresholds {
+            mccabe: self.max_mccabe,
+            cognitive: self.max_cognitive,
+            nesting: self.max_nesting,
+            sloc: self.max_sloc,
+            exits: self.max_exits,
+            magic_numbers: self.max_magic_numbers,
+            dereference_chain: self.max_deref_chain,
+            cognitive_gap: self.max_gap,
+        }
+        .or(self.standard.map(|standard| standard.thresholds()).unwrap_or_default())
+    }
+}
+
+impl Thresholds {
+    /// (metric name, limit, value accessor) for every threshold
+    fn limits(&self) -> [(&'static str, Option<u32>, MetricAccessor); 8] {
+        [
+            ("mccabe", self.mccabe, |func| func.mccabe),
+            ("cognitive", self.cognitive, |func| func.cognitive),
+            ("nesting", self.nesting, |func| func.nesting),
+            ("sloc", self.sloc, |func| func.sloc),
+            ("exit points", self.exits, |func| func.exit_points),
+            ("magic numbers", self.magic_numbers, |func| func.magic_numbers),
+            ("dereference chain", self.dereference_chain, |func| func.max_dereference_chain),
+            ("cognitive gap", self.cognitive_gap, |func| func.cognitive_gap()),
+        ]
+    }
+
+    /// These limits, with any unset one taken from `fallback`
+    fn or(self, fallback: Thresholds) -> Thresholds {
+        Thresholds {
+            mccabe: self.mccabe.or(fallback.mccabe),
+            cognitive: self.cognitive.or(fallback.cognitive),
+            nesting: self.nesting.or(fallback.nesting),
+            sloc: self.sloc.or(fallback.sloc),
+            exits: self.exits.or(fallback.exits),
+            magic_numbers: self.magic_numbers.or(fallback.magic_numbers),
+            dereference_chain: self.dereference_chain.or(fallback.dereference_chain),
+            cognitive_gap: self.cognitive_gap.or(fallback.cognitive_gap),
+        }
+    }
+
+    /// Metrics that must be computed for the thresholds that are set
+    fn required_fields(&self) -> Vec<Field> {
+        [
+            (self.mccabe.or(self.cognitive_gap), Field::Mccabe),
+            (self.cognitive.or(self.cognitive_gap), Field::Cognitive),
+            (self.nesting, Field::Nesting),
+            (self.sloc, Field::Sloc),
+            (self.exits, Field::Returns),
+        ]
+        .into_iter()
+        .filter_map(|(limit, field)| limit.map(|_| field))
+        .collect()
+    }
+}
+
+/// A function metric above its --max-* threshold
+struct Violation<'a> {
+    func: &'a FunctionMetrics,
+    metric: &'static str,
+    value: u32,
+    threshold: u32,
+}
+
+impl Violation<'_> {
+    fn message(&self) -> String {
+        format!("{}() has {} {} (max {})", self.func.name, self.metric, self.value, self.threshold)
+    }
+}
+
+/// Every threshold violation, in function order
+fn find_violations<'a>(all_metrics: &'a [FunctionMetrics], thresholds: &Thresholds) -> Vec<Violation<'a>> {
+    let limits = thresholds.limits();
+    all_metrics
+        .iter()
+        .flat_map(|func| {
+            limits.iter().filter_map(move |&(metric, limit, value_of)| {
+                let threshold = limit?;
+                let value = value_of(func);
+                (value > threshold && !func.suppresses(metric)).then_some(Violation { func, metric, value, threshold })
+            })
+        })
+        .collect()
+}
+
+/// Escape text for a workflow command; properties also escape ':' and ','
+fn escape_workflow_command(value: &str, property: bool) -> String {
+    let escaped = value.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A");
+    if property {
+        escaped.replace(':', "%3A").replace(',', "%2C")
+    } else {
+        escaped
+    }
+}
+
+/// GitHub Actions annotations: threshold violations are errors, otherwise the
+/// emoji band decides (😠 21-49 is a warning, 😢 50+ an error, lower bands are silent)
+fn format_github(all_metrics: &[FunctionMetrics], violations: &[Violation]) -> String {
+    let mut out = String::new();
+
+    for func in all_metrics {
+        let messages: Vec<String> = violations
+            .iter()
+            .filter(|violation| std::ptr::eq(violation.func, func))
+            .map(Violation::message)
+            .collect();
+
+        let complexity = func.max_complexity();
+        let (level, message) = if !messages.is_empty() {
+            ("error", messages.join("; "))
+        } else if complexity >= 50 {
+            ("error", format!("{}() has critical complexity {} (McCabe {}, Cognitive {})", func.name, complexity, func.mccabe, func.cognitive))
+        } else if complexity >= 21 {
+            ("warning", format!("{}() has high complexity {} (McCabe {}, Cognitive {})", func.name, complexity, func.mccabe, func.cognitive))
         } else {
-            // Both specified, require both
-            matches_func && matches_complexity
+            continue;
+        };
+
+        out.push_str(&format!(
+            "::{} file={},line={},title={}::{}\n",
+            level,
+            escape_workflow_command(&func.file_path, true),
+            func.line,
+            escape_workflow_command(&format!("knots: {}", func.name), true),
+            escape_workflow_command(&message, false)
+        ));
+    }
+
+    out
+}
+
+/// Settings that change how metrics are computed
+#[derive(Clone)]
+struct AnalysisOptions {
+    mccabe: McCabeOptions,
+    cognitive: CognitiveOptions,
+    test_scoring: TestScoringOptions,
+    /// Expand macros with a C compiler before parsing
+    preprocessor: Option<Preprocessor>,
+    /// Weight ABC conditions by nesting level
+    abc_weighted: bool,
+    /// Exclude initializer data from ABC and SLOC
+    data_aware: bool,
+    /// Functions declared in --public-headers; None means every non-static function is exported
+    public_api: Option<HashSet<String>>,
+    /// Thresholds checked after each file with --fail-fast, stopping at the first violation
+    fail_fast: Option<Thresholds>,
+    /// Layout of the threshold violation messages (--message-template)
+    message_template: MessageTemplate,
+    /// Conditions matching any of these mark an `if` body as error handling
+    error_patterns: Vec<Regex>,
+    /// Words counted as debt markers in comments
+    debt_markers: Vec<String>,
+    /// With --since, the Unix time before which changes are too old to report
+    since: Option<i64>,
+    /// With --lines, the first and last line a reported function must overlap
+    lines: Option<(usize, usize)>,
+    /// With --metrics, the only metrics to compute; the others, and the secondary
+    /// metrics outside `Field`, are left at zero and omitted from the JSON report
+    metrics: Option<Vec<Field>>,
+    /// Measure function-like macro bodies containing control flow as pseudo-functions
+    macros: bool,
+    /// Measure formatting consistency (--style-check)
+    style_check: bool,
+    /// Count McCabe by pmccabe's rules as well (--pmccabe)
+    pmccabe: bool,
+    /// With --include-docs, the longest doc comment kept, in characters
+    doc_limit: Option<usize>,
+    /// Macros from --define deciding which #ifdef branches are analyzed
+    defines: Option<Defines>,
+}
+
+impl AnalysisOptions {
+    fn computes(&self, field: Field) -> bool {
+        self.metrics.as_ref().map(|metrics| metrics.contains(&field)).unwrap_or(true)
+    }
+
+    /// JSON keys of the metrics --metrics leaves out; empty when every metric is computed
+    fn skipped_metrics(&self) -> Vec<&'static str> {
+        if self.metrics.is_none() {
+            return Vec::new();
+        }
+        Field::ALL
+            .iter()
+            .filter(|field| !self.computes(**field))
+            .flat_map(|field| field.json_keys().iter().copied())
+            .chain(SECONDARY_METRICS)
+            .collect()
+    }
+}
+
+/// Presentation settings shared by the text output paths
+struct OutputOptions {
+    verbose: bool,
+    /// Print only per-function lines, without summary sections
+    no_summary: bool,
+    /// Skip writing report.txt (--no-report)
+    no_report: bool,
+    /// Report unreferenced static functions separately instead of counting them
+    flag_unused_static: bool,
+    fields: Vec<Field>,
+    /// Decimal places for floating-point values
+    precision: usize,
+    /// Grade cut points, present when letter grades are enabled
+    grade_cut_points: Option<Vec<f64>>,
+    /// (min SLOC, max complexity) thresholds for the long-but-simple section
+    long_simple: (u32, u32),
+    /// Most per-function lines to print to the terminal (--limit)
+    limit: Option<usize>,
+    /// Sort key for the recursive summary's top 5; None ranks by the primary metric
+    rank_by: Option<RankExpr>,
+    /// Complexity figure for emoji indicators and rankings
+    primary: PrimaryMetric,
+}
+
+impl OutputOptions {
+    /// Emoji indicator for a function, followed by its letter grade when enabled
+    fn badge(&self, func: &FunctionMetrics) -> String {
+        let emoji = get_complexity_emoji(self.primary.complexity(func));
+        match &self.grade_cut_points {
+            Some(cut_points) => format!("{} {}", emoji, grade(func, cut_points)),
+            None => emoji.to_string(),
+        }
+    }
+}
+
+/// Whether `knots check` got --json, and its arguments for the default command without it
+///
+/// The trailing arguments take everything from the first path or option on, --json included,
+/// so it is accepted there too and removed before they are parsed again.
+fn split_check_json(json: bool, args: Vec<String>) -> (bool, Vec<String>) {
+    let trailing = args.iter().any(|arg| arg == "--json");
+    (json || trailing, args.into_iter().filter(|arg| arg != "--json").collect())
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    // `knots check` takes the same options as the default command after its own
+    let (args, check_json) = match args.command {
+        Some(Command::Check { json, args }) => {
+            let (json, args) = split_check_json(json, args);
+            (Args::parse_from(std::iter::once("knots".to_string()).chain(args)), Some(json))
+        }
+        _ => (args, None),
+    };
+
+    if let Some(Command::Diff {
+        old,
+        new,
+        precision,
+        tolerance,
+        no_color,
+    }) = &args.command
+    {
+        if *no_color {
+            colored::control::set_override(false);
+        }
+        let (old_report, new_report) = (load_json_report(old)?, load_json_report(new)?);
+        display_report_diff(&diff_reports(&old_report, &new_report, *tolerance), *precision as usize);
+        return Ok(());
+    }
+
+    if let Some(Command::Trend {
+        sqlite,
+        metric,
+        svg,
+        precision,
+    }) = &args.command
+    {
+        return show_trend(sqlite, *metric, *svg, *precision as usize);
+    }
+
+    if args.print_schema {
+        let schema = schemars::schema_for!(JsonReport);
+        println!("{}", serde_json::to_string_pretty(&schema).context("Failed to serialize schema")?);
+        return Ok(());
+    }
+
+    // Load filter rules
+    let include_rules = if let Some(path) = &args.include {
+        Some(FilterRules::from_file(path)?)
+    } else {
+        None
+    };
+
+    let exclude_rules = if let Some(path) = &args.exclude {
+        Some(FilterRules::from_file(path)?)
+    } else {
+        None
+    };
+
+    let grade_cut_points = if !args.grade {
+        None
+    } else if args.grade_cut_points.is_empty() {
+        Some(DEFAULT_GRADE_CUT_POINTS.to_vec())
+    } else {
+        if args.grade_cut_points.len() != DEFAULT_GRADE_CUT_POINTS.len()
+            || args.grade_cut_points.windows(2).any(|w| w[0] > w[1])
+        {
+            anyhow::bail!("--grade-cut-points must be four ascending values for grades A,B,C,D");
+        }
+        Some(args.grade_cut_points.clone())
+    };
+
+    let risk_weights = match args.risk_weights[..] {
+        [] => RiskWeights::DEFAULT,
+        [complexity, testability, documentation] if args.risk_weights.iter().all(|&weight| weight >= 0.0) => {
+            RiskWeights {
+                complexity,
+                testability,
+                documentation,
+            }
+        }
+        _ => anyhow::bail!("--risk-weights must be three non-negative values: complexity, test score, documentation"),
+    };
+
+    let output = OutputOptions {
+        verbose: args.verbose,
+        no_summary: args.no_summary,
+        no_report: args.no_report,
+        flag_unused_static: args.flag_unused_static,
+        fields: if !args.fields.is_empty() {
+            args.fields.clone()
+        } else if !args.metrics.is_empty() {
+            args.metrics.clone()
+        } else {
+            Field::DEFAULT.to_vec()
+        },
+        precision: args.precision as usize,
+        grade_cut_points,
+        long_simple: (args.long_simple_sloc, args.long_simple_complexity),
+        limit: args.limit,
+        rank_by: args.rank_by.clone(),
+        primary: args.primary_metric,
+    };
+
+    // Collect files to process
+    let mut file_flags = HashMap::new();
+    let (files, inaccessible_entries) = if let Some(compile_commands_path) = &args.compile_commands {
+        // Load files from compile_commands.json
+        let database = load_compile_commands(compile_commands_path, &include_rules, &exclude_rules)?;
+        file_flags = database.file_flags;
+        (database.files, 0)
+    } else if let Some(file_path) = &args.file {
+        // Use regular file/directory path
+        collect_files(file_path, args.recursive, args.prototypes, &include_rules, &exclude_rules)?
+    } else {
+        anyhow::bail!("Either FILE or --compile-commands must be specified");
+    };
+
+    let (files, generated_files) = if args.exclude_generated {
+        let pattern = args.generated_marker.as_deref().unwrap_or(DEFAULT_GENERATED_MARKER);
+        let marker = Regex::new(pattern).with_context(|| format!("Invalid --generated-marker regex: {}", pattern))?;
+        exclude_generated_files(files, &marker)
+    } else {
+        (files, 0)
+    };
+
+    if args.lines.is_some() && files.len() != 1 {
+        anyhow::bail!("--lines applies to a single file, but {} files were selected", files.len());
+    }
+
+    let extra_sections = args.stats
+        || args.by_author
+        || args.group_by_dir.is_some()
+        || args.by_emoji
+        || args.by_risk
+        || args.nested_switches
+        || args.normalized;
+    if args.summary_format == SummaryFormat::Json && extra_sections {
+        anyhow::bail!(
+            "--summary-format json cannot be combined with --stats, --by-author, --group-by-dir, --by-emoji, \
+             --by-risk, --nested-switches or --normalized"
+        );
+    }
+
+    if args.dry_run {
+        print!("{}", format_dry_run(&files, generated_files, inaccessible_entries));
+        return Ok(());
+    }
+
+    let thresholds = args.thresholds();
+
+    // Thresholds and --rank-by need their metrics even when --metrics leaves them out
+    let metrics = if args.metrics.is_empty() {
+        None
+    } else {
+        // These read secondary metrics, which --metrics never computes
+        let secondary_flags = [
+            (thresholds.magic_numbers.is_some(), "--max-magic-numbers"),
+            (thresholds.dereference_chain.is_some(), "--max-deref-chain"),
+            (args.warn_unreachable, "--warn-unreachable"),
+            (args.warn_const, "--warn-const"),
+            (args.require_default, "--require-default"),
+            (args.nested_switches, "--nested-switches"),
+            (args.format == OutputFormat::Dot, "--format dot"),
+            (args.sqlite.is_some(), "--sqlite"),
+        ];
+        if let Some((_, flag)) = secondary_flags.iter().find(|(set, _)| *set) {
+            anyhow::bail!("{} cannot be combined with --metrics", flag);
+        }
+
+        let mut metrics = args.metrics.clone();
+        let rank_fields = args.rank_by.as_ref().map(RankExpr::fields).unwrap_or_default();
+        let primary_fields = args.primary_metric.fields();
+        let file_fields = args.max_file_complexity.map(|_| Field::Mccabe);
+        let selected_fields = args.metric.into_iter().chain(args.sort);
+        let normalized_fields = if args.normalized {
+            vec![Field::Sloc, Field::Mccabe, Field::Cognitive, Field::Abc, Field::Returns, Field::TestScore]
+        } else {
+            Vec::new()
+        };
+        let grade_fields = if args.grade {
+            vec![Field::Mccabe, Field::Cognitive, Field::Nesting, Field::Sloc]
+        } else {
+            Vec::new()
+        };
+        let quadrant_fields = if args.matrix || args.by_risk {
+            vec![Field::Mccabe, Field::Cognitive, Field::TestScore]
+        } else {
+            Vec::new()
+        };
+        for field in thresholds
+            .required_fields()
+            .into_iter()
+            .chain(rank_fields)
+            .chain(primary_fields)
+            .chain(file_fields)
+            .chain(selected_fields)
+            .chain(normalized_fields)
+            .chain(grade_fields)
+            .chain(quadrant_fields)
+        {
+            if !metrics.contains(&field) {
+                metrics.push(field);
+            }
+        }
+        Some(metrics)
+    };
+
+    let public_api = match &args.public_headers {
+        Some(pattern) => Some(load_public_api(pattern)?),
+        None => None,
+    };
+
+    let error_patterns = if args.error_patterns.is_empty() {
+        DEFAULT_ERROR_PATTERNS.iter().map(|pattern| pattern.to_string()).collect()
+    } else {
+        args.error_patterns.clone()
+    };
+    let error_patterns = error_patterns
+        .iter()
+        .map(|pattern| Regex::new(pattern).with_context(|| format!("Invalid --error-pattern regex: {}", pattern)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let assert_macros = match (args.count_asserts, args.assert_macros.is_empty()) {
+        (false, _) => Vec::new(),
+        (true, true) => DEFAULT_ASSERT_MACROS.iter().map(|name| name.to_string()).collect(),
+        (true, false) => args.assert_macros.clone(),
+    };
+
+    let analysis_options = AnalysisOptions {
+        mccabe: McCabeOptions {
+            exclude_logical_ops: args.no_logical_ops,
+            assert_macros: assert_macros.clone(),
+            count_cases: args.switch_cases,
+        },
+        cognitive: CognitiveOptions {
+            count_fallthrough: args.count_fallthrough,
+            count_generic: args.count_generic,
+            assert_macros,
+            max_nesting_penalty: args.max_nesting_penalty,
+        },
+        test_scoring: TestScoringOptions {
+            credit_inline_docs: args.credit_inline_docs,
+        },
+        abc_weighted: args.abc_weighted,
+        data_aware: args.data_aware,
+        public_api,
+        fail_fast: args.fail_fast.then_some(thresholds),
+        message_template: args.message_template.clone().unwrap_or_default(),
+        error_patterns,
+        debt_markers: if args.debt_markers.is_empty() {
+            DEFAULT_DEBT_MARKERS.iter().map(|marker| marker.to_string()).collect()
+        } else {
+            args.debt_markers.clone()
+        },
+        since: args.since.map(since_cutoff).transpose()?,
+        lines: args.lines,
+        metrics,
+        macros: args.macros,
+        style_check: args.style_check,
+        pmccabe: args.pmccabe,
+        doc_limit: args.include_docs.then_some(args.doc_limit),
+        defines: (!args.define.is_empty()).then(|| Defines::new(&args.define)),
+        preprocessor: args.preprocess.as_ref().map(|compiler| Preprocessor {
+            compiler: compiler.clone(),
+            cflags: args
+                .cflags
+                .as_deref()
+                .unwrap_or("")
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
+            file_flags,
+        }),
+    };
+
+    if let Some(json) = check_json {
+        let options = AnalysisOptions {
+            fail_fast: None,
+            ..analysis_options
+        };
+        let (all_metrics, counts) = analyze_files(&files, &include_rules, &exclude_rules, &options)?;
+        if all_metrics.is_empty() {
+            anyhow::bail!("No functions found in any files (skipped {} files)", counts.skipped.total());
+        }
+
+        let mut gates = threshold_gates(&all_metrics, &thresholds);
+        if args.strict {
+            gates.push(missing_return_gate(&all_metrics));
+        }
+        if args.require_default {
+            gates.push(missing_default_gate(&all_metrics));
+        }
+        if let Some(limit) = args.max_file_complexity {
+            gates.push(file_complexity_gate(&all_metrics, limit));
+        }
+        if let Some(limit) = args.max_functions_per_file {
+            gates.push(functions_per_file_gate(&all_metrics, limit));
+        }
+        if let Some(reference) = &args.baseline_branch {
+            let baseline = analyze_baseline(reference, &files, &include_rules, &exclude_rules, &options)?;
+            let current = JsonReport {
+                functions: all_metrics.clone(),
+                ..JsonReport::default()
+            };
+            gates.push(baseline_gate(reference, &diff_reports(&baseline, &current, args.baseline_tolerance)));
+        }
+        if gates.is_empty() {
+            anyhow::bail!("No gates configured; set --max-*, --strict, or --baseline-branch");
+        }
+
+        let report = CheckReport::new(gates);
+        if json {
+            println!("{}", serde_json::to_string_pretty(&report).context("Failed to serialize check results")?);
+        } else {
+            print!("{}", format_table(&report));
+        }
+        if !report.passed {
+            anyhow::bail!("{} of {} gate(s) failed", report.failed_count(), report.gates.len());
+        }
+        return Ok(());
+    }
+
+    if let Some(name) = &args.scaffold {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .context("Failed to set C language")?;
+
+        for file in &files {
+            let source = read_source(file, &analysis_options)?;
+            let tree = parser
+                .parse(&source.code, None)
+                .with_context(|| format!("Failed to parse C code in {}", file.display()))?;
+            if let Some(stub) = scaffold(tree.root_node(), source.code.as_bytes(), name, args.framework) {
+                print!("{}", stub);
+                return Ok(());
+            }
+        }
+        anyhow::bail!("No function named {} found", name);
+    }
+
+    if args.prototypes {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .context("Failed to set C language")?;
+
+        let mut out = String::new();
+        for file in &files {
+            let source = read_source(file, &analysis_options)?;
+            let tree = parser
+                .parse(&source.code, None)
+                .with_context(|| format!("Failed to parse C code in {}", file.display()))?;
+            let prototypes = find_prototypes(tree.root_node(), source.code.as_bytes());
+            out.push_str(&format_prototypes(&prototypes, &source, &file.display().to_string()));
+        }
+        if out.is_empty() {
+            anyhow::bail!("No function prototypes found in any files");
+        }
+        print!("{}", out);
+        return Ok(());
+    }
+
+    if args.explain_summary {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .context("Failed to set C language")?;
+
+        let mut out = String::new();
+        for file in &files {
+            let source = read_source(file, &analysis_options)?;
+            let tree = parser
+                .parse(&source.code, None)
+                .with_context(|| format!("Failed to parse C code in {}", file.display()))?;
+            let file_path = file.display().to_string();
+            let mut cursor = tree.root_node().walk();
+            visit_functions(&mut cursor, &source.code, &mut |node, src| {
+                if let Some(parsed_name) = get_function_name(node, src.as_bytes()) {
+                    let name = source.function_names.get(&parsed_name).unwrap_or(&parsed_name);
+                    out.push_str(&explain_summary(node, &source, name, &file_path, &analysis_options.mccabe));
+                }
+            });
+        }
+        if out.is_empty() {
+            anyhow::bail!("No functions found in any files");
+        }
+        print!("{}", out);
+        return Ok(());
+    }
+
+    if let Some(wanted) = &args.explain_nesting {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .context("Failed to set C language")?;
+
+        // (nesting depth, explanation) of the deepest function so far, or of the named one
+        let mut found: Option<(u32, String)> = None;
+        for file in &files {
+            let source = read_source(file, &analysis_options)?;
+            let tree = parser
+                .parse(&source.code, None)
+                .with_context(|| format!("Failed to parse C code in {}", file.display()))?;
+            let mut cursor = tree.root_node().walk();
+            visit_functions(&mut cursor, &source.code, &mut |node, src| {
+                let Some(parsed_name) = get_function_name(node, src.as_bytes()) else {
+                    return;
+                };
+                let name = source.function_names.get(&parsed_name).unwrap_or(&parsed_name);
+                let depth = calculate_nesting_depth(node);
+                let wanted_here = match wanted {
+                    Some(wanted) => found.is_none() && name == wanted,
+                    None => match &found {
+                        Some((deepest, _)) => depth > *deepest,
+                        None => true,
+                    },
+                };
+                if wanted_here {
+                    found = Some((depth, explain_nesting(node, &source, name, &file.display().to_string())));
+                }
+            });
+        }
+        match (found, wanted) {
+            (Some((_, explanation)), _) => print!("{}", explanation),
+            (None, Some(name)) => anyhow::bail!("No function named {} found", name),
+            (None, None) => anyhow::bail!("No functions found in any files"),
+        }
+        return Ok(());
+    }
+
+    if args.format == OutputFormat::Editor && files.len() != 1 {
+        anyhow::bail!("--format editor takes a single file, but {} files were selected", files.len());
+    }
+
+    // Modes other than the default report; clap allows at most one
+    let report_mode = args.metric.is_some()
+        || args.pmccabe
+        || args.table
+        || args.tui
+        || args.matrix
+        || args.format != OutputFormat::Text;
+
+    // For single file mode, use traditional output
+    if files.len() == 1 && !report_mode {
+        let file = &files[0];
+        let source = read_source(file, &analysis_options)?;
+        let source_code = &source.code;
+
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .context("Failed to set C language")?;
+
+        let tree = parser
+            .parse(source_code, None)
+            .with_context(|| format!("Failed to parse C code in {}", file.display()))?;
+
+        let file_path = file.to_str().unwrap_or("");
+        let metrics = if args.summary_format == SummaryFormat::Json {
+            let mut metrics = collect_function_metrics(&tree, &source, file_path, &include_rules, &exclude_rules, &analysis_options);
+            remap_lines(&mut metrics, &source);
+            let (listed, _) = split_unused_statics(&metrics, &output);
+            write_function_report(&mut std::io::stderr().lock(), &listed, &output, output.limit)?;
+            println!("{}", format_summary_json(&RunSummary::of(&listed, &output, None)));
+            metrics
+        } else {
+            let mut metrics = analyze_code(&tree, &source, file_path, &output, &include_rules, &exclude_rules, &analysis_options)?;
+            remap_lines(&mut metrics, &source);
+            metrics
+        };
+
+        if let Some(name) = &args.show_source {
+            display_source(&metrics, name.as_deref(), args.source_lines, &output);
+        }
+
+        if args.stats {
+            display_metric_stats(&metrics, &output);
+        }
+
+        if args.by_author {
+            display_author_summary(&metrics, &output)?;
+        }
+
+        if let Some(depth) = args.group_by_dir {
+            display_directory_summary(&group_by_directory(&metrics, None, depth), depth, &output);
+        }
+
+        if args.by_emoji {
+            display_emoji_bands(&group_files_by_emoji(&metrics, output.primary), &output);
+        }
+
+        if args.by_risk {
+            display_highest_risk(&metrics, &risk_weights, &output);
+        }
+
+        if args.nested_switches {
+            display_nested_switches(&metrics, &output);
+        }
+
+        if args.normalized {
+            display_normalized(&metrics, &output);
+        }
+
+        if args.warn_unreachable {
+            warn_unreachable_code(&metrics);
+        }
+
+        if args.warn_const {
+            warn_const_candidates(&metrics);
+        }
+
+        check_missing_returns(&metrics, args.strict)?;
+
+        check_missing_defaults(&metrics, args.require_default)?;
+
+        warn_inconsistent_returns(&metrics);
+
+        warn_leak_risks(&metrics);
+
+        if let Some(standard) = args.standard.filter(|_| args.summary_format == SummaryFormat::Text) {
+            display_compliance(standard, &metrics, &thresholds, &output);
+        }
+
+        check_gates(&metrics, &thresholds, &args, &analysis_options.message_template)?;
+
+        save_results(&args, &files, &metrics)?;
+
+        if let Some(reference) = &args.baseline_branch {
+            compare_with_baseline(&args, reference, &files, &metrics, &include_rules, &exclude_rules, &analysis_options)?;
+        }
+        return Ok(());
+    }
+
+    // Every other mode reports on all files from one analysis
+    let (all_metrics, counts) = analyze_files(&files, &include_rules, &exclude_rules, &analysis_options)?;
+
+    // A file without functions prints nothing for --format editor, so plugins can poll any file
+    if all_metrics.is_empty() && args.format != OutputFormat::Editor {
+        anyhow::bail!("No functions found in any files (skipped {} files)", counts.skipped.total());
+    }
+    let counts = FileCounts {
+        inaccessible: inaccessible_entries,
+        generated: generated_files,
+        ..counts
+    };
+
+    if report_mode {
+        if let Some(field) = args.metric {
+            let aggregation = match &args.function {
+                Some(name) => Aggregation::Function(name),
+                None if args.max => Aggregation::Max,
+                None => Aggregation::Total,
+            };
+            println!("{}", metric_scalar(&all_metrics, field, aggregation, output.precision)?);
+        } else if args.pmccabe {
+            print!("{}", format_pmccabe(&all_metrics));
+        } else if args.table {
+            print!("{}", format_function_table(&all_metrics, args.sort, &output));
+        } else if args.tui {
+            browse(&all_metrics, output.primary)?;
+        } else if args.matrix {
+            save_results(&args, &files, &all_metrics)?;
+            if args.matrix_per_file {
+                display_testability_matrix_per_file(&all_metrics, &counts, output.precision);
+            } else {
+                display_testability_matrix(&all_metrics, &counts, output.precision);
+            }
+        } else {
+            // Machine-readable formats cover every file in one document
+            save_results(&args, &files, &all_metrics)?;
+            match args.format {
+                OutputFormat::Github => {
+                    let violations = find_violations(&all_metrics, &thresholds);
+                    print!("{}", format_github(&all_metrics, &violations));
+                    if !violations.is_empty() {
+                        anyhow::bail!("{} threshold violation(s)", violations.len());
+                    }
+                }
+                OutputFormat::Dot => print!("{}", format_dot(&all_metrics)),
+                OutputFormat::Badge => {
+                    let badge = format_badge(&all_metrics, output.primary, output.precision)?;
+                    match &args.badge_file {
+                        Some(path) => fs::write(path, format!("{}\n", badge))
+                            .with_context(|| format!("Failed to write badge: {}", path.display()))?,
+                        None => println!("{}", badge),
+                    }
+                }
+                OutputFormat::Editor => print!("{}", format_editor(&all_metrics)),
+                OutputFormat::Prometheus => {
+                    print!("{}", format_prometheus(&all_metrics, &output.fields, output.precision))
+                }
+                OutputFormat::Text => unreachable!("text is the default report"),
+            }
+        }
+        return check_gates(&all_metrics, &thresholds, &args, &analysis_options.message_template);
+    }
+
+    // Recursive mode with multiple files: write report, show summary
+    save_results(&args, &files, &all_metrics)?;
+
+    // Unused statics are split out for display but still count against the baseline
+    let analyzed_metrics = all_metrics;
+    let (all_metrics, unused_statics) = split_unused_statics(&analyzed_metrics, &output);
+
+    // Write detailed report to file
+    write_detailed_report(Path::new(REPORT_FILE), &all_metrics, &output)?;
+
+    if output.no_summary {
+        // Stream the same per-function lines as report.txt
+        write_function_report(&mut std::io::stdout().lock(), &all_metrics, &output, output.limit)?;
+    } else if args.summary_format == SummaryFormat::Json {
+        write_function_report(&mut std::io::stderr().lock(), &all_metrics, &output, output.limit)?;
+        println!("{}", format_summary_json(&RunSummary::of(&all_metrics, &output, Some(&counts))));
+    } else {
+        // Display summary with top 5 worst functions and totals/averages
+        display_recursive_summary(&all_metrics, &output, &counts);
+        display_long_simple_functions(&all_metrics, &output);
+        display_debt_markers(&all_metrics, &output);
+        display_nesting_dominated(&all_metrics, &output);
+        if args.metrics.is_empty() {
+            // --metrics skips the calls that tell whether a function is called
+            display_uncalled_functions(&all_metrics, &output);
+        }
+        display_unused_statics(&unused_statics);
+
+        if let Some(depth) = args.group_by_dir {
+            let root = args.file.as_deref().filter(|path| path.is_dir());
+            display_directory_summary(&group_by_directory(&all_metrics, root, depth), depth, &output);
+        }
+
+        if args.by_emoji {
+            display_emoji_bands(&group_files_by_emoji(&all_metrics, output.primary), &output);
+        }
+
+        if args.by_risk {
+            display_highest_risk(&all_metrics, &risk_weights, &output);
+        }
+
+        if args.nested_switches {
+            display_nested_switches(&all_metrics, &output);
+        }
+
+        if args.normalized {
+            display_normalized(&all_metrics, &output);
+        }
+
+        if let Some(name) = &args.show_source {
+            display_source(&all_metrics, name.as_deref(), args.source_lines, &output);
+        }
+    }
+
+    if args.stats {
+        display_metric_stats(&all_metrics, &output);
+    }
+
+    if args.by_author {
+        display_author_summary(&all_metrics, &output)?;
+    }
+
+    if args.warn_unreachable {
+        warn_unreachable_code(&all_metrics);
+    }
+
+    if args.warn_const {
+        warn_const_candidates(&all_metrics);
+    }
+
+    check_missing_returns(&all_metrics, args.strict)?;
+
+    check_missing_defaults(&all_metrics, args.require_default)?;
+
+    warn_inconsistent_returns(&all_metrics);
+
+    warn_leak_risks(&all_metrics);
+
+    if let Some(standard) = args.standard.filter(|_| args.summary_format == SummaryFormat::Text) {
+        display_compliance(standard, &all_metrics, &thresholds, &output);
+    }
+
+    check_gates(&all_metrics, &thresholds, &args, &analysis_options.message_template)?;
+
+    if let Some(reference) = &args.baseline_branch {
+        compare_with_baseline(&args, reference, &files, &analyzed_metrics, &include_rules, &exclude_rules, &analysis_options)?;
+    }
+
+    Ok(())
+}
+
+/// How --metric reduces the functions' values to one number
+#[derive(Debug, Clone, Copy)]
+enum Aggregation<'a> {
+    Total,
+    Max,
+    Function(&'a str),
+}
+
+/// pmccabe's output: one tab-separated line per function, macros left out
+fn format_pmccabe(all_metrics: &[FunctionMetrics]) -> String {
+    let mut out = String::new();
+    for func in all_metrics {
+        if let Some(counts) = func.pmccabe {
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\t{}({}): {}\n",
+                counts.modified,
+                counts.traditional,
+                counts.statements,
+                func.line,
+                func.end_line - func.line + 1,
+                func.file_path,
+                func.line,
+                func.name
+            ));
+        }
+    }
+    out
+}
+
+/// Longest function name --table prints before cutting it short
+const TABLE_NAME_WIDTH: usize = 40;
+
+/// --table: fixed-width columns sized to the widest cell of each, numbers right-aligned,
+/// followed by a note when any name was truncated
+fn format_function_table(all_metrics: &[FunctionMetrics], sort: Option<Field>, output: &OutputOptions) -> String {
+    let mut rows: Vec<&FunctionMetrics> = all_metrics.iter().collect();
+    if let Some(field) = sort {
+        rows.sort_by(|a, b| field.value(b).total_cmp(&field.value(a)));
+    }
+
+    let mut truncated = 0;
+    let names: Vec<String> = rows
+        .iter()
+        .map(|func| {
+            if func.name.chars().count() <= TABLE_NAME_WIDTH {
+                return func.name.clone();
+            }
+            truncated += 1;
+            let mut name: String = func.name.chars().take(TABLE_NAME_WIDTH - 1).collect();
+            name.push('…');
+            name
+        })
+        .collect();
+    let values: Vec<Vec<String>> = rows
+        .iter()
+        .map(|func| output.fields.iter().map(|field| field.format_value(func, output.precision)).collect())
+        .collect();
+
+    let name_width = names.iter().map(|name| name.chars().count()).max().unwrap_or(0).max("FUNCTION".len());
+    let widths: Vec<usize> = output
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(column, field)| values.iter().map(|row| row[column].len()).max().unwrap_or(0).max(field.label().len()))
+        .collect();
+
+    let mut out = format!("{:<name_width$}", "FUNCTION");
+    for (field, width) in output.fields.iter().zip(&widths) {
+        out.push_str(&format!("  {:>width$}", field.label().to_uppercase()));
+    }
+    out.push_str("  LOCATION\n");
+    for ((func, name), row) in rows.iter().zip(&names).zip(&values) {
+        out.push_str(&format!("{:<name_width$}", name));
+        for (value, width) in row.iter().zip(&widths) {
+            out.push_str(&format!("  {:>width$}", value));
+        }
+        out.push_str(&format!("  {}:{}\n", func.file_path, func.line));
+    }
+    if truncated > 0 {
+        out.push_str(&format!(
+            "\n{} function name{} truncated to {} characters\n",
+            truncated,
+            if truncated == 1 { "" } else { "s" },
+            TABLE_NAME_WIDTH
+        ));
+    }
+    out
+}
+
+/// One metric as a bare number; integer metrics print without decimals
+fn metric_scalar(all_metrics: &[FunctionMetrics], field: Field, aggregation: Aggregation, precision: usize) -> Result<String> {
+    let values = all_metrics.iter().map(|func| field.value(func));
+    let value = match aggregation {
+        Aggregation::Total => values.sum(),
+        Aggregation::Max => values.fold(0.0, f64::max),
+        Aggregation::Function(name) => match all_metrics.iter().find(|func| func.name == name) {
+            Some(func) => field.value(func),
+            None => anyhow::bail!("No function named '{}' was analyzed", name),
+        },
+    };
+    Ok(if field.is_float() {
+        format!("{:.*}", precision, value)
+    } else {
+        format!("{}", value as u64)
+    })
+}
+
+#[cfg(feature = "tui")]
+fn browse(all_metrics: &[FunctionMetrics], primary: PrimaryMetric) -> Result<()> {
+    tui::run(all_metrics, primary)
+}
+
+#[cfg(not(feature = "tui"))]
+fn browse(_all_metrics: &[FunctionMetrics], _primary: PrimaryMetric) -> Result<()> {
+    anyhow::bail!("--tui is not available in this build; rebuild knots with `--features tui`")
+}
+
+/// Analyze `files` as of a git ref and show how `metrics` differ from it (--baseline-branch)
+/// Fails when any function's max(McCabe, Cognitive) grew since the ref
+fn compare_with_baseline(
+    args: &Args,
+    reference: &str,
+    files: &[PathBuf],
+    metrics: &[FunctionMetrics],
+    include_rules: &Option<FilterRules>,
+    exclude_rules: &Option<FilterRules>,
+    analysis_options: &AnalysisOptions,
+) -> Result<()> {
+    let old = analyze_baseline(reference, files, include_rules, exclude_rules, analysis_options)?;
+    let new = JsonReport {
+        functions: metrics.to_vec(),
+        ..JsonReport::default()
+    };
+    let diff = diff_reports(&old, &new, args.baseline_tolerance);
+    println!("\nBaseline: {}\n", reference);
+    display_report_diff(&diff, args.precision as usize);
+
+    let regressions = diff.changed.iter().filter(|change| change.regression() > 0).count();
+    if regressions > 0 {
+        anyhow::bail!("{} function(s) got more complex since {}", regressions, reference);
+    }
+    Ok(())
+}
+
+/// Unix timestamp --since reaches back to
+#[cfg(feature = "git")]
+fn since_cutoff(window: std::time::Duration) -> Result<i64> {
+    Ok(recent::cutoff(window))
+}
+
+#[cfg(not(feature = "git"))]
+fn since_cutoff(_window: std::time::Duration) -> Result<i64> {
+    anyhow::bail!("--since is not available in this build; rebuild knots with `--features git`")
+}
+
+#[cfg(not(feature = "git"))]
+fn recent_lines(_file: &Path, _cutoff: i64) -> Result<HashSet<usize>> {
+    anyhow::bail!("--since is not available in this build; rebuild knots with `--features git`")
+}
+
+#[cfg(not(feature = "git"))]
+fn analyze_baseline(
+    _reference: &str,
+    _files: &[PathBuf],
+    _include_rules: &Option<FilterRules>,
+    _exclude_rules: &Option<FilterRules>,
+    _analysis_options: &AnalysisOptions,
+) -> Result<JsonReport> {
+    anyhow::bail!("--baseline-branch is not available in this build; rebuild knots with `--features git`")
+}
+
+/// Report for `files` as of a git ref, with file paths as they are named today
+#[cfg(feature = "git")]
+fn analyze_baseline(
+    reference: &str,
+    files: &[PathBuf],
+    include_rules: &Option<FilterRules>,
+    exclude_rules: &Option<FilterRules>,
+    analysis_options: &AnalysisOptions,
+) -> Result<JsonReport> {
+    let Some(first) = files.first() else {
+        return Ok(JsonReport::default());
+    };
+    let checkout = BaselineCheckout::new(reference, first)?;
+
+    // Files added since the ref have no baseline; their functions show up as added
+    let mut original_paths = HashMap::new();
+    let baseline_files: Vec<PathBuf> = files
+        .iter()
+        .filter_map(|file| {
+            let path = checkout.path_of(file)?;
+            original_paths.insert(path.to_str().unwrap_or("").to_string(), file.to_str().unwrap_or("").to_string());
+            Some(path)
+        })
+        .collect();
+
+    let options = AnalysisOptions {
+        fail_fast: None,
+        ..analysis_options.clone()
+    };
+    let (mut baseline_metrics, _) = analyze_files(&baseline_files, include_rules, exclude_rules, &options)?;
+    for func in &mut baseline_metrics {
+        if let Some(original) = original_paths.get(&func.file_path) {
+            func.file_path = original.clone();
+        }
+    }
+
+    Ok(JsonReport {
+        functions: baseline_metrics,
+        ..JsonReport::default()
+    })
+}
+
+/// Summary line counting functions with an active suppression comment, when there are any
+fn display_suppression_count(metrics: &[FunctionMetrics]) {
+    let suppressed = metrics.iter().filter(|func| !func.suppressed.is_empty()).count();
+    if suppressed > 0 {
+        println!("  Suppressed Functions: {} (knots:allow comments)", suppressed);
+    }
+}
+
+/// Report functions above any --max-* threshold and fail if there are any
+/// With `fail_fast`, only the first violation is reported
+/// Functions within every threshold
+fn count_compliant(all_metrics: &[FunctionMetrics], thresholds: &Thresholds) -> usize {
+    all_metrics
+        .iter()
+        .filter(|func| find_violations(std::slice::from_ref(func), thresholds).is_empty())
+        .count()
+}
+
+/// --standard: how many functions meet the standard's limits; violations are listed by check_thresholds
+fn display_compliance(
+    standard: Standard,
+    all_metrics: &[FunctionMetrics],
+    thresholds: &Thresholds,
+    output: &OutputOptions,
+) {
+    let limits: Vec<String> = thresholds
+        .limits()
+        .iter()
+        .filter_map(|&(metric, limit, _)| limit.map(|limit| format!("{} <= {}", metric, limit)))
+        .collect();
+    let compliant = count_compliant(all_metrics, thresholds);
+    println!("\n=== {} COMPLIANCE ===\n", standard.name().to_uppercase());
+    println!("  Limits: {}", limits.join(", "));
+    println!(
+        "  Compliant: {} of {} functions ({:.*}%)",
+        compliant,
+        all_metrics.len(),
+        output.precision,
+        100.0 * compliant as f64 / all_metrics.len().max(1) as f64
+    );
+}
+
+/// The --max-* gates every analysis mode enforces: per-function thresholds, then the per-file limits
+fn check_gates(
+    all_metrics: &[FunctionMetrics],
+    thresholds: &Thresholds,
+    args: &Args,
+    template: &MessageTemplate,
+) -> Result<()> {
+    check_thresholds(all_metrics, thresholds, args.fail_fast, template)?;
+    check_file_complexity(all_metrics, args.max_file_complexity)?;
+    check_functions_per_file(all_metrics, args.max_functions_per_file)
+}
+
+fn check_thresholds(
+    all_metrics: &[FunctionMetrics],
+    thresholds: &Thresholds,
+    fail_fast: bool,
+    template: &MessageTemplate,
+) -> Result<()> {
+    let mut violations = find_violations(all_metrics, thresholds);
+    if fail_fast {
+        violations.truncate(1);
+    }
+    for violation in &violations {
+        eprintln!("{}", template.render(violation));
+    }
+
+    if fail_fast && !violations.is_empty() {
+        anyhow::bail!("Stopped at the first threshold violation (--fail-fast)");
+    }
+    if !violations.is_empty() {
+        anyhow::bail!("{} threshold violation(s)", violations.len());
+    }
+    Ok(())
+}
+
+/// A file's function count and summed McCabe complexity, for the per-file limits
+struct FileTotals<'a> {
+    file_path: &'a str,
+    functions: usize,
+    total_mccabe: u32,
+}
+
+/// Totals for every file, in the order they were analyzed
+fn file_totals(all_metrics: &[FunctionMetrics]) -> Vec<FileTotals<'_>> {
+    group_by_file(all_metrics)
+        .into_iter()
+        .map(|(file_path, functions)| FileTotals {
+            file_path,
+            functions: functions.len(),
+            total_mccabe: functions.iter().map(|func| func.mccabe).sum(),
+        })
+        .collect()
+}
+
+/// Files over the --max-file-complexity budget
+fn find_file_violations(all_metrics: &[FunctionMetrics], limit: u32) -> Vec<FileTotals<'_>> {
+    file_totals(all_metrics)
+        .into_iter()
+        .filter(|totals| totals.total_mccabe > limit)
+        .collect()
+}
+
+/// Files defining more than --max-functions-per-file functions
+fn find_crowded_files(all_metrics: &[FunctionMetrics], limit: usize) -> Vec<FileTotals<'_>> {
+    file_totals(all_metrics)
+        .into_iter()
+        .filter(|totals| totals.functions > limit)
+        .collect()
+}
+
+fn check_file_complexity(all_metrics: &[FunctionMetrics], limit: Option<u32>) -> Result<()> {
+    let Some(limit) = limit else {
+        return Ok(());
+    };
+    let violations = find_file_violations(all_metrics, limit);
+    for violation in &violations {
+        eprintln!(
+            "Error: {}: total McCabe {} over {} functions (max {})",
+            violation.file_path, violation.total_mccabe, violation.functions, limit
+        );
+    }
+    if !violations.is_empty() {
+        anyhow::bail!("{} file(s) over the complexity budget", violations.len());
+    }
+    Ok(())
+}
+
+fn check_functions_per_file(all_metrics: &[FunctionMetrics], limit: Option<usize>) -> Result<()> {
+    let Some(limit) = limit else {
+        return Ok(());
+    };
+    let crowded = find_crowded_files(all_metrics, limit);
+    for totals in &crowded {
+        eprintln!("Error: {}: {} functions (max {})", totals.file_path, totals.functions, limit);
+    }
+    if !crowded.is_empty() {
+        anyhow::bail!("{} file(s) define too many functions", crowded.len());
+    }
+    Ok(())
+}
+
+/// Warn about non-void functions without a return statement; an error under --strict
+fn check_missing_returns(all_metrics: &[FunctionMetrics], strict: bool) -> Result<()> {
+    let missing: Vec<&FunctionMetrics> = all_metrics.iter().filter(|func| func.missing_return).collect();
+    for func in &missing {
+        eprintln!(
+            "Warning: {}:{}: non-void function {}() has no return statement",
+            func.file_path, func.line, func.name
+        );
+    }
+
+    if strict && !missing.is_empty() {
+        anyhow::bail!("{} non-void function(s) have no return statement", missing.len());
+    }
+    Ok(())
+}
+
+/// Warn about every switch without a `default:` label; with --require-default, fail if any
+fn check_missing_defaults(all_metrics: &[FunctionMetrics], require_default: bool) -> Result<()> {
+    let mut missing = 0;
+    for func in all_metrics {
+        for line in &func.missing_default_lines {
+            eprintln!("Warning: {}:{}: switch in {}() has no default label", func.file_path, line, func.name);
+            missing += 1;
+        }
+    }
+
+    if require_default && missing > 0 {
+        anyhow::bail!("{} switch statement(s) have no default label", missing);
+    }
+    Ok(())
+}
+
+/// Warn about every `return` that disagrees with its function's declared return type
+fn warn_inconsistent_returns(all_metrics: &[FunctionMetrics]) {
+    for func in all_metrics {
+        for line in &func.inconsistent_return_lines {
+            eprintln!(
+                "Warning: {}:{}: return in {}() does not match its declared return type",
+                func.file_path, line, func.name
+            );
+        }
+    }
+}
+
+/// Warn about functions that may leak memory when an error path returns early
+fn warn_leak_risks(all_metrics: &[FunctionMetrics]) {
+    for func in all_metrics.iter().filter(|func| func.leak_risk) {
+        eprintln!(
+            "Warning: {}:{}: possible leak on error path in {}() (allocates and has {} returns but no cleanup label)",
+            func.file_path, func.line, func.name, func.return_count
+        );
+    }
+}
+
+/// Print a warning to stderr for every unreachable statement found
+fn warn_unreachable_code(all_metrics: &[FunctionMetrics]) {
+    for func in all_metrics {
+        for line in &func.unreachable_lines {
+            eprintln!("Warning: {}:{}: unreachable code in {}()", func.file_path, line, func.name);
+        }
+    }
+}
+
+/// Print a warning to stderr for every pointer parameter that could point to const
+fn warn_const_candidates(all_metrics: &[FunctionMetrics]) {
+    for func in all_metrics {
+        for parameter in &func.const_candidates {
+            eprintln!(
+                "Warning: {}:{}: parameter `{}` of {}() could be const",
+                func.file_path, func.line, parameter, func.name
+            );
+        }
+    }
+}
+
+/// Read, parse, and collect function metrics for each file
+/// Unreadable or unparseable files are skipped with a warning, and files without
+/// functions are skipped silently; returns the metrics and file counts
+fn analyze_files(
+    files: &[PathBuf],
+    include_rules: &Option<FilterRules>,
+    exclude_rules: &Option<FilterRules>,
+    analysis_options: &AnalysisOptions,
+) -> Result<(Vec<FunctionMetrics>, FileCounts)> {
+    let mut all_metrics = Vec::new();
+    let mut skipped = SkipCounts::default();
+    let mut bytes = 0;
+    let mut parse_issues = Vec::new();
+
+    for file in files {
+        let source = match read_source(file, analysis_options) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Warning: Skipping {}: {:#}", file.display(), e);
+                skipped.read_error += 1;
+                continue;
+            }
+        };
+        let source_code = &source.code;
+
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .context("Failed to set C language")?;
+
+        let tree = match parser.parse(source_code, None) {
+            Some(t) => t,
+            None => {
+                eprintln!("Warning: Failed to parse {}", file.display());
+                skipped.parse_failure += 1;
+                continue;
+            }
+        };
+
+        let errors = count_parse_errors(tree.root_node());
+        let mut metrics = collect_function_metrics(&tree, &source, file.to_str().unwrap_or(""), include_rules, exclude_rules, analysis_options);
+        if metrics.is_empty() {
+            // Parse errors can swallow function definitions; otherwise the file has none
+            if errors.error_nodes > 0 {
+                skipped.parse_failure += 1;
+            } else {
+                skipped.no_functions += 1;
+            }
+            continue;
+        }
+        if errors.error_nodes > 0 {
+            parse_issues.push((file.display().to_string(), errors, ParseConfidence::of(&errors, source_code.len())));
+        }
+        bytes += source_code.len();
+        remap_lines(&mut metrics, &source);
+
+        if let Some(thresholds) = &analysis_options.fail_fast {
+            check_thresholds(&metrics, thresholds, true, &analysis_options.message_template)?;
+        }
+
+        all_metrics.extend(metrics);
+    }
+
+    let counts = FileCounts {
+        found: files.len(),
+        skipped,
+        bytes,
+        inaccessible: 0,
+        generated: 0,
+        parse_issues,
+    };
+    Ok((all_metrics, counts))
+}
+
+/// How far metrics for a file can be trusted, based on tree-sitter error recovery
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParseConfidence {
+    /// Parsed without errors
+    High,
+    /// A few recovered errors; metrics are mostly reliable
+    Medium,
+    /// Many errors or a large share of the file unparsed; metrics are unreliable
+    Low,
+}
+
+impl ParseConfidence {
+    /// Low when 10+ error nodes or more than 5% of the source sits inside ERROR nodes
+    fn of(errors: &ParseErrors, source_len: usize) -> Self {
+        if errors.error_nodes == 0 {
+            ParseConfidence::High
+        } else if errors.error_nodes >= 10 || errors.error_bytes * 20 > source_len {
+            ParseConfidence::Low
+        } else {
+            ParseConfidence::Medium
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            ParseConfidence::High => "High",
+            ParseConfidence::Medium => "Medium",
+            ParseConfidence::Low => "Low",
+        }
+    }
+}
+
+/// Read a file, expanding it with the preprocessor when --preprocess is set
+fn read_source(file: &Path, analysis_options: &AnalysisOptions) -> Result<SourceText> {
+    let mut source = match &analysis_options.preprocessor {
+        Some(preprocessor) => preprocessor.run(file)?,
+        None => {
+            let code = fs::read_to_string(file)
+                .with_context(|| format!("Failed to read file: {}", file.display()))?;
+            SourceText::plain(code)
+        }
+    };
+
+    if is_objc_file(file) {
+        let objc = objc::translate(&source.code);
+        source.code = objc.code;
+        source.function_names = objc.method_names;
+    }
+    Ok(source)
+}
+
+/// Translate reported line numbers from preprocessed output back to the original file
+fn remap_lines(metrics: &mut [FunctionMetrics], source: &SourceText) {
+    for func in metrics {
+        func.line = source.original_line(func.line);
+        func.end_line = source.original_line(func.end_line);
+        let lines = func
+            .unreachable_lines
+            .iter_mut()
+            .chain(&mut func.missing_default_lines)
+            .chain(&mut func.nested_switch_lines)
+            .chain(&mut func.inconsistent_return_lines);
+        for line in lines {
+            *line = source.original_line(*line);
+        }
+    }
+}
+
+/// Load file paths from compile_commands.json
+fn load_compile_commands(
+    compile_commands_path: &Path,
+    include_rules: &Option<FilterRules>,
+    exclude_rules: &Option<FilterRules>,
+) -> Result<CompilationDatabase> {
+    let content = fs::read_to_string(compile_commands_path)
+        .with_context(|| format!("Failed to read compile_commands.json: {}", compile_commands_path.display()))?;
+
+    let commands: Vec<CompileCommand> = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse compile_commands.json: {}", compile_commands_path.display()))?;
+
+    let mut database = CompilationDatabase {
+        files: Vec::new(),
+        file_flags: HashMap::new(),
+    };
+    let mut seen = HashSet::new();
+
+    for cmd in commands {
+        let file_path = PathBuf::from(&cmd.file);
+
+        // Only process C files
+        if !is_source_file(&file_path) {
+            continue;
+        }
+        if !should_process_file(&file_path.to_string_lossy(), include_rules, exclude_rules) {
+            continue;
+        }
+
+        // Use absolute path if available, otherwise resolve against the command's directory
+        let path = if file_path.is_absolute() {
+            file_path
+        } else {
+            let abs_path = PathBuf::from(&cmd.directory).join(&file_path);
+            if abs_path.exists() {
+                abs_path
+            } else if file_path.exists() {
+                file_path
+            } else {
+                continue;
+            }
+        };
+
+        // The same file may be compiled more than once (e.g. per target); analyze it once
+        let key = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if !seen.insert(key) {
+            continue;
+        }
+
+        let flags = preprocessor_flags(&cmd.argument_list(), Path::new(&cmd.directory));
+        database.file_flags.insert(path.clone(), flags);
+        database.files.push(path);
+    }
+
+    if database.files.is_empty() {
+        anyhow::bail!("No .c/.m files found in compile_commands.json");
+    }
+
+    Ok(database)
+}
+
+/// Parse a `--lines` range such as `40:75`
+fn parse_line_range(value: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("invalid line range '{}': expected START:END, e.g. 40:75", value);
+    let (start, end) = value.split_once(':').ok_or_else(invalid)?;
+    let start: usize = start.trim().parse().map_err(|_| invalid())?;
+    let end: usize = end.trim().parse().map_err(|_| invalid())?;
+    if start == 0 || start > end {
+        return Err(format!("invalid line range '{}': lines start at 1 and START must not exceed END", value));
+    }
+    Ok((start, end))
+}
+
+/// Collect files to process based on the path and recursive flag
+/// Returns the files and the number of directory entries that could not be accessed
+fn collect_files(
+    path: &Path,
+    recursive: bool,
+    headers: bool,
+    include_rules: &Option<FilterRules>,
+    exclude_rules: &Option<FilterRules>,
+) -> Result<(Vec<PathBuf>, usize)> {
+    let mut files = Vec::new();
+    let mut inaccessible = 0;
+
+    if path.is_file() {
+        // Single file mode
+        let file_str = path.to_string_lossy();
+        if should_process_file(&file_str, include_rules, exclude_rules) {
+            files.push(path.to_path_buf());
+        }
+    } else if path.is_dir() {
+        if !recursive {
+            anyhow::bail!(
+                "Path '{}' is a directory. Use -r/--recursive to process directories recursively.",
+                path.display()
+            );
+        }
+
+        // Recursive directory mode - only scan .c (and Objective-C .m/.mm) files by default
+        // (headers often contain inline/vendor code); --prototypes wants the headers too
+        inaccessible = walk_c_files(path, headers, include_rules, exclude_rules, &mut files);
+
+        if files.is_empty() {
+            let kinds = if headers { ".c/.m/.h" } else { ".c/.m" };
+            anyhow::bail!("No {} files found in directory: {}", kinds, path.display());
+        }
+    } else {
+        anyhow::bail!("Path '{}' does not exist", path.display());
+    }
+
+    Ok((files, inaccessible))
+}
+
+/// Language a file is parsed as, by extension
+fn source_language(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("m") => "Objective-C",
+        Some("mm") => "Objective-C++",
+        Some("h") => "C header",
+        _ => "C",
+    }
+}
+
+/// --dry-run: the selected files with their languages, and what was left out
+fn format_dry_run(files: &[PathBuf], generated: usize, inaccessible: usize) -> String {
+    let paths: Vec<String> = files.iter().map(|file| file.display().to_string()).collect();
+    let width = paths.iter().map(|path| path.chars().count()).max().unwrap_or(0);
+    let mut out = String::new();
+    for (path, file) in paths.iter().zip(files) {
+        out.push_str(&format!("{:<width$}  {}\n", path, source_language(file)));
+    }
+
+    out.push_str(&format!("\n{} file{} would be analyzed", files.len(), if files.len() == 1 { "" } else { "s" }));
+    let mut left_out = Vec::new();
+    if generated > 0 {
+        left_out.push(format!("{} generated", generated));
+    }
+    if inaccessible > 0 {
+        left_out.push(format!("{} inaccessible", inaccessible));
+    }
+    if !left_out.is_empty() {
+        out.push_str(&format!(" (skipped {})", left_out.join(", ")));
+    }
+    out.push('\n');
+    out
+}
+
+/// C and Objective-C translation units; headers are left out of directory scans
+fn is_source_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "c") || is_objc_file(path)
+}
+
+/// Walk a directory (following symlinks) and collect .c/.m/.mm files that pass the filters
+///
+/// Entries that cannot be read (permission denied, broken symlinks, symlink loops)
+/// are reported on stderr and skipped; returns how many there were. WalkDir detects
+/// symlink cycles itself and reports them as errors instead of descending again.
+/// Expand a --public-headers glob (e.g. "include/*.h") to the matching files
+/// The walk starts at the longest leading path without wildcards
+fn expand_glob(pattern: &str) -> Vec<PathBuf> {
+    if !pattern.contains('*') {
+        return vec![PathBuf::from(pattern)];
+    }
+
+    let base: PathBuf = Path::new(pattern)
+        .components()
+        .take_while(|component| !component.as_os_str().to_string_lossy().contains('*'))
+        .collect();
+    let base = if base.as_os_str().is_empty() { PathBuf::from(".") } else { base };
+    let pattern = pattern.strip_prefix("./").unwrap_or(pattern);
+
+    WalkDir::new(&base)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .filter(|path| {
+            let path_str = path.to_string_lossy();
+            glob_match(pattern, path_str.strip_prefix("./").unwrap_or(&path_str))
+        })
+        .collect()
+}
+
+/// Names of all functions declared in the headers matching --public-headers
+fn load_public_api(pattern: &str) -> Result<HashSet<String>> {
+    let headers = expand_glob(pattern);
+    if headers.is_empty() {
+        anyhow::bail!("No headers match --public-headers {}", pattern);
+    }
+
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(&tree_sitter_c::LANGUAGE.into())
+        .context("Failed to set C language")?;
+
+    let mut names = HashSet::new();
+    for header in headers {
+        let source_code = fs::read_to_string(&header)
+            .with_context(|| format!("Failed to read header: {}", header.display()))?;
+        let tree = parser
+            .parse(&source_code, None)
+            .with_context(|| format!("Failed to parse header: {}", header.display()))?;
+        names.extend(find_declared_functions(tree.root_node(), source_code.as_bytes()));
+    }
+    Ok(names)
+}
+
+/// Header comments left by common code generators (protobuf, bison, flex, and generic notices)
+const DEFAULT_GENERATED_MARKER: &str =
+    r"(?i)generated by|auto-?generated|do not edit|do not modify|a bison parser|lexical scanner generated";
+
+/// Number of leading lines searched for a generated-code marker
+const GENERATED_HEADER_LINES: usize = 10;
+
+/// Checks the first lines of a file for a generated-code marker
+/// Unreadable files are kept so the analysis reports them as skipped
+fn is_generated_file(path: &Path, marker: &Regex) -> bool {
+    let Ok(file) = fs::File::open(path) else {
+        return false;
+    };
+    BufReader::new(file)
+        .lines()
+        .take(GENERATED_HEADER_LINES)
+        .map_while(Result::ok)
+        .any(|line| marker.is_match(&line))
+}
+
+/// Drop generated files, returning the remaining files and how many were dropped
+fn exclude_generated_files(files: Vec<PathBuf>, marker: &Regex) -> (Vec<PathBuf>, usize) {
+    let total = files.len();
+    let kept: Vec<PathBuf> = files.into_iter().filter(|file| !is_generated_file(file, marker)).collect();
+    let excluded = total - kept.len();
+    (kept, excluded)
+}
+
+fn walk_c_files(
+    root: &Path,
+    headers: bool,
+    include_rules: &Option<FilterRules>,
+    exclude_rules: &Option<FilterRules>,
+    files: &mut Vec<PathBuf>,
+) -> usize {
+    let mut inaccessible = 0;
+
+    for entry in WalkDir::new(root).follow_links(true) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                inaccessible += 1;
+                let path = err.path().unwrap_or(root).display().to_string();
+                if let Some(ancestor) = err.loop_ancestor() {
+                    eprintln!("Warning: Skipping symlink loop: {} -> {}", path, ancestor.display());
+                } else if let Some(io_err) = err.io_error() {
+                    match io_err.kind() {
+                        std::io::ErrorKind::PermissionDenied => {
+                            eprintln!("Warning: Permission denied: {}", path);
+                        }
+                        std::io::ErrorKind::NotFound => {
+                            eprintln!("Warning: Broken symlink: {}", path);
+                        }
+                        _ => eprintln!("Warning: Cannot access {}: {}", path, io_err),
+                    }
+                } else {
+                    eprintln!("Warning: Cannot access {}: {}", path, err);
+                }
+                continue;
+            }
+        };
+
+        let file_path = entry.path();
+        let wanted = is_source_file(file_path) || (headers && file_path.extension().is_some_and(|ext| ext == "h"));
+        if entry.file_type().is_file() && wanted {
+            let file_str = file_path.to_string_lossy();
+            if should_process_file(&file_str, include_rules, exclude_rules) {
+                files.push(file_path.to_path_buf());
+            }
+        }
+    }
+
+    inaccessible
+}
+
+/// Check if a file should be processed based on include/exclude rules
+fn should_process_file(
+    file_path: &str,
+    include_rules: &Option<FilterRules>,
+    exclude_rules: &Option<FilterRules>,
+) -> bool {
+    // Check include rules first (whitelist)
+    if let Some(rules) = include_rules {
+        if !rules.matches_file(file_path) {
+            return false;
+        }
+    }
+
+    // Check exclude rules (blacklist) - if it matches exclude, DON'T process
+    if let Some(rules) = exclude_rules {
+        if rules.matches_file(file_path) {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Collect function metrics from a file
+fn collect_function_metrics(
+    tree: &Tree,
+    source: &SourceText,
+    file_path: &str,
+    include_rules: &Option<FilterRules>,
+    exclude_rules: &Option<FilterRules>,
+    analysis_options: &AnalysisOptions,
+) -> Vec<FunctionMetrics> {
+    let source_code = &source.code;
+    let root_node = tree.root_node();
+    let mut cursor = root_node.walk();
+    let mut metrics = Vec::new();
+    let unused_statics: HashSet<String> = find_unused_static_functions(root_node, source_code.as_bytes())
+        .into_iter()
+        .collect();
+    let aggregates = find_aggregate_typedefs(root_node, source_code.as_bytes());
+
+    let recent = match analysis_options.since {
+        Some(cutoff) => match recent_lines(Path::new(file_path), cutoff) {
+            Ok(lines) => Some(lines),
+            Err(e) => {
+                eprintln!("Warning: Skipping {} for --since: {:#}", file_path, e);
+                return metrics;
+            }
+        },
+        None => None,
+    };
+
+    // With --since, keep only functions with a recently changed line
+    let changed_recently = |node: Node| {
+        recent
+            .as_ref()
+            .map(|lines| {
+                (node.start_position().row..=node.end_position().row)
+                    .any(|row| lines.contains(&source.original_line(row + 1)))
+            })
+            .unwrap_or(true)
+    };
+    // With --lines, keep only functions overlapping the range
+    let in_line_range = |node: Node| {
+        analysis_options
+            .lines
+            .map(|(start, end)| {
+                source.original_line(node.start_position().row + 1) <= end
+                    && source.original_line(node.end_position().row + 1) >= start
+            })
+            .unwrap_or(true)
+    };
+    // With --define, skip functions in #ifdef branches the defines rule out
+    let selected_by_defines = |node: Node| {
+        analysis_options
+            .defines
+            .as_ref()
+            .map(|defines| defines.selects(node, source_code.as_bytes()))
+            .unwrap_or(true)
+    };
+    let is_public = |name: &str| {
+        analysis_options
+            .public_api
+            .as_ref()
+            .map(|public_api| public_api.contains(name))
+            .unwrap_or(true)
+    };
+
+    visit_functions(&mut cursor, source_code, &mut |node, src| {
+        if let Some(parsed_name) = get_function_name(node, src.as_bytes()) {
+            // Objective-C methods are parsed as placeholder functions; report their selectors
+            let name = source.function_names.get(&parsed_name).cloned().unwrap_or_else(|| parsed_name.clone());
+            let mut func = measure_function(node, src.as_bytes(), name, file_path, &aggregates, analysis_options);
+            func.unused_static = unused_statics.contains(&parsed_name);
+            func.is_static = is_static_function(node, src.as_bytes());
+            func.is_exported = !func.is_static && is_public(&func.name);
+
+            // Apply filter rules
+            if changed_recently(node)
+                && in_line_range(node)
+                && selected_by_defines(node)
+                && should_process_function(&func.name, func.max_complexity(), include_rules, exclude_rules)
+            {
+                metrics.push(func);
+            }
+        }
+    });
+
+    if analysis_options.macros {
+        let mut parser = tree_sitter::Parser::new();
+        if parser.set_language(&tree_sitter_c::LANGUAGE.into()).is_err() {
+            return metrics;
+        }
+        for macro_function in find_function_like_macros(root_node, source_code.as_bytes()) {
+            let Some(wrapped) = parser.parse(&macro_function.code, None) else {
+                continue;
+            };
+            let Some(node) = wrapped.root_node().named_child(0).filter(|node| node.kind() == "function_definition") else {
+                continue;
+            };
+            let code = macro_function.code.as_bytes();
+            let shape = calculate_control_flow_shape(node);
+            if shape.loops + shape.branches == 0 {
+                continue;
+            }
+
+            let mut func = measure_function(node, code, macro_function.name, file_path, &aggregates, analysis_options);
+            // The wrapper starts on line 1; move its lines to the #define
+            func.end_line += macro_function.line - 1;
+            func.line = macro_function.line;
+            let lines = func
+                .unreachable_lines
+                .iter_mut()
+                .chain(&mut func.missing_default_lines)
+                .chain(&mut func.nested_switch_lines)
+                .chain(&mut func.inconsistent_return_lines);
+            for line in lines {
+                *line += macro_function.line - 1;
+            }
+            func.is_exported = is_public(&func.name);
+
+            let define = find_macro_definition(root_node, macro_function.line);
+            if define.map(|define| changed_recently(define) && in_line_range(define)).unwrap_or(true)
+                && should_process_function(&func.name, func.max_complexity(), include_rules, exclude_rules)
+            {
+                metrics.push(func);
+            }
+        }
+    }
+
+    metrics
+}
+
+/// The `#define` starting on `line` (1-based), for mapping a macro pseudo-function back to the tree
+fn find_macro_definition(root: Node, line: usize) -> Option<Node> {
+    let mut node = root.descendant_for_point_range(
+        tree_sitter::Point { row: line - 1, column: 0 },
+        tree_sitter::Point { row: line - 1, column: 0 },
+    )?;
+    while node.kind() != "preproc_function_def" {
+        node = node.parent()?;
+    }
+    Some(node)
+}
+
+/// Metrics for one function definition; `unused_static` and `is_exported` are left for the caller
+/// `aggregates` is the file's struct and union typedefs, built once for all its functions
+fn measure_function(
+    node: Node,
+    source_code: &[u8],
+    name: String,
+    file_path: &str,
+    aggregates: &HashSet<String>,
+    analysis_options: &AnalysisOptions,
+) -> FunctionMetrics {
+    let mut core = core_metrics(node, source_code, aggregates, analysis_options);
+    if analysis_options.data_aware {
+        let data = calculate_initializer_data(node, source_code);
+        core.abc.assignments = core.abc.assignments.saturating_sub(data.assignments);
+        core.abc.branches = core.abc.branches.saturating_sub(data.branches);
+        core.sloc = core.sloc.saturating_sub(data.sloc);
+    }
+    // Secondary metrics (SECONDARY_METRICS) are only computed when every metric is requested,
+    // all in one walk; the few tied to a core metric follow that metric otherwise
+    let secondary = analysis_options.metrics.is_none();
+    let extra = if secondary {
+        calculate_secondary_metrics(node, source_code, &analysis_options.debt_markers, &analysis_options.error_patterns)
+    } else {
+        SecondaryMetrics {
+            exit_points: if analysis_options.computes(Field::Returns) {
+                calculate_exit_point_count(node, source_code)
+            } else {
+                0
+            },
+            switch_case_total: if analysis_options.computes(Field::Cognitive) {
+                calculate_switch_case_total(node)
+            } else {
+                0
+            },
+            computed_goto_count: if analysis_options.computes(Field::Mccabe) {
+                calculate_computed_goto_count(node, source_code)
+            } else {
+                0
+            },
+            missing_return: is_missing_return(node, source_code),
+            ..SecondaryMetrics::default()
+        }
+    };
+    let large_value_param_count =
+        if secondary { calculate_large_value_param_count(node, source_code, aggregates) } else { 0 };
+
+    FunctionMetrics {
+        name,
+        file_path: file_path.to_string(),
+        mccabe: core.mccabe,
+        cognitive: core.cognitive,
+        switch_case_total: extra.switch_case_total,
+        nesting: core.nesting,
+        sloc: core.sloc,
+        abc_magnitude: core.abc.magnitude(),
+        return_count: core.return_count,
+        exit_points: extra.exit_points,
+        computed_goto_count: extra.computed_goto_count,
+        essential_complexity: extra.essential_complexity,
+        magic_numbers: extra.magic_numbers,
+        static_local_count: extra.static_local_count,
+        debt_markers: extra.debt_markers,
+        max_dereference_chain: extra.max_dereference_chain,
+        max_call_nesting: extra.max_call_nesting,
+        calls: extra.calls,
+        generic_association_count: extra.generic_association_count,
+        config_branch_count: extra.config_branch_count,
+        error_handling_sloc: extra.error_handling_sloc,
+        large_value_param_count,
+        parameter_modifications: extra.parameter_modifications,
+        const_candidates: extra.const_candidates,
+        style: analysis_options.style_check.then(|| calculate_style_consistency(node, source_code)),
+        pmccabe: analysis_options.pmccabe.then(|| calculate_pmccabe(node, source_code)),
+        loop_count: core.shape.loops,
+        branch_count: core.shape.branches,
+        test_scoring: core.test_scoring,
+        unreachable_lines: extra.unreachable_lines,
+        missing_default_lines: extra.missing_default_lines,
+        max_switch_nesting: extra.max_switch_nesting,
+        nested_switch_lines: extra.nested_switch_lines,
+        inconsistent_return_lines: extra.inconsistent_return_lines,
+        unused_static: false,
+        line: node.start_position().row + 1,
+        end_line: node.end_position().row + 1,
+        missing_return: extra.missing_return,
+        is_noreturn: is_noreturn_function(node, source_code),
+        leak_risk: extra.leak_risk,
+        is_exported: true,
+        is_static: false,
+        suppressed: find_suppressions(node, source_code),
+        doc_comment: analysis_options
+            .doc_limit
+            .and_then(|limit| preceding_comment(node, source_code).map(|comment| truncate_doc_comment(comment, limit))),
+        skipped: analysis_options.skipped_metrics(),
+    }
+}
+
+/// The first `limit` characters of a doc comment, ending in an ellipsis when cut short
+fn truncate_doc_comment(comment: &str, limit: usize) -> String {
+    if comment.chars().count() <= limit {
+        return comment.to_string();
+    }
+    let mut truncated: String = comment.chars().take(limit).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// McCabe through test scoring for one function, in a single pass unless --metrics
+/// restricts them; metrics left out by --metrics are zero
+fn core_metrics(
+    node: Node,
+    source_code: &[u8],
+    aggregates: &HashSet<String>,
+    analysis_options: &AnalysisOptions,
+) -> AllMetrics {
+    if analysis_options.metrics.is_none() {
+        let options = MetricOptions {
+            mccabe: analysis_options.mccabe.clone(),
+            cognitive: analysis_options.cognitive.clone(),
+            test_scoring: analysis_options.test_scoring,
+            abc_weighted: analysis_options.abc_weighted,
+        };
+        return calculate_all_metrics_with_options(node, source_code, &options, aggregates);
+    }
+
+    let computes = |field| analysis_options.computes(field);
+    AllMetrics {
+        mccabe: if computes(Field::Mccabe) {
+            calculate_mccabe_complexity_with_options(node, source_code, &analysis_options.mccabe)
+        } else {
+            0
+        },
+        cognitive: if computes(Field::Cognitive) {
+            calculate_cognitive_complexity_with_options(node, source_code, &analysis_options.cognitive)
+        } else {
+            0
+        },
+        nesting: if computes(Field::Nesting) { calculate_nesting_depth(node) } else { 0 },
+        sloc: if computes(Field::Sloc) { calculate_sloc(node, source_code) } else { 0 },
+        abc: match (computes(Field::Abc), analysis_options.abc_weighted) {
+            (false, _) => AbcComplexity {
+                assignments: 0,
+                branches: 0,
+                conditions: 0,
+            },
+            (true, true) => calculate_abc_weighted(node, source_code),
+            (true, false) => calculate_abc_complexity(node, source_code),
+        },
+        return_count: if computes(Field::Returns) { calculate_return_count(node) } else { 0 },
+        shape: if computes(Field::Loops) || computes(Field::Branches) {
+            calculate_control_flow_shape(node)
+        } else {
+            ControlFlowShape::default()
+        },
+        test_scoring: if computes(Field::TestScore) {
+            calculate_test_scoring_with_options(node, source_code, &analysis_options.test_scoring, aggregates)
+        } else {
+            TestScoringMetric::default()
+        },
+    }
+}
+
+/// Check if a function should be processed based on include/exclude rules
+fn should_process_function(
+    function_name: &str,
+    complexity: u32,
+    include_rules: &Option<FilterRules>,
+    exclude_rules: &Option<FilterRules>,
+) -> bool {
+    // Check include rules first (whitelist)
+    if let Some(rules) = include_rules {
+        if !rules.matches_function(function_name) {
+            return false;
+        }
+        if !rules.matches_complexity(complexity) {
+            return false;
+        }
+    }
+
+    // Check exclude rules (blacklist) - if it matches exclude, DON'T process
+    // Only apply function/complexity filters if they're actually specified
+    if let Some(rules) = exclude_rules {
+        let matches_func = !rules.function_patterns.is_empty() && rules.matches_function(function_name);
+        let matches_complexity = (rules.min_complexity.is_some() || rules.max_complexity.is_some()) && rules.matches_complexity(complexity);
+
+        // If no function patterns specified, only check complexity
+        // If no complexity bounds specified, only check function patterns
+        // If both specified, require both to match
+        let should_exclude = if rules.function_patterns.is_empty() && rules.min_complexity.is_none() && rules.max_complexity.is_none() {
+            // No function-level filters, don't exclude based on function criteria
+            false
+        } else if rules.function_patterns.is_empty() {
+            // Only complexity filter
+            matches_complexity
+        } else if rules.min_complexity.is_none() && rules.max_complexity.is_none() {
+            // Only function pattern filter
+            matches_func
+        } else {
+            // Both specified, require both
+            matches_func && matches_complexity
+        };
+
+        if should_exclude {
+            return false;
+        }
+    }
+
+    true
+}
+
+fn analyze_code(
+    tree: &Tree,
+    source: &SourceText,
+    file_path: &str,
+    output: &OutputOptions,
+    include_rules: &Option<FilterRules>,
+    exclude_rules: &Option<FilterRules>,
+    analysis_options: &AnalysisOptions,
+) -> Result<Vec<FunctionMetrics>> {
+    let all_metrics = collect_function_metrics(tree, source, file_path, include_rules, exclude_rules, analysis_options);
+    let (metrics, unused_statics) = split_unused_statics(&all_metrics, output);
+
+    // Nothing to act on: one line instead of the breakdown, unless --verbose asks for detail
+    if let Some(line) = all_trivial_line(&metrics).filter(|_| !output.verbose && !output.no_summary) {
+        println!("{}", line);
+        display_unused_statics(&unused_statics);
+        return Ok(all_metrics);
+    }
+
+    let shown = output.limit.unwrap_or(metrics.len());
+
+    for func in metrics.iter().take(shown) {
+        let badge = output.badge(func);
+
+        if output.verbose {
+            println!("Function: {} {}", func.name, badge);
+            write_function_details(&mut std::io::stdout().lock(), func, output)?;
+        } else {
+            println!("{} {} ({})", badge, func.name, format_fields(func, output));
+        }
+    }
+    if shown < metrics.len() {
+        println!("{}", more_functions_line(metrics.len() - shown));
+    }
+
+    let function_count = metrics.len();
+
+    if output.no_summary {
+        return Ok(all_metrics);
+    }
+
+    // Print summary
+    println!();
+    println!("Summary:");
+    let rows = MetricTotals::of(&metrics).summary_rows(&metrics, output.precision);
+    println!("  Total Functions: {}", function_count);
+    for (name, total, _) in &rows {
+        println!("  Total {}: {}", name, total);
+    }
+    display_suppression_count(&metrics);
+    let errors = count_parse_errors(tree.root_node());
+    let confidence = ParseConfidence::of(&errors, source.code.len());
+    println!(
+        "  Parse Confidence: {} ({} error nodes{})",
+        confidence.label(),
+        errors.error_nodes,
+        if confidence == ParseConfidence::Low { ", metrics unreliable" } else { "" }
+    );
+
+    if function_count > 0 {
+        for (name, _, sum) in &rows {
+            println!("  Average {}: {:.*}", name, output.precision, sum / function_count as f64);
+        }
+        if let Some(score) = average_style_score(&metrics) {
+            println!("  Average Style Consistency: {:.*}%", output.precision, score);
+        }
+        if let Some(cut_points) = &output.grade_cut_points {
+            println!("  Average Grade: {}", format_average_grade(&metrics, cut_points, output.precision));
+        }
+    }
+
+    display_long_simple_functions(&metrics, output);
+    display_unused_statics(&unused_statics);
+
+    Ok(all_metrics)
+}
+
+/// "All N functions trivial ..." when every function has complexity 1, otherwise None
+fn all_trivial_line(metrics: &[FunctionMetrics]) -> Option<String> {
+    if metrics.is_empty() || metrics.iter().any(|func| func.max_complexity() > 1) {
+        return None;
+    }
+    Some(format!(
+        "All {} function{} trivial (complexity 1) — no action needed 😊",
+        metrics.len(),
+        if metrics.len() == 1 { "" } else { "s" }
+    ))
+}
+
+/// Functions with many lines but little branching, e.g. long init or register tables
+fn long_simple_functions<'a>(metrics: &'a [FunctionMetrics], output: &OutputOptions) -> Vec<&'a FunctionMetrics> {
+    let (min_sloc, max_complexity) = output.long_simple;
+    let mut long_simple: Vec<&FunctionMetrics> = metrics
+        .iter()
+        .filter(|func| func.sloc > min_sloc && func.max_complexity() < max_complexity)
+        .collect();
+    long_simple.sort_by_key(|func| std::cmp::Reverse(func.sloc));
+    long_simple
+}
+
+/// List long but simple functions as candidates for table-driven refactoring
+fn display_long_simple_functions(metrics: &[FunctionMetrics], output: &OutputOptions) {
+    let long_simple = long_simple_functions(metrics, output);
+    if long_simple.is_empty() {
+        return;
+    }
+
+    println!("\n=== LONG BUT SIMPLE FUNCTIONS ===\n");
+    for func in &long_simple {
+        println!(
+            "  {} [{}] (SLOC: {}, McCabe: {}, Cognitive: {}) - long but simple, consider table-driven",
+            func.name, func.file_path, func.sloc, func.mccabe, func.cognitive
+        );
+    }
+}
+
+/// Functions with the most debt markers, most first (ties by complexity), up to `limit`
+fn most_debt_marked(metrics: &[FunctionMetrics], limit: usize) -> Vec<&FunctionMetrics> {
+    let mut marked: Vec<&FunctionMetrics> = metrics.iter().filter(|func| func.debt_markers > 0).collect();
+    marked.sort_by(|a, b| {
+        b.debt_markers
+            .cmp(&a.debt_markers)
+            .then_with(|| b.max_complexity().cmp(&a.max_complexity()))
+    });
+    marked.truncate(limit);
+    marked
+}
+
+/// List the functions carrying the most TODO/FIXME-style markers next to their complexity
+fn display_debt_markers(metrics: &[FunctionMetrics], output: &OutputOptions) {
+    let marked = most_debt_marked(metrics, 5);
+    if marked.is_empty() {
+        return;
+    }
+
+    let total: u32 = metrics.iter().map(|func| func.debt_markers).sum();
+    println!("\n=== DEBT MARKERS ({} total) ===\n", total);
+    for func in marked {
+        println!(
+            "  {} {} [{}] ({} markers, McCabe: {}, Cognitive: {})",
+            output.badge(func),
+            func.name,
+            func.file_path,
+            func.debt_markers,
+            func.mccabe,
+            func.cognitive
+        );
+    }
+}
+
+/// Smallest cognitive-McCabe gap the nesting-dominated section reports
+const NESTING_GAP_MIN: u32 = 5;
+
+/// Functions whose cognitive complexity most exceeds McCabe, widest gap first, up to `limit`
+fn nesting_dominated(metrics: &[FunctionMetrics], limit: usize) -> Vec<&FunctionMetrics> {
+    let mut dominated: Vec<&FunctionMetrics> =
+        metrics.iter().filter(|func| func.cognitive_gap() >= NESTING_GAP_MIN).collect();
+    dominated.sort_by_key(|func| std::cmp::Reverse(func.cognitive_gap()));
+    dominated.truncate(limit);
+    dominated
+}
+
+/// Highest documentation score a function can earn in its test score
+const DOCUMENTATION_SCORE_MAX: i32 = 10;
+
+/// Weights of the three terms of `FunctionMetrics::risk_score` (--risk-weights)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RiskWeights {
+    complexity: f64,
+    testability: f64,
+    documentation: f64,
+}
+
+impl RiskWeights {
+    const DEFAULT: RiskWeights = RiskWeights {
+        complexity: 1.0,
+        testability: 1.0,
+        documentation: 1.0,
+    };
+}
+
+/// Functions by descending risk score, up to `limit`; ties keep file order
+fn highest_risk<'a>(
+    metrics: &'a [FunctionMetrics],
+    weights: &RiskWeights,
+    limit: usize,
+) -> Vec<(&'a FunctionMetrics, f64)> {
+    let mut ranked: Vec<(&FunctionMetrics, f64)> =
+        metrics.iter().map(|func| (func, func.risk_score(weights))).collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked.truncate(limit);
+    ranked
+}
+
+/// List the complex, hard-to-test, undocumented functions to review first
+fn display_highest_risk(metrics: &[FunctionMetrics], weights: &RiskWeights, output: &OutputOptions) {
+    println!("\n=== HIGHEST RISK FUNCTIONS ===\n");
+    for (func, risk) in highest_risk(metrics, weights, 5) {
+        println!(
+            "  {} {} [{}] (Risk: {:.*}; Max Complexity: {}, Test Score: {}, Documentation: {})",
+            output.badge(func),
+            func.name,
+            func.file_path,
+            output.precision,
+            risk,
+            func.max_complexity(),
+            func.test_scoring.total_score,
+            func.test_scoring.documentation_score
+        );
+    }
+}
+
+/// Functions with a switch nested in another switch, deepest nesting first
+fn nested_switches(metrics: &[FunctionMetrics]) -> Vec<&FunctionMetrics> {
+    let mut nested: Vec<&FunctionMetrics> = metrics.iter().filter(|func| func.max_switch_nesting > 1).collect();
+    nested.sort_by_key(|func| std::cmp::Reverse(func.max_switch_nesting));
+    nested
+}
+
+/// List every function with a switch inside another switch's case, with the inner switches' lines
+fn display_nested_switches(metrics: &[FunctionMetrics], output: &OutputOptions) {
+    let nested = nested_switches(metrics);
+    if nested.is_empty() {
+        return;
+    }
+
+    println!("\n=== NESTED SWITCHES ===\n");
+    for func in nested {
+        let lines: Vec<String> = func.nested_switch_lines.iter().map(|line| line.to_string()).collect();
+        println!(
+            "  {} {} [{}] (Switch Nesting: {}) - nested switch on line{} {}",
+            output.badge(func),
+            func.name,
+            func.file_path,
+            func.max_switch_nesting,
+            if lines.len() == 1 { "" } else { "s" },
+            lines.join(", ")
+        );
+    }
+}
+
+/// List structurally simple but deeply nested functions, the candidates for guard clauses
+fn display_nesting_dominated(metrics: &[FunctionMetrics], output: &OutputOptions) {
+    let dominated = nesting_dominated(metrics, 5);
+    if dominated.is_empty() {
+        return;
+    }
+
+    println!("\n=== NESTING-DOMINATED FUNCTIONS ===\n");
+    for func in dominated {
+        println!(
+            "  {} {} [{}] (Cognitive: {}, McCabe: {}, gap {}, Nesting: {}) - flatten with guard clauses",
+            output.badge(func),
+            func.name,
+            func.file_path,
+            func.cognitive,
+            func.mccabe,
+            func.cognitive_gap(),
+            func.nesting
+        );
+    }
+}
+
+/// Separate unreferenced static functions from the rest when --flag-unused-static is set
+fn split_unused_statics(metrics: &[FunctionMetrics], output: &OutputOptions) -> (Vec<FunctionMetrics>, Vec<FunctionMetrics>) {
+    metrics
+        .iter()
+        .cloned()
+        .partition(|func| !(output.flag_unused_static && func.unused_static))
+}
+
+/// Lowest complexity the no-callers section reports: the 😐 band and worse
+const UNCALLED_MIN_COMPLEXITY: u32 = 11;
+
+/// Complex functions nothing in the analyzed files calls, most complex first
+///
+/// A static function qualifies when nothing in its file references it, not even by
+/// taking its address; any other function when no analyzed function calls it by name.
+/// `main` is never reported.
+fn uncalled_complex_functions<'a>(metrics: &'a [FunctionMetrics], output: &OutputOptions) -> Vec<&'a FunctionMetrics> {
+    let called: HashSet<&str> = metrics
+        .iter()
+        .flat_map(|func| func.calls.iter().filter(move |callee| **callee != func.name))
+        .map(String::as_str)
+        .collect();
+    let mut uncalled: Vec<&FunctionMetrics> = metrics
+        .iter()
+        .filter(|func| output.primary.complexity(func) >= UNCALLED_MIN_COMPLEXITY && func.name != "main")
+        .filter(|func| if func.is_static { func.unused_static } else { !called.contains(func.name.as_str()) })
+        .collect();
+    uncalled.sort_by_key(|func| std::cmp::Reverse(output.primary.complexity(func)));
+    uncalled
+}
+
+/// List complex functions with no detected callers, which may be deletable
+fn display_uncalled_functions(metrics: &[FunctionMetrics], output: &OutputOptions) {
+    let uncalled = uncalled_complex_functions(metrics, output);
+    if uncalled.is_empty() {
+        return;
+    }
+
+    println!("\n=== COMPLEX FUNCTIONS WITH NO CALLERS ===\n");
+    for func in &uncalled {
+        println!(
+            "  {} [{}] (McCabe: {}, Cognitive: {}) - {}",
+            func.name,
+            func.file_path,
+            func.mccabe,
+            func.cognitive,
+            if func.is_static { "static, never referenced in its file" } else { "not called by any analyzed file" }
+        );
+    }
+    println!("\n  Best-effort: calls from outside the analyzed files and through function pointers are not seen");
+}
+
+/// List static functions with no references in their file (likely dead code)
+fn display_unused_statics(unused_statics: &[FunctionMetrics]) {
+    if unused_statics.is_empty() {
+        return;
+    }
+
+    println!("\n=== POTENTIALLY UNUSED STATIC FUNCTIONS ===\n");
+    for func in unused_statics {
+        println!("  {} [{}] (McCabe: {}, Cognitive: {})", func.name, func.file_path, func.mccabe, func.cognitive);
+    }
+    println!("\n  {} static function(s) are never referenced and were left out of the totals", unused_statics.len());
+}
+
+/// Where recursive analysis writes its detailed report
+const REPORT_FILE: &str = "report.txt";
+
+/// Write detailed report to `path` for recursive analysis; nothing is written with --no-report
+fn write_detailed_report(path: &Path, all_metrics: &[FunctionMetrics], output: &OutputOptions) -> Result<()> {
+    if output.no_report {
+        return Ok(());
+    }
+    let mut file = fs::File::create(path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+
+    write_function_report(&mut file, all_metrics, output, None)
+}
+
+/// Write a function's metrics, one per line, as listed under its name by --verbose
+fn write_function_details<W: Write>(out: &mut W, func: &FunctionMetrics, output: &OutputOptions) -> Result<()> {
+    writeln!(out, "  McCabe Complexity: {}", func.mccabe)?;
+    writeln!(out, "  Essential Complexity: {}", func.essential_complexity)?;
+    writeln!(out, "  Cognitive Complexity: {}{}", func.cognitive, switch_case_note(func))?;
+    writeln!(out, "  Nesting Depth: {}", func.nesting)?;
+    writeln!(out, "  SLOC: {}", func.sloc)?;
+    writeln!(out, "  ABC Magnitude: {:.*}", output.precision, func.abc_magnitude)?;
+    writeln!(out, "  Return Count: {}", func.return_count)?;
+    writeln!(out, "  Loops: {}, Branches: {}", func.loop_count, func.branch_count)?;
+    writeln!(out, "  Exit Points: {}", func.exit_points)?;
+    if func.computed_goto_count > 0 {
+        writeln!(out, "  Computed Gotos: {}", func.computed_goto_count)?;
+    }
+    writeln!(out, "  Magic Numbers: {}", func.magic_numbers)?;
+    writeln!(out, "  Longest Dereference Chain: {}", func.max_dereference_chain)?;
+    writeln!(out, "  Deepest Call Nesting: {}", func.max_call_nesting)?;
+    if func.max_switch_nesting > 1 {
+        writeln!(out, "  Switch Nesting: {}", func.max_switch_nesting)?;
+    }
+    if func.generic_association_count > 0 {
+        writeln!(out, "  _Generic Associations: {}", func.generic_association_count)?;
+    }
+    if func.config_branch_count > 0 {
+        writeln!(out, "  Config Branches: {} (#if/#ifdef/#elif)", func.config_branch_count)?;
+    }
+    if func.static_local_count > 0 {
+        writeln!(out, "  Static Locals: {} (non-reentrant)", func.static_local_count)?;
+    }
+    if func.debt_markers > 0 {
+        writeln!(out, "  Debt Markers: {}", func.debt_markers)?;
+    }
+    if func.leak_risk {
+        writeln!(out, "  Leak Risk: possible leak on error path")?;
+    }
+    if func.is_noreturn {
+        writeln!(out, "  Never Returns: declared noreturn")?;
+    }
+    writeln!(
+        out,
+        "  Error Handling: {} of {} SLOC ({:.*}%)",
+        func.error_handling_sloc,
+        func.sloc,
+        output.precision,
+        func.error_handling_ratio() * 100.0
+    )?;
+    writeln!(out, "  Large By-Value Params: {}", func.large_value_param_count)?;
+    if func.parameter_modifications > 0 {
+        writeln!(out, "  Parameter Modifications: {}", func.parameter_modifications)?;
+    }
+    if !func.const_candidates.is_empty() {
+        writeln!(out, "  Could Be Const: {}", func.const_candidates.join(", "))?;
+    }
+    if let Some(style) = &func.style {
+        writeln!(out, "  Style Consistency: {}", format_style(style, output.precision))?;
+    }
+    writeln!(out, "  Test Scoring: {} ({})", func.test_scoring.total_score, func.test_scoring.classification())?;
+    writeln!(out, "    - Signature: {}", func.test_scoring.signature_score)?;
+    writeln!(out, "    - Dependency: {}", func.test_scoring.dependency_score)?;
+    writeln!(out, "    - Observable: {}", func.test_scoring.observable_score)?;
+    writeln!(out, "    - Implementation: {}", func.test_scoring.implementation_score)?;
+    writeln!(out, "    - Documentation: {}", func.test_scoring.documentation_score)?;
+    writeln!(out, "  Max Complexity: {}", func.max_complexity())?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Line noting how many functions --limit left out of a listing
+fn more_functions_line(hidden: usize) -> String {
+    format!("… and {} more function(s)", hidden)
+}
+
+/// Write one entry per function (including its file path) to any writer
+/// With `limit`, only the first `limit` functions are written, followed by a count of the rest
+fn write_function_report<W: Write>(
+    file: &mut W,
+    all_metrics: &[FunctionMetrics],
+    output: &OutputOptions,
+    limit: Option<usize>,
+) -> Result<()> {
+    let shown = limit.unwrap_or(all_metrics.len()).min(all_metrics.len());
+    for func in &all_metrics[..shown] {
+        let badge = output.badge(func);
+
+        if output.verbose {
+            writeln!(file, "Function: {} {} [{}]", func.name, badge, func.file_path)?;
+            write_function_details(file, func, output)?;
+        } else {
+            writeln!(file, "{} {} [{}] ({})", badge, func.name, func.file_path, format_fields(func, output))?;
+        }
+    }
+
+    if shown < all_metrics.len() {
+        writeln!(file, "{}", more_functions_line(all_metrics.len() - shown))?;
+    }
+    Ok(())
+}
+
+/// Version of the JSON report layout; bump whenever a serialized field changes
+const JSON_SCHEMA_VERSION: u32 = 32;
+
+/// First schema version whose functions record `is_static`
+const IS_STATIC_SCHEMA_VERSION: u32 = 21;
+
+/// Per-function JSON report that can be built up across several partial runs
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct JsonReport {
+    /// Layout version of this document (see `knots --print-schema`)
+    #[serde(default)]
+    schema_version: u32,
+    functions: Vec<FunctionMetrics>,
+}
+
+impl Default for JsonReport {
+    fn default() -> Self {
+        Self {
+            schema_version: JSON_SCHEMA_VERSION,
+            functions: Vec::new(),
+        }
+    }
+}
+
+/// Merge freshly analyzed functions into a report
+/// Entries for every analyzed file are replaced; entries for other files are kept
+fn merge_report(report: &mut JsonReport, analyzed_files: &[PathBuf], metrics: &[FunctionMetrics]) {
+    let analyzed: HashSet<String> = analyzed_files
+        .iter()
+        .map(|file| file.to_str().unwrap_or("").to_string())
+        .collect();
+
+    report.functions.retain(|func| !analyzed.contains(&func.file_path));
+    report.functions.extend(metrics.iter().cloned());
+    report.functions.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+}
+
+/// Write results to the --append report and --sqlite database, when given
+fn save_results(args: &Args, files: &[PathBuf], metrics: &[FunctionMetrics]) -> Result<()> {
+    if let Some(report_path) = &args.append {
+        append_to_json_report(report_path, files, metrics)?;
+    }
+    if let Some(db_path) = &args.sqlite {
+        let near = files.first().map(PathBuf::as_path).unwrap_or(Path::new("."));
+        record_run(db_path, near, metrics)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn record_run(db_path: &Path, near: &Path, metrics: &[FunctionMetrics]) -> Result<()> {
+    sqlite::record_run(db_path, metrics, sqlite::head_commit(near).as_deref())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn record_run(_db_path: &Path, _near: &Path, _metrics: &[FunctionMetrics]) -> Result<()> {
+    anyhow::bail!("--sqlite is not available in this build; rebuild knots with `--features sqlite`")
+}
+
+/// Print the per-run averages recorded in a --sqlite database (knots trend)
+#[cfg(feature = "sqlite")]
+fn show_trend(db_path: &Path, metric: Field, svg: bool, precision: usize) -> Result<()> {
+    let points = sqlite::load_trend(db_path, metric)?;
+    if points.is_empty() {
+        anyhow::bail!("No runs recorded in {}", db_path.display());
+    }
+    if svg {
+        print!("{}", trend::format_svg(&points, metric.key(), precision));
+    } else {
+        print!("{}", trend::format_text(&points, metric.key(), precision));
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn show_trend(_db_path: &Path, _metric: Field, _svg: bool, _precision: usize) -> Result<()> {
+    anyhow::bail!("knots trend is not available in this build; rebuild knots with `--features sqlite`")
+}
+
+/// Merge results into an existing JSON report (or create it) and write it back
+fn append_to_json_report(report_path: &Path, analyzed_files: &[PathBuf], metrics: &[FunctionMetrics]) -> Result<()> {
+    let mut report = if report_path.exists() {
+        load_json_report(report_path)?
+    } else {
+        JsonReport::default()
+    };
+
+    merge_report(&mut report, analyzed_files, metrics);
+    report.schema_version = JSON_SCHEMA_VERSION;
+
+    let json = serde_json::to_string_pretty(&report_to_json(&report)?).context("Failed to serialize report")?;
+    fs::write(report_path, json)
+        .with_context(|| format!("Failed to write report: {}", report_path.display()))?;
+
+    Ok(())
+}
+
+/// The report as a JSON value, without the metrics each function's --metrics run skipped
+fn report_to_json(report: &JsonReport) -> Result<serde_json::Value> {
+    let mut json = serde_json::to_value(report).context("Failed to serialize report")?;
+    if let Some(functions) = json.get_mut("functions").and_then(serde_json::Value::as_array_mut) {
+        for (func, value) in report.functions.iter().zip(functions) {
+            if let Some(object) = value.as_object_mut() {
+                object.retain(|key, _| !func.skipped.contains(&key.as_str()));
+            }
+        }
+    }
+    Ok(json)
+}
+
+/// Read a JSON report written by --append
+fn load_json_report(report_path: &Path) -> Result<JsonReport> {
+    let content = fs::read_to_string(report_path)
+        .with_context(|| format!("Failed to read report: {}", report_path.display()))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse report JSON: {}", report_path.display()))
+}
+
+/// Metric changes for a function present in both reports
+struct FunctionChange<'a> {
+    old: &'a FunctionMetrics,
+    new: &'a FunctionMetrics,
+    /// Fields whose value changed
+    fields: Vec<Field>,
+}
+
+impl FunctionChange<'_> {
+    /// Growth in max(McCabe, Cognitive); positive means the function got worse
+    fn regression(&self) -> i64 {
+        self.new.max_complexity() as i64 - self.old.max_complexity() as i64
+    }
+}
+
+/// Differences between two reports, matched by (file, function name)
+struct ReportDiff<'a> {
+    /// Changed functions, largest regression first
+    changed: Vec<FunctionChange<'a>>,
+    added: Vec<&'a FunctionMetrics>,
+    removed: Vec<&'a FunctionMetrics>,
+}
+
+/// Float metrics count as changed only when they moved by more than `tolerance`
+fn diff_reports<'a>(old: &'a JsonReport, new: &'a JsonReport, tolerance: f64) -> ReportDiff<'a> {
+    // Reports from before `is_static` was recorded say every function is extern
+    let compare_linkage = old.schema_version.min(new.schema_version) >= IS_STATIC_SCHEMA_VERSION;
+    let find = |report: &'a JsonReport, func: &FunctionMetrics| {
+        report
+            .functions
+            .iter()
+            .find(|other| other.same_function(func, compare_linkage))
+    };
+
+    let mut changed = Vec::new();
+    let mut added = Vec::new();
+    for new_func in &new.functions {
+        match find(old, new_func) {
+            Some(old_func) => {
+                let fields: Vec<Field> = Field::ALL
+                    .iter()
+                    .copied()
+                    .filter(|field| {
+                        let delta = (field.value(new_func) - field.value(old_func)).abs();
+                        if field.is_float() {
+                            delta > tolerance
+                        } else {
+                            delta != 0.0
+                        }
+                    })
+                    .collect();
+                if !fields.is_empty() {
+                    changed.push(FunctionChange { old: old_func, new: new_func, fields });
+                }
+            }
+            None => added.push(new_func),
+        }
+    }
+    let removed = old
+        .functions
+        .iter()
+        .filter(|old_func| find(new, old_func).is_none())
+        .collect();
+
+    changed.sort_by(|a, b| b.regression().cmp(&a.regression()).then_with(|| a.new.name.cmp(&b.new.name)));
+    added.sort_by_key(|func| std::cmp::Reverse(func.max_complexity()));
+
+    ReportDiff { changed, added, removed }
+}
+
+fn display_report_diff(diff: &ReportDiff, precision: usize) {
+    println!("=== CHANGED FUNCTIONS ({}) ===\n", diff.changed.len());
+    for change in &diff.changed {
+        let deltas: Vec<String> = change
+            .fields
+            .iter()
+            .map(|field| {
+                let delta = field.value(change.new) - field.value(change.old);
+                let text = format!(
+                    "{} {} -> {} ({})",
+                    field.key(),
+                    field.format_value(change.old, precision),
+                    field.format_value(change.new, precision),
+                    format_delta(*field, delta, precision)
+                );
+                // Higher is worse for every metric, including the test score
+                if delta > 0.0 {
+                    text.red().to_string()
+                } else {
+                    text.green().to_string()
+                }
+            })
+            .collect();
+        println!("  {} [{}]: {}", change.new.name, change.new.file_path, deltas.join(", "));
+    }
+
+    println!("\n=== ADDED FUNCTIONS ({}) ===\n", diff.added.len());
+    for func in &diff.added {
+        println!("  {} {} [{}] (McCabe: {}, Cognitive: {})", "+".red(), func.name, func.file_path, func.mccabe, func.cognitive);
+    }
+
+    println!("\n=== REMOVED FUNCTIONS ({}) ===\n", diff.removed.len());
+    for func in &diff.removed {
+        println!("  {} {} [{}] (McCabe: {}, Cognitive: {})", "-".green(), func.name, func.file_path, func.mccabe, func.cognitive);
+    }
+}
+
+/// Signed change, e.g. "+4" or "-1.25" for ABC
+fn format_delta(field: Field, delta: f64, precision: usize) -> String {
+    match field {
+        Field::Abc => format!("{:+.*}", precision, delta),
+        _ => format!("{:+}", delta as i64),
+    }
+}
+
+/// Display summary with top 5 worst functions and totals/averages
+fn display_recursive_summary(all_metrics: &[FunctionMetrics], output: &OutputOptions, counts: &FileCounts) {
+    match (&output.rank_by, output.primary) {
+        (Some(expr), _) => println!("\n=== TOP 5 WORST FUNCTIONS (by {}) ===\n", expr),
+        (None, PrimaryMetric::Max) => println!("\n=== TOP 5 WORST FUNCTIONS ===\n"),
+        (None, primary) => println!(
+            "\n=== TOP 5 WORST FUNCTIONS (by {}) ===\n",
+            primary.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default()
+        ),
+    }
+    for (i, func) in worst_functions(all_metrics, output.rank_by.as_ref(), output.primary).iter().take(5).enumerate() {
+        println!(
+            "{}. {} {} [{}]",
+            i + 1,
+            output.badge(func),
+            func.name,
+            func.file_path
+        );
+        match &output.rank_by {
+            Some(expr) => println!("   Rank: {:.*}, {}", output.precision, expr.eval(func), format_fields(func, output)),
+            None => println!("   {}", format_fields(func, output)),
+        }
+    }
+
+    let rows = MetricTotals::of(all_metrics).summary_rows(all_metrics, output.precision);
+    let function_count = all_metrics.len();
+
+    println!("\n=== TOTALS & AVERAGES ===\n");
+    println!("  Total Functions: {}", function_count);
+    for (name, total, _) in &rows {
+        println!("  Total {}: {}", name, total);
+    }
+    display_suppression_count(all_metrics);
+
+    if function_count > 0 {
+        println!();
+        for (name, _, sum) in &rows {
+            println!("  Average {}: {:.*}", name, output.precision, sum / function_count as f64);
+        }
+        if let Some(score) = average_style_score(all_metrics) {
+            println!("  Average Style Consistency: {:.*}%", output.precision, score);
+        }
+        if let Some(cut_points) = &output.grade_cut_points {
+            println!("  Average Grade: {}", format_average_grade(all_metrics, cut_points, output.precision));
+        }
+
+        println!();
+        for field in &output.fields {
+            if let Some((min, max)) = metric_range(all_metrics, *field) {
+                println!(
+                    "  {}: min {} ({}), max {} ({})",
+                    field.label(),
+                    field.format_value(min, output.precision),
+                    min.name,
+                    field.format_value(max, output.precision),
+                    max.name
+                );
+            }
+        }
+    }
+
+    if let Some(cut_points) = &output.grade_cut_points {
+        display_file_grades(all_metrics, cut_points, output.precision);
+    }
+
+    if !output.no_report {
+        println!("\nDetailed per-function output written to {}", REPORT_FILE);
+    }
+    println!();
+    display_files_processed(counts);
+}
+
+/// Per-metric sums over a set of functions
+#[derive(Debug, Default, Serialize)]
+struct MetricTotals {
+    mccabe: u64,
+    cognitive: u64,
+    nesting: u64,
+    sloc: u64,
+    abc_magnitude: f64,
+    return_count: u64,
+    test_score: i64,
+}
+
+/// Per-function means of the `MetricTotals`
+#[derive(Debug, Serialize)]
+struct MetricAverages {
+    mccabe: f64,
+    cognitive: f64,
+    nesting: f64,
+    sloc: f64,
+    abc_magnitude: f64,
+    return_count: f64,
+    test_score: f64,
+}
+
+/// `MetricTotals` per 100 SLOC, comparable between files of any size (--normalized)
+#[derive(Debug, PartialEq)]
+struct MetricDensity {
+    mccabe: f64,
+    cognitive: f64,
+    abc_magnitude: f64,
+    return_count: f64,
+    test_score: f64,
+}
+
+impl MetricTotals {
+    fn of(all_metrics: &[FunctionMetrics]) -> Self {
+        let mut totals = MetricTotals::default();
+        for func in all_metrics {
+            totals.add(func);
+        }
+        totals
+    }
+
+    fn add(&mut self, func: &FunctionMetrics) {
+        self.mccabe += func.mccabe as u64;
+        self.cognitive += func.cognitive as u64;
+        self.nesting += func.nesting as u64;
+        self.sloc += func.sloc as u64;
+        self.abc_magnitude += func.abc_magnitude;
+        self.return_count += func.return_count as u64;
+        self.test_score += func.test_scoring.total_score as i64;
+    }
+
+    /// Each total per 100 SLOC; None without any SLOC
+    fn per_100_sloc(&self) -> Option<MetricDensity> {
+        if self.sloc == 0 {
+            return None;
+        }
+        let scale = 100.0 / self.sloc as f64;
+        Some(MetricDensity {
+            mccabe: self.mccabe as f64 * scale,
+            cognitive: self.cognitive as f64 * scale,
+            abc_magnitude: self.abc_magnitude * scale,
+            return_count: self.return_count as f64 * scale,
+            test_score: self.test_score as f64 * scale,
+        })
+    }
+
+    /// (name, total, sum) of each metric every function computed, for the "Total" and
+    /// "Average" summary lines; metrics --metrics skipped have no row rather than a total of 0
+    fn summary_rows(&self, all_metrics: &[FunctionMetrics], precision: usize) -> Vec<(&'static str, String, f64)> {
+        let rows = [
+            (Field::Mccabe, "McCabe Complexity", self.mccabe.to_string(), self.mccabe as f64),
+            (Field::Cognitive, "Cognitive Complexity", self.cognitive.to_string(), self.cognitive as f64),
+            (Field::Nesting, "Nesting Depth", self.nesting.to_string(), self.nesting as f64),
+            (Field::Sloc, "SLOC", self.sloc.to_string(), self.sloc as f64),
+            (Field::Abc, "ABC Magnitude", format!("{:.*}", precision, self.abc_magnitude), self.abc_magnitude),
+            (Field::Returns, "Return Count", self.return_count.to_string(), self.return_count as f64),
+            (Field::TestScore, "Test Score", self.test_score.to_string(), self.test_score as f64),
+        ];
+        rows.into_iter()
+            .filter(|(field, ..)| all_metrics.iter().all(|func| func.computes(*field)))
+            .map(|(_, name, total, sum)| (name, total, sum))
+            .collect()
+    }
+
+    /// Each total divided by `count`, which must not be 0
+    fn averages(&self, count: usize) -> MetricAverages {
+        let count = count as f64;
+        MetricAverages {
+            mccabe: self.mccabe as f64 / count,
+            cognitive: self.cognitive as f64 / count,
+            nesting: self.nesting as f64 / count,
+            sloc: self.sloc as f64 / count,
+            abc_magnitude: self.abc_magnitude / count,
+            return_count: self.return_count as f64 / count,
+            test_score: self.test_score as f64 / count,
+        }
+    }
+}
+
+/// One of the worst functions in a `RunSummary`
+#[derive(Debug, Serialize)]
+struct SummaryFunction<'a> {
+    name: &'a str,
+    file: &'a str,
+    line: usize,
+    mccabe: u32,
+    cognitive: u32,
+}
+
+/// The FILES PROCESSED section of a `RunSummary`
+#[derive(Debug, Serialize)]
+struct FileSummary {
+    found: usize,
+    processed: usize,
+    skipped: usize,
+    inaccessible: usize,
+    generated: usize,
+    with_parse_errors: usize,
+}
+
+/// Everything the text summary sections report, for --summary-format json
+#[derive(Debug, Serialize)]
+struct RunSummary<'a> {
+    /// Layout version shared with the JSON report
+    schema_version: u32,
+    functions: usize,
+    totals: MetricTotals,
+    /// Absent when there are no functions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    averages: Option<MetricAverages>,
+    /// Up to 5 functions, worst first, ranked like TOP 5 WORST FUNCTIONS
+    worst: Vec<SummaryFunction<'a>>,
+    /// Absent for a single file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    files: Option<FileSummary>,
+}
+
+impl<'a> RunSummary<'a> {
+    fn of(all_metrics: &'a [FunctionMetrics], output: &OutputOptions, counts: Option<&FileCounts>) -> Self {
+        let totals = MetricTotals::of(all_metrics);
+        RunSummary {
+            schema_version: JSON_SCHEMA_VERSION,
+            functions: all_metrics.len(),
+            averages: (!all_metrics.is_empty()).then(|| totals.averages(all_metrics.len())),
+            totals,
+            worst: worst_functions(all_metrics, output.rank_by.as_ref(), output.primary)
+                .into_iter()
+                .take(5)
+                .map(|func| SummaryFunction {
+                    name: &func.name,
+                    file: &func.file_path,
+                    line: func.line,
+                    mccabe: func.mccabe,
+                    cognitive: func.cognitive,
+                })
+                .collect(),
+            files: counts.map(|counts| FileSummary {
+                found: counts.found,
+                processed: counts.found - counts.skipped.total(),
+                skipped: counts.skipped.total(),
+                inaccessible: counts.inaccessible,
+                generated: counts.generated,
+                with_parse_errors: counts.parse_issues.len(),
+            }),
+        }
+    }
+}
+
+fn format_summary_json(summary: &RunSummary) -> String {
+    serde_json::to_string_pretty(summary).unwrap_or_default()
+}
+
+/// `--show-source`: a function's lines from `source` (the whole file) with line numbers,
+/// keeping the first and last lines and eliding the middle when there are over `max_lines`
+fn format_source_snippet(source: &str, func: &FunctionMetrics, max_lines: usize) -> String {
+    let first = func.line.max(1);
+    let lines: Vec<&str> = source.lines().skip(first - 1).take((func.end_line + 1).saturating_sub(first)).collect();
+    let width = (first + lines.len().saturating_sub(1)).to_string().len();
+    let numbered = |index: usize| format!("{:>width$} | {}\n", first + index, lines[index]);
+
+    let mut out = format!("--- {}() [{}:{}-{}] ---\n", func.name, func.file_path, first, func.end_line);
+    if lines.len() <= max_lines {
+        (0..lines.len()).for_each(|index| out.push_str(&numbered(index)));
+    } else {
+        let head = max_lines.div_ceil(2);
+        let tail = max_lines - head;
+        (0..head).for_each(|index| out.push_str(&numbered(index)));
+        out.push_str(&format!("{:>width$} | ... {} lines omitted ...\n", "", lines.len() - head - tail));
+        (lines.len() - tail..lines.len()).for_each(|index| out.push_str(&numbered(index)));
+    }
+    out
+}
+
+/// Print the source of the named function, or of the top 5 worst, after the summary
+fn display_source(all_metrics: &[FunctionMetrics], name: Option<&str>, max_lines: usize, output: &OutputOptions) {
+    let functions: Vec<&FunctionMetrics> = match name {
+        Some(name) => all_metrics.iter().filter(|func| func.name == name).collect(),
+        None => worst_functions(all_metrics, output.rank_by.as_ref(), output.primary).into_iter().take(5).collect(),
+    };
+    if functions.is_empty() {
+        eprintln!("Warning: --show-source: no function named {}", name.unwrap_or_default());
+        return;
+    }
+
+    println!("\n=== SOURCE ===\n");
+    for func in functions {
+        match fs::read_to_string(&func.file_path) {
+            Ok(source) => println!("{}", format_source_snippet(&source, func, max_lines)),
+            Err(e) => eprintln!("Warning: --show-source: cannot read {}: {}", func.file_path, e),
+        }
+    }
+}
+
+/// Functions sorted worst first, by --rank-by or else by the primary metric
+fn worst_functions<'a>(
+    all_metrics: &'a [FunctionMetrics],
+    rank_by: Option<&RankExpr>,
+    primary: PrimaryMetric,
+) -> Vec<&'a FunctionMetrics> {
+    let mut sorted: Vec<&FunctionMetrics> = all_metrics.iter().collect();
+    match rank_by {
+        Some(expr) => sorted.sort_by(|a, b| expr.eval(b).total_cmp(&expr.eval(a))),
+        None => sorted.sort_by_key(|f| std::cmp::Reverse(primary.complexity(f))),
+    }
+    sorted
+}
+
+/// Functions with the lowest and highest value of a metric (first one wins on ties)
+fn metric_range(all_metrics: &[FunctionMetrics], field: Field) -> Option<(&FunctionMetrics, &FunctionMetrics)> {
+    let min = all_metrics
+        .iter()
+        .min_by(|a, b| field.value(a).total_cmp(&field.value(b)))?;
+    let max = all_metrics
+        .iter()
+        .min_by(|a, b| field.value(b).total_cmp(&field.value(a)))?;
+    Some((min, max))
+}
+
+/// File totals shown in the FILES PROCESSED section
+struct FileCounts {
+    /// Files selected for analysis
+    found: usize,
+    /// Files left out of the analysis, by reason
+    skipped: SkipCounts,
+    /// Source bytes in the processed files
+    bytes: usize,
+    /// Directory entries that could not be accessed while scanning
+    inaccessible: usize,
+    /// Files left out by --exclude-generated
+    generated: usize,
+    /// Files that parsed with errors: (path, errors, confidence)
+    parse_issues: Vec<(String, ParseErrors, ParseConfidence)>,
+}
+
+/// Why files were left out of the analysis
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct SkipCounts {
+    /// Unreadable, not UTF-8, or failed to preprocess
+    read_error: usize,
+    /// tree-sitter gave up, or parse errors left no function definitions
+    parse_failure: usize,
+    /// Parsed cleanly but defined no functions (or none passed the filters)
+    no_functions: usize,
+}
+
+impl SkipCounts {
+    fn total(&self) -> usize {
+        self.read_error + self.parse_failure + self.no_functions
+    }
+}
+
+fn display_files_processed(counts: &FileCounts) {
+    println!("=== FILES PROCESSED ===\n");
+    println!("  Total files found: {}", counts.found);
+    println!("  Successfully processed: {} ({} bytes)", counts.found - counts.skipped.total(), counts.bytes);
+    if counts.skipped.total() > 0 {
+        println!("  Skipped: {}", counts.skipped.total());
+        for (reason, count) in [
+            ("read errors (I/O, encoding, preprocessor)", counts.skipped.read_error),
+            ("parse failures", counts.skipped.parse_failure),
+            ("no functions found", counts.skipped.no_functions),
+        ] {
+            if count > 0 {
+                println!("    {}: {}", reason, count);
+            }
+        }
+    }
+    if counts.inaccessible > 0 {
+        println!("  Inaccessible entries (permissions, broken links, loops): {}", counts.inaccessible);
+    }
+    if counts.generated > 0 {
+        println!("  Excluded as generated: {}", counts.generated);
+    }
+
+    if !counts.parse_issues.is_empty() {
+        let processed = counts.found - counts.skipped.total();
+        let low = counts
+            .parse_issues
+            .iter()
+            .filter(|(_, _, confidence)| *confidence == ParseConfidence::Low)
+            .count();
+        let medium = counts.parse_issues.len() - low;
+        println!(
+            "  Parse confidence: {} high, {} medium, {} low",
+            processed - counts.parse_issues.len(),
+            medium,
+            low
+        );
+        for (path, errors, confidence) in &counts.parse_issues {
+            if *confidence == ParseConfidence::Low {
+                println!("    Unreliable: {} ({} error nodes)", path, errors.error_nodes);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+struct FunctionMetrics {
+    name: String,
+    file_path: String,
+    /// Line where the function definition starts
+    #[serde(default)]
+    line: usize,
+    /// Line where the function definition ends
+    #[serde(default)]
+    end_line: usize,
+    // The metric fields default to 0 because a run with --metrics omits the ones it skipped
+    #[serde(default)]
+    mccabe: u32,
+    #[serde(default)]
+    cognitive: u32,
+    /// `case` labels across all switches; cognitive complexity counts each switch once
+    #[serde(default)]
+    switch_case_total: u32,
+    #[serde(default)]
+    nesting: u32,
+    #[serde(default)]
+    sloc: u32,
+    #[serde(default)]
+    abc_magnitude: f64,
+    #[serde(default)]
+    return_count: u32,
+    /// Returns plus cleanup gotos and exit/abort/longjmp calls
+    #[serde(default)]
+    exit_points: u32,
+    /// GCC `goto *ptr;` jumps, weighted 2 in McCabe and cognitive complexity
+    #[serde(default)]
+    computed_goto_count: u32,
+    /// 1 plus each unstructured jump (goto, early return, loop break, continue, exit call)
+    #[serde(default)]
+    essential_complexity: u32,
+    /// Numeric literals other than 0 and 1 outside defines, enums, array sizes, and case labels
+    #[serde(default)]
+    magic_numbers: u32,
+    /// `static` local variables, which make the function non-reentrant
+    #[serde(default)]
+    static_local_count: u32,
+    /// TODO/FIXME-style markers in the function's comments
+    #[serde(default)]
+    debt_markers: u32,
+    /// Longest member-access chain in one expression (`a->b->c` is 3)
+    #[serde(default)]
+    max_dereference_chain: u32,
+    /// Deepest nesting of calls inside other calls' arguments (`a(b(c()))` is 3)
+    #[serde(default)]
+    max_call_nesting: u32,
+    /// Functions called directly by name, in order of first call
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    calls: Vec<String>,
+    /// Type associations in C11 `_Generic` selections; compile-time, so not part of McCabe
+    #[serde(default)]
+    generic_association_count: u32,
+    /// `#if`/`#ifdef`/`#elif` branches inside the function; build-time, so not part of McCabe
+    #[serde(default)]
+    config_branch_count: u32,
+    /// SLOC inside `if` bodies whose condition checks for an error
+    #[serde(default)]
+    error_handling_sloc: u32,
+    /// Struct or union parameters passed by value instead of through a pointer
+    #[serde(default)]
+    large_value_param_count: u32,
+    /// Assignments and `++`/`--` that change one of the function's own parameters
+    #[serde(default)]
+    parameter_modifications: u32,
+    /// Pointer parameters the function never writes through, which could point to const
+    #[serde(default)]
+    const_candidates: Vec<String>,
+    /// Indentation and brace placement consistency, with --style-check
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    style: Option<StyleConsistency>,
+    /// McCabe and statement counts by pmccabe's rules, with --pmccabe; only printed in pmccabe's format
+    #[serde(skip)]
+    pmccabe: Option<PmccabeCounts>,
+    /// `for`, `while`, and `do` loops
+    #[serde(default)]
+    loop_count: u32,
+    /// `if` and `switch` statements and ternaries
+    #[serde(default)]
+    branch_count: u32,
+    #[serde(default)]
+    test_scoring: TestScoringMetric,
+    /// Lines of statements that can never execute
+    #[serde(default)]
+    unreachable_lines: Vec<usize>,
+    /// Lines of `switch` statements with no `default:` label
+    #[serde(default)]
+    missing_default_lines: Vec<usize>,
+    /// Deepest nesting of switches inside one another's cases (a switch in a switch is 2)
+    #[serde(default)]
+    max_switch_nesting: u32,
+    /// Lines of `switch` statements nested inside another switch
+    #[serde(default)]
+    nested_switch_lines: Vec<usize>,
+    /// Lines of bare `return;` in a non-void function, or `return value;` in a void one
+    #[serde(default)]
+    inconsistent_return_lines: Vec<usize>,
+    /// Static function with no references elsewhere in its file
+    #[serde(default)]
+    unused_static: bool,
+    /// Non-void function with no return statement
+    #[serde(default)]
+    missing_return: bool,
+    /// Declared never to return (`_Noreturn`, `__attribute__((noreturn))`, ...), so it is
+    /// exempt from the return checks
+    #[serde(default)]
+    is_noreturn: bool,
+    /// Allocates memory and returns from more than one place without a cleanup label
+    #[serde(default)]
+    leak_risk: bool,
+    /// Part of the public interface: not static, and declared in --public-headers when given
+    #[serde(default)]
+    is_exported: bool,
+    /// Declared `static`: same-named statics in different files are different functions
+    #[serde(default)]
+    is_static: bool,
+    /// Gates silenced by a `knots:allow` or `knots-disable-next-line` comment before the function
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    suppressed: Vec<String>,
+    /// The comment block before the definition, with --include-docs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    doc_comment: Option<String>,
+    /// JSON keys of the metrics --metrics skipped, left out of the report rather than written as 0
+    #[serde(skip)]
+    skipped: Vec<&'static str>,
+}
+
+impl FunctionMetrics {
+    /// Whether `other` is this function in another run: same file, name, and, when
+    /// `compare_linkage` is set, the same static/extern linkage
+    fn same_function(&self, other: &FunctionMetrics, compare_linkage: bool) -> bool {
+        self.file_path == other.file_path
+            && self.name == other.name
+            && (!compare_linkage || self.is_static == other.is_static)
+    }
+
+    fn max_complexity(&self) -> u32 {
+        std::cmp::max(self.mccabe, self.cognitive)
+    }
+
+    /// Whether `field` was computed, i.e. not left out by --metrics
+    fn computes(&self, field: Field) -> bool {
+        !field.json_keys().iter().any(|key| self.skipped.contains(key))
+    }
+
+    /// How far cognitive complexity exceeds McCabe; nesting is what drives it up
+    fn cognitive_gap(&self) -> u32 {
+        self.cognitive.saturating_sub(self.mccabe)
+    }
+
+    /// Composite review priority: `C * max complexity + T * test score + D * (10 - documentation score)`
+    ///
+    /// Documentation scores run from 0 to 10, so an undocumented function gets the full
+    /// documentation term and a fully annotated one none.
+    fn risk_score(&self, weights: &RiskWeights) -> f64 {
+        let documented = self.test_scoring.documentation_score.clamp(0, DOCUMENTATION_SCORE_MAX);
+        let undocumented = DOCUMENTATION_SCORE_MAX - documented;
+        weights.complexity * self.max_complexity() as f64
+            + weights.testability * self.test_scoring.total_score as f64
+            + weights.documentation * undocumented as f64
+    }
+
+    /// Whether a suppression comment silences the gate for `metric` (e.g. "exit points",
+    /// written `exit_points` in the comment)
+    fn suppresses(&self, metric: &str) -> bool {
+        self.suppressed
+            .iter()
+            .any(|name| name == SUPPRESS_ALL || name.replace(['_', '-'], " ") == metric)
+    }
+
+    /// Fraction of the function's SLOC spent in error-handling blocks
+    fn error_handling_ratio(&self) -> f64 {
+        if self.sloc == 0 {
+            0.0
+        } else {
+            self.error_handling_sloc as f64 / self.sloc as f64
+        }
+    }
+}
+
+/// Default maintainability score cut points for grades A, B, C, D (anything above is F)
+const DEFAULT_GRADE_CUT_POINTS: [f64; 4] = [0.5, 0.75, 1.0, 1.5];
+
+/// Blends McCabe, cognitive complexity, nesting, and SLOC into one maintainability score
+///
+/// Each metric is divided by its recommended limit (McCabe 10, Cognitive 15, Nesting 4,
+/// SLOC 50) and weighted 30/30/20/20, so a function sitting exactly at every limit
+/// scores 1.0. Lower is better.
+fn maintainability_score(func: &FunctionMetrics) -> f64 {
+    0.3 * func.mccabe as f64 / 10.0
+        + 0.3 * func.cognitive as f64 / 15.0
+        + 0.2 * func.nesting as f64 / 4.0
+        + 0.2 * func.sloc as f64 / 50.0
+}
+
+/// Maps a maintainability score to a letter grade using ascending cut points for A-D
+fn grade_for_score(score: f64, cut_points: &[f64]) -> char {
+    ['A', 'B', 'C', 'D']
+        .iter()
+        .zip(cut_points)
+        .find(|(_, &cut)| score <= cut)
+        .map(|(&letter, _)| letter)
+        .unwrap_or('F')
+}
+
+/// Letter grade (A-F) for a single function
+fn grade(func: &FunctionMetrics, cut_points: &[f64]) -> char {
+    grade_for_score(maintainability_score(func), cut_points)
+}
+
+/// Grade of the average maintainability score, e.g. "B (0.62)"
+fn format_average_grade(metrics: &[FunctionMetrics], cut_points: &[f64], precision: usize) -> String {
+    let average = metrics.iter().map(maintainability_score).sum::<f64>() / metrics.len().max(1) as f64;
+    format!("{} ({:.*})", grade_for_score(average, cut_points), precision, average)
+}
+
+/// Each file's `MetricTotals` in first-seen order
+fn totals_by_file(all_metrics: &[FunctionMetrics]) -> Vec<(&str, MetricTotals)> {
+    let mut order: Vec<&str> = Vec::new();
+    let mut files: HashMap<&str, MetricTotals> = HashMap::new();
+    for func in all_metrics {
+        files
+            .entry(&func.file_path)
+            .or_insert_with(|| {
+                order.push(&func.file_path);
+                MetricTotals::default()
+            })
+            .add(func);
+    }
+    order.into_iter().map(|path| (path, files.remove(path).unwrap_or_default())).collect()
+}
+
+/// One line of --normalized output: a label, its SLOC, and its metrics per 100 SLOC
+fn format_density(label: &str, totals: &MetricTotals, precision: usize) -> String {
+    match totals.per_100_sloc() {
+        Some(density) => format!(
+            "  {} ({} SLOC): McCabe {:.*}, Cognitive {:.*}, ABC {:.*}, Returns {:.*}, Test Score {:.*}",
+            label,
+            totals.sloc,
+            precision,
+            density.mccabe,
+            precision,
+            density.cognitive,
+            precision,
+            density.abc_magnitude,
+            precision,
+            density.return_count,
+            precision,
+            density.test_score
+        ),
+        None => format!("  {} (0 SLOC): -", label),
+    }
+}
+
+/// Display metric totals per 100 SLOC for the whole run and for each file, densest McCabe first
+fn display_normalized(all_metrics: &[FunctionMetrics], output: &OutputOptions) {
+    let mut files = totals_by_file(all_metrics);
+    let mccabe_density = |totals: &MetricTotals| totals.per_100_sloc().map(|density| density.mccabe).unwrap_or(0.0);
+    files.sort_by(|a, b| mccabe_density(&b.1).total_cmp(&mccabe_density(&a.1)));
+
+    println!("\n=== PER 100 SLOC ===\n");
+    println!("{}", format_density("All files", &MetricTotals::of(all_metrics), output.precision));
+    for (path, totals) in &files {
+        println!("{}", format_density(path, totals, output.precision));
+    }
+}
+
+/// Display the average grade of each file, worst first
+fn display_file_grades(all_metrics: &[FunctionMetrics], cut_points: &[f64], precision: usize) {
+    // (file path, score sum, function count) in first-seen order
+    let mut files: Vec<(&str, f64, usize)> = Vec::new();
+    for func in all_metrics {
+        let score = maintainability_score(func);
+        match files.iter_mut().find(|(path, _, _)| *path == func.file_path) {
+            Some((_, sum, count)) => {
+                *sum += score;
+                *count += 1;
+            }
+            None => files.push((&func.file_path, score, 1)),
+        }
+    }
+
+    let mut graded: Vec<(&str, f64)> = files
+        .into_iter()
+        .map(|(path, sum, count)| (path, sum / count as f64))
+        .collect();
+    graded.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    println!("\n=== FILE GRADES ===\n");
+    for (path, average) in graded {
+        println!("  {} ({:.*}) {}", grade_for_score(average, cut_points), precision, average, path);
+    }
+}
+
+/// Totals for all functions in one directory prefix
+#[derive(Debug)]
+struct DirectorySummary {
+    directory: String,
+    functions: usize,
+    total_mccabe: u32,
+    total_cognitive: u32,
+    total_sloc: u32,
+    /// Sum of max(McCabe, Cognitive), used for ranking
+    total_complexity: u32,
+}
+
+/// Directory prefix of a file, keeping `depth` levels below `root` (or from the start of the path)
+fn directory_key(file_path: &str, root: Option<&Path>, depth: usize) -> String {
+    let path = Path::new(file_path);
+    let (base, relative) = match root.and_then(|root| path.strip_prefix(root).ok().map(|rel| (root, rel))) {
+        Some((root, relative)) => (root.to_path_buf(), relative),
+        None => (PathBuf::new(), path),
+    };
+
+    let mut key = base;
+    for component in relative.parent().into_iter().flat_map(|dir| dir.components()).take(depth) {
+        key.push(component);
+    }
+
+    if key.as_os_str().is_empty() {
+        ".".to_string()
+    } else {
+        key.display().to_string()
+    }
+}
+
+/// Aggregate functions by directory prefix, highest total complexity first
+fn group_by_directory(all_metrics: &[FunctionMetrics], root: Option<&Path>, depth: usize) -> Vec<DirectorySummary> {
+    // Same first-seen grouping as the per-file grades
+    let mut directories: Vec<DirectorySummary> = Vec::new();
+    for func in all_metrics {
+        let directory = directory_key(&func.file_path, root, depth);
+        let index = match directories.iter().position(|dir| dir.directory == directory) {
+            Some(index) => index,
+            None => {
+                directories.push(DirectorySummary {
+                    directory,
+                    functions: 0,
+                    total_mccabe: 0,
+                    total_cognitive: 0,
+                    total_sloc: 0,
+                    total_complexity: 0,
+                });
+                directories.len() - 1
+            }
+        };
+        let summary = &mut directories[index];
+        summary.functions += 1;
+        summary.total_mccabe += func.mccabe;
+        summary.total_cognitive += func.cognitive;
+        summary.total_sloc += func.sloc;
+        summary.total_complexity += func.max_complexity();
+    }
+
+    directories.sort_by_key(|dir| std::cmp::Reverse(dir.total_complexity));
+    directories
+}
+
+fn display_directory_summary(directories: &[DirectorySummary], depth: usize, output: &OutputOptions) {
+    println!("\n=== DIRECTORIES (depth {}) ===\n", depth);
+    for dir in directories {
+        let count = dir.functions as f64;
+        println!("  {}", dir.directory);
+        println!(
+            "   Functions: {}, McCabe: {} (avg {:.*}), Cognitive: {} (avg {:.*}), SLOC: {}",
+            dir.functions,
+            dir.total_mccabe,
+            output.precision,
+            dir.total_mccabe as f64 / count,
+            dir.total_cognitive,
+            output.precision,
+            dir.total_cognitive as f64 / count,
+            dir.total_sloc
+        );
+    }
+}
+
+/// Complexity band for --by-emoji: (emoji, range label), worst band first
+const EMOJI_BANDS: [(&str, &str); 4] = [("😢", "50+"), ("😠", "21-49"), ("😐", "11-20"), ("😊", "1-10")];
+
+/// A file's worst and average max(McCabe, Cognitive)
+#[derive(Debug)]
+struct FileBand<'a> {
+    file_path: &'a str,
+    worst: u32,
+    average: f64,
+}
+
+/// Files bucketed by the emoji of their worst function, in EMOJI_BANDS order
+/// Within a band, files are sorted worst first
+fn group_files_by_emoji(all_metrics: &[FunctionMetrics], primary: PrimaryMetric) -> Vec<Vec<FileBand<'_>>> {
+    let mut bands: Vec<Vec<FileBand>> = EMOJI_BANDS.iter().map(|_| Vec::new()).collect();
+    for (file_path, functions) in group_by_file(all_metrics) {
+        let worst = functions.iter().map(|func| primary.complexity(func)).max().unwrap_or(0);
+        let total: u32 = functions.iter().map(|func| primary.complexity(func)).sum();
+        let emoji = get_complexity_emoji(worst);
+        if let Some(band) = EMOJI_BANDS.iter().position(|(band_emoji, _)| *band_emoji == emoji) {
+            bands[band].push(FileBand {
+                file_path,
+                worst,
+                average: total as f64 / functions.len() as f64,
+            });
+        }
+    }
+
+    for band in &mut bands {
+        band.sort_by(|a, b| b.worst.cmp(&a.worst).then_with(|| a.file_path.cmp(b.file_path)));
+    }
+    bands
+}
+
+fn display_emoji_bands(bands: &[Vec<FileBand>], output: &OutputOptions) {
+    println!("\n=== FILES BY WORST FUNCTION ===\n");
+    for ((emoji, range), files) in EMOJI_BANDS.iter().zip(bands) {
+        println!("  {} {} ({} files)", emoji, range, files.len());
+        for file in files {
+            println!("     {} (worst: {}, avg: {:.*})", file.file_path, file.worst, output.precision, file.average);
+        }
+    }
+}
+
+#[cfg(feature = "git")]
+fn display_author_summary(all_metrics: &[FunctionMetrics], output: &OutputOptions) -> Result<()> {
+    let summaries = complexity_by_author(all_metrics);
+    println!("\n=== COMPLEXITY BY AUTHOR ===\n");
+    if summaries.is_empty() {
+        println!("No functions in files tracked by git");
+    } else {
+        print!("{}", format_author_table(&summaries, output.precision));
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "git"))]
+fn display_author_summary(_all_metrics: &[FunctionMetrics], _output: &OutputOptions) -> Result<()> {
+    anyhow::bail!("--by-author is not available in this build; rebuild knots with `--features git`")
+}
+
+/// Mean and population standard deviation
+fn mean_and_std_dev(values: &[f64]) -> (f64, f64) {
+    if values.is_empty() {
+        return (0.0, 0.0);
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    (mean, variance.sqrt())
+}
+
+/// Pearson correlation coefficient; `None` when either series is constant
+fn pearson_correlation(xs: &[f64], ys: &[f64]) -> Option<f64> {
+    let (mean_x, std_x) = mean_and_std_dev(xs);
+    let (mean_y, std_y) = mean_and_std_dev(ys);
+    if xs.len() != ys.len() || std_x == 0.0 || std_y == 0.0 {
+        return None;
+    }
+    let covariance = xs
+        .iter()
+        .zip(ys)
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum::<f64>()
+        / xs.len() as f64;
+    Some(covariance / (std_x * std_y))
+}
+
+/// Counts per equal-width bin between the smallest and largest value
+/// Returns (bin start, bin end, count) for each bin
+fn histogram(values: &[f64], bins: usize) -> Vec<(f64, f64, usize)> {
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if values.is_empty() || min == max {
+        return vec![(min, max, values.len())];
+    }
+
+    let width = (max - min) / bins as f64;
+    let mut counts = vec![0; bins];
+    for value in values {
+        let bin = (((value - min) / width) as usize).min(bins - 1);
+        counts[bin] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, count)| (min + width * i as f64, min + width * (i + 1) as f64, count))
+        .collect()
+}
+
+/// Display distribution, correlation, and histograms for the selected metrics
+fn display_metric_stats(all_metrics: &[FunctionMetrics], output: &OutputOptions) {
+    let series: Vec<(Field, Vec<f64>)> = output
+        .fields
+        .iter()
+        .map(|field| (*field, all_metrics.iter().map(|func| field.value(func)).collect()))
+        .collect();
+    let precision = output.precision;
+
+    println!("\n=== METRIC STATISTICS ({} functions) ===\n", all_metrics.len());
+    println!("  {:<10} {:>10} {:>10} {:>10} {:>10}", "Metric", "Mean", "StdDev", "Min", "Max");
+    for (field, values) in &series {
+        let (mean, std_dev) = mean_and_std_dev(values);
+        let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        println!(
+            "  {:<10} {:>10.*} {:>10.*} {:>10.*} {:>10.*}",
+            field.label(), precision, mean, precision, std_dev, precision, min, precision, max
+        );
+    }
+
+    println!("\n  Pearson correlation (r):\n");
+    print!("  {:<10}", "");
+    for (field, _) in &series {
+        print!(" {:>10}", field.label());
+    }
+    println!();
+    for (row_field, row) in &series {
+        print!("  {:<10}", row_field.label());
+        for (_, column) in &series {
+            match pearson_correlation(row, column) {
+                Some(r) => print!(" {:>10.*}", precision, r),
+                None => print!(" {:>10}", "-"),
+            }
+        }
+        println!();
+    }
+
+    println!("\n  Histograms:");
+    for (field, values) in &series {
+        println!("\n  {}", field.label());
+        let bins = histogram(values, 5);
+        let largest = bins.iter().map(|(_, _, count)| *count).max().unwrap_or(0).max(1);
+        for (start, end, count) in bins {
+            let bar = "#".repeat((count * 30).div_ceil(largest));
+            let line = format!("    {:>8.*} - {:<8.*} {:>6} {}", precision, start, precision, end, count, bar);
+            println!("{}", line.trim_end());
+        }
+    }
+}
+
+/// Testability matrix quadrant of a function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quadrant {
+    QuickWins,
+    InvestTests,
+    AddDocs,
+    Refactor,
+}
+
+impl Quadrant {
+    const ALL: [Quadrant; 4] = [Quadrant::QuickWins, Quadrant::InvestTests, Quadrant::AddDocs, Quadrant::Refactor];
+
+    /// McCabe above 10 is high complexity; a TestScore above 10 is hard to test
+    fn of(func: &FunctionMetrics) -> Self {
+        let low_complexity = func.mccabe <= 10;
+        let easy_to_test = func.test_scoring.total_score <= 10;
+
+        match (low_complexity, easy_to_test) {
+            (true, true) => Quadrant::QuickWins,
+            (false, true) => Quadrant::InvestTests,
+            (true, false) => Quadrant::AddDocs,
+            (false, false) => Quadrant::Refactor,
+        }
+    }
+
+    /// Section heading and its underline
+    fn heading(&self) -> (&'static str, &'static str) {
+        match self {
+            Quadrant::QuickWins => (
+                "📊 QUICK WINS (Low Complexity, Easy to Test) - Automate!",
+                "=========================================================",
+            ),
+            Quadrant::InvestTests => (
+                "🎯 INVEST IN TESTS (High Complexity, Easy to Test)",
+                "==================================================",
+            ),
+            Quadrant::AddDocs => (
+                "📝 ADD DOCS (Low Complexity, Hard to Test)",
+                "===========================================",
+            ),
+            Quadrant::Refactor => (
+                "🚨 REFACTOR (High Complexity, Hard to Test) - HIGH RISK!",
+                "========================================================",
+            ),
+        }
+    }
+
+    fn marker(&self) -> &'static str {
+        match self {
+            Quadrant::QuickWins => "✓",
+            Quadrant::InvestTests => "→",
+            Quadrant::AddDocs => "⚠",
+            Quadrant::Refactor => "⛔",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Quadrant::QuickWins => "Quick Wins:",
+            Quadrant::InvestTests => "Invest Tests:",
+            Quadrant::AddDocs => "Add Docs:",
+            Quadrant::Refactor => "Refactor:",
+        }
+    }
+}
+
+/// Display one testability matrix covering all functions across all files
+fn display_testability_matrix(all_metrics: &[FunctionMetrics], counts: &FileCounts, precision: usize) {
+    display_matrix(&all_metrics.iter().collect::<Vec<_>>(), "TESTABILITY MATRIX", precision);
+
+    if counts.found > 1 {
+        println!();
+        display_files_processed(counts);
+    }
+}
+
+/// Display a separate testability matrix for each file (--matrix-per-file)
+fn display_testability_matrix_per_file(all_metrics: &[FunctionMetrics], counts: &FileCounts, precision: usize) {
+    for (file_path, functions) in group_by_file(all_metrics) {
+        display_matrix(&functions, &format!("TESTABILITY MATRIX: {}", file_path), precision);
+    }
+
+    if counts.found > 1 {
+        println!();
+        display_files_processed(counts);
+    }
+}
+
+/// Group functions by file, keeping files in the order they were analyzed
+fn group_by_file(all_metrics: &[FunctionMetrics]) -> Vec<(&str, Vec<&FunctionMetrics>)> {
+    let mut groups: Vec<(&str, Vec<&FunctionMetrics>)> = Vec::new();
+    for func in all_metrics {
+        match groups.iter_mut().find(|(file_path, _)| *file_path == func.file_path) {
+            Some((_, functions)) => functions.push(func),
+            None => groups.push((&func.file_path, vec![func])),
+        }
+    }
+    groups
+}
+
+/// Print the four quadrants and a summary with each quadrant's share of the functions
+fn display_matrix(all_metrics: &[&FunctionMetrics], title: &str, precision: usize) {
+    println!("\n=== {} ===\n", title);
+
+    let mut totals = Vec::new();
+    for quadrant in Quadrant::ALL {
+        let functions: Vec<&FunctionMetrics> = all_metrics.iter().copied().filter(|func| Quadrant::of(func) == quadrant).collect();
+        let (heading, underline) = quadrant.heading();
+        println!("{}", heading);
+        println!("{}", underline);
+        if functions.is_empty() {
+            println!("  (none)");
+        }
+        for func in &functions {
+            if func.file_path.is_empty() {
+                println!("  {} {} (McCabe: {}, TestScore: {})", quadrant.marker(), func.name, func.mccabe, func.test_scoring.total_score);
+            } else {
+                println!(
+                    "  {} {} [{}] (McCabe: {}, TestScore: {})",
+                    quadrant.marker(),
+                    func.name,
+                    func.file_path,
+                    func.mccabe,
+                    func.test_scoring.total_score
+                );
+            }
+        }
+        println!();
+        totals.push((quadrant, functions.len()));
+    }
+
+    // Print summary
+    println!("=== SUMMARY ===\n");
+    let total = all_metrics.len().max(1) as f64;
+    for (quadrant, count) in totals {
+        println!(
+            "  {:<14} {} functions ({:.*}%)",
+            quadrant.label(),
+            count,
+            precision,
+            count as f64 * 100.0 / total
+        );
+    }
+    println!("  {:<14} {} functions", "Total:", all_metrics.len());
+}
+
+/// --prototypes: one line per prototype with its signature and documentation scores
+fn format_prototypes(prototypes: &[PrototypeScore], source: &SourceText, file_path: &str) -> String {
+    let mut out = String::new();
+    for prototype in prototypes {
+        let name = source.function_names.get(&prototype.name).unwrap_or(&prototype.name);
+        out.push_str(&format!(
+            "{}() [{}:{}] Signature: {}, Documentation: {}, Difficulty: {}\n",
+            name,
+            file_path,
+            source.original_line(prototype.line),
+            prototype.signature_score,
+            prototype.documentation_score,
+            prototype.difficulty()
+        ));
+    }
+    out
+}
+
+/// `--explain-nesting`: a function's nesting depth and the control statements leading to it
+fn explain_nesting(node: Node, source: &SourceText, name: &str, file_path: &str) -> String {
+    let steps = find_deepest_nesting_path(node);
+    let path = if steps.is_empty() {
+        "no control statements".to_string()
+    } else {
+        steps
+            .iter()
+            .map(|step| format!("{} (line {})", step.label, source.original_line(step.line)))
+            .collect::<Vec<_>>()
+            .join(" > ")
+    };
+    format!(
+        "{}() at {}:{}: nesting depth {}\n  {}\n",
+        name,
+        file_path,
+        source.original_line(node.start_position().row + 1),
+        calculate_nesting_depth(node),
+        path
+    )
+}
+
+/// `--explain-summary`: a function's McCabe complexity and the kinds of decision behind it
+fn explain_summary(node: Node, source: &SourceText, name: &str, file_path: &str, options: &McCabeOptions) -> String {
+    let tally = tally_mccabe_decisions(node, source.code.as_bytes(), options);
+    let kinds = if tally.is_empty() {
+        "no decisions".to_string()
+    } else {
+        tally
+            .iter()
+            .map(|(kind, count)| format!("{} {}", count, kind))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    format!(
+        "{}() [{}:{}] McCabe {}: {}\n",
+        name,
+        file_path,
+        source.original_line(node.start_position().row + 1),
+        1 + tally.iter().map(|(_, count)| count).sum::<u32>(),
+        kinds
+    )
+}
+
+fn visit_functions<F>(cursor: &mut TreeCursor, source_code: &str, callback: &mut F)
+where
+    F: FnMut(Node, &str),
+{
+    let node = cursor.node();
+
+    if node.kind() == "function_definition" {
+        callback(node, source_code);
+    }
+
+    if cursor.goto_first_child() {
+        loop {
+            visit_functions(cursor, source_code, callback);
+            if !cursor.goto_next_sibling() {
+                break;
+            }
+        }
+        cursor.goto_parent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "git")]
+    use crate::test_support::commit_file;
+    use crate::test_support::TempDir;
+    use clap::{CommandFactory, FromArgMatches};
+
+    fn analysis_options() -> AnalysisOptions {
+        AnalysisOptions {
+            mccabe: McCabeOptions::default(),
+            cognitive: CognitiveOptions::default(),
+            test_scoring: TestScoringOptions::default(),
+            preprocessor: None,
+            abc_weighted: false,
+            data_aware: false,
+            public_api: None,
+            fail_fast: None,
+            message_template: MessageTemplate::default(),
+            error_patterns: Vec::new(),
+            debt_markers: DEFAULT_DEBT_MARKERS.iter().map(|marker| marker.to_string()).collect(),
+            since: None,
+            lines: None,
+            metrics: None,
+            macros: false,
+            style_check: false,
+            pmccabe: false,
+            doc_limit: None,
+            defines: None,
+        }
+    }
+
+    fn function_metrics(name: &str, mccabe: u32, cognitive: u32, nesting: u32, sloc: u32) -> FunctionMetrics {
+        FunctionMetrics {
+            name: name.to_string(),
+            file_path: String::new(),
+            mccabe,
+            cognitive,
+            switch_case_total: 0,
+            nesting,
+            sloc,
+            abc_magnitude: 0.0,
+            return_count: 1,
+            exit_points: 1,
+            computed_goto_count: 0,
+            essential_complexity: 1,
+            static_local_count: 0,
+            debt_markers: 0,
+            max_dereference_chain: 0,
+            max_call_nesting: 0,
+            calls: Vec::new(),
+            generic_association_count: 0,
+            config_branch_count: 0,
+            magic_numbers: 0,
+            error_handling_sloc: 0,
+            large_value_param_count: 0,
+            parameter_modifications: 0,
+            const_candidates: Vec::new(),
+            style: None,
+            pmccabe: None,
+            loop_count: 0,
+            branch_count: 0,
+            test_scoring: TestScoringMetric {
+                signature_score: 0,
+                dependency_score: 0,
+                observable_score: 0,
+                implementation_score: 0,
+                documentation_score: 0,
+                total_score: 0,
+            },
+            unreachable_lines: Vec::new(),
+            missing_default_lines: Vec::new(),
+            max_switch_nesting: 0,
+            nested_switch_lines: Vec::new(),
+            inconsistent_return_lines: Vec::new(),
+            unused_static: false,
+            line: 1,
+            end_line: 1,
+            missing_return: false,
+            is_noreturn: false,
+            leak_risk: false,
+            is_exported: true,
+            is_static: false,
+            suppressed: Vec::new(),
+            doc_comment: None,
+            skipped: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_grade_simple_function() {
+        // Small getter: 0.03 + 0.0 + 0.05 + 0.02 = 0.10
+        let func = function_metrics("get_state", 1, 0, 1, 5);
+        assert_eq!(grade(&func, &DEFAULT_GRADE_CUT_POINTS), 'A');
+    }
+
+    #[test]
+    fn test_grade_at_recommended_limits() {
+        // Exactly at every limit scores 1.0, the top of the C band
+        let func = function_metrics("parse_frame", 10, 15, 4, 50);
+        assert_eq!(grade(&func, &DEFAULT_GRADE_CUT_POINTS), 'C');
+    }
+
+    #[test]
+    fn test_grade_complex_function() {
+        // 0.9 + 1.2 + 0.4 + 0.48 = 2.98
+        let func = function_metrics("HAL_RCC_OscConfig", 30, 60, 8, 120);
+        assert_eq!(grade(&func, &DEFAULT_GRADE_CUT_POINTS), 'F');
+    }
+
+    #[test]
+    fn test_prometheus_output() {
+        let mut func = function_metrics("parse_\"cmd\"", 14, 20, 3, 40);
+        func.file_path = "src\\cmd.c".to_string();
+        func.line = 12;
+        let out = format_prometheus(&[func], &[Field::Mccabe], 2);
+        assert!(out.contains("# TYPE knots_function_mccabe gauge\n"));
+        assert!(out.contains("knots_function_mccabe{file=\"src\\\\cmd.c\",func=\"parse_\\\"cmd\\\"\",line=\"12\"} 14\n"));
+        assert!(out.contains("knots_total_mccabe 14\n"));
+        assert!(out.contains("knots_function_count 1\n"));
+        assert!(!out.contains("knots_function_cognitive"));
+
+        // Two #ifdef variants of one static function stay separate series
+        let mut debug = function_metrics("log_event", 3, 2, 1, 10);
+        debug.line = 10;
+        let mut release = function_metrics("log_event", 1, 0, 1, 2);
+        release.line = 20;
+        let out = format_prometheus(&[debug, release], &[Field::Mccabe], 2);
+        assert!(out.contains("knots_function_mccabe{file=\"\",func=\"log_event\",line=\"10\"} 3\n"));
+        assert!(out.contains("knots_function_mccabe{file=\"\",func=\"log_event\",line=\"20\"} 1\n"));
+    }
+
+    #[test]
+    fn test_merge_report_replaces_reanalyzed_files() {
+        let mut old_a = function_metrics("old_a", 3, 3, 1, 10);
+        old_a.file_path = "src/a.c".to_string();
+        let mut kept_b = function_metrics("kept_b", 2, 2, 1, 10);
+        kept_b.file_path = "src/b.c".to_string();
+        let mut report = JsonReport {
+            schema_version: JSON_SCHEMA_VERSION,
+            functions: vec![old_a, kept_b],
+        };
+
+        let mut new_a = function_metrics("new_a", 5, 5, 2, 20);
+        new_a.file_path = "src/a.c".to_string();
+        merge_report(&mut report, &[PathBuf::from("src/a.c")], &[new_a]);
+
+        let names: Vec<&str> = report.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["new_a", "kept_b"]);
+    }
+
+    #[test]
+    fn test_grade_custom_cut_points() {
+        let func = function_metrics("parse_frame", 10, 15, 4, 50);
+        assert_eq!(grade(&func, &[1.0, 2.0, 3.0, 4.0]), 'A');
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_walk_skips_broken_symlink_and_unreadable_dir() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = TempDir::new("walk");
+        let root = dir.path();
+        fs::create_dir_all(root.join("locked")).unwrap();
+        fs::write(root.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+        fs::write(root.join("locked/hidden.c"), "void hidden(void) {}\n").unwrap();
+        symlink(root.join("missing.c"), root.join("broken.c")).unwrap();
+        symlink(root, root.join("loop")).unwrap();
+        fs::set_permissions(root.join("locked"), fs::Permissions::from_mode(0o000)).unwrap();
+        // Permission bits are not enforced for root, so only expect the failure when it applies
+        let locked_is_unreadable = fs::read_dir(root.join("locked")).is_err();
+
+        let mut files = Vec::new();
+        let inaccessible = walk_c_files(root, false, &None, &None, &mut files);
+
+        fs::set_permissions(root.join("locked"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        // Broken symlink and symlink loop, plus the locked directory when unreadable
+        let expected = if locked_is_unreadable { 3 } else { 2 };
+        assert_eq!(inaccessible, expected);
+        assert!(files.contains(&root.join("main.c")));
+        assert_eq!(files.len(), if locked_is_unreadable { 1 } else { 2 });
+    }
+
+    #[test]
+    fn test_pearson_correlation() {
+        let mccabe = [1.0, 2.0, 3.0, 4.0];
+        let doubled = [2.0, 4.0, 6.0, 8.0];
+        let reversed = [4.0, 3.0, 2.0, 1.0];
+        assert!((pearson_correlation(&mccabe, &doubled).unwrap() - 1.0).abs() < 1e-9);
+        assert!((pearson_correlation(&mccabe, &reversed).unwrap() + 1.0).abs() < 1e-9);
+        assert_eq!(pearson_correlation(&mccabe, &[5.0, 5.0, 5.0, 5.0]), None);
+    }
+
+    #[test]
+    fn test_histogram_bins() {
+        let bins = histogram(&[1.0, 2.0, 2.0, 10.0, 11.0], 5);
+        let counts: Vec<usize> = bins.iter().map(|(_, _, count)| *count).collect();
+        assert_eq!(counts, vec![3, 0, 0, 0, 2]);
+        assert_eq!(bins[0].0, 1.0);
+        assert_eq!(bins[4].1, 11.0);
+    }
+
+    #[test]
+    fn test_parse_confidence_levels() {
+        let clean = ParseErrors::default();
+        let few = ParseErrors { error_nodes: 2, error_bytes: 10 };
+        let large_share = ParseErrors { error_nodes: 1, error_bytes: 200 };
+        assert_eq!(ParseConfidence::of(&clean, 1000), ParseConfidence::High);
+        assert_eq!(ParseConfidence::of(&few, 1000), ParseConfidence::Medium);
+        assert_eq!(ParseConfidence::of(&large_share, 1000), ParseConfidence::Low);
+    }
+
+    #[test]
+    fn test_format_dot() {
+        let func = |name: &str, file: &str, mccabe: u32, calls: &[&str]| FunctionMetrics {
+            file_path: file.to_string(),
+            calls: calls.iter().map(|call| call.to_string()).collect(),
+            ..function_metrics(name, mccabe, 1, 1, 10)
+        };
+        let metrics = vec![
+            func("parse", "src/a.c", 24, &["lex", "printf", "init"]),
+            func("lex", "src/a.c", 3, &[]),
+            func("init", "src/b.c", 2, &[]),
+        ];
+
+        let dot = format_dot(&metrics);
+        assert!(dot.starts_with("digraph knots {\n"));
+        assert!(dot.contains("    subgraph cluster_0 {\n        label=\"src/a.c\";\n"));
+        assert!(dot.contains("        \"src/a.c:parse\" [label=\"parse (24)\", fillcolor=orange];\n"));
+        assert!(dot.contains("        \"src/a.c:lex\" [label=\"lex (3)\", fillcolor=palegreen];\n"));
+        // Only calls within the file become edges
+        assert_eq!(dot.matches(" -> ").count(), 1);
+        assert!(dot.contains("        \"src/a.c:parse\" -> \"src/a.c:lex\";\n"));
+        assert!(dot.contains("subgraph cluster_1"));
+        assert_eq!(dot_quote("a\"b"), "\"a\\\"b\"");
+    }
+
+    #[test]
+    fn test_file_complexity_budget() {
+        let mut metrics: Vec<FunctionMetrics> = (0..5)
+            .map(|i| FunctionMetrics {
+                file_path: "src/big.c".to_string(),
+                ..function_metrics(&format!("step{}", i), 6, 6, 2, 20)
+            })
+            .collect();
+        metrics.push(FunctionMetrics {
+            file_path: "src/small.c".to_string(),
+            ..function_metrics("helper", 8, 8, 2, 20)
+        });
+
+        // No function is complex on its own, but big.c sums to 30
+        let violations = find_file_violations(&metrics, 25);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].file_path, "src/big.c");
+        assert_eq!((violations[0].functions, violations[0].total_mccabe), (5, 30));
+        assert!(check_file_complexity(&metrics, Some(25)).is_err());
+        assert!(check_file_complexity(&metrics, Some(30)).is_ok());
+
+        let gate = file_complexity_gate(&metrics, 25);
+        assert!(!gate.passed);
+        assert_eq!(gate.offenders[0].detail, "total McCabe 30 over 5 functions (max 25)");
+    }
+
+    #[test]
+    fn test_functions_per_file() {
+        let mut metrics: Vec<FunctionMetrics> = (0..12)
+            .map(|i| FunctionMetrics {
+                file_path: "src/util.c".to_string(),
+                ..function_metrics(&format!("util_{}", i), 1, 0, 1, 3)
+            })
+            .collect();
+        metrics.push(FunctionMetrics {
+            file_path: "src/main.c".to_string(),
+            ..function_metrics("main", 2, 1, 1, 10)
+        });
+
+        let crowded = find_crowded_files(&metrics, 10);
+        assert_eq!(crowded.len(), 1);
+        assert_eq!((crowded[0].file_path, crowded[0].functions), ("src/util.c", 12));
+        assert!(check_functions_per_file(&metrics, Some(10)).is_err());
+        assert!(check_functions_per_file(&metrics, Some(12)).is_ok());
+
+        let gate = functions_per_file_gate(&metrics, 10);
+        assert!(!gate.passed);
+        assert_eq!(gate.offenders[0].detail, "12 functions (max 10)");
+    }
+
+    #[test]
+    fn test_standard_profiles() {
+        let metrics = vec![
+            function_metrics("parse_config", 8, 12, 3, 70),
+            function_metrics("init", 2, 1, 1, 12),
+        ];
+
+        // NASA's 60-line rule flags the 70-line function
+        let nasa = Thresholds::default().or(Standard::Nasa.thresholds());
+        let violations = find_violations(&metrics, &nasa);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].message(), "parse_config() has sloc 70 (max 60)");
+        assert_eq!(count_compliant(&metrics, &nasa), 1);
+
+        // Explicit --max-* options override the standard
+        let relaxed = Thresholds {
+            sloc: Some(100),
+            ..Thresholds::default()
+        }
+        .or(Standard::Nasa.thresholds());
+        assert_eq!(count_compliant(&metrics, &relaxed), 2);
+
+        let misra = Standard::Misra.thresholds();
+        assert_eq!((misra.mccabe, misra.nesting, misra.exits), (Some(10), Some(4), Some(1)));
+    }
+
+    #[test]
+    fn test_skip_reasons() {
+        let root = TempDir::new("skip");
+        fs::write(root.join("ok.c"), "int ok(void) { return 0; }\n").unwrap();
+        fs::write(root.join("latin1.c"), b"/* caf\xe9 */ int f(void) { return 0; }\n").unwrap();
+        fs::write(root.join("broken.c"), "@@ { int ( ;; }}} ##\n").unwrap();
+        fs::write(root.join("decls.h"), "int ok(void);\nextern int count;\n").unwrap();
+
+        let names = ["ok.c", "missing.c", "latin1.c", "broken.c", "decls.h"];
+        let files: Vec<PathBuf> = names.iter().map(|name| root.join(name)).collect();
+        let (metrics, counts) = analyze_files(&files, &None, &None, &analysis_options()).unwrap();
+
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(
+            counts.skipped,
+            SkipCounts {
+                read_error: 2,
+                parse_failure: 1,
+                no_functions: 1,
+            }
+        );
+        assert_eq!(counts.found - counts.skipped.total(), 1);
+        assert_eq!(counts.bytes, "int ok(void) { return 0; }\n".len());
+        assert!(counts.parse_issues.is_empty());
+    }
+
+    #[test]
+    fn test_group_by_directory_nested_tree() {
+        let dir = TempDir::new("group");
+        let root = dir.path();
+        fs::create_dir_all(root.join("net/tcp")).unwrap();
+        fs::create_dir_all(root.join("drivers")).unwrap();
+        fs::write(root.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+        fs::write(root.join("net/socket.c"), "int open_socket(int f) { if (f) return 1; return 0; }\n").unwrap();
+        fs::write(root.join("net/tcp/conn.c"), "int connect_to(int a, int b) { if (a && b) return 1; return 0; }\n").unwrap();
+        fs::write(root.join("drivers/uart.c"), "void uart_init(void) {}\n").unwrap();
+
+        let (files, _) = collect_files(root, true, false, &None, &None).unwrap();
+        let options = analysis_options();
+        let (metrics, _) = analyze_files(&files, &None, &None, &options).unwrap();
+
+        let shallow = group_by_directory(&metrics, Some(root), 1);
+        let deep = group_by_directory(&metrics, Some(root), 2);
+
+        let net = root.join("net").display().to_string();
+        assert_eq!(shallow[0].directory, net);
+        assert_eq!(shallow[0].functions, 2);
+        assert_eq!(shallow[0].total_mccabe, 5);
+        assert_eq!(shallow.len(), 3);
+
+        let tcp = root.join("net/tcp").display().to_string();
+        assert!(deep.iter().any(|dir| dir.directory == tcp && dir.functions == 1));
+        assert_eq!(deep.len(), 4);
+    }
+
+    #[test]
+    fn test_diff_reports() {
+        let report = |functions: Vec<FunctionMetrics>| JsonReport {
+            schema_version: JSON_SCHEMA_VERSION,
+            functions,
+        };
+        let old = report(vec![
+            function_metrics("parse", 8, 10, 2, 40),
+            function_metrics("init", 3, 2, 1, 10),
+            function_metrics("legacy", 5, 5, 1, 20),
+            function_metrics("stable", 2, 1, 1, 5),
+        ]);
+        let new = report(vec![
+            function_metrics("parse", 12, 14, 3, 40),
+            function_metrics("init", 2, 1, 1, 10),
+            function_metrics("stable", 2, 1, 1, 5),
+            function_metrics("validate", 4, 3, 2, 12),
+        ]);
+
+        let diff = diff_reports(&old, &new, 0.0);
+        let changed: Vec<&str> = diff.changed.iter().map(|c| c.new.name.as_str()).collect();
+        assert_eq!(changed, vec!["parse", "init"]);
+        assert_eq!(diff.changed[0].regression(), 4);
+        assert_eq!(diff.changed[0].fields, vec![Field::Mccabe, Field::Cognitive, Field::Nesting]);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].name, "validate");
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].name, "legacy");
+        assert_eq!(format_delta(Field::Mccabe, 4.0, 2), "+4");
+    }
+
+    #[test]
+    fn test_diff_reports_same_named_statics() {
+        let function = |file: &str, name: &str, mccabe: u32, is_static: bool| FunctionMetrics {
+            file_path: file.to_string(),
+            is_static,
+            ..function_metrics(name, mccabe, 1, 1, 10)
+        };
+        let report = |functions: Vec<FunctionMetrics>| JsonReport {
+            schema_version: JSON_SCHEMA_VERSION,
+            functions,
+        };
+        let old = report(vec![
+            function("src/a.c", "helper", 2, true),
+            function("src/b.c", "helper", 3, true),
+            function("src/b.c", "run", 1, false),
+        ]);
+        let new = report(vec![
+            function("src/a.c", "helper", 5, true),
+            function("src/b.c", "helper", 3, true),
+            function("src/b.c", "run", 1, true),
+        ]);
+
+        // Only a.c's helper changed; b.c's is matched to itself, not merged with a.c's
+        let diff = diff_reports(&old, &new, 0.0);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].new.file_path, "src/a.c");
+        assert_eq!(diff.changed[0].old.mccabe, 2);
+
+        // run() becoming static is a different function
+        assert_eq!((diff.added.len(), diff.removed.len()), (1, 1));
+        assert_eq!(diff.added[0].name, "run");
+
+        // Older reports did not record linkage, so it is ignored
+        let legacy = JsonReport {
+            schema_version: IS_STATIC_SCHEMA_VERSION - 1,
+            ..report(old.functions.clone())
+        };
+        let diff = diff_reports(&legacy, &new, 0.0);
+        assert_eq!((diff.changed.len(), diff.added.len(), diff.removed.len()), (1, 0, 0));
+    }
+
+    #[test]
+    fn test_diff_reports_tolerance() {
+        let with_abc = |mccabe: u32, abc: f64| FunctionMetrics {
+            abc_magnitude: abc,
+            ..function_metrics("parse", mccabe, 4, 1, 20)
+        };
+        let report = |func: FunctionMetrics| JsonReport {
+            schema_version: JSON_SCHEMA_VERSION,
+            functions: vec![func],
+        };
+        let old = report(with_abc(5, 12.04));
+
+        // A sub-epsilon ABC change is ignored
+        let noise = report(with_abc(5, 12.08));
+        assert!(diff_reports(&old, &noise, 0.1).changed.is_empty());
+        assert_eq!(diff_reports(&old, &noise, 0.0).changed[0].fields, vec![Field::Abc]);
+
+        // Integer metrics stay exact whatever the tolerance
+        let grown = report(with_abc(6, 12.08));
+        assert_eq!(diff_reports(&old, &grown, 5.0).changed[0].fields, vec![Field::Mccabe]);
+    }
+
+    #[test]
+    fn test_long_simple_functions() {
+        let output = OutputOptions {
+            verbose: false,
+            no_summary: false,
+            no_report: false,
+            flag_unused_static: false,
+            fields: Field::DEFAULT.to_vec(),
+            precision: 2,
+            grade_cut_points: None,
+            long_simple: (100, 5),
+            limit: None,
+            rank_by: None,
+            primary: PrimaryMetric::Max,
+        };
+        let metrics = vec![
+            function_metrics("init_registers", 2, 1, 1, 180),
+            function_metrics("dispatch", 12, 15, 3, 150),
+            function_metrics("small", 1, 0, 1, 10),
+            function_metrics("setup_table", 4, 3, 1, 120),
+        ];
+
+        let names: Vec<&str> = long_simple_functions(&metrics, &output).iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["init_registers", "setup_table"]);
+    }
+
+    #[test]
+    fn test_metric_range_names_extremes() {
+        let metrics = vec![
+            function_metrics("init", 1, 0, 1, 8),
+            function_metrics("parse_packet", 57, 80, 6, 300),
+            function_metrics("reset", 1, 0, 1, 4),
+            function_metrics("dispatch", 57, 40, 3, 120),
+        ];
+
+        let (min, max) = metric_range(&metrics, Field::Mccabe).unwrap();
+        assert_eq!((min.name.as_str(), max.name.as_str()), ("init", "parse_packet"));
+        let (min, max) = metric_range(&metrics, Field::Sloc).unwrap();
+        assert_eq!((min.name.as_str(), max.name.as_str()), ("reset", "parse_packet"));
+        assert!(metric_range(&[], Field::Mccabe).is_none());
+    }
+
+    #[test]
+    fn test_github_annotations() {
+        let mut parse = function_metrics("parse", 12, 25, 3, 80);
+        parse.file_path = "src/parse.c".to_string();
+        parse.line = 42;
+        let mut huge = function_metrics("huge", 60, 90, 8, 400);
+        huge.file_path = "src/huge.c".to_string();
+        huge.line = 7;
+        let metrics = vec![parse, huge, function_metrics("small", 2, 1, 1, 5)];
+
+        // Without thresholds the emoji bands decide the severity
+        let output = format_github(&metrics, &[]);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("::warning file=src/parse.c,line=42,title=knots%3A parse::"));
+        assert!(lines[1].starts_with("::error file=src/huge.c,line=7,"));
+
+        let thresholds = Thresholds {
+            mccabe: Some(10),
+            ..Thresholds::default()
         };
+        let violations = find_violations(&metrics, &thresholds);
+        assert_eq!(violations.len(), 2);
+        let output = format_github(&metrics, &violations);
+        assert!(output.starts_with("::error file=src/parse.c,line=42,title=knots%3A parse::parse() has mccabe 12 (max 10)\n"));
+    }
 
-        if should_exclude {
-            return false;
+    #[test]
+    fn test_exclude_generated_files() {
+        let root = TempDir::new("generated");
+        let files = [
+            ("msg.pb-c.c", "/* Generated by the protocol buffer compiler.  DO NOT EDIT! */\n"),
+            ("parser.tab.c", "/* A Bison parser, made by GNU Bison 3.8.2.  */\n"),
+            ("lex.yy.c", "#line 1 \"lex.yy.c\"\n\n/* A lexical scanner generated by flex */\n"),
+            ("main.c", "/* Entry point; generated files live in gen/ */\nint main(void) { return 0; }\n"),
+        ];
+        for (name, content) in files {
+            fs::write(root.join(name), content).unwrap();
         }
+        let paths: Vec<PathBuf> = files.iter().map(|(name, _)| root.join(name)).collect();
+
+        let marker = Regex::new(DEFAULT_GENERATED_MARKER).unwrap();
+        let (kept, excluded) = exclude_generated_files(paths, &marker);
+
+        assert_eq!(excluded, 3);
+        assert_eq!(kept, vec![root.join("main.c")]);
     }
 
-    true
-}
+    #[test]
+    fn test_matrix_quadrants_by_file() {
+        let mut parse = function_metrics("parse", 15, 12, 3, 40);
+        parse.file_path = "src/parser.c".to_string();
+        parse.test_scoring.total_score = 20;
+        let mut init = function_metrics("init", 2, 1, 1, 10);
+        init.file_path = "src/main.c".to_string();
+        let mut lex = function_metrics("lex", 12, 8, 2, 30);
+        lex.file_path = "src/parser.c".to_string();
 
-fn analyze_code(
-    tree: &Tree,
-    source_code: &str,
-    verbose: bool,
-    include_rules: &Option<FilterRules>,
-    exclude_rules: &Option<FilterRules>,
-) -> Result<()> {
-    let metrics = collect_function_metrics(tree, source_code, "", include_rules, exclude_rules);
-
-    let mut total_mccabe = 0;
-    let mut total_cognitive = 0;
-    let mut total_nesting = 0;
-    let mut total_sloc = 0;
-    let mut total_abc_magnitude = 0.0;
-    let mut total_return_count = 0;
-    let mut total_test_score: i64 = 0;
-
-    for func in &metrics {
-        total_mccabe += func.mccabe;
-        total_cognitive += func.cognitive;
-        total_nesting += func.nesting;
-        total_sloc += func.sloc;
-        total_abc_magnitude += func.abc_magnitude;
-        total_return_count += func.return_count;
-        total_test_score += func.test_scoring.total_score as i64;
-
-        let emoji = get_complexity_emoji(func.max_complexity());
-
-        if verbose {
-            println!("Function: {} {}", func.name, emoji);
-            println!("  McCabe Complexity: {}", func.mccabe);
-            println!("  Cognitive Complexity: {}", func.cognitive);
-            println!("  Nesting Depth: {}", func.nesting);
-            println!("  SLOC: {}", func.sloc);
-            println!("  ABC Magnitude: {:.2}", func.abc_magnitude);
-            println!("  Return Count: {}", func.return_count);
-            println!("  Test Scoring: {} ({})", func.test_scoring.total_score, func.test_scoring.classification());
-            println!("    - Signature: {}", func.test_scoring.signature_score);
-            println!("    - Dependency: {}", func.test_scoring.dependency_score);
-            println!("    - Observable: {}", func.test_scoring.observable_score);
-            println!("    - Implementation: {}", func.test_scoring.implementation_score);
-            println!("    - Documentation: {}", func.test_scoring.documentation_score);
-            println!("  Max Complexity: {}", func.max_complexity());
-            println!();
-        } else {
-            println!(
-                "{} {} (McCabe: {}, Cognitive: {}, Nesting: {}, SLOC: {}, ABC: {:.2}, Returns: {}, TestScore: {})",
-                emoji, func.name, func.mccabe, func.cognitive, func.nesting, func.sloc, func.abc_magnitude, func.return_count, func.test_scoring.total_score
-            );
+        assert_eq!(Quadrant::of(&parse), Quadrant::Refactor);
+        assert_eq!(Quadrant::of(&init), Quadrant::QuickWins);
+        assert_eq!(Quadrant::of(&lex), Quadrant::InvestTests);
+
+        let metrics = vec![parse, init, lex];
+        let groups: Vec<(&str, Vec<&str>)> = group_by_file(&metrics)
+            .into_iter()
+            .map(|(file, functions)| (file, functions.iter().map(|func| func.name.as_str()).collect()))
+            .collect();
+        assert_eq!(groups, vec![("src/parser.c", vec!["parse", "lex"]), ("src/main.c", vec!["init"])]);
+    }
+
+    #[test]
+    fn test_metrics_only_skips_other_metrics() {
+        let source = SourceText::plain("int clamp(int v) {\n    if (v > 9) {\n        return 9;\n    }\n    return v;\n}\n".to_string());
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_c::LANGUAGE.into()).unwrap();
+        let tree = parser.parse(&source.code, None).unwrap();
+
+        let options = AnalysisOptions {
+            metrics: Some(vec![Field::Mccabe]),
+            ..analysis_options()
+        };
+        let metrics = collect_function_metrics(&tree, &source, "clamp.c", &None, &None, &options);
+        assert_eq!(metrics[0].mccabe, 2);
+        assert_eq!((metrics[0].cognitive, metrics[0].sloc, metrics[0].return_count), (0, 0, 0));
+        assert_eq!(metrics[0].test_scoring.total_score, 0);
+
+        // The summaries print no "Total SLOC: 0" for metrics that were never computed
+        let rows = MetricTotals::of(&metrics).summary_rows(&metrics, 2);
+        assert_eq!(rows, vec![("McCabe Complexity", "2".to_string(), 2.0)]);
+
+        // Skipped metrics are absent from the JSON report, not 0, and read back as 0
+        let report = JsonReport {
+            functions: metrics,
+            ..JsonReport::default()
+        };
+        let json = report_to_json(&report).unwrap();
+        let function = json["functions"][0].as_object().unwrap();
+        assert_eq!(function["mccabe"], 2);
+        for key in ["cognitive", "sloc", "test_scoring", "magic_numbers", "unreachable_lines", "calls"] {
+            assert!(!function.contains_key(key), "{} should be omitted", key);
         }
+        let loaded: JsonReport = serde_json::from_value(json).unwrap();
+        assert_eq!((loaded.functions[0].mccabe, loaded.functions[0].sloc), (2, 0));
+
+        let metrics = collect_function_metrics(&tree, &source, "clamp.c", &None, &None, &analysis_options());
+        assert_eq!((metrics[0].cognitive, metrics[0].sloc, metrics[0].return_count), (1, 6, 2));
+        assert!(metrics[0].skipped.is_empty());
+        assert_eq!(MetricTotals::of(&metrics).summary_rows(&metrics, 2).len(), 7);
     }
 
-    let function_count = metrics.len();
+    #[test]
+    fn test_data_aware_initializers() {
+        let code = "int lookup(int key) {\n    static const int table[] = {\n        [0] = 10,\n        [1] = 20,\n        [2] = scale(30),\n    };\n    return table[key];\n}\n";
+        let source = SourceText::plain(code.to_string());
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_c::LANGUAGE.into()).unwrap();
+        let tree = parser.parse(&source.code, None).unwrap();
 
-    // Print summary
-    println!();
-    println!("Summary:");
-    println!("  Total Functions: {}", function_count);
-    println!("  Total McCabe Complexity: {}", total_mccabe);
-    println!("  Total Cognitive Complexity: {}", total_cognitive);
-    println!("  Total Nesting Depth: {}", total_nesting);
-    println!("  Total SLOC: {}", total_sloc);
-    println!("  Total ABC Magnitude: {:.2}", total_abc_magnitude);
-    println!("  Total Return Count: {}", total_return_count);
-    println!("  Total Test Score: {}", total_test_score);
+        let metrics = collect_function_metrics(&tree, &source, "lookup.c", &None, &None, &analysis_options());
+        assert_eq!((metrics[0].sloc, metrics[0].abc_magnitude), (8, 1.0));
 
-    if function_count > 0 {
-        println!("  Average McCabe Complexity: {:.2}", total_mccabe as f64 / function_count as f64);
-        println!("  Average Cognitive Complexity: {:.2}", total_cognitive as f64 / function_count as f64);
-        println!("  Average Nesting Depth: {:.2}", total_nesting as f64 / function_count as f64);
-        println!("  Average SLOC: {:.2}", total_sloc as f64 / function_count as f64);
-        println!("  Average ABC Magnitude: {:.2}", total_abc_magnitude / function_count as f64);
-        println!("  Average Return Count: {:.2}", total_return_count as f64 / function_count as f64);
-        println!("  Average Test Score: {:.2}", total_test_score as f64 / function_count as f64);
+        let options = AnalysisOptions {
+            data_aware: true,
+            ..analysis_options()
+        };
+        let metrics = collect_function_metrics(&tree, &source, "lookup.c", &None, &None, &options);
+        assert_eq!((metrics[0].sloc, metrics[0].abc_magnitude), (4, 0.0));
     }
 
-    Ok(())
-}
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_tui_browser() {
+        use ratatui::crossterm::event::KeyCode;
+        use tui::{Action, Browser};
 
-/// Write detailed report to report.txt for recursive analysis
-fn write_detailed_report(all_metrics: &[FunctionMetrics], verbose: bool) -> Result<()> {
-    let mut file = fs::File::create("report.txt")
-        .context("Failed to create report.txt")?;
+        let metrics = vec![
+            FunctionMetrics {
+                file_path: "src/a.c".to_string(),
+                line: 10,
+                ..function_metrics("parse", 12, 30, 4, 80)
+            },
+            FunctionMetrics {
+                file_path: "src/a.c".to_string(),
+                line: 3,
+                ..function_metrics("init", 2, 1, 1, 6)
+            },
+            FunctionMetrics {
+                file_path: "src/b.c".to_string(),
+                line: 1,
+                ..function_metrics("parse_header", 15, 12, 3, 40)
+            },
+        ];
+        fn names<'a>(browser: &Browser<'a>) -> Vec<&'a str> {
+            browser.rows().iter().map(|func| func.name.as_str()).collect()
+        }
 
-    for func in all_metrics {
-        let emoji = get_complexity_emoji(func.max_complexity());
-
-        if verbose {
-            writeln!(file, "Function: {} {} [{}]", func.name, emoji, func.file_path)?;
-            writeln!(file, "  McCabe Complexity: {}", func.mccabe)?;
-            writeln!(file, "  Cognitive Complexity: {}", func.cognitive)?;
-            writeln!(file, "  Nesting Depth: {}", func.nesting)?;
-            writeln!(file, "  SLOC: {}", func.sloc)?;
-            writeln!(file, "  ABC Magnitude: {:.2}", func.abc_magnitude)?;
-            writeln!(file, "  Return Count: {}", func.return_count)?;
-            writeln!(file, "  Test Scoring: {} ({})", func.test_scoring.total_score, func.test_scoring.classification())?;
-            writeln!(file, "    - Signature: {}", func.test_scoring.signature_score)?;
-            writeln!(file, "    - Dependency: {}", func.test_scoring.dependency_score)?;
-            writeln!(file, "    - Observable: {}", func.test_scoring.observable_score)?;
-            writeln!(file, "    - Implementation: {}", func.test_scoring.implementation_score)?;
-            writeln!(file, "    - Documentation: {}", func.test_scoring.documentation_score)?;
-            writeln!(file, "  Max Complexity: {}", func.max_complexity())?;
-            writeln!(file)?;
-        } else {
-            writeln!(
-                file,
-                "{} {} [{}] (McCabe: {}, Cognitive: {}, Nesting: {}, SLOC: {}, ABC: {:.2}, Returns: {}, TestScore: {})",
-                emoji, func.name, func.file_path, func.mccabe, func.cognitive, func.nesting, func.sloc, func.abc_magnitude, func.return_count, func.test_scoring.total_score
-            )?;
+        let mut browser = Browser::new(&metrics, PrimaryMetric::Max);
+        assert_eq!(names(&browser), vec!["parse", "parse_header", "init"]);
+
+        // McCabe descending, then ascending on a second press
+        browser.handle_key(KeyCode::Char('m'));
+        assert_eq!(names(&browser), vec!["parse_header", "parse", "init"]);
+        browser.handle_key(KeyCode::Char('m'));
+        assert_eq!(names(&browser), vec!["init", "parse", "parse_header"]);
+
+        // The 😠 band (21-49) holds only parse
+        browser.handle_key(KeyCode::Char('b'));
+        assert!(browser.rows().is_empty());
+        browser.handle_key(KeyCode::Char('b'));
+        assert_eq!(names(&browser), vec!["parse"]);
+        assert_eq!(browser.handle_key(KeyCode::Enter), Action::Open("src/a.c".to_string(), 10));
+        browser.handle_key(KeyCode::Char('b'));
+        browser.handle_key(KeyCode::Char('b'));
+        browser.handle_key(KeyCode::Char('b'));
+
+        // Typed name filter, cleared with Esc
+        for key in [KeyCode::Char('/'), KeyCode::Char('H'), KeyCode::Char('e'), KeyCode::Enter] {
+            browser.handle_key(key);
         }
+        assert_eq!(names(&browser), vec!["parse_header"]);
+        browser.handle_key(KeyCode::Char('/'));
+        browser.handle_key(KeyCode::Esc);
+        assert_eq!(browser.rows().len(), 3);
+        assert_eq!(browser.handle_key(KeyCode::Char('q')), Action::Quit);
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_metric_scalar() {
+        let mut metrics = vec![
+            function_metrics("parse", 12, 30, 4, 80),
+            function_metrics("init", 2, 1, 1, 6),
+        ];
+        metrics[0].abc_magnitude = 3.75;
+        metrics[1].abc_magnitude = 1.5;
 
-/// Display summary with top 5 worst functions and totals/averages
-fn display_recursive_summary(all_metrics: &[FunctionMetrics], total_files: usize, skipped_files: usize) {
-    // Sort by worst complexity (max of McCabe and Cognitive)
-    let mut sorted = all_metrics.to_vec();
-    sorted.sort_by(|a, b| b.max_complexity().cmp(&a.max_complexity()));
-
-    println!("\n=== TOP 5 WORST FUNCTIONS ===\n");
-    for (i, func) in sorted.iter().take(5).enumerate() {
-        let emoji = get_complexity_emoji(func.max_complexity());
-        println!(
-            "{}. {} {} [{}]",
-            i + 1,
-            emoji,
-            func.name,
-            func.file_path
-        );
-        println!("   McCabe: {}, Cognitive: {}, Nesting: {}, SLOC: {}, ABC: {:.2}, Returns: {}, TestScore: {}",
-            func.mccabe, func.cognitive, func.nesting, func.sloc, func.abc_magnitude, func.return_count, func.test_scoring.total_score
-        );
+        assert_eq!(metric_scalar(&metrics, Field::Mccabe, Aggregation::Total, 2).unwrap(), "14");
+        assert_eq!(metric_scalar(&metrics, Field::Cognitive, Aggregation::Max, 2).unwrap(), "30");
+        assert_eq!(metric_scalar(&metrics, Field::Sloc, Aggregation::Function("init"), 2).unwrap(), "6");
+        assert_eq!(metric_scalar(&metrics, Field::Abc, Aggregation::Total, 1).unwrap(), "5.2");
+        assert!(metric_scalar(&metrics, Field::Mccabe, Aggregation::Function("missing"), 2).is_err());
     }
 
-    // Calculate totals and averages
-    let mut total_mccabe: u64 = 0;
-    let mut total_cognitive: u64 = 0;
-    let mut total_nesting: u64 = 0;
-    let mut total_sloc: u64 = 0;
-    let mut total_abc_magnitude = 0.0;
-    let mut total_return_count: u64 = 0;
-    let mut total_test_score: i64 = 0;
+    #[test]
+    fn test_no_report() {
+        let dir = TempDir::new("no-report");
+        let path = dir.join(REPORT_FILE);
+        let mut output = OutputOptions {
+            verbose: false,
+            no_summary: false,
+            no_report: true,
+            flag_unused_static: false,
+            fields: vec![Field::Mccabe],
+            precision: 2,
+            grade_cut_points: None,
+            long_simple: (100, 5),
+            limit: None,
+            rank_by: None,
+            primary: PrimaryMetric::Max,
+        };
+        let metrics = vec![function_metrics("parse", 12, 30, 4, 80)];
 
-    for func in all_metrics {
-        total_mccabe += func.mccabe as u64;
-        total_cognitive += func.cognitive as u64;
-        total_nesting += func.nesting as u64;
-        total_sloc += func.sloc as u64;
-        total_abc_magnitude += func.abc_magnitude;
-        total_return_count += func.return_count as u64;
-        total_test_score += func.test_scoring.total_score as i64;
+        write_detailed_report(&path, &metrics, &output).unwrap();
+        assert!(!path.exists());
+
+        output.no_report = false;
+        write_detailed_report(&path, &metrics, &output).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("parse"));
     }
 
-    let function_count = all_metrics.len();
+    #[test]
+    fn test_line_range() {
+        assert_eq!(parse_line_range("4:9"), Ok((4, 9)));
+        assert!(parse_line_range("9:4").is_err());
+        assert!(parse_line_range("0:3").is_err());
+        assert!(parse_line_range("12").is_err());
 
-    println!("\n=== TOTALS & AVERAGES ===\n");
-    println!("  Total Functions: {}", function_count);
-    println!("  Total McCabe Complexity: {}", total_mccabe);
-    println!("  Total Cognitive Complexity: {}", total_cognitive);
-    println!("  Total Nesting Depth: {}", total_nesting);
-    println!("  Total SLOC: {}", total_sloc);
-    println!("  Total ABC Magnitude: {:.2}", total_abc_magnitude);
-    println!("  Total Return Count: {}", total_return_count);
-    println!("  Total Test Score: {}", total_test_score);
+        let code = "int a(void) {\n    return 1;\n}\n\nint b(int x) {\n    if (x) return 2;\n    return 0;\n}\n\nint c(void) { return 3; }\n";
+        let source = SourceText::plain(code.to_string());
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_c::LANGUAGE.into()).unwrap();
+        let tree = parser.parse(&source.code, None).unwrap();
 
-    if function_count > 0 {
-        println!();
-        println!("  Average McCabe Complexity: {:.2}", total_mccabe as f64 / function_count as f64);
-        println!("  Average Cognitive Complexity: {:.2}", total_cognitive as f64 / function_count as f64);
-        println!("  Average Nesting Depth: {:.2}", total_nesting as f64 / function_count as f64);
-        println!("  Average SLOC: {:.2}", total_sloc as f64 / function_count as f64);
-        println!("  Average ABC Magnitude: {:.2}", total_abc_magnitude / function_count as f64);
-        println!("  Average Return Count: {:.2}", total_return_count as f64 / function_count as f64);
-        println!("  Average Test Score: {:.2}", total_test_score as f64 / function_count as f64);
+        let names = |lines: (usize, usize)| {
+            let options = AnalysisOptions {
+                lines: Some(lines),
+                ..analysis_options()
+            };
+            collect_function_metrics(&tree, &source, "abc.c", &None, &None, &options)
+                .into_iter()
+                .map(|func| func.name)
+                .collect::<Vec<_>>()
+        };
+        // A cursor inside b(), a selection spanning the end of a() into b(), and a blank line
+        assert_eq!(names((6, 6)), vec!["b"]);
+        assert_eq!(names((3, 5)), vec!["a", "b"]);
+        assert!(names((9, 9)).is_empty());
     }
 
-    println!("\nDetailed per-function output written to report.txt");
-    println!("\n=== FILES PROCESSED ===\n");
-    println!("  Total files found: {}", total_files);
-    println!("  Successfully processed: {}", total_files - skipped_files);
-    if skipped_files > 0 {
-        println!("  Skipped (encoding/parse errors): {}", skipped_files);
+    #[test]
+    fn test_pmccabe_format() {
+        let code = "int sign(int x) {\n    if (x < 0 && x != -1)\n        return -1;\n    return x > 0 ? 1 : 0;\n}\n";
+        let source = SourceText::plain(code.to_string());
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_c::LANGUAGE.into()).unwrap();
+        let tree = parser.parse(&source.code, None).unwrap();
+        let options = AnalysisOptions {
+            pmccabe: true,
+            ..analysis_options()
+        };
+        let metrics = collect_function_metrics(&tree, &source, "sign.c", &None, &None, &options);
+        assert_eq!(format_pmccabe(&metrics), "4\t4\t3\t1\t5\tsign.c(1): sign\n");
+
+        // Without --pmccabe there is nothing to print
+        let metrics = collect_function_metrics(&tree, &source, "sign.c", &None, &None, &analysis_options());
+        assert_eq!(format_pmccabe(&metrics), "");
     }
-}
 
-#[derive(Debug, Clone)]
-struct FunctionMetrics {
-    name: String,
-    file_path: String,
-    mccabe: u32,
-    cognitive: u32,
-    nesting: u32,
-    sloc: u32,
-    abc_magnitude: f64,
-    return_count: u32,
-    test_scoring: TestScoringMetric,
-}
+    #[test]
+    fn test_format_editor() {
+        let mut metrics = vec![function_metrics("init", 1, 0, 0, 4), function_metrics("parse", 7, 11, 3, 40)];
+        (metrics[0].line, metrics[0].end_line) = (3, 8);
+        (metrics[1].line, metrics[1].end_line) = (10, 62);
 
-impl FunctionMetrics {
-    fn max_complexity(&self) -> u32 {
-        std::cmp::max(self.mccabe, self.cognitive)
+        assert_eq!(format_editor(&metrics), "init\t1\t0\t3\t8\nparse\t7\t11\t10\t62\n");
+        assert_eq!(format_editor(&[]), "");
     }
-}
 
-/// Display testability matrix for all functions
-fn display_testability_matrix(all_metrics: &[FunctionMetrics], total_files: usize, skipped_files: usize) {
-    // Categorize functions into quadrants
-    let mut quick_wins = Vec::new();
-    let mut invest_tests = Vec::new();
-    let mut add_docs = Vec::new();
-    let mut refactor = Vec::new();
+    #[test]
+    fn test_format_badge() {
+        let metrics = vec![
+            function_metrics("parse", 9, 6, 2, 40),
+            function_metrics("init", 2, 1, 1, 10),
+            function_metrics("dispatch", 12, 14, 3, 80),
+        ];
+        assert_eq!(
+            format_badge(&metrics, PrimaryMetric::Max, 1).unwrap(),
+            r#"{"schemaVersion":1,"label":"complexity","message":"avg 8.3","color":"green"}"#
+        );
+        assert_eq!(badge_color(10.4), "green");
+        assert_eq!(badge_color(10.5), "yellow");
+        assert_eq!(badge_color(21.0), "orange");
+        assert_eq!(badge_color(63.2), "red");
+    }
 
-    for func in all_metrics {
-        let low_complexity = func.mccabe <= 10;
-        let easy_to_test = func.test_scoring.total_score <= 10;
+    #[test]
+    fn test_suppression_comments() {
+        let code = "// knots:allow mccabe\nint table(int x) {\n    if (x) return 1;\n    if (x > 2) return 2;\n    return 0;\n}\n\nint plain(int x) {\n    if (x) return 1;\n    if (x > 2) return 2;\n    return 0;\n}\n";
+        let source = SourceText::plain(code.to_string());
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_c::LANGUAGE.into()).unwrap();
+        let tree = parser.parse(&source.code, None).unwrap();
+        let metrics = collect_function_metrics(&tree, &source, "gate.c", &None, &None, &analysis_options());
+        assert_eq!(metrics[0].suppressed, vec!["mccabe"]);
+        assert!(metrics[1].suppressed.is_empty());
 
-        match (low_complexity, easy_to_test) {
-            (true, true) => quick_wins.push(func),
-            (false, true) => invest_tests.push(func),
-            (true, false) => add_docs.push(func),
-            (false, false) => refactor.push(func),
-        }
+        // Both are over the limit; only the unsuppressed one fails the gate
+        let thresholds = Thresholds {
+            mccabe: Some(2),
+            sloc: Some(3),
+            ..Thresholds::default()
+        };
+        let violations: Vec<(&str, &str)> = find_violations(&metrics, &thresholds)
+            .iter()
+            .map(|violation| (violation.func.name.as_str(), violation.metric))
+            .collect();
+        assert_eq!(violations, vec![("table", "sloc"), ("plain", "mccabe"), ("plain", "sloc")]);
+
+        // Metrics are still reported, marked as suppressed
+        let output = OutputOptions {
+            verbose: false,
+            no_summary: false,
+            no_report: false,
+            flag_unused_static: false,
+            fields: vec![Field::Mccabe],
+            precision: 2,
+            grade_cut_points: None,
+            long_simple: (100, 5),
+            limit: None,
+            rank_by: None,
+            primary: PrimaryMetric::Max,
+        };
+        assert_eq!(format_fields(&metrics[0], &output), "McCabe: 3, Suppressed: mccabe");
     }
 
-    // Print matrix results
-    println!("\n=== TESTABILITY MATRIX ===\n");
+    #[test]
+    fn test_fields_selection() {
+        let mut output = OutputOptions {
+            verbose: false,
+            no_summary: false,
+            no_report: false,
+            flag_unused_static: false,
+            fields: Field::DEFAULT.to_vec(),
+            precision: 1,
+            grade_cut_points: None,
+            long_simple: (100, 5),
+            limit: None,
+            rank_by: None,
+            primary: PrimaryMetric::Max,
+        };
+        let func = function_metrics("parse", 4, 6, 2, 30);
+        assert_eq!(
+            format_fields(&func, &output),
+            "McCabe: 4, Cognitive: 6, Nesting: 2, SLOC: 30, ABC: 0.0, Returns: 1, TestScore: 0"
+        );
 
-    println!("📊 QUICK WINS (Low Complexity, Easy to Test) - Automate!");
-    println!("=========================================================");
-    if quick_wins.is_empty() {
-        println!("  (none)");
-    } else {
-        for func in &quick_wins {
-            if func.file_path.is_empty() {
-                println!("  ✓ {} (McCabe: {}, TestScore: {})", func.name, func.mccabe, func.test_scoring.total_score);
-            } else {
-                println!("  ✓ {} [{}] (McCabe: {}, TestScore: {})", func.name, func.file_path, func.mccabe, func.test_scoring.total_score);
-            }
-        }
+        // Columns follow the order given on the command line, not the default order
+        let args = Args::parse_from(["knots", "f.c", "--fields", "test_score,sloc,mccabe,loops"]);
+        assert_eq!(args.fields, vec![Field::TestScore, Field::Sloc, Field::Mccabe, Field::Loops]);
+        output.fields = args.fields;
+        assert_eq!(format_fields(&func, &output), "TestScore: 0, SLOC: 30, McCabe: 4, Loops: 0");
     }
-    println!();
 
-    println!("🎯 INVEST IN TESTS (High Complexity, Easy to Test)");
-    println!("==================================================");
-    if invest_tests.is_empty() {
-        println!("  (none)");
-    } else {
-        for func in &invest_tests {
-            if func.file_path.is_empty() {
-                println!("  → {} (McCabe: {}, TestScore: {})", func.name, func.mccabe, func.test_scoring.total_score);
-            } else {
-                println!("  → {} [{}] (McCabe: {}, TestScore: {})", func.name, func.file_path, func.mccabe, func.test_scoring.total_score);
-            }
+    #[test]
+    fn test_function_table() {
+        let output = OutputOptions {
+            verbose: false,
+            no_summary: false,
+            no_report: false,
+            flag_unused_static: false,
+            fields: vec![Field::Sloc, Field::Mccabe],
+            precision: 2,
+            grade_cut_points: None,
+            long_simple: (100, 5),
+            limit: None,
+            rank_by: None,
+            primary: PrimaryMetric::Max,
+        };
+        let long_name = "a".repeat(45);
+        let mut metrics = vec![
+            function_metrics("init", 2, 1, 1, 8),
+            function_metrics(&long_name, 12, 3, 2, 120),
+        ];
+        for func in &mut metrics {
+            func.file_path = "test.c".to_string();
         }
+
+        let table = format_function_table(&metrics, None, &output);
+        let lines: Vec<&str> = table.lines().collect();
+        let name = format!("{}…", "a".repeat(39));
+        assert_eq!(lines[0], format!("{:<40}  SLOC  MCCABE  LOCATION", "FUNCTION"));
+        assert_eq!(lines[1], format!("{:<40}     8       2  test.c:1", "init"));
+        assert_eq!(lines[2], format!("{}   120      12  test.c:1", name));
+        assert_eq!(lines[4], "1 function name truncated to 40 characters");
+
+        // Sorted largest first; without long names the columns only get as wide as needed
+        metrics[1].name = "parse".to_string();
+        let table = format_function_table(&metrics, Some(Field::Mccabe), &output);
+        assert_eq!(
+            table,
+            "FUNCTION  SLOC  MCCABE  LOCATION\n\
+             parse      120      12  test.c:1\n\
+             init         8       2  test.c:1\n"
+        );
     }
-    println!();
 
-    println!("📝 ADD DOCS (Low Complexity, Hard to Test)");
-    println!("===========================================");
-    if add_docs.is_empty() {
-        println!("  (none)");
-    } else {
-        for func in &add_docs {
-            if func.file_path.is_empty() {
-                println!("  ⚠ {} (McCabe: {}, TestScore: {})", func.name, func.mccabe, func.test_scoring.total_score);
-            } else {
-                println!("  ⚠ {} [{}] (McCabe: {}, TestScore: {})", func.name, func.file_path, func.mccabe, func.test_scoring.total_score);
-            }
-        }
+    #[test]
+    fn test_all_trivial_line() {
+        let mut metrics = vec![function_metrics("get_count", 1, 0, 0, 1), function_metrics("set_count", 1, 0, 0, 1)];
+        assert_eq!(
+            all_trivial_line(&metrics).as_deref(),
+            Some("All 2 functions trivial (complexity 1) — no action needed 😊")
+        );
+
+        metrics.push(function_metrics("parse", 2, 1, 1, 6));
+        assert_eq!(all_trivial_line(&metrics), None);
+        assert_eq!(all_trivial_line(&[]), None);
     }
-    println!();
 
-    println!("🚨 REFACTOR (High Complexity, Hard to Test) - HIGH RISK!");
-    println!("========================================================");
-    if refactor.is_empty() {
-        println!("  (none)");
-    } else {
-        for func in &refactor {
-            if func.file_path.is_empty() {
-                println!("  ⛔ {} (McCabe: {}, TestScore: {})", func.name, func.mccabe, func.test_scoring.total_score);
-            } else {
-                println!("  ⛔ {} [{}] (McCabe: {}, TestScore: {})", func.name, func.file_path, func.mccabe, func.test_scoring.total_score);
+    #[test]
+    fn test_threshold_precedence() {
+        // Flag over environment over --standard, which limits only SLOC (to 60) for NASA. Instead of
+        // reading the process environment, each argument's variable is swapped for a default value
+        // taken from `env`, which clap ranks below the flag just as it does the variable
+        fn parse(env: &[(&str, &'static str)], extra: &[&str]) -> Thresholds {
+            let mut command = Args::command();
+            let ids: Vec<String> = command
+                .get_arguments()
+                .filter(|arg| arg.get_env().is_some())
+                .map(|arg| arg.get_id().to_string())
+                .collect();
+            for id in ids {
+                command = command.mut_arg(id, |arg| {
+                    match env.iter().find(|(variable, _)| arg.get_env() == Some(std::ffi::OsStr::new(variable))) {
+                        Some(&(_, value)) => arg.env(None).default_value(value),
+                        None => arg.env(None),
+                    }
+                });
             }
+            let matches = command.get_matches_from(["knots", "x.c", "--standard", "nasa"].iter().chain(extra));
+            Args::from_arg_matches(&matches).unwrap().thresholds()
         }
+        let env = [("KNOTS_MAX_SLOC", "80"), ("KNOTS_MAX_MCCABE", "12")];
+        let from_env = parse(&env, &[]);
+        let from_flag = parse(&env, &["--max-sloc", "100"]);
+        let from_standard = parse(&[], &[]);
+
+        assert_eq!((from_env.sloc, from_env.mccabe), (Some(80), Some(12)));
+        assert_eq!((from_flag.sloc, from_flag.mccabe), (Some(100), Some(12)));
+        assert_eq!((from_standard.sloc, from_standard.mccabe), (Some(60), None));
     }
-    println!();
 
-    // Print summary
-    println!("=== SUMMARY ===\n");
-    println!("  Quick Wins:    {} functions", quick_wins.len());
-    println!("  Invest Tests:  {} functions", invest_tests.len());
-    println!("  Add Docs:      {} functions", add_docs.len());
-    println!("  Refactor:      {} functions", refactor.len());
-    println!("  Total:         {} functions", all_metrics.len());
+    #[test]
+    fn test_uncalled_complex_functions() {
+        let output = OutputOptions {
+            verbose: false,
+            no_summary: false,
+            no_report: false,
+            flag_unused_static: false,
+            fields: vec![Field::Mccabe],
+            precision: 2,
+            grade_cut_points: None,
+            long_simple: (100, 5),
+            limit: None,
+            rank_by: None,
+            primary: PrimaryMetric::Max,
+        };
+        let mut metrics = vec![
+            function_metrics("legacy_parse", 18, 25, 4, 90),
+            function_metrics("handler", 15, 20, 3, 60),
+            function_metrics("export_report", 12, 14, 3, 50),
+            function_metrics("dispatch", 14, 16, 3, 40),
+            function_metrics("helper", 2, 1, 1, 5),
+            function_metrics("main", 20, 22, 3, 70),
+        ];
+        // legacy_parse: static, never referenced; handler: static, referenced through a table
+        metrics[0].is_static = true;
+        metrics[0].unused_static = true;
+        metrics[1].is_static = true;
+        // main calls dispatch, and dispatch calls itself; nothing calls export_report or helper
+        metrics[3].calls = vec!["dispatch".to_string()];
+        metrics[5].calls = vec!["dispatch".to_string()];
 
-    if total_files > 1 {
-        println!();
-        println!("=== FILES PROCESSED ===\n");
-        println!("  Total files found: {}", total_files);
-        println!("  Successfully processed: {}", total_files - skipped_files);
-        if skipped_files > 0 {
-            println!("  Skipped (encoding/parse errors): {}", skipped_files);
+        let names: Vec<&str> = uncalled_complex_functions(&metrics, &output)
+            .iter()
+            .map(|func| func.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["legacy_parse", "export_report"]);
+    }
+
+    #[test]
+    fn test_message_template() {
+        let mut metrics = vec![function_metrics("parse", 14, 9, 2, 40)];
+        metrics[0].file_path = "src/parse.c".to_string();
+        metrics[0].line = 12;
+        let thresholds = Thresholds {
+            mccabe: Some(10),
+            ..Thresholds::default()
+        };
+        let violations = find_violations(&metrics, &thresholds);
+
+        assert_eq!(
+            MessageTemplate::default().render(&violations[0]),
+            "Error: src/parse.c:12: parse() has mccabe 14 (max 10)"
+        );
+        let template = MessageTemplate::parse("{file}({line}): error KN01: {func} {metric}={value} > {threshold} {{ci}}").unwrap();
+        assert_eq!(template.render(&violations[0]), "src/parse.c(12): error KN01: parse mccabe=14 > 10 {ci}");
+
+        let unknown = MessageTemplate::parse("{file}: {function}").unwrap_err();
+        assert!(unknown.contains("unknown placeholder {function}"), "{}", unknown);
+        assert!(MessageTemplate::parse("{file").unwrap_err().contains("unclosed"));
+        assert!(MessageTemplate::parse("file}").unwrap_err().contains("unmatched"));
+    }
+
+    #[test]
+    fn test_nesting_dominated() {
+        let source = SourceText::plain(
+            "int nested(int *a, int n) {
+    for (int i = 0; i < n; i++) {
+        if (a[i]) {
+            while (a[i] > 1) {
+                if (a[i] % 2)
+                    a[i]--;
+                else
+                    a[i] /= 2;
+            }
         }
     }
+    return 0;
 }
+int flat(int x) {
+    if (x < 0)
+        return -1;
+    if (x == 0)
+        return 0;
+    return 1;
+}
+"
+            .to_string(),
+        );
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_c::LANGUAGE.into()).unwrap();
+        let tree = parser.parse(&source.code, None).unwrap();
+        let metrics = collect_function_metrics(&tree, &source, "f.c", &None, &None, &analysis_options());
 
-fn visit_functions<F>(cursor: &mut TreeCursor, source_code: &str, callback: &mut F)
-where
-    F: FnMut(Node, &str),
-{
-    let node = cursor.node();
+        // nested: for +1, if +2, while +3, if +4, else +1 = 11 against McCabe 5
+        assert_eq!((metrics[0].cognitive, metrics[0].mccabe, metrics[0].cognitive_gap()), (11, 5, 6));
+        assert_eq!((metrics[1].cognitive, metrics[1].mccabe, metrics[1].cognitive_gap()), (2, 3, 0));
+        let dominated = nesting_dominated(&metrics, 5);
+        assert_eq!(dominated.len(), 1);
+        assert_eq!(dominated[0].name, "nested");
 
-    if node.kind() == "function_definition" {
-        callback(node, source_code);
+        let thresholds = Thresholds {
+            cognitive_gap: Some(5),
+            ..Thresholds::default()
+        };
+        let violations = find_violations(&metrics, &thresholds);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].message(), "nested() has cognitive gap 6 (max 5)");
     }
 
-    if cursor.goto_first_child() {
-        loop {
-            visit_functions(cursor, source_code, callback);
-            if !cursor.goto_next_sibling() {
-                break;
-            }
+    #[test]
+    fn test_dry_run() {
+        let files = [PathBuf::from("src/main.c"), PathBuf::from("src/ui/view.m"), PathBuf::from("inc/api.h")];
+        assert_eq!(
+            format_dry_run(&files, 2, 0),
+            "src/main.c     C\nsrc/ui/view.m  Objective-C\ninc/api.h      C header\n\n3 files would be analyzed (skipped 2 generated)\n"
+        );
+        assert_eq!(format_dry_run(&files[..1], 0, 1), "src/main.c  C\n\n1 file would be analyzed (skipped 1 inaccessible)\n");
+        assert_eq!(source_language(Path::new("bridge.mm")), "Objective-C++");
+    }
+
+    #[test]
+    fn test_output_modes_are_exclusive() {
+        assert!(Args::try_parse_from(["knots", "-r", "src", "--table"]).is_ok());
+        assert!(Args::try_parse_from(["knots", "-r", "src", "--table", "--matrix"]).is_err());
+        assert!(Args::try_parse_from(["knots", "-r", "src", "--pmccabe", "--format", "github"]).is_err());
+        assert!(Args::try_parse_from(["knots", "-r", "src", "--metric", "sloc", "--format", "prometheus"]).is_err());
+    }
+
+    #[test]
+    fn test_summary_format() {
+        let args = Args::try_parse_from(["knots", "-r", "src", "--format", "text", "--summary-format", "json"]).unwrap();
+        assert_eq!((args.format, args.summary_format), (OutputFormat::Text, SummaryFormat::Json));
+        assert!(Args::try_parse_from(["knots", "-r", "src", "--summary-format", "json", "--no-summary"]).is_err());
+
+        let output = OutputOptions {
+            verbose: false,
+            no_summary: false,
+            no_report: false,
+            flag_unused_static: false,
+            fields: vec![Field::Mccabe, Field::Cognitive],
+            precision: 2,
+            grade_cut_points: None,
+            long_simple: (100, 5),
+            limit: None,
+            rank_by: None,
+            primary: PrimaryMetric::Max,
+        };
+        let mut metrics = vec![function_metrics("parse", 12, 9, 3, 40), function_metrics("init", 2, 1, 1, 10)];
+        metrics[0].file_path = "src/parse.c".to_string();
+        metrics[0].line = 7;
+
+        // The listing stays text while the summary of the same run is JSON
+        let mut listing = Vec::new();
+        write_function_report(&mut listing, &metrics, &output, None).unwrap();
+        let listing = String::from_utf8(listing).unwrap();
+        assert!(listing.contains("parse [src/parse.c] (McCabe: 12, Cognitive: 9)"), "{}", listing);
+
+        let summary: serde_json::Value = serde_json::from_str(&format_summary_json(&RunSummary::of(&metrics, &output, None))).unwrap();
+        assert_eq!(summary["schema_version"], JSON_SCHEMA_VERSION);
+        assert_eq!(summary["functions"], 2);
+        assert_eq!(summary["totals"]["mccabe"], 14);
+        assert_eq!(summary["averages"]["cognitive"], 5.0);
+        assert_eq!(summary["worst"][0]["name"], "parse");
+        assert_eq!(summary["worst"][0]["line"], 7);
+        assert!(summary.get("files").is_none());
+    }
+
+    #[test]
+    fn test_source_snippet() {
+        let source = "#include <stdio.h>\n\nint clamp(int x) {\n    if (x < 0)\n        return 0;\n    return x;\n}\n";
+        let mut func = function_metrics("clamp", 2, 1, 2, 5);
+        func.file_path = "clamp.c".to_string();
+        func.line = 3;
+        func.end_line = 7;
+
+        let snippet = format_source_snippet(source, &func, 40);
+        let mut lines = snippet.lines();
+        assert_eq!(lines.next(), Some("--- clamp() [clamp.c:3-7] ---"));
+        let body: Vec<&str> = lines.map(|line| line.split_once(" | ").unwrap().1).collect();
+        let expected: Vec<&str> = source.lines().skip(2).collect();
+        assert_eq!(body, expected);
+        assert!(snippet.contains("4 |     if (x < 0)\n"));
+
+        // The middle of a long function is elided
+        let snippet = format_source_snippet(source, &func, 2);
+        assert_eq!(
+            snippet,
+            "--- clamp() [clamp.c:3-7] ---\n3 | int clamp(int x) {\n  | ... 3 lines omitted ...\n7 | }\n"
+        );
+    }
+
+    #[test]
+    fn test_highest_risk() {
+        let scored = |name: &str, mccabe: u32, cognitive: u32, total_score: i32, documentation_score: i32| {
+            let mut func = function_metrics(name, mccabe, cognitive, 1, 20);
+            func.test_scoring.total_score = total_score;
+            func.test_scoring.documentation_score = documentation_score;
+            func
+        };
+        let metrics = vec![
+            scored("documented_tangle", 15, 22, 30, 10),
+            scored("simple_helper", 2, 1, 5, 0),
+            scored("undocumented_tangle", 15, 22, 30, 0),
+            scored("hard_to_test", 4, 3, 45, 2),
+        ];
+
+        // 22 + 30 + 10 beats 4 + 45 + 8, which beats 22 + 30 + 0 and 2 + 5 + 10
+        let weights = RiskWeights::DEFAULT;
+        assert_eq!(metrics[2].risk_score(&weights), 62.0);
+        let names: Vec<&str> = highest_risk(&metrics, &weights, 5).iter().map(|(func, _)| func.name.as_str()).collect();
+        assert_eq!(names, vec!["undocumented_tangle", "hard_to_test", "documented_tangle", "simple_helper"]);
+
+        // Weighting complexity alone ties the tangles, which keep file order
+        let complexity_only = RiskWeights {
+            complexity: 1.0,
+            testability: 0.0,
+            documentation: 0.0,
+        };
+        let ranked = highest_risk(&metrics, &complexity_only, 3);
+        let names: Vec<&str> = ranked.iter().map(|(func, _)| func.name.as_str()).collect();
+        assert_eq!(names, vec!["documented_tangle", "undocumented_tangle", "hard_to_test"]);
+        assert_eq!(ranked[2].1, 4.0);
+
+        // Documentation scores outside 0..=10 are clamped
+        assert_eq!(scored("odd", 1, 1, 0, 14).risk_score(&weights), 1.0);
+    }
+
+    #[test]
+    fn test_nested_switches() {
+        let source = SourceText::plain(
+            "int flat(int x) {
+    switch (x) {
+    case 1: return 1;
+    }
+    return 0;
+}
+int nested(int state, int event) {
+    switch (state) {
+    case 0:
+        switch (event) {
+        case 1: return 1;
         }
-        cursor.goto_parent();
+        break;
     }
+    return 0;
 }
+"
+            .to_string(),
+        );
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_c::LANGUAGE.into()).unwrap();
+        let tree = parser.parse(&source.code, None).unwrap();
+        let metrics = collect_function_metrics(&tree, &source, "f.c", &None, &None, &analysis_options());
 
-fn get_function_name(node: Node, source_code: &str) -> Option<String> {
-    let mut cursor = node.walk();
+        assert_eq!(metrics[0].max_switch_nesting, 1);
+        assert_eq!(metrics[1].max_switch_nesting, 2);
+        assert_eq!(metrics[1].nested_switch_lines, vec![10]);
+        let nested = nested_switches(&metrics);
+        assert_eq!(nested.len(), 1);
+        assert_eq!(nested[0].name, "nested");
+    }
 
-    for child in node.children(&mut cursor) {
-        if child.kind() == "function_declarator" {
-            return get_declarator_name(child, source_code);
-        } else if child.kind() == "pointer_declarator" {
-            // For functions returning pointers, the function_declarator is nested inside
-            if let Some(name) = get_function_name_from_declarator(child, source_code) {
-                return Some(name);
-            }
+    #[test]
+    fn test_per_100_sloc() {
+        let in_file = |path: &str, mut func: FunctionMetrics| {
+            func.file_path = path.to_string();
+            func
+        };
+        // small.c: McCabe 6 over 30 SLOC; large.c: McCabe 10 over 200 SLOC
+        let metrics = vec![
+            in_file("small.c", function_metrics("parse", 4, 5, 2, 20)),
+            in_file("large.c", function_metrics("init", 6, 4, 1, 150)),
+            in_file("small.c", function_metrics("check", 2, 1, 1, 10)),
+            in_file("large.c", function_metrics("run", 4, 3, 2, 50)),
+        ];
+
+        let files = totals_by_file(&metrics);
+        assert_eq!(files.len(), 2);
+        let (path, small) = &files[0];
+        assert_eq!((*path, small.mccabe, small.sloc), ("small.c", 6, 30));
+        let small = small.per_100_sloc().unwrap();
+        assert_eq!(small.mccabe, 20.0);
+        assert_eq!(small.cognitive, 20.0);
+        assert_eq!(small.return_count, 2.0 * 100.0 / 30.0);
+        let large = files[1].1.per_100_sloc().unwrap();
+        assert_eq!(large.mccabe, 5.0);
+        assert_eq!(large.cognitive, 3.5);
+
+        // The run's density weighs files by size: 16 McCabe over 230 SLOC, not the mean of 20 and 5
+        let project = MetricTotals::of(&metrics).per_100_sloc().unwrap();
+        assert!((project.mccabe - 1600.0 / 230.0).abs() < 1e-9);
+        assert_eq!(
+            format_density("All files", &MetricTotals::of(&metrics), 2),
+            "  All files (230 SLOC): McCabe 6.96, Cognitive 5.65, ABC 0.00, Returns 1.74, Test Score 0.00"
+        );
+        assert_eq!(MetricTotals::default().per_100_sloc(), None);
+    }
+
+    #[test]
+    fn test_function_report_limit() {
+        let output = OutputOptions {
+            verbose: false,
+            no_summary: true,
+            no_report: false,
+            flag_unused_static: false,
+            fields: vec![Field::Mccabe],
+            precision: 2,
+            grade_cut_points: None,
+            long_simple: (100, 5),
+            limit: Some(2),
+            rank_by: None,
+            primary: PrimaryMetric::Max,
+        };
+        let metrics = vec![
+            function_metrics("a", 1, 0, 1, 5),
+            function_metrics("b", 2, 1, 1, 5),
+            function_metrics("c", 3, 2, 1, 5),
+            function_metrics("d", 4, 3, 1, 5),
+        ];
+
+        let mut out = Vec::new();
+        write_function_report(&mut out, &metrics, &output, output.limit).unwrap();
+        let lines: Vec<String> = String::from_utf8(out).unwrap().lines().map(str::to_string).collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].contains(" b [] (McCabe: 2)"));
+        assert_eq!(lines[2], "… and 2 more function(s)");
+
+        let mut out = Vec::new();
+        write_function_report(&mut out, &metrics, &output, None).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 4);
+
+        // The verbose report lists the same details as the verbose terminal output
+        let output = OutputOptions { verbose: true, ..output };
+        let mut func = function_metrics("parse", 12, 9, 3, 40);
+        func.file_path = "src/parse.c".to_string();
+        func.exit_points = 2;
+        func.magic_numbers = 4;
+        func.static_local_count = 1;
+        func.leak_risk = true;
+        let mut out = Vec::new();
+        write_function_report(&mut out, std::slice::from_ref(&func), &output, None).unwrap();
+        let report = String::from_utf8(out).unwrap();
+        let mut details = Vec::new();
+        write_function_details(&mut details, &func, &output).unwrap();
+        assert!(report.starts_with("Function: parse "));
+        assert!(report.ends_with(&String::from_utf8(details).unwrap()));
+        let expected =
+            ["  Essential Complexity:", "  Exit Points: 2", "  Magic Numbers: 4", "  Static Locals: 1", "  Leak Risk:"];
+        for line in expected {
+            assert!(report.contains(line), "{} missing from\n{}", line, report);
         }
     }
 
-    None
-}
+    #[test]
+    fn test_group_files_by_emoji() {
+        let mut metrics = Vec::new();
+        for (file, name, mccabe) in [("a.c", "a1", 3), ("a.c", "a2", 60), ("b.c", "b1", 12), ("c.c", "c1", 2), ("c.c", "c2", 4)] {
+            let mut func = function_metrics(name, mccabe, 0, 1, 10);
+            func.file_path = file.to_string();
+            metrics.push(func);
+        }
+
+        let bands = group_files_by_emoji(&metrics, PrimaryMetric::Max);
+        let paths: Vec<Vec<&str>> = bands.iter().map(|band| band.iter().map(|file| file.file_path).collect()).collect();
+        assert_eq!(paths, vec![vec!["a.c"], vec![], vec!["b.c"], vec!["c.c"]]);
+        assert_eq!(bands[0][0].worst, 60);
+        assert_eq!(bands[0][0].average, 31.5);
+        assert_eq!(bands[3][0].average, 3.0);
+    }
+
+    #[test]
+    fn test_macro_pseudo_functions() {
+        let source = SourceText::plain("#define LOOP(n) for(int i=0;i<n;i++)\n#define MAX(a, b) ((a) > (b) ? (a) : (b))\n#define CALL(f) f()\n\nvoid run(void) {\n    LOOP(3) tick();\n}\n".to_string());
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_c::LANGUAGE.into()).unwrap();
+        let tree = parser.parse(&source.code, None).unwrap();
 
-fn get_function_name_from_declarator(node: Node, source_code: &str) -> Option<String> {
-    let mut cursor = node.walk();
+        let names = |metrics: &[FunctionMetrics]| metrics.iter().map(|func| func.name.clone()).collect::<Vec<_>>();
+        let metrics = collect_function_metrics(&tree, &source, "loop.h", &None, &None, &analysis_options());
+        assert_eq!(names(&metrics), vec!["run"]);
 
-    for child in node.children(&mut cursor) {
-        if child.kind() == "function_declarator" {
-            return get_declarator_name(child, source_code);
-        } else if child.kind() == "pointer_declarator" {
-            if let Some(name) = get_function_name_from_declarator(child, source_code) {
-                return Some(name);
-            }
+        let options = AnalysisOptions {
+            macros: true,
+            ..analysis_options()
+        };
+        let metrics = collect_function_metrics(&tree, &source, "loop.h", &None, &None, &options);
+        assert_eq!(names(&metrics), vec!["run", "LOOP", "MAX"]);
+        let looped = &metrics[1];
+        assert_eq!((looped.line, looped.mccabe, looped.cognitive, looped.loop_count), (1, 2, 1, 1));
+        assert_eq!((metrics[2].line, metrics[2].branch_count), (2, 1));
+    }
+
+    #[test]
+    fn test_primary_metric_ranking() {
+        // (name, McCabe, Cognitive): branchy dispatch vs deeply nested logic
+        let metrics = vec![
+            function_metrics("dispatch", 20, 4, 1, 60),
+            function_metrics("nested", 8, 18, 4, 40),
+            function_metrics("balanced", 14, 13, 2, 30),
+        ];
+        let ranking = |primary: PrimaryMetric| {
+            worst_functions(&metrics, None, primary).iter().map(|f| f.name.as_str()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(ranking(PrimaryMetric::Mccabe), vec!["dispatch", "balanced", "nested"]);
+        assert_eq!(ranking(PrimaryMetric::Cognitive), vec!["nested", "balanced", "dispatch"]);
+        assert_eq!(ranking(PrimaryMetric::Max), vec!["dispatch", "nested", "balanced"]);
+        // 0.4 x McCabe + 0.6 x Cognitive: 10.4 -> 10, 14, 13.4 -> 13
+        assert_eq!(ranking(PrimaryMetric::Blend), vec!["nested", "balanced", "dispatch"]);
+        assert_eq!(PrimaryMetric::Blend.complexity(&metrics[0]), 10);
+    }
+
+    #[test]
+    fn test_rank_by_expression() {
+        let mut tested = function_metrics("tested", 10, 10, 1, 20);
+        tested.test_scoring.total_score = 0;
+        let mut untestable = function_metrics("untestable", 6, 4, 1, 20);
+        untestable.test_scoring.total_score = 30;
+        let metrics = vec![tested, untestable, function_metrics("simple", 1, 0, 1, 5)];
+
+        let names = |sorted: Vec<&FunctionMetrics>| sorted.iter().map(|f| f.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(worst_functions(&metrics, None, PrimaryMetric::Max)), vec!["tested", "untestable", "simple"]);
+
+        let expr = RankExpr::parse("mccabe*2 + test_score").unwrap();
+        assert_eq!(expr.eval(&metrics[1]), 42.0);
+        assert_eq!(names(worst_functions(&metrics, Some(&expr), PrimaryMetric::Max)), vec!["untestable", "tested", "simple"]);
+
+        let arithmetic = RankExpr::parse("1 + 2 * (3 - -1) / 4").unwrap();
+        assert_eq!(arithmetic.eval(&metrics[2]), 3.0);
+    }
+
+    #[test]
+    fn test_style_check_reporting() {
+        let source = SourceText::plain("int f(int x) {\n    if (x) {\n\treturn 1;\n    }\n    return 0;\n}\n".to_string());
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_c::LANGUAGE.into()).unwrap();
+        let tree = parser.parse(&source.code, None).unwrap();
+
+        let metrics = collect_function_metrics(&tree, &source, "f.c", &None, &None, &analysis_options());
+        assert!(metrics[0].style.is_none());
+
+        let options = AnalysisOptions {
+            style_check: true,
+            ..analysis_options()
+        };
+        let metrics = collect_function_metrics(&tree, &source, "f.c", &None, &None, &options);
+        let style = metrics[0].style.unwrap();
+        assert_eq!((style.indented_lines, style.off_style_indent_lines), (4, 1));
+        assert_eq!(format_style(&style, 0), "80% (1 of 4 indented lines, 0 of 1 braces off-style)");
+        assert_eq!(average_style_score(&metrics), Some(80.0));
+        assert_eq!(average_style_score(&[function_metrics("g", 1, 0, 1, 1)]), None);
+    }
+
+    #[test]
+    fn test_doc_comment() {
+        let source = SourceText::plain(
+            "/* Clamp a reading to the sensor's range */\nint clamp(int x) { return x; }\nint bare(void) { return 0; }\n"
+                .to_string(),
+        );
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_c::LANGUAGE.into()).unwrap();
+        let tree = parser.parse(&source.code, None).unwrap();
+
+        let metrics = collect_function_metrics(&tree, &source, "f.c", &None, &None, &analysis_options());
+        assert_eq!(metrics[0].doc_comment, None);
+
+        let options = AnalysisOptions {
+            doc_limit: Some(2000),
+            ..analysis_options()
+        };
+        let metrics = collect_function_metrics(&tree, &source, "f.c", &None, &None, &options);
+        assert_eq!(metrics[0].doc_comment.as_deref(), Some("/* Clamp a reading to the sensor's range */"));
+        assert_eq!(metrics[1].doc_comment, None);
+        let json = serde_json::to_value(&metrics[0]).unwrap();
+        assert_eq!(json["doc_comment"], "/* Clamp a reading to the sensor's range */");
+
+        assert_eq!(truncate_doc_comment("/* Clamp */", 7), "/* Clam…");
+        assert_eq!(truncate_doc_comment("/* Clamp */", 11), "/* Clamp */");
+    }
+
+    #[test]
+    fn test_check_gates() {
+        let mut metrics = vec![function_metrics("parse", 14, 9, 2, 40), function_metrics("init", 3, 2, 1, 10)];
+        metrics[1].missing_return = true;
+        let thresholds = Thresholds {
+            mccabe: Some(10),
+            nesting: Some(4),
+            ..Thresholds::default()
+        };
+
+        let gates = threshold_gates(&metrics, &thresholds);
+        let summary: Vec<(&str, bool, usize)> = gates.iter().map(|gate| (gate.name.as_str(), gate.passed, gate.offenders.len())).collect();
+        assert_eq!(summary, vec![("mccabe", false, 1), ("nesting", true, 0)]);
+        assert_eq!(gates[0].offenders[0].detail, "mccabe 14 (max 10)");
+
+        let old = JsonReport {
+            functions: vec![function_metrics("parse", 12, 9, 2, 40), function_metrics("init", 3, 2, 1, 10)],
+            ..JsonReport::default()
+        };
+        let new = JsonReport {
+            functions: metrics.clone(),
+            ..JsonReport::default()
+        };
+        let baseline = baseline_gate("main", &diff_reports(&old, &new, 0.0));
+        assert_eq!(baseline.offenders[0].detail, "max complexity 12 -> 14");
+
+        let report = CheckReport::new(vec![missing_return_gate(&metrics), baseline]);
+        assert!(!report.passed);
+        assert_eq!(report.failed_count(), 2);
+        let table = format_table(&report);
+        assert!(table.starts_with("GATE            LIMIT  RESULT  OFFENDERS\nmissing return  none   FAIL    1\n"));
+        assert!(table.ends_with("Result: FAIL (0 of 2 gates passed)\n"));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["schema_version"], JSON_SCHEMA_VERSION);
+        assert_eq!(json["passed"], false);
+    }
+
+    #[test]
+    fn test_check_json_before_or_after_paths() {
+        for argv in [
+            vec!["knots", "check", "--json", "src", "-r", "--max-mccabe", "15"],
+            vec!["knots", "check", "src", "-r", "--json", "--max-mccabe", "15"],
+            vec!["knots", "check", "-r", "src", "--max-mccabe", "15", "--json"],
+        ] {
+            let Some(Command::Check { json, args }) = Args::try_parse_from(&argv).unwrap().command else {
+                panic!("{:?} is not a check command", argv);
+            };
+            let (json, args) = split_check_json(json, args);
+            assert!(json, "{:?}", argv);
+            let args = Args::try_parse_from(std::iter::once("knots".to_string()).chain(args)).unwrap();
+            assert!(args.recursive);
+            assert_eq!(args.max_mccabe, Some(15));
         }
+
+        let argv = ["knots", "check", "src"];
+        let Some(Command::Check { json, args }) = Args::try_parse_from(argv).unwrap().command else {
+            panic!("not a check command");
+        };
+        assert_eq!(split_check_json(json, args), (false, vec!["src".to_string()]));
     }
 
-    None
-}
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_records_each_run() {
+        let dir = TempDir::new("sqlite");
+        let db_path = dir.join("knots.db");
 
-fn get_declarator_name(node: Node, source_code: &str) -> Option<String> {
-    let mut cursor = node.walk();
+        let mut parse = function_metrics("parse", 12, 9, 3, 40);
+        parse.file_path = "src/parse.c".to_string();
+        sqlite::record_run(&db_path, &[parse.clone(), function_metrics("init", 1, 0, 1, 5)], Some("abc123")).unwrap();
+        parse.mccabe = 14;
+        sqlite::record_run(&db_path, &[parse], None).unwrap();
 
-    for child in node.children(&mut cursor) {
-        if child.kind() == "identifier" {
-            return Some(child.utf8_text(source_code.as_bytes()).ok()?.to_string());
-        } else if child.kind() == "pointer_declarator" || child.kind() == "function_declarator" {
-            if let Some(name) = get_declarator_name(child, source_code) {
-                return Some(name);
-            }
+        let connection = rusqlite::Connection::open(&db_path).unwrap();
+        let rows: Vec<(String, u32, Option<String>)> = connection
+            .prepare("SELECT function, mccabe, git_commit FROM function_metrics WHERE file_path = 'src/parse.c' ORDER BY rowid")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows, vec![("parse".to_string(), 12, Some("abc123".to_string())), ("parse".to_string(), 14, None)]);
+
+        let total: u32 = connection.query_row("SELECT COUNT(*) FROM function_metrics", [], |row| row.get(0)).unwrap();
+        assert_eq!(total, 3);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_trend() {
+        let dir = TempDir::new("trend");
+        let db_path = dir.join("knots.db");
+
+        let runs = [
+            (vec![function_metrics("parse", 12, 9, 3, 40), function_metrics("init", 2, 0, 1, 5)], "abc123"),
+            (vec![function_metrics("parse", 16, 9, 3, 40), function_metrics("init", 2, 0, 1, 5)], "def456"),
+        ];
+        for (functions, commit) in &runs {
+            sqlite::record_run(&db_path, functions, Some(commit)).unwrap();
         }
+
+        let points = sqlite::load_trend(&db_path, Field::Mccabe).unwrap();
+        let summary: Vec<(Option<&str>, f64, usize)> = points
+            .iter()
+            .map(|point| (point.git_commit.as_deref(), point.average, point.functions))
+            .collect();
+        assert_eq!(summary, vec![(Some("abc123"), 7.0, 2), (Some("def456"), 9.0, 2)]);
+        assert_eq!(points[0].date.len(), "YYYY-MM-DD".len());
+        assert_eq!(sqlite::load_trend(&db_path, Field::TestScore).unwrap().len(), 2);
     }
 
-    None
+    #[cfg(feature = "git")]
+    #[test]
+    fn test_complexity_by_author() {
+        let dir = TempDir::new("author");
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let commit = |name: &str, contents: &str| {
+            commit_file(&repo, "a.c", contents, &git2::Signature::now(name, "dev@example.com").unwrap());
+        };
+
+        // Ada writes both functions; Grace then rewrites most of parse()
+        let first = "int parse(int x) {\n    return x;\n}\nint init(void) {\n    return 0;\n}\n";
+        commit("Ada", first);
+        let second = "int parse(int x) {\n    if (x) {\n        x++;\n        return 1;\n    }\n    return x;\n}\nint init(void) {\n    return 0;\n}\n";
+        commit("Grace", second);
+
+        let file = dir.join("a.c").to_string_lossy().to_string();
+        let func = |name: &str, line: usize, end_line: usize, mccabe: u32| FunctionMetrics {
+            file_path: file.clone(),
+            line,
+            end_line,
+            ..function_metrics(name, mccabe, 1, 1, 3)
+        };
+        let untracked = FunctionMetrics {
+            file_path: dir.join("new.c").to_string_lossy().to_string(),
+            ..func("helper", 1, 3, 9)
+        };
+        let metrics = vec![func("parse", 1, 7, 2), func("init", 8, 10, 1), untracked];
+
+        let summaries = complexity_by_author(&metrics);
+        let totals: Vec<(&str, usize, u32)> =
+            summaries.iter().map(|s| (s.author.as_str(), s.functions, s.total_complexity)).collect();
+        assert_eq!(totals, vec![("Grace", 1, 2), ("Ada", 1, 1)]);
+        assert_eq!(
+            format_author_table(&summaries, 1),
+            "AUTHOR  FUNCTIONS  TOTAL  AVERAGE\nGrace           1      2      2.0\nAda             1      1      1.0\n"
+        );
+    }
 }
@@ -0,0 +1,665 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Objective-C source rewritten into C that the tree-sitter-c grammar can parse
+pub struct ObjcSource {
+    /// Translated code; every line stays on its original line number
+    pub code: String,
+    /// Placeholder C function name for each method -> `-[Class selector:]`
+    pub method_names: HashMap<String, String>,
+}
+
+/// Checks for Objective-C (`.m`) and Objective-C++ (`.mm`) files
+pub fn is_objc_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "m" || ext == "mm")
+}
+
+/// Rewrite Objective-C into equivalent C for complexity analysis
+///
+/// - `@interface`/`@protocol` blocks and other top-level `@` directives are blanked
+/// - method definitions become C functions named `__knots_objc_method_N`
+/// - message sends `[recv sel:a with:b]` become calls `__knots_send(recv, a, b)`
+/// - block literals `^(...){ ... }` become calls `__knots_block({ ... })`, whose compound
+///   statement argument tree-sitter-c accepts, so the block's body counts toward its method
+/// - fast enumeration `for (id x in items)` becomes `for (id x; items;)`
+/// - `@try`/`@catch`/`@finally`/`@synchronized`/`@autoreleasepool` keep only their blocks
+///
+/// Control flow (`if`, loops, `switch`, `&&`, `?:`) is left untouched, so the metrics
+/// of a method match those of the same logic written in C.
+pub fn translate(source: &str) -> ObjcSource {
+    let bytes = source.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut method_names = HashMap::new();
+    let mut class_name: Option<String> = None;
+    let mut line_start = true;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if let Some(end) = skip_literal_or_comment(bytes, i) {
+            out.extend_from_slice(&bytes[i..end]);
+            line_start = false;
+            i = end;
+            continue;
+        }
+
+        match bytes[i] {
+            b'@' => {
+                let word = identifier_at(bytes, i + 1);
+                let after_word = i + 1 + word.len();
+                let end = match word {
+                    // `@"..."` in a global initializer
+                    "" => i + 1,
+                    "interface" | "protocol" => {
+                        let line_end = find_byte(bytes, after_word, b'\n').unwrap_or(bytes.len());
+                        match find_byte(bytes, after_word, b';') {
+                            // `@protocol Foo;` is a forward declaration
+                            Some(semi) if semi < line_end => semi + 1,
+                            _ => find_directive(bytes, after_word, "@end").map_or(bytes.len(), |end| end + 4),
+                        }
+                    }
+                    "implementation" => {
+                        let name_start = skip_whitespace(bytes, after_word);
+                        let name = identifier_at(bytes, name_start);
+                        class_name = Some(name.to_string());
+                        let mut end = name_start + name.len();
+                        // Category `(Name)` and instance variable block `{ ... }`
+                        let next = skip_whitespace(bytes, end);
+                        if bytes.get(next) == Some(&b'(') {
+                            end = matching(bytes, next, b'(', b')').map_or(next, |close| close + 1);
+                        }
+                        let next = skip_whitespace(bytes, end);
+                        if bytes.get(next) == Some(&b'{') {
+                            end = matching(bytes, next, b'{', b'}').map_or(next, |close| close + 1);
+                        }
+                        end
+                    }
+                    "end" => {
+                        class_name = None;
+                        after_word
+                    }
+                    _ => find_byte(bytes, after_word, b';').map_or(bytes.len(), |semi| semi + 1),
+                };
+                if word.is_empty() {
+                    // Keep the string literal itself, without the '@'
+                    i = end;
+                    continue;
+                }
+                push_newlines(&mut out, &bytes[i..end.min(bytes.len())]);
+                line_start = false;
+                i = end;
+            }
+            b'-' | b'+' if line_start && class_name.is_some() => {
+                let header_end = (i..bytes.len())
+                    .find(|&j| bytes[j] == b'{' || bytes[j] == b';')
+                    .unwrap_or(bytes.len());
+                line_start = false;
+                if bytes.get(header_end) != Some(&b'{') {
+                    push_newlines(&mut out, &bytes[i..(header_end + 1).min(bytes.len())]);
+                    i = header_end + 1;
+                    continue;
+                }
+
+                let placeholder = format!("__knots_objc_method_{}", method_names.len());
+                let header = &source[i..header_end];
+                let (signature, selector) = translate_method_header(header, &placeholder);
+                let class = class_name.as_deref().unwrap_or("");
+                method_names.insert(placeholder, format!("{}[{} {}]", &header[..1], class, selector));
+                out.extend_from_slice(signature.as_bytes());
+
+                i = push_block(&mut out, source, header_end);
+            }
+            b'{' => {
+                line_start = false;
+                i = push_block(&mut out, source, i);
+            }
+            byte => {
+                if byte == b'\n' {
+                    line_start = true;
+                } else if !byte.is_ascii_whitespace() {
+                    line_start = false;
+                }
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    ObjcSource {
+        code: String::from_utf8(out).unwrap_or_default(),
+        method_names,
+    }
+}
+
+/// Copy a `{ ... }` block starting at `open`, translating its body; returns the index after it
+fn push_block(out: &mut Vec<u8>, source: &str, open: usize) -> usize {
+    match matching(source.as_bytes(), open, b'{', b'}') {
+        Some(close) => {
+            out.push(b'{');
+            out.extend_from_slice(translate_body(&source[open + 1..close]).as_bytes());
+            out.push(b'}');
+            close + 1
+        }
+        None => {
+            out.extend_from_slice(translate_body(&source[open..]).as_bytes());
+            source.len()
+        }
+    }
+}
+
+/// Rewrite `- (int)scale:(int)x by:(int)factor` into `int NAME(int x, int factor)`
+/// Returns the C signature (padded with the header's newlines) and the selector
+fn translate_method_header(header: &str, name: &str) -> (String, String) {
+    let bytes = header.as_bytes();
+    let mut i = skip_whitespace(bytes, 1);
+
+    let mut return_type = "id".to_string();
+    if bytes.get(i) == Some(&b'(') {
+        if let Some(close) = matching(bytes, i, b'(', b')') {
+            return_type = clean_type(&header[i + 1..close]);
+            i = close + 1;
+        }
+    }
+
+    let mut selector = String::new();
+    let mut params = Vec::new();
+    loop {
+        i = skip_whitespace(bytes, i);
+        if bytes.get(i) == Some(&b',') {
+            // Variadic method: `format:(NSString *)fmt, ...`
+            params.push("...".to_string());
+            break;
+        }
+        let label = identifier_at(bytes, i);
+        if label.is_empty() && bytes.get(i) != Some(&b':') {
+            break;
+        }
+        let after_label = skip_whitespace(bytes, i + label.len());
+        if bytes.get(after_label) != Some(&b':') {
+            // Unary selector; anything after parameters is an attribute macro
+            if params.is_empty() {
+                selector.push_str(label);
+            }
+            break;
+        }
+        selector.push_str(label);
+        selector.push(':');
+        i = skip_whitespace(bytes, after_label + 1);
+
+        let mut param_type = "id".to_string();
+        if bytes.get(i) == Some(&b'(') {
+            if let Some(close) = matching(bytes, i, b'(', b')') {
+                param_type = clean_type(&header[i + 1..close]);
+                i = skip_whitespace(bytes, close + 1);
+            }
+        }
+        let param_name = identifier_at(bytes, i);
+        i += param_name.len();
+        params.push(format!("{} {}", param_type, param_name));
+    }
+
+    let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+    let newlines = "\n".repeat(header.matches('\n').count());
+    (format!("{} {}({}){}", return_type, name, params, newlines), selector)
+}
+
+/// Drop protocol qualifiers (`id<NSCopying>`) and nullability/ARC keywords from a type
+fn clean_type(objc_type: &str) -> String {
+    let mut cleaned = String::new();
+    let mut angle_depth = 0;
+    for c in objc_type.chars() {
+        match c {
+            '<' => angle_depth += 1,
+            '>' => angle_depth -= 1,
+            _ if angle_depth == 0 => cleaned.push(c),
+            _ => {}
+        }
+    }
+    cleaned
+        .split_whitespace()
+        .filter(|word| {
+            !matches!(
+                *word,
+                "nullable" | "nonnull" | "null_unspecified" | "_Nullable" | "_Nonnull" | "_Null_unspecified"
+                    | "__kindof" | "__weak" | "__strong" | "__unsafe_unretained" | "__autoreleasing" | "__block"
+                    | "oneway" | "in" | "out" | "inout" | "bycopy" | "byref"
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Rewrite Objective-C expressions inside a function or method body
+fn translate_body(body: &str) -> String {
+    let bytes = body.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    // Last non-whitespace byte written, to tell subscripts `a[i]` from message sends
+    let mut last_significant = None;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if let Some(end) = skip_literal_or_comment(bytes, i) {
+            out.extend_from_slice(&bytes[i..end]);
+            if !bytes[i..end].starts_with(b"/") {
+                last_significant = Some(b'"');
+            }
+            i = end;
+            continue;
+        }
+
+        match bytes[i] {
+            b'@' => {
+                let word = identifier_at(bytes, i + 1);
+                let after_word = i + 1 + word.len();
+                match word {
+                    "try" | "finally" | "autoreleasepool" => i = after_word,
+                    "catch" | "synchronized" | "selector" | "encode" | "protocol" => {
+                        let open = skip_whitespace(bytes, after_word);
+                        let end = if bytes.get(open) == Some(&b'(') {
+                            matching(bytes, open, b'(', b')').map_or(after_word, |close| close + 1)
+                        } else {
+                            after_word
+                        };
+                        if !matches!(word, "catch" | "synchronized") {
+                            out.push(b'0');
+                            last_significant = Some(b'0');
+                        }
+                        push_newlines(&mut out, &bytes[i..end]);
+                        i = end;
+                    }
+                    _ => match bytes.get(i + 1) {
+                        // Collection literals: arrays keep their elements, dictionaries become a value
+                        Some(b'[') => match matching(bytes, i + 1, b'[', b']') {
+                            Some(close) => {
+                                out.push(b'(');
+                                out.extend_from_slice(translate_body(&body[i + 2..close]).as_bytes());
+                                out.push(b')');
+                                last_significant = Some(b')');
+                                i = close + 1;
+                            }
+                            None => i += 1,
+                        },
+                        Some(b'{') => match matching(bytes, i + 1, b'{', b'}') {
+                            Some(close) => {
+                                out.push(b'0');
+                                push_newlines(&mut out, &bytes[i..close]);
+                                last_significant = Some(b'0');
+                                i = close + 1;
+                            }
+                            None => i += 1,
+                        },
+                        // `@"..."`, `@(expr)`, `@42`, `@YES`
+                        _ => i += 1,
+                    },
+                }
+            }
+            b'^' => match block_literal_body(bytes, i) {
+                Some((open, close)) => {
+                    out.extend_from_slice(b"__knots_block({");
+                    push_newlines(&mut out, &bytes[i..open]);
+                    out.extend_from_slice(translate_body(&body[open + 1..close]).as_bytes());
+                    out.extend_from_slice(b"})");
+                    last_significant = Some(b')');
+                    i = close + 1;
+                }
+                None => {
+                    out.push(b'^');
+                    last_significant = Some(b'^');
+                    i += 1;
+                }
+            },
+            b'[' if !is_subscript(&out, last_significant) => {
+                match matching(bytes, i, b'[', b']') {
+                    Some(close) => {
+                        out.extend_from_slice(translate_message_send(&body[i + 1..close]).as_bytes());
+                        last_significant = Some(b')');
+                        i = close + 1;
+                    }
+                    None => {
+                        out.push(b'[');
+                        i += 1;
+                    }
+                }
+            }
+            b'?' if bytes.get(skip_whitespace(bytes, i + 1)) == Some(&b':') => {
+                // GNU `a ?: b` shorthand, which tree-sitter-c does not accept
+                out.extend_from_slice(b"?0");
+                last_significant = Some(b'0');
+                i += 1;
+            }
+            byte if is_identifier_start(byte) => {
+                let word = identifier_at(bytes, i);
+                let end = i + word.len();
+                match fast_enumeration(bytes, end).filter(|_| word == "for") {
+                    Some((open, in_pos, close)) => {
+                        out.extend_from_slice(b"for (");
+                        out.extend_from_slice(translate_body(&body[open + 1..in_pos]).as_bytes());
+                        out.push(b';');
+                        out.extend_from_slice(translate_body(&body[in_pos + 2..close]).as_bytes());
+                        out.extend_from_slice(b";)");
+                        last_significant = Some(b')');
+                        i = close + 1;
+                    }
+                    None => {
+                        out.extend_from_slice(word.as_bytes());
+                        last_significant = Some(bytes[end - 1]);
+                        i = end;
+                    }
+                }
+            }
+            byte => {
+                out.push(byte);
+                if !byte.is_ascii_whitespace() {
+                    last_significant = Some(byte);
+                }
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8(out).unwrap_or_default()
+}
+
+/// For `for (Type *x in items)` after the `for` keyword, return the positions of the
+/// opening parenthesis, the `in` keyword, and the closing parenthesis
+fn fast_enumeration(bytes: &[u8], after_for: usize) -> Option<(usize, usize, usize)> {
+    let open = skip_whitespace(bytes, after_for);
+    if bytes.get(open) != Some(&b'(') {
+        return None;
+    }
+    let close = matching(bytes, open, b'(', b')')?;
+    let in_pos = top_level_positions(&bytes[..close], open + 1).find(|&j| {
+        bytes[j - 1].is_ascii_whitespace() && identifier_at(bytes, j) == "in" && bytes[j + 2].is_ascii_whitespace()
+    })?;
+    Some((open, in_pos, close))
+}
+
+/// A `[` after an operand (`a[i]`, `f()[i]`, `m[i][j]`) is a subscript; after an
+/// operator, keyword, or punctuation it starts a message send
+fn is_subscript(out: &[u8], last_significant: Option<u8>) -> bool {
+    match last_significant {
+        Some(b')' | b']') => true,
+        Some(byte) if is_identifier_char(byte) => {
+            let trimmed = out.trim_ascii_end();
+            let start = trimmed
+                .iter()
+                .rposition(|&byte| !is_identifier_char(byte))
+                .map_or(0, |pos| pos + 1);
+            !matches!(&trimmed[start..], b"return" | b"case" | b"else" | b"do")
+        }
+        _ => false,
+    }
+}
+
+/// For a block literal `^{`, `^(int x) {`, or `^BOOL(id obj) {` at `caret`,
+/// return the positions of its opening and closing braces
+fn block_literal_body(bytes: &[u8], caret: usize) -> Option<(usize, usize)> {
+    let mut i = skip_whitespace(bytes, caret + 1);
+    // Optional return type, which must be followed by the parameter list
+    let return_type = identifier_at(bytes, i);
+    if !return_type.is_empty() {
+        let mut j = skip_whitespace(bytes, i + return_type.len());
+        while bytes.get(j) == Some(&b'*') {
+            j = skip_whitespace(bytes, j + 1);
+        }
+        if bytes.get(j) != Some(&b'(') {
+            return None;
+        }
+        i = j;
+    }
+    if bytes.get(i) == Some(&b'(') {
+        i = skip_whitespace(bytes, matching(bytes, i, b'(', b')')? + 1);
+    }
+    // `a ^ b` and `a ^ (b)` are XOR, not blocks
+    if bytes.get(i) != Some(&b'{') {
+        return None;
+    }
+    Some((i, matching(bytes, i, b'{', b'}')?))
+}
+
+/// Rewrite the inside of `[receiver label:arg other:arg2]` as `__knots_send(receiver, arg, arg2)`
+fn translate_message_send(inner: &str) -> String {
+    let bytes = inner.as_bytes();
+    let receiver_start = skip_whitespace(bytes, 0);
+    let receiver_end = top_level_positions(bytes, receiver_start)
+        .find(|&j| bytes[j].is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+
+    // Keyword labels `name:` preceded by whitespace, outside nested expressions
+    let labels: Vec<(usize, usize)> = top_level_positions(bytes, receiver_end)
+        .filter(|&j| bytes[j - 1].is_ascii_whitespace() && is_identifier_start(bytes[j]))
+        .filter_map(|j| {
+            let label = identifier_at(bytes, j);
+            let colon = j + label.len();
+            (bytes.get(colon) == Some(&b':')).then_some((j, colon + 1))
+        })
+        .collect();
+
+    let mut call = String::from("__knots_send(");
+    call.push_str(&translate_body(&inner[..receiver_end]));
+    match labels.first() {
+        None => push_newlines_str(&mut call, &inner[receiver_end..]),
+        Some(&(first_start, _)) => {
+            call.push_str(&inner[receiver_end..first_start]);
+            for (index, &(_, arg_start)) in labels.iter().enumerate() {
+                let arg_end = labels.get(index + 1).map_or(inner.len(), |&(next_start, _)| next_start);
+                call.push_str(", ");
+                call.push_str(&translate_body(&inner[arg_start..arg_end]));
+            }
+        }
+    }
+    call.push(')');
+    call
+}
+
+/// Indexes from `start` that are not inside brackets, parentheses, braces, literals, or comments
+fn top_level_positions(bytes: &[u8], start: usize) -> impl Iterator<Item = usize> + '_ {
+    let mut depth = 0i32;
+    let mut skip_until = start;
+    (start..bytes.len()).filter(move |&j| {
+        if j < skip_until {
+            return false;
+        }
+        if let Some(end) = skip_literal_or_comment(bytes, j) {
+            skip_until = end;
+            return false;
+        }
+        match bytes[j] {
+            b'(' | b'[' | b'{' => {
+                depth += 1;
+                false
+            }
+            b')' | b']' | b'}' => {
+                depth -= 1;
+                false
+            }
+            _ => depth == 0,
+        }
+    })
+}
+
+/// If a string/char literal or comment starts at `i`, return the index just past it
+fn skip_literal_or_comment(bytes: &[u8], i: usize) -> Option<usize> {
+    match (bytes[i], bytes.get(i + 1)) {
+        (b'/', Some(b'/')) => Some(find_byte(bytes, i, b'\n').unwrap_or(bytes.len())),
+        (b'/', Some(b'*')) => Some(
+            bytes[i + 2..]
+                .windows(2)
+                .position(|window| window == b"*/")
+                .map_or(bytes.len(), |pos| i + 2 + pos + 2),
+        ),
+        (quote @ (b'"' | b'\''), _) => {
+            let mut j = i + 1;
+            while j < bytes.len() && bytes[j] != quote && bytes[j] != b'\n' {
+                j += if bytes[j] == b'\\' { 2 } else { 1 };
+            }
+            Some((j + 1).min(bytes.len()))
+        }
+        _ => None,
+    }
+}
+
+/// Index of the bracket closing the one at `open`, skipping literals and comments
+fn matching(bytes: &[u8], open: usize, open_byte: u8, close_byte: u8) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = open;
+    while i < bytes.len() {
+        if let Some(end) = skip_literal_or_comment(bytes, i) {
+            i = end;
+            continue;
+        }
+        if bytes[i] == open_byte {
+            depth += 1;
+        } else if bytes[i] == close_byte {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Position of a top-level `@end`-style directive at or after `from`
+fn find_directive(bytes: &[u8], from: usize, directive: &str) -> Option<usize> {
+    let mut i = from;
+    while i < bytes.len() {
+        if let Some(end) = skip_literal_or_comment(bytes, i) {
+            i = end;
+            continue;
+        }
+        if bytes[i..].starts_with(directive.as_bytes())
+            && !bytes.get(i + directive.len()).is_some_and(|&byte| is_identifier_char(byte))
+        {
+            return Some(i);
+        }
+        i += 1;
+    }
+    None
+}
+
+fn find_byte(bytes: &[u8], from: usize, byte: u8) -> Option<usize> {
+    bytes.get(from..)?.iter().position(|&b| b == byte).map(|pos| from + pos)
+}
+
+fn skip_whitespace(bytes: &[u8], mut i: usize) -> usize {
+    while bytes.get(i).is_some_and(|byte| byte.is_ascii_whitespace()) {
+        i += 1;
+    }
+    i
+}
+
+fn is_identifier_start(byte: u8) -> bool {
+    byte.is_ascii_alphabetic() || byte == b'_'
+}
+
+fn is_identifier_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+/// The C identifier starting at `i`, or "" if there is none
+fn identifier_at(bytes: &[u8], i: usize) -> &str {
+    if !bytes.get(i).is_some_and(|&byte| is_identifier_start(byte)) {
+        return "";
+    }
+    let end = (i..bytes.len()).find(|&j| !is_identifier_char(bytes[j])).unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[i..end]).unwrap_or("")
+}
+
+/// Keep only the line breaks of removed text so later lines keep their numbers
+fn push_newlines(out: &mut Vec<u8>, removed: &[u8]) {
+    out.extend(removed.iter().filter(|&&byte| byte == b'\n'));
+}
+
+fn push_newlines_str(out: &mut String, removed: &str) {
+    out.extend(removed.chars().filter(|&c| c == '\n'));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use knots::complexity::{calculate_mccabe_complexity, get_function_name};
+
+    #[test]
+    fn test_translate_objc_method() {
+        let source = r#"#import "Sensor.h"
+
+@interface Sensor : NSObject
+- (int)scale:(int)value by:(int)factor;
+@end
+
+@implementation Sensor {
+    int _offset;
+}
+
+- (int)scale:(int)value by:(int)factor {
+    if (value > [self limit] && factor != 0) {
+        [self.log write:@"clamped" level:2];
+        return [self limit];
+    }
+    return value * factor + _offset;
+}
+
+- (void)reset {
+    [self.items enumerateObjectsUsingBlock:^(id obj, NSUInteger idx, BOOL *stop) {
+        if (obj == nil) { *stop = YES; }
+    }];
+}
+@end
+"#;
+        let objc = translate(source);
+        assert_eq!(objc.code.lines().count(), source.lines().count());
+
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_c::LANGUAGE.into()).unwrap();
+        let tree = parser.parse(&objc.code, None).unwrap();
+        let root = tree.root_node();
+        assert!(!root.has_error(), "{}", objc.code);
+
+        let mut cursor = root.walk();
+        let methods: Vec<_> = root
+            .children(&mut cursor)
+            .filter(|node| node.kind() == "function_definition")
+            .map(|node| {
+                let name = get_function_name(node, objc.code.as_bytes()).unwrap();
+                (objc.method_names[&name].clone(), node.start_position().row + 1, calculate_mccabe_complexity(node, objc.code.as_bytes()))
+            })
+            .collect();
+
+        assert_eq!(
+            methods,
+            vec![("-[Sensor scale:by:]".to_string(), 11, 3), ("-[Sensor reset]".to_string(), 19, 2)]
+        );
+    }
+
+    #[test]
+    fn test_translate_block_literals() {
+        let source = r#"@implementation Queue
+- (void)drain {
+    dispatch_async(queue, ^{
+        for (id job in self.jobs) {
+            if ([job ready]) { [job run]; }
+        }
+    });
+    [self.items sortUsingComparator:^NSComparisonResult(id a, id b) { return a < b ? -1 : 1; }];
+    int mask = flags ^ (1 << 3);
+}
+@end
+"#;
+        let objc = translate(source);
+        assert_eq!(objc.code.lines().count(), source.lines().count());
+        assert!(objc.code.contains("__knots_block({"));
+        assert!(objc.code.contains("flags ^ (1 << 3)"));
+
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_c::LANGUAGE.into()).unwrap();
+        let tree = parser.parse(&objc.code, None).unwrap();
+        let root = tree.root_node();
+        assert!(!root.has_error(), "{}", objc.code);
+
+        let method = root.named_child(0).unwrap();
+        assert_eq!(method.kind(), "function_definition");
+        // Base 1 + for + if + ?: from the two blocks
+        assert_eq!(calculate_mccabe_complexity(method, objc.code.as_bytes()), 4);
+    }
+}
@@ -0,0 +1,370 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tree_sitter::Node;
+
+/// Compiler used to expand macros before analysis (`--preprocess`)
+#[derive(Clone)]
+pub struct Preprocessor {
+    pub compiler: String,
+    pub cflags: Vec<String>,
+    /// Per-file flags taken from compile_commands.json, appended after `cflags`
+    pub file_flags: HashMap<PathBuf, Vec<String>>,
+}
+
+/// Macros set with `--define`, which pick among `#ifdef`/`#if` branches without running a
+/// preprocessor: functions in branches the defines rule out are skipped
+///
+/// Only `#ifdef`, `#ifndef`, `#elifdef`, `#elifndef`, and `#if`/`#elif` conditions made of
+/// `defined(NAME)`, `NAME`, integer literals, `!`, and parentheses are evaluated. Other
+/// conditions (arithmetic, `&&`, `||`, function-like macros) are treated as unknown and
+/// their branches kept. Macros `#define`d in the source itself are not seen.
+#[derive(Debug, Clone, Default)]
+pub struct Defines {
+    values: HashMap<String, String>,
+}
+
+impl Defines {
+    pub fn new(defines: &[(String, String)]) -> Self {
+        Defines {
+            values: defines.iter().cloned().collect(),
+        }
+    }
+
+    /// Whether every enclosing conditional that can be evaluated selects the branch holding `node`
+    pub fn selects(&self, node: Node, source_code: &[u8]) -> bool {
+        let mut child = node;
+        while let Some(parent) = child.parent() {
+            if let Some(taken) = self.condition(parent, source_code) {
+                // The `alternative` (#elif/#else) is only reached when the condition is false
+                let in_alternative = parent
+                    .child_by_field_name("alternative")
+                    .is_some_and(|alternative| alternative.id() == child.id());
+                if taken == in_alternative {
+                    return false;
+                }
+            }
+            child = parent;
+        }
+        true
+    }
+
+    /// The value of a conditional directive's own condition; None for other nodes and
+    /// conditions too complex to evaluate
+    fn condition(&self, node: Node, source_code: &[u8]) -> Option<bool> {
+        match node.kind() {
+            "preproc_ifdef" | "preproc_elifdef" => {
+                let negated = node.child(0).is_some_and(|directive| directive.kind().ends_with("ndef"));
+                let name = node.child_by_field_name("name")?.utf8_text(source_code).ok()?;
+                Some(self.values.contains_key(name) != negated)
+            }
+            "preproc_if" | "preproc_elif" => self
+                .evaluate(node.child_by_field_name("condition")?, source_code)
+                .map(|value| value != 0),
+            _ => None,
+        }
+    }
+
+    fn evaluate(&self, expr: Node, source_code: &[u8]) -> Option<i64> {
+        let text = expr.utf8_text(source_code).ok()?;
+        match expr.kind() {
+            "number_literal" => parse_integer(text),
+            // An undefined macro is 0 in an #if; one defined as something other than a number is unknown
+            "identifier" => match self.values.get(text) {
+                Some(value) => parse_integer(value),
+                None => Some(0),
+            },
+            "preproc_defined" => {
+                let name = expr.named_child(0)?.utf8_text(source_code).ok()?;
+                Some(i64::from(self.values.contains_key(name)))
+            }
+            "unary_expression" if expr.child_by_field_name("operator")?.kind() == "!" => self
+                .evaluate(expr.child_by_field_name("argument")?, source_code)
+                .map(|value| i64::from(value == 0)),
+            "parenthesized_expression" => self.evaluate(expr.named_child(0)?, source_code),
+            _ => None,
+        }
+    }
+}
+
+/// A decimal, hex, or octal integer, ignoring `u`/`l` suffixes
+fn parse_integer(text: &str) -> Option<i64> {
+    let digits = text.trim().trim_end_matches(['u', 'U', 'l', 'L']);
+    if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8).ok()
+    } else {
+        digits.parse().ok()
+    }
+}
+
+/// Parse a `--define` value: `NAME` or `NAME=VALUE`; a bare name is defined as 1, as with `cc -D`
+pub fn parse_define(value: &str) -> Result<(String, String), String> {
+    let (name, value) = value.split_once('=').unwrap_or((value, "1"));
+    let is_identifier = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !is_identifier {
+        return Err(format!("invalid macro name '{}': expected NAME or NAME=VALUE", name));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Source code to parse, with a map back to the original lines when preprocessed
+pub struct SourceText {
+    /// Code to parse; after preprocessing, lines that came from included files are blanked
+    pub code: String,
+    /// Original line number for each line of `code` (0 = unknown, empty = identity)
+    pub line_map: Vec<usize>,
+    /// Names to report for functions whose parsed name is a placeholder (Objective-C methods)
+    pub function_names: HashMap<String, String>,
+}
+
+impl SourceText {
+    /// Source read as-is, where every line maps to itself
+    pub fn plain(code: String) -> Self {
+        Self {
+            code,
+            line_map: Vec::new(),
+            function_names: HashMap::new(),
+        }
+    }
+
+    /// Map a 1-based line of the preprocessed code back to the original file
+    pub fn original_line(&self, line: usize) -> usize {
+        match line.checked_sub(1).and_then(|index| self.line_map.get(index)) {
+            Some(&original) if original > 0 => original,
+            _ => line,
+        }
+    }
+}
+
+impl Preprocessor {
+    /// Run `<compiler> -E <cflags> <file>` and map its output back to the file's lines
+    pub fn run(&self, file: &Path) -> Result<SourceText> {
+        let output = Command::new(&self.compiler)
+            .arg("-E")
+            .args(&self.cflags)
+            .args(self.file_flags.get(file).into_iter().flatten())
+            .arg(file)
+            .output()
+            .with_context(|| format!("Failed to run preprocessor '{}'", self.compiler))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!(
+                "'{} -E' failed for {}: {}",
+                self.compiler,
+                file.display(),
+                stderr.lines().next().unwrap_or("").trim()
+            );
+        }
+
+        let expanded = String::from_utf8(output.stdout)
+            .with_context(|| format!("Preprocessor output for {} is not valid UTF-8", file.display()))?;
+
+        Ok(map_line_markers(&expanded))
+    }
+}
+
+/// Keep the flags of a compile command that affect preprocessing (-I, -D, -U, -include, ...)
+/// Relative include paths are resolved against the entry's `directory`
+pub fn preprocessor_flags(arguments: &[String], directory: &Path) -> Vec<String> {
+    const PATH_FLAGS: [&str; 4] = ["-I", "-isystem", "-iquote", "-idirafter"];
+    const VALUE_FLAGS: [&str; 4] = ["-D", "-U", "-include", "-imacros"];
+
+    let resolve = |path: &str| directory.join(path).to_string_lossy().into_owned();
+
+    let mut flags = Vec::new();
+    let mut args = arguments.iter();
+    while let Some(arg) = args.next() {
+        if let Some(flag) = PATH_FLAGS.iter().find(|flag| arg.starts_with(**flag)) {
+            let path = match &arg[flag.len()..] {
+                "" => match args.next() {
+                    Some(path) => path.as_str(),
+                    None => break,
+                },
+                path => path,
+            };
+            flags.push(flag.to_string());
+            flags.push(resolve(path));
+        } else if let Some(flag) = VALUE_FLAGS.iter().find(|flag| arg.starts_with(**flag)) {
+            flags.push(arg.clone());
+            if arg.len() == flag.len() {
+                flags.extend(args.next().cloned());
+            }
+        } else if arg.starts_with("-std=") {
+            flags.push(arg.clone());
+        }
+    }
+    flags
+}
+
+/// Parse a GCC line marker (`# 12 "file.c" 1 3`) or `#line 12 "file.c"` directive
+/// Returns the line number of the next line and the file name, if one is given
+fn parse_line_marker(line: &str) -> Option<(usize, Option<&str>)> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start();
+    let rest = rest.strip_prefix("line").unwrap_or(rest).trim_start();
+
+    let digits_end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    if digits_end == 0 {
+        return None;
+    }
+    let number = rest[..digits_end].parse().ok()?;
+
+    let file = rest[digits_end..]
+        .trim_start()
+        .strip_prefix('"')
+        .and_then(|quoted| quoted.find('"').map(|end| &quoted[..end]));
+
+    Some((number, file))
+}
+
+/// Strip line markers from preprocessor output and record where each line came from
+///
+/// The first marker names the main file. Lines from any other file (system and
+/// user headers) are replaced by empty lines, so only functions defined in the
+/// translation unit itself are analyzed while line positions stay aligned.
+fn map_line_markers(expanded: &str) -> SourceText {
+    let mut code = String::with_capacity(expanded.len());
+    let mut line_map = Vec::new();
+
+    let mut main_file: Option<String> = None;
+    let mut in_main_file = true;
+    let mut next_line = 1;
+
+    for line in expanded.lines() {
+        if let Some((number, file)) = parse_line_marker(line) {
+            if let Some(file) = file {
+                let main = main_file.get_or_insert_with(|| file.to_string());
+                in_main_file = file == main;
+            }
+            next_line = number;
+            code.push('\n');
+            line_map.push(0);
+            continue;
+        }
+
+        if in_main_file {
+            code.push_str(line);
+            line_map.push(next_line);
+        } else {
+            line_map.push(0);
+        }
+        code.push('\n');
+        next_line += 1;
+    }
+
+    SourceText {
+        code,
+        line_map,
+        function_names: HashMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_line_markers() {
+        let expanded = r#"# 1 "main.c"
+# 1 "<built-in>"
+int builtin_noise;
+# 1 "main.c"
+# 1 "util.h" 1
+int helper(int x) { return x; }
+# 2 "main.c" 2
+
+int run(int x) {
+#line 40 "main.c"
+    return x > 0 ? 1 : 0;
+}
+"#;
+        let source = map_line_markers(expanded);
+        let lines: Vec<&str> = source.code.lines().collect();
+
+        // Header and built-in lines are blanked; marker lines become empty lines
+        assert_eq!(lines.len(), 12);
+        assert_eq!(lines[2], "");
+        assert_eq!(lines[5], "");
+        assert_eq!(lines[8], "int run(int x) {");
+        assert_eq!(source.original_line(9), 3);
+        assert_eq!(source.original_line(11), 40);
+        assert_eq!(source.original_line(12), 41);
+    }
+
+    #[test]
+    fn test_preprocessor_flags() {
+        let arguments: Vec<String> = "cc -c -O2 -Iinclude -I /opt/sdk/inc -DBOARD_REV=2 -D NDEBUG -std=c11 -Wall -o main.o main.c"
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+
+        let flags = preprocessor_flags(&arguments, Path::new("/build"));
+        assert_eq!(
+            flags,
+            vec!["-I", "/build/include", "-I", "/opt/sdk/inc", "-DBOARD_REV=2", "-D", "NDEBUG", "-std=c11"]
+        );
+    }
+
+    #[test]
+    fn test_defines_select_branches() {
+        let code = "
+#ifdef DEBUG
+void dump_state(void) {}
+#else
+void quiet(void) {}
+#endif
+#ifndef DEBUG
+void fast_path(void) {}
+#endif
+#if defined(PRODUCTION) && LEVEL > 2
+void unknown(void) {}
+#elif !defined(PRODUCTION)
+void lab_only(void) {}
+#endif
+#if LEVEL
+void leveled(void) {}
+#endif
+void always(void) {}
+";
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_c::LANGUAGE.into()).unwrap();
+        let tree = parser.parse(code, None).unwrap();
+        let selected = |defines: &Defines| {
+            let mut names = Vec::new();
+            let mut stack = vec![tree.root_node()];
+            while let Some(node) = stack.pop() {
+                if node.kind() == "function_definition" {
+                    if defines.selects(node, code.as_bytes()) {
+                        let declarator = node.child_by_field_name("declarator").unwrap();
+                        let name = declarator.child_by_field_name("declarator").unwrap();
+                        names.push(name.utf8_text(code.as_bytes()).unwrap().to_string());
+                    }
+                    continue;
+                }
+                let mut cursor = node.walk();
+                let children: Vec<Node> = node.children(&mut cursor).collect();
+                stack.extend(children.into_iter().rev());
+            }
+            names
+        };
+
+        // #ifdef DEBUG included
+        let debug = Defines::new(&[parse_define("DEBUG").unwrap()]);
+        assert_eq!(selected(&debug), vec!["dump_state", "unknown", "lab_only", "always"]);
+
+        // #ifdef DEBUG excluded; the && condition is unknown, so its branch is kept
+        let production = Defines::new(&[parse_define("PRODUCTION").unwrap(), parse_define("LEVEL=3").unwrap()]);
+        assert_eq!(selected(&production), vec!["quiet", "fast_path", "unknown", "leveled", "always"]);
+
+        let level_zero = Defines::new(&[parse_define("LEVEL=0").unwrap()]);
+        assert_eq!(selected(&level_zero), vec!["quiet", "fast_path", "unknown", "lab_only", "always"]);
+
+        assert_eq!(parse_define("BOARD_REV=2"), Ok(("BOARD_REV".to_string(), "2".to_string())));
+        assert!(parse_define("2FAST").is_err());
+        assert!(parse_define("=1").is_err());
+    }
+}
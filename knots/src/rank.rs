@@ -0,0 +1,205 @@
+use crate::{Field, FunctionMetrics};
+use std::fmt;
+
+/// A `--rank-by` expression such as `mccabe*2 + test_score`
+///
+/// Supports numbers, metric names (the `--fields` keys), `+ - * /`, unary minus,
+/// and parentheses, with the usual precedence.
+#[derive(Debug, Clone)]
+pub struct RankExpr {
+    text: String,
+    root: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Metric(Field),
+    Negate(Box<Expr>),
+    Binary(Box<Expr>, char, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Symbol(char),
+}
+
+impl RankExpr {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = ExprParser { tokens, pos: 0 };
+        let root = parser.sum()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(format!("unexpected {:?} in rank expression", token));
+        }
+        Ok(RankExpr {
+            text: text.trim().to_string(),
+            root,
+        })
+    }
+
+    /// Sort key for a function; higher ranks first
+    pub fn eval(&self, func: &FunctionMetrics) -> f64 {
+        eval(&self.root, func)
+    }
+
+    /// Metrics the expression reads, so --metrics can compute them
+    pub fn fields(&self) -> Vec<Field> {
+        let mut fields = Vec::new();
+        collect_fields(&self.root, &mut fields);
+        fields
+    }
+}
+
+impl fmt::Display for RankExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                number.push(c);
+                chars.next();
+            }
+            let value = number.parse().map_err(|_| format!("invalid number '{}' in rank expression", number))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || **c == '_') {
+                name.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Name(name));
+        } else if "+-*/()".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            return Err(format!("unexpected character '{}' in rank expression", c));
+        }
+    }
+    Ok(tokens)
+}
+
+struct ExprParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl ExprParser {
+    fn next_symbol(&mut self, symbols: &str) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Symbol(c)) if symbols.contains(*c) => {
+                self.pos += 1;
+                Some(*c)
+            }
+            _ => None,
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some(op) = self.next_symbol("+-") {
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.factor()?;
+        while let Some(op) = self.next_symbol("*/") {
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.factor()?));
+        }
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        if self.next_symbol("-").is_some() {
+            return Ok(Expr::Negate(Box::new(self.factor()?)));
+        }
+        if self.next_symbol("(").is_some() {
+            let expr = self.sum()?;
+            return match self.next_symbol(")") {
+                Some(_) => Ok(expr),
+                None => Err("missing ')' in rank expression".to_string()),
+            };
+        }
+
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Name(name)) => Field::ALL
+                .iter()
+                .find(|field| field.key() == name)
+                .map(|field| Expr::Metric(*field))
+                .ok_or_else(|| {
+                    let known: Vec<&str> = Field::ALL.iter().map(|field| field.key()).collect();
+                    format!("unknown metric '{}' in rank expression (expected one of: {})", name, known.join(", "))
+                }),
+            Some(token) => Err(format!("unexpected {:?} in rank expression", token)),
+            None => Err("rank expression ends unexpectedly".to_string()),
+        }
+    }
+}
+
+fn eval(expr: &Expr, func: &FunctionMetrics) -> f64 {
+    match expr {
+        Expr::Number(value) => *value,
+        Expr::Metric(field) => field.value(func),
+        Expr::Negate(inner) => -eval(inner, func),
+        Expr::Binary(left, op, right) => {
+            let (left, right) = (eval(left, func), eval(right, func));
+            match op {
+                '+' => left + right,
+                '-' => left - right,
+                '*' => left * right,
+                _ => left / right,
+            }
+        }
+    }
+}
+
+fn collect_fields(expr: &Expr, fields: &mut Vec<Field>) {
+    match expr {
+        Expr::Number(_) => {}
+        Expr::Metric(field) => {
+            if !fields.contains(field) {
+                fields.push(*field);
+            }
+        }
+        Expr::Negate(inner) => collect_fields(inner, fields),
+        Expr::Binary(left, _, right) => {
+            collect_fields(left, fields);
+            collect_fields(right, fields);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank_expression() {
+        let expr = RankExpr::parse("mccabe*2 + test_score").unwrap();
+        assert_eq!(expr.fields(), vec![Field::Mccabe, Field::TestScore]);
+        assert_eq!(expr.to_string(), "mccabe*2 + test_score");
+
+        let negated = RankExpr::parse("-(sloc)").unwrap();
+        assert_eq!(negated.root, Expr::Negate(Box::new(Expr::Metric(Field::Sloc))));
+        assert!(RankExpr::parse("mccabe * complexity").unwrap_err().contains("unknown metric 'complexity'"));
+        assert!(RankExpr::parse("mccabe +").is_err());
+        assert!(RankExpr::parse("(mccabe").is_err());
+        assert!(RankExpr::parse("mccabe % 2").is_err());
+    }
+}
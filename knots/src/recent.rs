@@ -0,0 +1,143 @@
+#[cfg(feature = "git")]
+use anyhow::{Context, Result};
+#[cfg(feature = "git")]
+use git2::Repository;
+#[cfg(feature = "git")]
+use std::collections::HashSet;
+#[cfg(feature = "git")]
+use std::path::Path;
+use std::time::Duration;
+#[cfg(feature = "git")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Parse a `--since` window such as `90m`, `12h`, `7d`, or `2w`
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let invalid = || format!("invalid duration '{}': expected a number followed by s, m, h, d, or w", value);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" | "" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("invalid duration unit '{}': expected s, m, h, d, or w", unit)),
+    };
+    amount.checked_mul(seconds).map(Duration::from_secs).ok_or_else(invalid)
+}
+
+/// Unix timestamp `window` before now
+#[cfg(feature = "git")]
+pub fn cutoff(window: Duration) -> i64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    now.saturating_sub(window).as_secs() as i64
+}
+
+/// A run of consecutive lines last changed by the same commit
+#[cfg(feature = "git")]
+pub struct BlameHunk {
+    /// First line (1-based)
+    pub start: usize,
+    pub lines: usize,
+    /// Author name and commit time; None for uncommitted edits
+    pub author: Option<(String, i64)>,
+}
+
+/// git blame of `file`'s current contents, so uncommitted edits get hunks of their own
+///
+/// Fails if the file is not tracked in a git repository.
+#[cfg(feature = "git")]
+pub fn blame_file(file: &Path) -> Result<Vec<BlameHunk>> {
+    let path = file
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", file.display()))?;
+    let repo = Repository::discover(&path).context("not in a git repository")?;
+    let workdir = repo.workdir().context("git repository has no working directory")?;
+    let relative = path
+        .strip_prefix(workdir.canonicalize()?)
+        .context("file is outside the git working directory")?;
+
+    let contents = std::fs::read(&path).with_context(|| format!("Failed to read {}", file.display()))?;
+    let committed = repo
+        .blame_file(relative, None)
+        .context("file is not tracked by git")?;
+    let blame = committed.blame_buffer(&contents).context("git blame failed")?;
+
+    let hunks = blame
+        .iter()
+        .map(|hunk| BlameHunk {
+            start: hunk.final_start_line(),
+            lines: hunk.lines_in_hunk(),
+            // Uncommitted lines have no commit (and no signature) yet
+            author: repo.find_commit(hunk.final_commit_id()).ok().map(|commit| {
+                let author = commit.author();
+                (author.name().unwrap_or("unknown").to_string(), author.when().seconds())
+            }),
+        })
+        .collect();
+    Ok(hunks)
+}
+
+/// Lines of `file` (1-based) last changed at or after `cutoff`, according to git blame
+///
+/// Uncommitted edits count as recent. Fails if the file is not tracked in a git repository.
+#[cfg(feature = "git")]
+pub fn recent_lines(file: &Path, cutoff: i64) -> Result<HashSet<usize>> {
+    let mut lines = HashSet::new();
+    for hunk in blame_file(file)? {
+        let is_recent = hunk.author.map(|(_, time)| time >= cutoff).unwrap_or(true);
+        if is_recent {
+            lines.extend(hunk.start..hunk.start + hunk.lines);
+        }
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "git")]
+    use crate::test_support::{commit_file, TempDir};
+    #[cfg(feature = "git")]
+    use git2::{Signature, Time};
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 86400)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 3600)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 86400)));
+        assert_eq!(parse_duration("3"), Ok(Duration::from_secs(3 * 86400)));
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("5y").is_err());
+        assert_eq!(
+            parse_duration("99999999999999999w"),
+            Err("invalid duration '99999999999999999w': expected a number followed by s, m, h, d, or w".to_string())
+        );
+    }
+
+    #[cfg(feature = "git")]
+    #[test]
+    fn test_recent_lines() {
+        let dir = TempDir::new("recent");
+        let repo = Repository::init(dir.path()).unwrap();
+
+        // Commit the file with a timestamp far in the past
+        let author = Signature::new("Dev", "dev@example.com", &Time::new(946_684_800, 0)).unwrap();
+        commit_file(&repo, "old.c", "int a(void) {\n    return 1;\n}\n", &author);
+        let file = dir.join("old.c");
+
+        let window = parse_duration("7d").unwrap();
+        assert!(recent_lines(&file, cutoff(window)).unwrap().is_empty());
+
+        // An uncommitted edit is recent
+        std::fs::write(&file, "int a(void) {\n    return 2;\n}\n").unwrap();
+        assert_eq!(recent_lines(&file, cutoff(window)).unwrap(), HashSet::from([2]));
+
+        // Untracked files are not under git
+        let untracked = dir.join("new.c");
+        std::fs::write(&untracked, "int b(void) { return 0; }\n").unwrap();
+        assert!(recent_lines(&untracked, cutoff(window)).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::trend::TrendPoint;
+use crate::{Field, FunctionMetrics};
+use anyhow::{Context, Result};
+#[cfg(feature = "git")]
+use git2::Repository;
+use rusqlite::{params, Connection, OpenFlags};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One row per function per run; `run_timestamp` and `git_commit` identify the run
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS function_metrics (
+    run_timestamp INTEGER NOT NULL,
+    git_commit TEXT,
+    file_path TEXT NOT NULL,
+    function TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    mccabe INTEGER NOT NULL,
+    cognitive INTEGER NOT NULL,
+    nesting INTEGER NOT NULL,
+    sloc INTEGER NOT NULL,
+    abc_magnitude REAL NOT NULL,
+    return_count INTEGER NOT NULL,
+    exit_points INTEGER NOT NULL,
+    magic_numbers INTEGER NOT NULL,
+    loop_count INTEGER NOT NULL,
+    branch_count INTEGER NOT NULL,
+    switch_case_total INTEGER NOT NULL,
+    test_score INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS function_metrics_function ON function_metrics (file_path, function);
+CREATE INDEX IF NOT EXISTS function_metrics_run ON function_metrics (run_timestamp);
+";
+
+/// Commit checked out in the repository containing `path`, if any
+#[cfg(feature = "git")]
+pub fn head_commit(path: &Path) -> Option<String> {
+    let repo = Repository::discover(path).ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    Some(commit.id().to_string())
+}
+
+/// Without the `git` feature, runs are recorded without a commit
+#[cfg(not(feature = "git"))]
+pub fn head_commit(_path: &Path) -> Option<String> {
+    None
+}
+
+/// Insert one run's per-function metrics into a SQLite database (--sqlite),
+/// creating the table if needed; all rows share the run's timestamp and commit
+pub fn record_run(db_path: &Path, metrics: &[FunctionMetrics], git_commit: Option<&str>) -> Result<()> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+    let mut connection = Connection::open(db_path)
+        .with_context(|| format!("Failed to open SQLite database: {}", db_path.display()))?;
+    connection.execute_batch(SCHEMA).context("Failed to create SQLite schema")?;
+
+    let transaction = connection.transaction()?;
+    {
+        let mut insert = transaction.prepare(
+            "INSERT INTO function_metrics (run_timestamp, git_commit, file_path, function, line, mccabe, cognitive,
+                nesting, sloc, abc_magnitude, return_count, exit_points, magic_numbers, loop_count, branch_count,
+                switch_case_total, test_score)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+        )?;
+        for func in metrics {
+            insert.execute(params![
+                timestamp,
+                git_commit,
+                func.file_path,
+                func.name,
+                func.line as i64,
+                func.mccabe,
+                func.cognitive,
+                func.nesting,
+                func.sloc,
+                func.abc_magnitude,
+                func.return_count,
+                func.exit_points,
+                func.magic_numbers,
+                func.loop_count,
+                func.branch_count,
+                func.switch_case_total,
+                func.test_scoring.total_score,
+            ])?;
+        }
+    }
+    transaction
+        .commit()
+        .with_context(|| format!("Failed to write SQLite database: {}", db_path.display()))
+}
+
+/// Column holding a metric in the function_metrics table
+fn column(field: Field) -> &'static str {
+    match field {
+        Field::Mccabe => "mccabe",
+        Field::Cognitive => "cognitive",
+        Field::Nesting => "nesting",
+        Field::Sloc => "sloc",
+        Field::Abc => "abc_magnitude",
+        Field::Returns => "return_count",
+        Field::TestScore => "test_score",
+        Field::Loops => "loop_count",
+        Field::Branches => "branch_count",
+    }
+}
+
+/// Per-function average of a metric for every recorded run, oldest first (knots trend)
+pub fn load_trend(db_path: &Path, field: Field) -> Result<Vec<TrendPoint>> {
+    let connection = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open SQLite database: {}", db_path.display()))?;
+    let query = format!(
+        "SELECT date(run_timestamp, 'unixepoch'), git_commit, AVG({}), COUNT(*) FROM function_metrics
+         GROUP BY run_timestamp, git_commit ORDER BY run_timestamp, MIN(rowid)",
+        column(field)
+    );
+    let mut select = connection
+        .prepare(&query)
+        .with_context(|| format!("Failed to read runs from {}", db_path.display()))?;
+    let points = select
+        .query_map([], |row| {
+            Ok(TrendPoint {
+                date: row.get(0)?,
+                git_commit: row.get(1)?,
+                average: row.get(2)?,
+                functions: row.get(3)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(points)
+}
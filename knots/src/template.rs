@@ -0,0 +1,92 @@
+use crate::Violation;
+
+/// Placeholders a --message-template may use
+const PLACEHOLDERS: [&str; 6] = ["file", "line", "func", "metric", "value", "threshold"];
+
+/// The layout of a threshold violation line printed on stderr
+const DEFAULT_TEMPLATE: &str = "Error: {file}:{line}: {func}() has {metric} {value} (max {threshold})";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Placeholder(&'static str),
+}
+
+/// A validated --message-template: literal text with `{name}` placeholders, where `{{`
+/// and `}}` stand for literal braces
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageTemplate {
+    segments: Vec<Segment>,
+}
+
+impl MessageTemplate {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err("unclosed '{' in message template (write '{{' for a literal brace)".to_string()),
+                        }
+                    }
+                    let placeholder = PLACEHOLDERS.iter().find(|&&known| known == name).ok_or_else(|| {
+                        format!(
+                            "unknown placeholder {{{}}} in message template (expected one of {})",
+                            name,
+                            PLACEHOLDERS.map(|known| format!("{{{}}}", known)).join(", ")
+                        )
+                    })?;
+                    if !literal.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder(placeholder));
+                }
+                '}' => return Err("unmatched '}' in message template (write '}}' for a literal brace)".to_string()),
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Text(literal));
+        }
+        Ok(MessageTemplate { segments })
+    }
+
+    /// The message for one violation
+    pub fn render(&self, violation: &Violation) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => out.push_str(text),
+                Segment::Placeholder(name) => out.push_str(&match *name {
+                    "file" => violation.func.file_path.clone(),
+                    "line" => violation.func.line.to_string(),
+                    "func" => violation.func.name.clone(),
+                    "metric" => violation.metric.to_string(),
+                    "value" => violation.value.to_string(),
+                    _ => violation.threshold.to_string(),
+                }),
+            }
+        }
+        out
+    }
+}
+
+impl Default for MessageTemplate {
+    fn default() -> Self {
+        MessageTemplate::parse(DEFAULT_TEMPLATE).expect("default message template is valid")
+    }
+}
@@ -0,0 +1,43 @@
+use std::path::{Path, PathBuf};
+
+/// An empty directory under the system temp dir, removed when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// `knots-<name>-test-<pid>`, so tests running in parallel need distinct names
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("knots-{}-test-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Write `contents` to `file` (relative to the work tree) and commit it on HEAD as `author`
+#[cfg(feature = "git")]
+pub fn commit_file(repo: &git2::Repository, file: &str, contents: &str, author: &git2::Signature) {
+    let path = repo.workdir().unwrap().join(file);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, contents).unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new(file)).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+    repo.commit(Some("HEAD"), author, author, &format!("Update {}", file), &tree, &parents).unwrap();
+}
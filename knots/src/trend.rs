@@ -0,0 +1,166 @@
+/// One run recorded in a --sqlite database
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrendPoint {
+    /// UTC day of the run, YYYY-MM-DD
+    pub date: String,
+    pub git_commit: Option<String>,
+    /// Mean of the metric over the run's functions
+    pub average: f64,
+    pub functions: usize,
+}
+
+impl TrendPoint {
+    /// Date plus the abbreviated commit, when the run recorded one
+    fn label(&self) -> String {
+        match &self.git_commit {
+            Some(commit) => format!("{} {}", self.date, &commit[..commit.len().min(7)]),
+            None => self.date.clone(),
+        }
+    }
+}
+
+/// Bar heights of the terminal sparkline, lowest first
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+const SVG_WIDTH: f64 = 320.0;
+/// Height of the plot, above the date labels
+const SVG_PLOT_HEIGHT: f64 = 60.0;
+const SVG_LABEL_HEIGHT: f64 = 14.0;
+const SVG_MARGIN: f64 = 4.0;
+
+/// Lowest and highest averages
+fn value_range(points: &[TrendPoint]) -> (f64, f64) {
+    points.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), point| {
+        (low.min(point.average), high.max(point.average))
+    })
+}
+
+/// Position of `value` between `low` (0.0) and `high` (1.0); a flat series sits in the middle
+fn normalize(value: f64, (low, high): (f64, f64)) -> f64 {
+    if high <= low {
+        0.5
+    } else {
+        (value - low) / (high - low)
+    }
+}
+
+/// Sparkline of the averages, with the change and the first and last runs labeled;
+/// `points` must not be empty
+pub fn format_text(points: &[TrendPoint], metric: &str, precision: usize) -> String {
+    let range = value_range(points);
+    let spark: String = points
+        .iter()
+        .map(|point| SPARK_LEVELS[(normalize(point.average, range) * (SPARK_LEVELS.len() - 1) as f64).round() as usize])
+        .collect();
+    let (first, last) = (&points[0], &points[points.len() - 1]);
+    format!(
+        "{} average over {} run(s): {:.*} -> {:.*} ({:+.*})\n{}\n{} .. {}\n",
+        metric,
+        points.len(),
+        precision,
+        first.average,
+        precision,
+        last.average,
+        precision,
+        last.average - first.average,
+        spark,
+        first.label(),
+        last.label()
+    )
+}
+
+/// SVG sparkline of the averages; hovering a point shows its date, commit, and value,
+/// and the first and last runs label the axis. `points` must not be empty
+pub fn format_svg(points: &[TrendPoint], metric: &str, precision: usize) -> String {
+    let range = value_range(points);
+    let step = (SVG_WIDTH - 2.0 * SVG_MARGIN) / points.len().saturating_sub(1).max(1) as f64;
+    let coordinates: Vec<(f64, f64)> = points
+        .iter()
+        .enumerate()
+        .map(|(index, point)| {
+            let x = if points.len() == 1 { SVG_WIDTH / 2.0 } else { SVG_MARGIN + index as f64 * step };
+            let y = SVG_MARGIN + (1.0 - normalize(point.average, range)) * (SVG_PLOT_HEIGHT - 2.0 * SVG_MARGIN);
+            (x, y)
+        })
+        .collect();
+
+    let height = SVG_PLOT_HEIGHT + SVG_LABEL_HEIGHT;
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+        w = SVG_WIDTH,
+        h = height
+    );
+    out.push_str(&format!("  <title>{} average per run</title>\n", metric));
+    let polyline: Vec<String> = coordinates.iter().map(|(x, y)| format!("{:.1},{:.1}", x, y)).collect();
+    out.push_str(&format!(
+        "  <polyline fill=\"none\" stroke=\"steelblue\" stroke-width=\"1.5\" points=\"{}\"/>\n",
+        polyline.join(" ")
+    ));
+    for (point, (x, y)) in points.iter().zip(&coordinates) {
+        out.push_str(&format!(
+            "  <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"2\" fill=\"steelblue\"><title>{}: {} {:.*} ({} functions)</title></circle>\n",
+            x,
+            y,
+            point.label(),
+            metric,
+            precision,
+            point.average,
+            point.functions
+        ));
+    }
+    let baseline = height - 3.0;
+    out.push_str(&format!(
+        "  <text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"10\">{}</text>\n",
+        SVG_MARGIN,
+        baseline,
+        points[0].label()
+    ));
+    if points.len() > 1 {
+        out.push_str(&format!(
+            "  <text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"10\" text-anchor=\"end\">{}</text>\n",
+            SVG_WIDTH - SVG_MARGIN,
+            baseline,
+            points[points.len() - 1].label()
+        ));
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(date: &str, commit: Option<&str>, average: f64) -> TrendPoint {
+        TrendPoint {
+            date: date.to_string(),
+            git_commit: commit.map(str::to_string),
+            average,
+            functions: 10,
+        }
+    }
+
+    #[test]
+    fn test_trend_sparklines() {
+        let points = vec![
+            point("2026-01-05", Some("0123456789abcdef"), 4.0),
+            point("2026-02-02", None, 6.0),
+            point("2026-03-09", Some("fedcba9876543210"), 5.0),
+        ];
+        assert_eq!(
+            format_text(&points, "mccabe", 2),
+            "mccabe average over 3 run(s): 4.00 -> 5.00 (+1.00)\n▁█▅\n2026-01-05 0123456 .. 2026-03-09 fedcba9\n"
+        );
+
+        let svg = format_svg(&points, "mccabe", 2);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"320\" height=\"74\""));
+        assert!(svg.contains("points=\"4.0,56.0 160.0,4.0 316.0,30.0\""));
+        assert!(svg.contains("<title>2026-02-02: mccabe 6.00 (10 functions)</title>"));
+        assert!(svg.contains("text-anchor=\"end\">2026-03-09 fedcba9</text>"));
+
+        // A single run, or a flat series, sits mid-height
+        let flat = format_svg(&points[..1], "mccabe", 2);
+        assert!(flat.contains("points=\"160.0,30.0\""));
+        assert!(!flat.contains("text-anchor"));
+    }
+}
@@ -0,0 +1,301 @@
+use crate::{get_complexity_emoji, FunctionMetrics, PrimaryMetric, EMOJI_BANDS};
+use anyhow::{Context, Result};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::cmp::Ordering;
+use std::process::Command;
+
+/// Column the --tui table is sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// The --primary-metric figure
+    Primary,
+    Mccabe,
+    Cognitive,
+    Nesting,
+    Sloc,
+    /// File path, then line
+    Location,
+}
+
+impl SortKey {
+    fn compare(self, a: &FunctionMetrics, b: &FunctionMetrics, primary: PrimaryMetric) -> Ordering {
+        match self {
+            SortKey::Primary => primary.complexity(a).cmp(&primary.complexity(b)),
+            SortKey::Mccabe => a.mccabe.cmp(&b.mccabe),
+            SortKey::Cognitive => a.cognitive.cmp(&b.cognitive),
+            SortKey::Nesting => a.nesting.cmp(&b.nesting),
+            SortKey::Sloc => a.sloc.cmp(&b.sloc),
+            SortKey::Location => a.file_path.cmp(&b.file_path).then(a.line.cmp(&b.line)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SortKey::Primary => "primary",
+            SortKey::Mccabe => "mccabe",
+            SortKey::Cognitive => "cognitive",
+            SortKey::Nesting => "nesting",
+            SortKey::Sloc => "sloc",
+            SortKey::Location => "location",
+        }
+    }
+}
+
+/// What the event loop should do after a key press
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+    None,
+    Quit,
+    /// Open the file at the line in $EDITOR
+    Open(String, usize),
+}
+
+/// Sort, band filter, name filter, and selection over a run's functions
+pub struct Browser<'a> {
+    metrics: &'a [FunctionMetrics],
+    primary: PrimaryMetric,
+    sort: SortKey,
+    descending: bool,
+    /// Index into EMOJI_BANDS, or None for every band
+    band: Option<usize>,
+    /// Case-insensitive substring of the function name
+    filter: String,
+    /// Keys go to the name filter until Enter or Esc
+    typing: bool,
+    rows: Vec<&'a FunctionMetrics>,
+    state: TableState,
+}
+
+impl<'a> Browser<'a> {
+    /// All functions, most complex first
+    pub fn new(metrics: &'a [FunctionMetrics], primary: PrimaryMetric) -> Self {
+        let mut browser = Browser {
+            metrics,
+            primary,
+            sort: SortKey::Primary,
+            descending: true,
+            band: None,
+            filter: String::new(),
+            typing: false,
+            rows: Vec::new(),
+            state: TableState::default(),
+        };
+        browser.refresh();
+        browser
+    }
+
+    /// The functions shown, in display order
+    pub fn rows(&self) -> &[&'a FunctionMetrics] {
+        &self.rows
+    }
+
+    pub fn selected(&self) -> Option<&'a FunctionMetrics> {
+        self.state.selected().and_then(|index| self.rows.get(index).copied())
+    }
+
+    /// Reapply the filters and sort, keeping the selection in range
+    fn refresh(&mut self) {
+        let filter = self.filter.to_lowercase();
+        let primary = self.primary;
+        let band = self.band.map(|band| EMOJI_BANDS[band].0);
+        self.rows = self
+            .metrics
+            .iter()
+            .filter(|func| band.map(|emoji| get_complexity_emoji(primary.complexity(func)) == emoji).unwrap_or(true))
+            .filter(|func| func.name.to_lowercase().contains(&filter))
+            .collect();
+
+        let (sort, descending) = (self.sort, self.descending);
+        self.rows.sort_by(|a, b| {
+            let order = sort.compare(a, b, primary);
+            if descending {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+
+        let selected = match self.rows.len() {
+            0 => None,
+            len => Some(self.state.selected().unwrap_or(0).min(len - 1)),
+        };
+        self.state.select(selected);
+    }
+
+    /// Sort by `key`; choosing the current key again reverses the order
+    fn sort_by(&mut self, key: SortKey) {
+        if self.sort == key {
+            self.descending = !self.descending;
+        } else {
+            self.sort = key;
+            // Numbers read best largest first, locations in file order
+            self.descending = key != SortKey::Location;
+        }
+        self.refresh();
+    }
+
+    fn move_selection(&mut self, offset: isize) {
+        if let Some(selected) = self.state.selected() {
+            let last = self.rows.len().saturating_sub(1) as isize;
+            self.state.select(Some((selected as isize + offset).clamp(0, last) as usize));
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyCode) -> Action {
+        if self.typing {
+            match key {
+                KeyCode::Char(c) => self.filter.push(c),
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Enter => self.typing = false,
+                KeyCode::Esc => {
+                    self.typing = false;
+                    self.filter.clear();
+                }
+                _ => return Action::None,
+            }
+            self.refresh();
+            return Action::None;
+        }
+
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('p') => self.sort_by(SortKey::Primary),
+            KeyCode::Char('m') => self.sort_by(SortKey::Mccabe),
+            KeyCode::Char('c') => self.sort_by(SortKey::Cognitive),
+            KeyCode::Char('n') => self.sort_by(SortKey::Nesting),
+            KeyCode::Char('s') => self.sort_by(SortKey::Sloc),
+            KeyCode::Char('f') => self.sort_by(SortKey::Location),
+            KeyCode::Char('b') => {
+                self.band = match self.band {
+                    None => Some(0),
+                    Some(band) if band + 1 < EMOJI_BANDS.len() => Some(band + 1),
+                    Some(_) => None,
+                };
+                self.refresh();
+            }
+            KeyCode::Char('/') => self.typing = true,
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::PageDown => self.move_selection(20),
+            KeyCode::PageUp => self.move_selection(-20),
+            KeyCode::Home | KeyCode::Char('g') => self.move_selection(isize::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => self.move_selection(isize::MAX / 2),
+            KeyCode::Enter => {
+                if let Some(func) = self.selected() {
+                    return Action::Open(func.file_path.clone(), func.line);
+                }
+            }
+            _ => {}
+        }
+        Action::None
+    }
+
+    /// One line describing the sort and filters
+    fn status(&self) -> String {
+        let band = self
+            .band
+            .map(|band| format!("{} {}", EMOJI_BANDS[band].0, EMOJI_BANDS[band].1))
+            .unwrap_or_else(|| "all".to_string());
+        let cursor = if self.typing { "_" } else { "" };
+        format!(
+            " {} of {} functions | sort: {} {} | band: {} | name: {}{} ",
+            self.rows().len(),
+            self.metrics.len(),
+            self.sort.name(),
+            if self.descending { "desc" } else { "asc" },
+            band,
+            self.filter,
+            cursor
+        )
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [table_area, help_area] = Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+
+        let header = Row::new(["", "PRIMARY", "MCCABE", "COGN", "NEST", "SLOC", "FUNCTION", "LOCATION"])
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        let primary = self.primary;
+        let rows = self.rows.iter().map(|func| {
+            let complexity = primary.complexity(func);
+            Row::new([
+                get_complexity_emoji(complexity).to_string(),
+                complexity.to_string(),
+                func.mccabe.to_string(),
+                func.cognitive.to_string(),
+                func.nesting.to_string(),
+                func.sloc.to_string(),
+                func.name.clone(),
+                format!("{}:{}", func.file_path, func.line),
+            ])
+        });
+        let widths = [
+            Constraint::Length(2),
+            Constraint::Length(7),
+            Constraint::Length(6),
+            Constraint::Length(4),
+            Constraint::Length(4),
+            Constraint::Length(5),
+            Constraint::Percentage(30),
+            Constraint::Fill(1),
+        ];
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title(self.status()))
+            .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, table_area, &mut self.state);
+
+        frame.render_widget(
+            Line::from(" sort: p/m/c/n/s/f  band: b  name: /  open: Enter  quit: q"),
+            help_area,
+        );
+    }
+}
+
+/// Browse `metrics` until the user quits (--tui)
+pub fn run(metrics: &[FunctionMetrics], primary: PrimaryMetric) -> Result<()> {
+    let mut browser = Browser::new(metrics, primary);
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut browser);
+    ratatui::restore();
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, browser: &mut Browser) -> Result<()> {
+    loop {
+        terminal.draw(|frame| browser.draw(frame))?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match browser.handle_key(key.code) {
+            Action::None => {}
+            Action::Quit => return Ok(()),
+            Action::Open(file, line) => {
+                ratatui::restore();
+                let opened = open_in_editor(&file, line);
+                *terminal = ratatui::init();
+                opened?;
+            }
+        }
+    }
+}
+
+/// Run `$EDITOR +LINE FILE` (vi when EDITOR is unset) and wait for it to exit
+fn open_in_editor(file: &str, line: usize) -> Result<()> {
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    Command::new(&editor)
+        .arg(format!("+{}", line))
+        .arg(file)
+        .status()
+        .with_context(|| format!("Failed to run editor '{}'", editor))?;
+    Ok(())
+}